@@ -0,0 +1,225 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    bitboard::{PieceType, Team},
+    board::{BoardState, History},
+    opponents::{expected_line, piece_value, rank_moves, SearchInfo},
+    pgn::{write_movetext, PgnGame},
+    r#move::Move,
+};
+
+// How much worse than the best move (in centipawns) a move can be before it drops to the next grade
+const GOOD_LOSS: i32 = 50;
+const INACCURACY_LOSS: i32 = 100;
+const MISTAKE_LOSS: i32 = 300;
+const EVAL_GRAPH_LIMIT: i32 = 1000; // Evals further than this either way sit at the edge of an eval graph
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MoveQuality {
+    Brilliant,
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+impl MoveQuality {
+    // The annotation symbol PGN puts after a move of this quality
+    pub fn pgn_suffix(&self) -> &'static str {
+        match self {
+            MoveQuality::Brilliant => "!!",
+            MoveQuality::Best | MoveQuality::Good => "",
+            MoveQuality::Inaccuracy => "?!",
+            MoveQuality::Mistake => "?",
+            MoveQuality::Blunder => "??",
+        }
+    }
+    // The numeric annotation glyph for the same mark, which PGN readers show however they like
+    pub fn nag(&self) -> Option<u8> {
+        match self {
+            MoveQuality::Brilliant => Some(3),
+            MoveQuality::Best | MoveQuality::Good => None,
+            MoveQuality::Inaccuracy => Some(6),
+            MoveQuality::Mistake => Some(2),
+            MoveQuality::Blunder => Some(4),
+        }
+    }
+}
+impl Display for MoveQuality {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/*
+    Grades a move by how much it gave up against the best one. `eval_diff` is the best move's score minus the played
+    move's score, from the mover's point of view. Finding the best move while leaving a piece hanging is brilliant
+*/
+pub fn classify(
+    board: &BoardState,
+    played_move: Move,
+    best_move: Move,
+    eval_diff: i32,
+) -> MoveQuality {
+    let eval_diff = eval_diff.max(0);
+
+    if played_move == best_move || eval_diff == 0 {
+        if is_sacrifice(board, played_move) {
+            MoveQuality::Brilliant
+        } else {
+            MoveQuality::Best
+        }
+    } else if eval_diff < GOOD_LOSS {
+        MoveQuality::Good
+    } else if eval_diff < INACCURACY_LOSS {
+        MoveQuality::Inaccuracy
+    } else if eval_diff < MISTAKE_LOSS {
+        MoveQuality::Mistake
+    } else {
+        MoveQuality::Blunder
+    }
+}
+
+// Whether the move puts a piece where the opponent can take it for less than it's worth
+fn is_sacrifice(board: &BoardState, played_move: Move) -> bool {
+    let moved_type = board.piece_list[played_move.start];
+    let captured_value = played_move
+        .captures
+        .map_or(0, |captured| piece_value(captured.piece_type));
+
+    let mut after = *board;
+    if moved_type == PieceType::Pawn || after.make_move(played_move).is_err() {
+        return false;
+    }
+    after.is_attacked_by(played_move.target, after.active_team)
+        && piece_value(moved_type) > captured_value
+}
+
+// One move of a game after the engine has been over it
+#[derive(Debug, Clone)]
+pub struct AnnotatedMove {
+    pub played: Move,
+    pub san: String,
+    pub mover: Team,
+    pub quality: MoveQuality,
+    pub eval: i32, // After the move, in centipawns from white's point of view
+    pub loss: i32, // How much worse than the best move it was, for the mover
+    pub better_line: Option<Vec<String>>, // What the engine preferred, for anything worse than good
+}
+
+/*
+    Searches every position of a line `search_budget` plies deep and grades each move played in it.
+    Stops early if a move can't be played
+*/
+pub fn analyse_moves(
+    start: BoardState,
+    moves: &[Move],
+    search_budget: i32,
+) -> Result<Vec<AnnotatedMove>, String> {
+    let mut board = start;
+    let mut history = History::default();
+    let mut analysed = Vec::new();
+
+    for played in moves {
+        let ranked = rank_moves(&board, &history, search_budget, &mut SearchInfo::default());
+        let Some(&(best_move, best_eval)) = ranked.first() else {
+            break;
+        };
+        let played_eval = ranked
+            .iter()
+            .find(|(legal_move, _)| legal_move == played)
+            .map_or(best_eval, |(_, eval)| *eval);
+        let quality = classify(
+            &board,
+            *played,
+            best_move,
+            best_eval.saturating_sub(played_eval),
+        );
+        let sign = if board.active_team == Team::White {
+            1
+        } else {
+            -1
+        };
+
+        analysed.push(AnnotatedMove {
+            played: *played,
+            san: played.to_san(&board),
+            mover: board.active_team,
+            quality,
+            eval: played_eval.saturating_mul(sign),
+            loss: (best_eval.saturating_sub(played_eval)).max(0),
+            better_line: (!matches!(
+                quality,
+                MoveQuality::Brilliant | MoveQuality::Best | MoveQuality::Good
+            ))
+            .then(|| expected_line(&board, best_move, search_budget)),
+        });
+
+        if history.make_move(&mut board, *played).is_err() {
+            return Err(format!(
+                "Couldn't play {} in {}",
+                played.to_uci_on(board.size),
+                board.as_fen()
+            ));
+        }
+    }
+    Ok(analysed)
+}
+
+// How far up an eval graph an eval from white's point of view goes, from 0 with black winning to 1 with white winning
+pub fn eval_graph_height(eval: i32) -> f32 {
+    (eval.clamp(-EVAL_GRAPH_LIMIT, EVAL_GRAPH_LIMIT) + EVAL_GRAPH_LIMIT) as f32
+        / (2 * EVAL_GRAPH_LIMIT) as f32
+}
+
+// The moves of a PGN game's mainline, checked against the position each is played in
+pub fn pgn_moves(game: &PgnGame) -> Result<Vec<Move>, String> {
+    let mut board = game.start_board()?;
+    let mut moves = Vec::new();
+
+    for san in &game.moves {
+        let played = Move::from_san(&board, san)
+            .ok_or_else(|| format!("Illegal move {san} in {}", board.as_fen()))?;
+        board
+            .make_move(played)
+            .map_err(|_| format!("Couldn't play {san} in {}", board.as_fen()))?;
+        moves.push(played);
+    }
+    Ok(moves)
+}
+
+// The ply a game starts on, counting white's first move as 0
+pub fn first_ply(board: &BoardState) -> usize {
+    (board.turn_clock.max(1) as usize - 1) * 2 + (board.active_team == Team::Black) as usize
+}
+
+/*
+    Replays a game, searching every position `search_budget` plies deep, and writes it back out as PGN. Each move
+    gets its eval from white's point of view in pawns and its quality mark, and anything worse than good is followed
+    by the line the engine preferred
+*/
+pub fn annotate_game(game: &PgnGame, search_budget: i32) -> Result<String, String> {
+    let start = game.start_board()?;
+    let analysed = analyse_moves(start, &pgn_moves(game)?, search_budget)?;
+    let annotated: Vec<String> = analysed
+        .iter()
+        .map(|analysed_move| {
+            let mut entry = format!(
+                "{}{} {{{:+.2}}}",
+                analysed_move.san,
+                analysed_move.quality.pgn_suffix(),
+                analysed_move.eval as f32 / 100.0
+            );
+            if let Some(line) = &analysed_move.better_line {
+                entry.push_str(&format!(" ({})", line.join(" ")));
+            }
+            entry
+        })
+        .collect();
+
+    Ok(format!(
+        "{}\n{}",
+        game.header(),
+        write_movetext(first_ply(&start), &annotated, &game.result)
+    ))
+}
@@ -0,0 +1,613 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+
+use crate::{
+    analysis::first_ply,
+    bitboard::Team,
+    board::{BoardState, GameStatus, History, Variant},
+    opponents::{
+        uci::{UciEngine, DEFAULT_UCI_MOVE_TIME},
+        ChessOpponent, EngineOptions, MoveComputer, SearchStats,
+    },
+    pgn::write_movetext,
+    START_POS_CHESS,
+};
+
+// How many standard errors either side of a match score the Elo margin covers, for 95% confidence
+const ELO_CONFIDENCE: f64 = 1.96;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+impl MatchResult {
+    pub fn win_for(team: Team) -> MatchResult {
+        if team == Team::White {
+            MatchResult::WhiteWins
+        } else {
+            MatchResult::BlackWins
+        }
+    }
+}
+impl Display for MatchResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MatchResult::WhiteWins => "1-0",
+            MatchResult::BlackWins => "0-1",
+            MatchResult::Draw => "1/2-1/2",
+        })
+    }
+}
+
+/*
+    When an automated game gets called early instead of being played out. Scores are in centipawns and move counts
+    are full moves, so both engines have to agree for that many of their own moves in a row.
+*/
+#[derive(Debug, Copy, Clone)]
+pub struct Adjudication {
+    pub resign_score: i32,
+    pub resign_moves: usize,
+    pub draw_score: i32,
+    pub draw_moves: usize,
+    pub draw_after_move: usize, // Openings often hover around 0, so draws aren't called before this move
+    pub move_limit: usize,      // Anything still going after this many moves is a draw
+}
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication {
+            resign_score: 1000,
+            resign_moves: 3,
+            draw_score: 10,
+            draw_moves: 8,
+            draw_after_move: 40,
+            move_limit: 200,
+        }
+    }
+}
+
+// Keeps the running streaks the adjudication rules need as a game is played
+pub struct Adjudicator {
+    rules: Adjudication,
+    scores: [Option<i32>; 2], // Each engine's last score, from white's point of view
+    resign_streak: usize,
+    draw_streak: usize,
+}
+impl Adjudicator {
+    pub fn new(rules: Adjudication) -> Adjudicator {
+        Adjudicator {
+            rules,
+            scores: [None, None],
+            resign_streak: 0,
+            draw_streak: 0,
+        }
+    }
+
+    /*
+        Feeds in the score the engine that just moved reported, from white's point of view, or None if it doesn't
+        report scores. Returns a result once the rules call the game
+    */
+    pub fn record(
+        &mut self,
+        mover: Team,
+        score: Option<i32>,
+        board: &BoardState,
+    ) -> Option<MatchResult> {
+        if let Some(verdict) = tablebase_verdict(board) {
+            return Some(verdict);
+        }
+
+        self.scores[mover as usize] = score;
+        let plies = |moves: usize| moves * 2;
+
+        match self.scores {
+            [Some(white_score), Some(black_score)] => {
+                let winning_side = if white_score.min(black_score) >= self.rules.resign_score {
+                    Some(Team::White)
+                } else if white_score.max(black_score) <= -self.rules.resign_score {
+                    Some(Team::Black)
+                } else {
+                    None
+                };
+                self.resign_streak = if winning_side.is_some() {
+                    self.resign_streak + 1
+                } else {
+                    0
+                };
+                if let Some(winner) =
+                    winning_side.filter(|_| self.resign_streak >= plies(self.rules.resign_moves))
+                {
+                    return Some(MatchResult::win_for(winner));
+                }
+
+                let level = white_score.abs().max(black_score.abs()) <= self.rules.draw_score;
+                let past_opening = board.turn_clock as usize > self.rules.draw_after_move;
+                self.draw_streak = if level && past_opening {
+                    self.draw_streak + 1
+                } else {
+                    0
+                };
+                if self.draw_streak >= plies(self.rules.draw_moves) {
+                    return Some(MatchResult::Draw);
+                }
+            }
+            _ => {
+                self.resign_streak = 0;
+                self.draw_streak = 0;
+            }
+        }
+
+        if board.turn_clock as usize > self.rules.move_limit {
+            return Some(MatchResult::Draw);
+        }
+        None
+    }
+}
+
+/*
+    The result a tablebase would give for this position, if we know it. There are no tablebases to probe yet, so
+    this only covers the material no one can mate with, which every tablebase calls a draw. Tablebases are for
+    standard chess, where a variant's king can still walk to the hill or a lone piece still give a check that counts
+*/
+pub fn tablebase_verdict(board: &BoardState) -> Option<MatchResult> {
+    (matches!(board.variant, Variant::Standard | Variant::Capablanca)
+        && board.has_insufficient_material())
+    .then_some(MatchResult::Draw)
+}
+
+// A finished automated game
+pub struct MatchGame {
+    pub result: MatchResult,
+    pub adjudicated: bool,
+    pub moves: Vec<String>,      // In SAN
+    pub stats: [SearchStats; 2], // White's engine, then black's
+}
+
+/*
+    Plays one game between two opponents without the UI, calling it early whenever the adjudication rules allow
+*/
+pub fn play_game<'a>(
+    white: &'a mut dyn MoveComputer,
+    black: &'a mut dyn MoveComputer,
+    start: BoardState,
+    rules: Adjudication,
+) -> MatchGame {
+    let mut board = start;
+    let mut history = History::default();
+    let players = [white, black];
+    let mut adjudicator = Adjudicator::new(rules);
+    let mut moves = Vec::new();
+    let mut stats = [SearchStats::default(); 2];
+
+    loop {
+        let mover = board.active_team;
+
+        match history.status(&board) {
+            GameStatus::Ongoing => {}
+            status => {
+                let result = status
+                    .winner()
+                    .map_or(MatchResult::Draw, MatchResult::win_for);
+                return MatchGame {
+                    result,
+                    adjudicated: false,
+                    moves,
+                    stats,
+                };
+            }
+        }
+
+        let started = Instant::now();
+        let outcome = players[mover as usize].think(board, &history, false, &mut |_| {});
+        stats[mover as usize].record(&outcome.info, started.elapsed());
+        let Some(chosen) = outcome.best_move else {
+            // An engine that can't come up with a move while it has legal ones forfeits
+            return MatchGame {
+                result: MatchResult::win_for(mover.opponent()),
+                adjudicated: true,
+                moves,
+                stats,
+            };
+        };
+
+        moves.push(chosen.to_san(&board));
+        if history.make_move(&mut board, chosen).is_err() {
+            return MatchGame {
+                result: MatchResult::win_for(mover.opponent()),
+                adjudicated: true,
+                moves,
+                stats,
+            };
+        }
+
+        // Engines that actually searched report a score for the side they moved for
+        let white_score = (outcome.info.depth > 0).then(|| {
+            if mover == Team::White {
+                outcome.info.score
+            } else {
+                -outcome.info.score
+            }
+        });
+        if let Some(result) = adjudicator.record(mover, white_score, &board) {
+            return MatchGame {
+                result,
+                adjudicated: true,
+                moves,
+                stats,
+            };
+        }
+    }
+}
+
+// A tournament player, either one of our own opponents or an engine running in another process
+pub struct Entrant {
+    pub name: String,
+    pub engine: Box<dyn MoveComputer>,
+}
+
+/*
+    Reads an entrant from the command line: `randy`, `matt:<depth>`, `ada:<ms>` or `uci:<path>[:<ms>]`, where a UCI
+    engine gets DEFAULT_UCI_MOVE_TIME a move unless told otherwise
+*/
+pub fn parse_entrant(spec: &str) -> Result<Entrant, String> {
+    let (kind, setting) = spec.split_once(':').unwrap_or((spec, ""));
+    let number = |text: &str| {
+        text.parse::<u64>()
+            .map_err(|_| format!("{spec} needs a number after the colon"))
+    };
+
+    let (name, engine): (String, Box<dyn MoveComputer>) = match kind.to_lowercase().as_str() {
+        "randy" => (String::from("Randy"), Box::new(ChessOpponent::Randy)),
+        "matt" => {
+            let opponent = ChessOpponent::Matt(number(setting)?.max(1) as i32);
+            (opponent.to_string(), Box::new(opponent))
+        }
+        "ada" => {
+            let opponent = ChessOpponent::ada(EngineOptions::with_move_time(
+                Duration::from_millis(number(setting)?),
+            ));
+            (opponent.to_string(), Box::new(opponent))
+        }
+        "uci" => {
+            // Paths can have colons of their own, so the move time is only split off the end when it's a number
+            let (path, move_time) = match setting.rsplit_once(':') {
+                Some((path, millis)) if millis.parse::<u64>().is_ok() => {
+                    (path, Duration::from_millis(number(millis)?))
+                }
+                _ => (setting, DEFAULT_UCI_MOVE_TIME),
+            };
+            let engine = UciEngine::launch(path, move_time)?;
+            (engine.to_string(), Box::new(engine))
+        }
+        _ => return Err(format!("Unknown entrant {spec}")),
+    };
+    Ok(Entrant { name, engine })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pairing {
+    pub round: usize,
+    pub white: usize, // Indexes into the entrants
+    pub black: usize,
+}
+
+/*
+    Schedules `cycles` round-robins with the circle method, so nobody plays twice in a round. Each cycle is played
+    twice with colours reversed, so every pair meets once with each colour. With an odd number of entrants someone
+    sits out each round
+*/
+pub fn round_robin(entrants: usize, cycles: usize) -> Vec<Pairing> {
+    let slots = entrants + entrants % 2;
+    let rounds_per_leg = slots.saturating_sub(1);
+    // Slot 0 stays put while everyone else rotates around it
+    let seat = |round: usize, slot: usize| {
+        if slot == 0 {
+            0
+        } else {
+            (slot - 1 + round) % rounds_per_leg + 1
+        }
+    };
+    let mut pairings = Vec::new();
+
+    for leg in 0..cycles * 2 {
+        for round in 0..rounds_per_leg {
+            for table in 0..slots / 2 {
+                let (first, second) = (seat(round, table), seat(round, slots - 1 - table));
+                if first >= entrants || second >= entrants {
+                    continue;
+                }
+                let (white, black) = if (table + round + leg) % 2 == 0 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                pairings.push(Pairing {
+                    round: leg * rounds_per_leg + round + 1,
+                    white,
+                    black,
+                });
+            }
+        }
+    }
+    pairings
+}
+
+// Who scored what against whom. Points are kept in halves so draws add up exactly
+pub struct Crosstable {
+    pub names: Vec<String>,
+    half_points: Vec<Vec<u32>>, // [player][opponent]
+    games: Vec<Vec<u32>>,
+}
+impl Crosstable {
+    pub fn new(names: Vec<String>) -> Crosstable {
+        let size = names.len();
+        Crosstable {
+            names,
+            half_points: vec![vec![0; size]; size],
+            games: vec![vec![0; size]; size],
+        }
+    }
+    pub fn record(&mut self, pairing: Pairing, result: MatchResult) {
+        let (white_half_points, black_half_points) = match result {
+            MatchResult::WhiteWins => (2, 0),
+            MatchResult::BlackWins => (0, 2),
+            MatchResult::Draw => (1, 1),
+        };
+        self.half_points[pairing.white][pairing.black] += white_half_points;
+        self.half_points[pairing.black][pairing.white] += black_half_points;
+        self.games[pairing.white][pairing.black] += 1;
+        self.games[pairing.black][pairing.white] += 1;
+    }
+    pub fn score(&self, player: usize) -> f32 {
+        self.half_points[player].iter().sum::<u32>() as f32 / 2.0
+    }
+    // Entrants from the highest score down
+    pub fn standings(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        order.sort_by(|a, b| self.score(*b).total_cmp(&self.score(*a)));
+        order
+    }
+}
+impl Display for Crosstable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let order = self.standings();
+        let name_width = self.names.iter().map(String::len).max().unwrap_or(0);
+
+        write!(f, "{:>3}  {:<name_width$}", "#", "Entrant")?;
+        for column in 1..=order.len() {
+            write!(f, " {column:>4}")?;
+        }
+        writeln!(f, "  Score")?;
+        for (place, player) in order.iter().enumerate() {
+            write!(f, "{:>3}  {:<name_width$}", place + 1, self.names[*player])?;
+            for opponent in &order {
+                if opponent == player || self.games[*player][*opponent] == 0 {
+                    write!(f, " {:>4}", "-")?;
+                } else {
+                    write!(
+                        f,
+                        " {:>4}",
+                        self.half_points[*player][*opponent] as f32 / 2.0
+                    )?;
+                }
+            }
+            writeln!(
+                f,
+                "  {} / {}",
+                self.score(*player),
+                self.games[*player].iter().sum::<u32>()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Games alternating colours between the first two entrants, so each plays white in every other one
+pub fn match_pairings(games: usize) -> Vec<Pairing> {
+    (0..games)
+        .map(|game| Pairing {
+            round: game + 1,
+            white: game % 2,
+            black: 1 - game % 2,
+        })
+        .collect()
+}
+
+// How a match between two entrants has gone, from the first one's side
+#[derive(Debug, Clone, Default)]
+pub struct MatchScore {
+    pub names: [String; 2],
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+impl MatchScore {
+    pub fn new(names: [String; 2]) -> MatchScore {
+        MatchScore {
+            names,
+            ..Default::default()
+        }
+    }
+    pub fn record(&mut self, pairing: Pairing, result: MatchResult) {
+        let first_won = match result {
+            MatchResult::Draw => {
+                self.draws += 1;
+                return;
+            }
+            MatchResult::WhiteWins => pairing.white == 0,
+            MatchResult::BlackWins => pairing.black == 0,
+        };
+        if first_won {
+            self.wins += 1;
+        } else {
+            self.losses += 1;
+        }
+    }
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+    // The first entrant's points, a draw being worth half
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+    /*
+        The Elo difference the score points to in the first entrant's favour, and the margin either side of it.
+        There's none to give before any games or after a clean sweep, which no finite difference explains
+    */
+    pub fn elo_difference(&self) -> Option<(f64, f64)> {
+        let games = self.games() as f64;
+        let score = self.points() / games;
+        if self.games() == 0 || score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games;
+        let margin = ELO_CONFIDENCE * (variance / games).sqrt();
+        // Scores right at 0 or 1 would be an infinite difference, so the error bars stop just short of them
+        let elo = |score: f64| -400.0 * (1.0 / score.clamp(0.001, 0.999) - 1.0).log10();
+
+        Some((
+            elo(score),
+            (elo(score + margin) - elo(score - margin)) / 2.0,
+        ))
+    }
+}
+impl Display for MatchScore {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name_width = self.names.iter().map(String::len).max().unwrap_or(0);
+        let games = self.games();
+        writeln!(
+            f,
+            "{:<name_width$} {:>4} {:>4} {:>4}  Score",
+            "Entrant", "W", "D", "L"
+        )?;
+        writeln!(
+            f,
+            "{:<name_width$} {:>4} {:>4} {:>4}  {} / {games}",
+            self.names[0],
+            self.wins,
+            self.draws,
+            self.losses,
+            self.points()
+        )?;
+        writeln!(
+            f,
+            "{:<name_width$} {:>4} {:>4} {:>4}  {} / {games}",
+            self.names[1],
+            self.losses,
+            self.draws,
+            self.wins,
+            games as f64 - self.points()
+        )?;
+        match self.elo_difference() {
+            Some((elo, margin)) => write!(
+                f,
+                "Elo difference: {elo:+.0} \u{b1} {margin:.0} for {}",
+                self.names[0]
+            ),
+            None => write!(f, "Elo difference: too one-sided to estimate"),
+        }
+    }
+}
+
+/*
+    The positions in an opening suite, one FEN a line. EPD lines work too, since anything after the first four
+    fields that isn't a move counter is dropped. Blank lines and ones starting with `#` are left out
+*/
+pub fn read_openings(text: &str) -> Result<Vec<BoardState>, String> {
+    let mut openings = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let counters: Vec<&str> = fields
+            .iter()
+            .skip(4)
+            .take(2)
+            .take_while(|field| field.parse::<u32>().is_ok())
+            .copied()
+            .collect();
+        let fen = match counters.as_slice() {
+            [halfmoves, moves] => format!("{} {halfmoves} {moves}", fields[..4].join(" ")),
+            _ => format!("{} 0 1", fields[..fields.len().min(4)].join(" ")),
+        };
+        let board = BoardState::from_fen(fen.clone())
+            .map_err(|err| format!("Invalid opening ({err}): {line}"))?;
+        openings.push(board);
+    }
+    Ok(openings)
+}
+
+// One game of a tournament, written out as PGN. Games that didn't start from the usual position say where they did
+pub fn tournament_game_pgn(
+    names: &[String],
+    pairing: Pairing,
+    game: &MatchGame,
+    start: &BoardState,
+) -> String {
+    let mut header = format!(
+        "[Event \"chess-r tournament\"]\n[Site \"chess-r\"]\n[Date \"{}\"]\n[Round \"{}\"]\n[White \"{}\"]\n[Black \"{}\"]\n[Result \"{}\"]\n",
+        Utc::now().format("%Y.%m.%d"),
+        pairing.round,
+        names[pairing.white],
+        names[pairing.black],
+        game.result
+    );
+    if game.adjudicated {
+        header.push_str("[Termination \"adjudication\"]\n");
+    }
+    let fen = start.as_fen();
+    if fen != START_POS_CHESS {
+        header.push_str(&format!("[SetUp \"1\"]\n[FEN \"{fen}\"]\n"));
+    }
+    format!(
+        "{header}\n{}",
+        write_movetext(first_ply(start), &game.moves, &game.result.to_string())
+    )
+}
+
+/*
+    Plays every pairing, calling `on_game` as each game finishes. Each two games in a row start from the next of
+    `openings`, going back round to the first when they run out, so a match that alternates colours plays every
+    opening from both sides. Returns the crosstable and every game as PGN
+*/
+pub fn play_tournament(
+    entrants: &mut [Entrant],
+    pairings: &[Pairing],
+    openings: &[BoardState],
+    rules: Adjudication,
+    on_game: &mut dyn FnMut(Pairing, &MatchGame),
+) -> (Crosstable, String) {
+    let names: Vec<String> = entrants
+        .iter()
+        .map(|entrant| entrant.name.clone())
+        .collect();
+    let mut crosstable = Crosstable::new(names.clone());
+    let mut pgn = Vec::new();
+
+    for (index, pairing) in pairings.iter().enumerate() {
+        // Both engines are borrowed out of the list at once, so it's split between them
+        let (low, high) = entrants.split_at_mut(pairing.white.max(pairing.black));
+        let (first, second) = (&mut low[pairing.white.min(pairing.black)], &mut high[0]);
+        let (white, black) = if pairing.white < pairing.black {
+            (first, second)
+        } else {
+            (second, first)
+        };
+
+        let start = openings[index / 2 % openings.len()];
+        let game = play_game(white.engine.as_mut(), black.engine.as_mut(), start, rules);
+        crosstable.record(*pairing, game.result);
+        pgn.push(tournament_game_pgn(&names, *pairing, &game, &start));
+        on_game(*pairing, &game);
+    }
+    (crosstable, pgn.join("\n"))
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use ggez::{
+    audio::{SoundSource, Source},
+    Context, GameResult,
+};
+
+use crate::{board::BoardState, r#move::Move};
+
+pub const DEFAULT_SOUND_PACK: &str = "default"; // The sounds in /sounds itself, rather than in a pack folder
+const SOUND_EXTENSIONS: [&str; 2] = ["ogg", "wav"]; // Tried in this order for each sound
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sound {
+    Intro, // A new game starting
+    Move,
+    Capture,
+    Castle,
+    Check,
+    Promotion,
+    LowTime, // The player's clock running down past LOW_TIME_WARNING
+    GameEnd,
+}
+impl Sound {
+    pub const ALL: [Sound; 8] = [
+        Sound::Intro,
+        Sound::Move,
+        Sound::Capture,
+        Sound::Castle,
+        Sound::Check,
+        Sound::Promotion,
+        Sound::LowTime,
+        Sound::GameEnd,
+    ];
+    // What a pack's file for the sound is called, less the extension
+    pub fn id(self) -> &'static str {
+        match self {
+            Sound::Intro => "bass_intro",
+            Sound::Move => "piece_move",
+            Sound::Capture => "capture",
+            Sound::Castle => "castle",
+            Sound::Check => "check",
+            Sound::Promotion => "promotion",
+            Sound::LowTime => "low_time",
+            Sound::GameEnd => "game_end",
+        }
+    }
+    // How loud it plays at full volume. The default pack's own sounds were recorded much louder than the rest
+    fn level(self) -> f32 {
+        match self {
+            Sound::Intro | Sound::Move | Sound::Capture => 0.1,
+            _ => 0.5,
+        }
+    }
+    // What's played instead when a pack doesn't have this one, if anything is
+    fn fallback(self) -> Option<Sound> {
+        match self {
+            Sound::Capture | Sound::Castle | Sound::Check | Sound::Promotion => Some(Sound::Move),
+            _ => None,
+        }
+    }
+    // The sound for `played`, now that it's been made and led to `after`. A check drowns out everything else
+    pub fn for_move(played: Move, after: &BoardState) -> Sound {
+        if after.is_team_checked(after.active_team) {
+            Sound::Check
+        } else if played.promotion.is_some() {
+            Sound::Promotion
+        } else if played.is_castle {
+            Sound::Castle
+        } else if played.captures.is_some() {
+            Sound::Capture
+        } else {
+            Sound::Move
+        }
+    }
+}
+
+/*
+    Plays the game's sounds. Anything can ask for a sound with `queue`, without a Context to hand, and what's been
+    asked for is played together once a frame
+*/
+pub struct AudioManager {
+    pub pack: String,
+    sources: HashMap<Sound, Source>,
+    queued: Vec<Sound>,
+}
+impl Default for AudioManager {
+    fn default() -> Self {
+        AudioManager {
+            pack: String::from(DEFAULT_SOUND_PACK),
+            sources: HashMap::new(),
+            queued: Vec::new(),
+        }
+    }
+}
+impl AudioManager {
+    /*
+        Swaps in the sounds from a pack, which is a folder of .ogg or .wav files under /sounds named like the default
+        ones sitting in /sounds itself. A sound the pack doesn't have falls back to a plainer one, or stays silent
+    */
+    pub fn load_pack(&mut self, ctx: &mut Context, pack: &str) {
+        let folder = if pack == DEFAULT_SOUND_PACK {
+            String::from("/sounds")
+        } else {
+            format!("/sounds/{pack}")
+        };
+        self.sources.clear();
+        self.pack = pack.to_string();
+
+        for sound in Sound::ALL {
+            let id = sound.id();
+            let source = SOUND_EXTENSIONS
+                .iter()
+                .find_map(|extension| Source::new(ctx, format!("{folder}/{id}.{extension}")).ok());
+            match source {
+                Some(source) => {
+                    self.sources.insert(sound, source);
+                }
+                None => tracing::warn!("No {id} sound in the {pack} sound pack"),
+            }
+        }
+    }
+    // The default pack, then every folder under /sounds
+    pub fn packs(ctx: &Context) -> Vec<String> {
+        let mut packs: Vec<String> = ctx
+            .fs
+            .read_dir("/sounds")
+            .map(|entries| {
+                entries
+                    .filter(|entry| ctx.fs.is_dir(entry))
+                    .filter_map(|entry| Some(entry.file_name()?.to_string_lossy().into_owned()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        packs.sort();
+        packs.dedup();
+        packs.insert(0, String::from(DEFAULT_SOUND_PACK));
+        packs
+    }
+    // Moves on to the next pack along, back to the default after the last
+    pub fn next_pack(&mut self, ctx: &mut Context) {
+        let packs = AudioManager::packs(ctx);
+        let next = packs
+            .iter()
+            .position(|pack| *pack == self.pack)
+            .map_or(0, |current| (current + 1) % packs.len());
+        self.load_pack(ctx, &packs[next]);
+    }
+    pub fn queue(&mut self, sound: Sound) {
+        if !self.queued.contains(&sound) {
+            self.queued.push(sound);
+        }
+    }
+    // Plays everything queued since last time at `volume`, from 0 to 1. Muted, the queue is just emptied
+    pub fn play_queued(&mut self, ctx: &mut Context, volume: f32, muted: bool) -> GameResult<()> {
+        if muted {
+            self.queued.clear();
+        }
+        for sound in std::mem::take(&mut self.queued) {
+            let Some(playing) = std::iter::successors(Some(sound), |sound| sound.fallback())
+                .find(|sound| self.sources.contains_key(sound))
+            else {
+                continue;
+            };
+            let level = playing.level() * volume;
+            if let Some(source) = self.sources.get_mut(&playing) {
+                source.set_volume(level);
+                source.play(ctx)?;
+            }
+        }
+        Ok(())
+    }
+}
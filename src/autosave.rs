@@ -0,0 +1,213 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::{
+    bitboard::Team,
+    board::{BoardState, Variant},
+    clock::{ChessClock, TimeControl},
+    opponents::{ChessOpponent, EngineOptions},
+    r#move::Move,
+};
+
+pub const AUTOSAVE_FILE: &str = "autosave.txt"; // The game in progress, kept in case the app doesn't close cleanly
+
+// Each side's time control and what was left on its clock when the game was saved, white's first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedClock {
+    pub controls: [TimeControl; 2],
+    pub remaining: [Duration; 2],
+}
+impl SavedClock {
+    pub fn of(clock: &ChessClock) -> SavedClock {
+        SavedClock {
+            controls: [clock.control(Team::White), clock.control(Team::Black)],
+            remaining: [clock.remaining(Team::White), clock.remaining(Team::Black)],
+        }
+    }
+}
+
+/*
+    A game that was still being played, written out every so often so a crash or a closed window doesn't lose it.
+    Like a correspondence game it's plain text, one `key value` per line, with the moves in UCI from the start
+    position. The file goes once the game ends
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedGame {
+    pub player_team: Team,
+    pub opponent: String, // As written by opponent_spec
+    pub variant: Variant,
+    pub start_fen: String,
+    pub moves: Vec<String>,
+    pub clock: Option<SavedClock>,
+    pub saved_at: DateTime<Utc>,
+}
+impl SavedGame {
+    // The game saved at `path`, or None when there isn't one to pick back up
+    pub fn load(path: &PathBuf) -> Result<Option<SavedGame>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {err}", path.display()))?;
+        SavedGame::parse(&text)
+            .map(Some)
+            .map_err(|err| format!("{}: {err}", path.display()))
+    }
+    pub fn parse(text: &str) -> Result<SavedGame, String> {
+        let field = |key: &str| {
+            text.lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+                .map(str::trim)
+                .ok_or_else(|| format!("no {key} line"))
+        };
+
+        let player_team = match field("player")? {
+            "White" => Team::White,
+            "Black" => Team::Black,
+            other => return Err(format!("Unknown player team {other}")),
+        };
+        let variant = field("variant")?;
+        let clock = match field("clock") {
+            Ok(clock) => Some(parse_clock(clock).ok_or_else(|| format!("Bad clock {clock}"))?),
+            Err(_) => None,
+        };
+        let game = SavedGame {
+            player_team,
+            opponent: field("opponent")?.to_string(),
+            variant: Variant::from_name(variant)
+                .ok_or_else(|| format!("Unknown variant {variant}"))?,
+            start_fen: field("start")?.to_string(),
+            moves: field("moves")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            clock,
+            saved_at: field("saved")?
+                .parse()
+                .map_err(|err| format!("Bad saved time: {err}"))?,
+        };
+        // Fails now on a file that can't be replayed, rather than once the player has asked for it back
+        game.opponent()?;
+        game.board()?;
+        Ok(game)
+    }
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "player {:?}\nopponent {}\nvariant {}\nstart {}\nmoves {}\n",
+            self.player_team,
+            self.opponent,
+            self.variant,
+            self.start_fen,
+            self.moves.join(" ")
+        );
+        if let Some(clock) = self.clock {
+            text.push_str(&format!(
+                "clock {} {} {} {}\n",
+                clock.controls[0],
+                clock.controls[1],
+                clock.remaining[0].as_millis(),
+                clock.remaining[1].as_millis()
+            ));
+        }
+        text.push_str(&format!("saved {}\n", self.saved_at.to_rfc3339()));
+        text
+    }
+    pub fn save(&self, path: &PathBuf) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|err| format!("Couldn't save {}: {err}", path.display()))
+    }
+    // Deletes the save at `path` once there's nothing left to resume, if there is one
+    pub fn discard(path: &PathBuf) -> Result<(), String> {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Couldn't remove {}: {err}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+    pub fn start_board(&self) -> Result<BoardState, String> {
+        BoardState::from_fen(self.start_fen.clone())
+            .map(|board| board.with_variant(self.variant))
+            .map_err(|err| format!("Invalid start FEN ({err}): {}", self.start_fen))
+    }
+    // The start position with every move so far played on it
+    pub fn board(&self) -> Result<BoardState, String> {
+        let mut board = self.start_board()?;
+        for uci in &self.moves {
+            let played = Move::from_uci(&board, uci)
+                .ok_or_else(|| format!("Illegal move {uci} in {}", board.as_fen()))?;
+            board
+                .make_move(played)
+                .map_err(|err| format!("Couldn't play {uci}: {err:?}"))?;
+        }
+        Ok(board)
+    }
+    pub fn opponent(&self) -> Result<ChessOpponent, String> {
+        opponent_from_spec(&self.opponent)
+            .ok_or_else(|| format!("Unknown opponent {}", self.opponent))
+    }
+    // What the prompt to pick the game back up says about it
+    pub fn prompt_text(&self) -> String {
+        format!(
+            "There's an unfinished game from {}:\n{} as {:?} against {}, {} moves in\n\nEnter: pick it back up\nEsc: set up a new game",
+            self.saved_at.with_timezone(&Local).format("%H:%M on %d %b"),
+            self.variant,
+            self.player_team,
+            self.opponent,
+            self.moves.len().div_ceil(2)
+        )
+    }
+}
+
+// Both time controls followed by what's left on each clock in milliseconds, white's first
+fn parse_clock(text: &str) -> Option<SavedClock> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let [white, black, white_left, black_left] = fields[..] else {
+        return None;
+    };
+    Some(SavedClock {
+        controls: [TimeControl::parse(white)?, TimeControl::parse(black)?],
+        remaining: [
+            Duration::from_millis(white_left.parse().ok()?),
+            Duration::from_millis(black_left.parse().ok()?),
+        ],
+    })
+}
+
+/*
+    The opponent written out like `--spectate` takes it, as matt:3 or uci:<path>, so a resumed game is against the
+    same one. Ada's move time and skill level follow her name, in milliseconds and levels
+*/
+pub fn opponent_spec(opponent: &ChessOpponent) -> String {
+    match opponent {
+        ChessOpponent::Randy => String::from("randy"),
+        ChessOpponent::Matt(depth) => format!("matt:{depth}"),
+        ChessOpponent::Ada(options, _) => format!(
+            "ada:{}:{}",
+            options.move_time.as_millis(),
+            options.skill_level
+        ),
+        ChessOpponent::Uci(path, ..) => format!("uci:{}", path.display()),
+        ChessOpponent::Human => String::from("human"),
+    }
+}
+pub fn opponent_from_spec(spec: &str) -> Option<ChessOpponent> {
+    let (kind, setting) = spec.split_once(':').unwrap_or((spec, ""));
+    match kind {
+        "randy" => Some(ChessOpponent::Randy),
+        "matt" => Some(ChessOpponent::Matt(setting.parse().ok()?)),
+        "ada" => {
+            let (move_time, skill_level) = setting.split_once(':')?;
+            Some(ChessOpponent::ada(EngineOptions {
+                move_time: Duration::from_millis(move_time.parse().ok()?),
+                skill_level: skill_level.parse().ok()?,
+                ..EngineOptions::default()
+            }))
+        }
+        "uci" if !setting.is_empty() => Some(ChessOpponent::uci(PathBuf::from(setting))),
+        "human" => Some(ChessOpponent::Human),
+        _ => None,
+    }
+}
@@ -4,8 +4,6 @@ use std::{
 };
 
 // bitboard.rs
-use bitvec::prelude::*;
-
 use crate::{
     board::BoardState,
     r#move::{Move, Piece},
@@ -37,6 +35,9 @@ pub enum PieceType {
     Knight = 4,
     Queen = 5,
     King = 6,
+    Archbishop = 7, // Bishop + knight, from Capablanca chess
+    Chancellor = 8, // Rook + knight, from Capablanca chess
+    Nightrider = 9, // Rides a knight's leap, see pieces.rs
 }
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
@@ -60,7 +61,8 @@ pub const CHESS_FILE_ARRAY: [ChessFile; 8] = [
     ChessFile::G,
     ChessFile::H,
 ];
-pub const PIECE_TYPE_ARRAY: [PieceType; 7] = [
+pub const PIECE_TYPES: usize = 10;
+pub const PIECE_TYPE_ARRAY: [PieceType; PIECE_TYPES] = [
     PieceType::None,
     PieceType::Pawn,
     PieceType::Rook,
@@ -68,40 +70,139 @@ pub const PIECE_TYPE_ARRAY: [PieceType; 7] = [
     PieceType::Knight,
     PieceType::Queen,
     PieceType::King,
+    PieceType::Archbishop,
+    PieceType::Chancellor,
+    PieceType::Nightrider,
 ];
 
+/*
+    The shapes of board a game can be played on, files across by ranks up. Squares are numbered from a1 along each
+    rank in turn, so a square's file is `square % files` and its rank `square / files`, and each size has its own
+    move tables built for it, see MoveTables. A new size needs an entry here and in BOARD_SIZES, and has to fit its
+    squares in a Bitboard
+*/
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+pub enum BoardSize {
+    #[default]
+    Standard, // 8x8
+    TenByEight, // Capablanca's, with room for the archbishop and chancellor
+}
+pub const BOARD_SIZES: [BoardSize; 2] = [BoardSize::Standard, BoardSize::TenByEight];
+// The most squares any size has. The piece list and every table by square are this long
+pub const MAX_SQUARES: usize = 80;
+pub const FILE_LETTERS: [char; 10] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j'];
+
+impl BoardSize {
+    pub const fn files(self) -> usize {
+        match self {
+            BoardSize::Standard => 8,
+            BoardSize::TenByEight => 10,
+        }
+    }
+    pub const fn ranks(self) -> usize {
+        match self {
+            BoardSize::Standard | BoardSize::TenByEight => 8,
+        }
+    }
+    pub const fn squares(self) -> usize {
+        self.files() * self.ranks()
+    }
+    pub const fn file(self, square: usize) -> usize {
+        square % self.files()
+    }
+    pub const fn rank(self, square: usize) -> usize {
+        square / self.files()
+    }
+    pub const fn square(self, file: usize, rank: usize) -> usize {
+        rank * self.files() + file
+    }
+    // The last rank, where white's pieces start out on black's side
+    pub const fn last_rank(self) -> usize {
+        self.ranks() - 1
+    }
+    // `square` moved by a step of (files, ranks), unless that would take it off a side of the board
+    pub const fn offset(self, square: usize, (files, ranks): (i32, i32)) -> Option<usize> {
+        let file = self.file(square) as i32 + files;
+        let rank = self.rank(square) as i32 + ranks;
+        if file >= 0 && file < self.files() as i32 && rank >= 0 && rank < self.ranks() as i32 {
+            Some(self.square(file as usize, rank as usize))
+        } else {
+            None
+        }
+    }
+    // The size with this many files and ranks, if there is one
+    pub fn from_dimensions(files: usize, ranks: usize) -> Option<BoardSize> {
+        BOARD_SIZES
+            .into_iter()
+            .find(|size| size.files() == files && size.ranks() == ranks)
+    }
+    // Every square on a rank
+    pub const fn rank_mask(self, rank: usize) -> Bitboard {
+        Bitboard {
+            state: ((1 << self.files()) - 1) << (rank * self.files()),
+        }
+    }
+    // Every square on the board
+    pub const fn all(self) -> Bitboard {
+        Bitboard {
+            state: (1 << self.squares()) - 1,
+        }
+    }
+    // The letter a file goes by, a for the first
+    pub fn file_letter(self, file: usize) -> char {
+        FILE_LETTERS[file]
+    }
+    // A square in algebraic notation, e.g. e4, where the files run from a
+    pub fn notation(self, square: usize) -> Option<String> {
+        (square < self.squares()).then(|| {
+            format!(
+                "{}{}",
+                self.file_letter(self.file(square)),
+                self.rank(square) + 1
+            )
+        })
+    }
+    pub fn square_at(self, notation: &str) -> Option<usize> {
+        let mut chars = notation.chars();
+        let letter = chars.next()?;
+        let file = FILE_LETTERS[..self.files()]
+            .iter()
+            .position(|known| *known == letter)?;
+        let rank_text = chars.as_str();
+        let rank = rank_text.parse::<usize>().ok().filter(|rank| {
+            (1..=self.ranks()).contains(rank) && !rank_text.starts_with(['0', '+'])
+        })?;
+        Some(self.square(file, rank - 1))
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Bitboard {
-    pub state: u64,
+    pub state: u128,
 }
+// Drawn as a standard board, since a Bitboard doesn't know the size it's on
 impl fmt::Display for Bitboard {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "\n  a b c d e f g h")?;
+        let size = BoardSize::Standard;
+        write!(f, "\n ")?;
+        for letter in &FILE_LETTERS[..size.files()] {
+            write!(f, " {letter}")?;
+        }
 
-        let state_slice = self.state.view_bits::<Lsb0>();
-        for rank in (0..8).rev() {
+        for rank in (0..size.ranks()).rev() {
             write!(f, "\n{} ", rank + 1)?;
 
-            for file in 0..8 {
-                let square_idx = (rank * 8) + file;
-                let bit_opt = state_slice.get(square_idx);
-                if let Some(bit) = bit_opt {
-                    let string = String::from("");
-
-                    // Leave the square_idx if statement for easy testing of what index maps to what position
-                    write!(
-                        f,
-                        "{string}{} ",
-                        bit.then(|| {
-                            if square_idx == 1 {
-                                "Z"
-                            } else {
-                                "X"
-                            }
-                        })
-                        .unwrap_or("O")
-                    )?;
-                }
+            for file in 0..size.files() {
+                let square_idx = size.square(file, rank);
+                // Leave the square_idx if statement for easy testing of what index maps to what position
+                let mark = if !self.get_bit(square_idx) {
+                    "O"
+                } else if square_idx == 1 {
+                    "Z"
+                } else {
+                    "X"
+                };
+                write!(f, "{mark} ")?;
             }
         }
         write!(f, "")
@@ -143,61 +244,48 @@ impl BitAndAssign for Bitboard {
 }
 
 impl Bitboard {
+    // A square's index on a standard board from its notation, e.g. e4. Boards of other sizes use BoardSize::square_at
     pub fn al_notation_to_bit_idx(notation: &str) -> Option<usize> {
-        let list = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
+        BoardSize::Standard.square_at(notation)
+    }
 
-        let split: Vec<char> = notation.chars().collect();
+    // A square's notation on a standard board. Boards of other sizes use BoardSize::notation
+    pub fn bit_idx_to_al_notation(bit: usize) -> Option<String> {
+        BoardSize::Standard.notation(bit)
+    }
 
-        let file = list.iter().position(|n| n == &split[0]);
+    pub const fn from_square(square: usize) -> Bitboard {
+        Bitboard { state: 1 << square }
+    }
 
-        if let Some(file_id) = file {
-            let rank = split[1].to_digit(10);
-            if let Some(rank_id) = rank {
-                let result = ((rank_id - 1) * 8) + file_id as u32;
-                Some(result as usize)
+    pub fn set_bit(&mut self, index: usize, value: bool) {
+        if index < u128::BITS as usize {
+            if value {
+                self.state |= 1 << index;
             } else {
-                None
+                self.state &= !(1 << index);
             }
-        } else {
-            None
         }
     }
 
-    pub fn bit_idx_to_al_notation(bit: usize) -> Option<String> {
-        let list = ["a", "b", "c", "d", "e", "f", "g", "h"];
-
-        if !(0..64).contains(&bit) {
-            return None;
-        }
-        let rank_num = bit.div_floor(8);
-        let file_num = bit % 8;
-
-        let file_str = list[file_num];
-
-        Some(format!("{}{}", file_str, rank_num + 1))
+    pub fn get_bit(&self, index: usize) -> bool {
+        index < u128::BITS as usize && self.state & (1 << index) != 0
     }
 
-    pub fn set_bit<O: BitOrder>(&mut self, index: usize, value: bool) {
-        let bit_slice = self.state.view_bits_mut::<O>();
-
-        let bits = 64;
-        if index < bits {
-            bit_slice.set(index, value);
-        }
+    pub fn count(&self) -> usize {
+        self.state.count_ones() as usize
     }
 
-    pub fn get_bit<Order: BitOrder>(&self, index: usize) -> bool {
-        let bit_slice = self.state.view_bits::<Order>();
-
-        let bits = 64;
-        if index < bits {
-            let bit_ref_option = bit_slice.get(index);
-            if let Some(bit_ref) = bit_ref_option {
-                return bit_ref.then_some(1).is_some();
-            }
-        }
-
-        false
+    // The squares that are set, lowest first
+    pub fn iter_ones(self) -> impl Iterator<Item = usize> {
+        let mut remaining = self.state;
+        std::iter::from_fn(move || {
+            (remaining != 0).then(|| {
+                let square = remaining.trailing_zeros() as usize;
+                remaining &= remaining - 1;
+                square
+            })
+        })
     }
 
     pub fn get_move_from_bit(
@@ -209,8 +297,8 @@ impl Bitboard {
         team: Team,
         board_state: &BoardState,
     ) -> Option<Move> {
-        if bitboard.get_bit::<Lsb0>(square) {
-            let capture = (enemy_bitboard & !friendly_bitboard).get_bit::<Lsb0>(square).then(|| {board_state.get_piece_at_pos(square).expect("Enemy bitboard should not have a positive bit where a piece does not exist")});
+        if bitboard.get_bit(square) {
+            let capture = (enemy_bitboard & !friendly_bitboard).get_bit(square).then(|| {board_state.get_piece_at_pos(square).expect("Enemy bitboard should not have a positive bit where a piece does not exist")});
             let start = attacking_piece.position;
 
             return Some(Move {
@@ -220,7 +308,8 @@ impl Bitboard {
                 is_castle: attacking_piece.piece_type == PieceType::King
                     && square.abs_diff(start) == 2,
                 is_pawn_double: attacking_piece.piece_type == PieceType::Pawn
-                    && square.abs_diff(start) == 16,
+                    && square.abs_diff(start) == 2 * board_state.size.files(),
+                promotion: None,
             });
         } else {
             return None;
@@ -235,7 +324,7 @@ impl Bitboard {
         // Create move objs from all parts of the board
         let mut move_list: Vec<Move> = Vec::new();
 
-        let range_end: usize = 64;
+        let range_end: usize = board_state.size.squares();
         let range_start: usize = 0;
 
         let friendly_bitboard = board_state.get_team_coverage(team);
@@ -267,10 +356,10 @@ impl Iterator for BitboardIterator {
     fn next(&mut self) -> Option<Self::Item> {
         self.head += 1;
 
-        if self.head >= 64 {
+        if self.head >= u128::BITS as usize {
             return None;
         } else {
-            return Some(self.board.get_bit::<Lsb0>(self.head));
+            return Some(self.board.get_bit(self.head));
         }
     }
 }
@@ -2,6 +2,7 @@ use bitvec::{order::Lsb0, view::BitView};
 
 use crate::{
     bitboard::*,
+    pieces::{between, definition, piece_from_letter, pin_line},
     r#move::{Move, MoveError, Piece, *},
 };
 use std::{
@@ -9,101 +10,585 @@ use std::{
     fmt::{self},
 };
 
-const LIST_OF_PIECES: &str = "kqrbnpKQRBNP";
 const SPLITTER: char = '/';
 
-// Returns a table of the distance to the edges of the board for every square where index 0 of a square's table is the distance to the top, 1 is bottom, 2 is right, 3 is left, 4 is topright, 5 is bottomright, 6 is bottomleft, 7 is topleft.
-pub fn compute_edges() -> [[usize; 8]; 64] {
-    let mut square_list = [[0; 8]; 64];
+pub static ZOBRIST: ZobristKeys = ZobristKeys::generate();
+const ZOBRIST_SEED: u64 = 0x3500_C4E5_5000_0001;
 
-    for (square_pos, entry) in square_list.iter_mut().enumerate() {
-        let rank = square_pos.div_floor(8);
-        let file = square_pos % 8;
+const fn min_distance(left: usize, right: usize) -> usize {
+    if left < right {
+        left
+    } else {
+        right
+    }
+}
+// Returns a table of the distance to the edges of a board of `size` for every square where index 0 of a square's table is the distance to the top, 1 is bottom, 2 is right, 3 is left, 4 is topright, 5 is bottomright, 6 is topleft, 7 is bottomleft.
+pub const fn compute_edges(size: BoardSize) -> [[usize; 8]; MAX_SQUARES] {
+    let mut square_list = [[0; 8]; MAX_SQUARES];
 
-        let top_dist = 7 - rank;
+    let mut square_pos = 0;
+    while square_pos < size.squares() {
+        let rank = size.rank(square_pos);
+        let file = size.file(square_pos);
+
+        let top_dist = size.last_rank() - rank;
         let bottom_dist = rank;
         let left_dist = file;
-        let right_dist = 7 - file;
+        let right_dist = size.files() - 1 - file;
 
-        *entry = [
+        square_list[square_pos] = [
             top_dist,
             bottom_dist,
             right_dist,
             left_dist,
-            top_dist.min(right_dist),
-            bottom_dist.min(right_dist),
-            top_dist.min(left_dist),
-            bottom_dist.min(left_dist),
+            min_distance(top_dist, right_dist),
+            min_distance(bottom_dist, right_dist),
+            min_distance(top_dist, left_dist),
+            min_distance(bottom_dist, left_dist),
         ];
+        square_pos += 1;
     }
 
     square_list
 }
 
+// One step of splitmix64, giving the next state and its output
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut mixed = state;
+    mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, mixed ^ (mixed >> 31))
+}
+
+/*
+    Random keys for Zobrist hashing. A position's hash is the XOR of the keys for every piece on its square, its
+    castling rights and en passant square, the side to move if that's black, and in three-check the checks each side
+    has given
+*/
+pub struct ZobristKeys {
+    pub pieces: [[[u64; MAX_SQUARES]; PIECE_TYPES]; 2],
+    pub castling: [u64; 16],
+    pub en_passant: [u64; MAX_SQUARES],
+    pub checks: [[u64; 4]; 2],
+    pub black_to_move: u64,
+}
+impl ZobristKeys {
+    const fn generate() -> ZobristKeys {
+        let mut keys = ZobristKeys {
+            pieces: [[[0; MAX_SQUARES]; PIECE_TYPES]; 2],
+            castling: [0; 16],
+            en_passant: [0; MAX_SQUARES],
+            checks: [[0; 4]; 2],
+            black_to_move: 0,
+        };
+        let mut state = ZOBRIST_SEED;
+        let mut key;
+
+        let mut team = 0;
+        while team < 2 {
+            let mut piece_type = 0;
+            while piece_type < PIECE_TYPES {
+                let mut square = 0;
+                while square < MAX_SQUARES {
+                    (state, key) = splitmix64(state);
+                    keys.pieces[team][piece_type][square] = key;
+                    square += 1;
+                }
+                piece_type += 1;
+            }
+            team += 1;
+        }
+        let mut rights = 0;
+        while rights < 16 {
+            (state, key) = splitmix64(state);
+            keys.castling[rights] = key;
+            rights += 1;
+        }
+        let mut square = 0;
+        while square < MAX_SQUARES {
+            (state, key) = splitmix64(state);
+            keys.en_passant[square] = key;
+            square += 1;
+        }
+        let mut team = 0;
+        while team < 2 {
+            let mut checks = 0;
+            while checks < 4 {
+                (state, key) = splitmix64(state);
+                keys.checks[team][checks] = key;
+                checks += 1;
+            }
+            team += 1;
+        }
+        (_, keys.black_to_move) = splitmix64(state);
+        keys
+    }
+}
+
 #[derive(Debug)]
 pub enum FENErr {
-    BadState,
+    BadPiece(char),
+    BadRank(usize),
     BadTeam,
+    BadCastling(String),
+    BadEnPassant(String),
     MalformedNumber,
+    IncompleteBoard,
+    TooManyParts,
+    KingCount(Team, usize),
+    PawnOnBackRank(String), // The square, named for the board it's on
+    OpponentInCheck(Team),
 }
 impl fmt::Display for FENErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::BadState => {
-                writeln!(f, "Bad character exists in the state section of FEN string")
+            Self::BadPiece(char) => {
+                writeln!(f, "'{char}' is not a piece or a count of empty squares")
+            }
+            Self::BadRank(rank) => {
+                writeln!(
+                    f,
+                    "Rank {rank} does not describe as many squares as the board is wide"
+                )
             }
             Self::BadTeam => {
                 writeln!(f, "Team char is not either 'b' or 'w'")
             }
+            Self::BadCastling(rights) => {
+                writeln!(
+                    f,
+                    "Castling rights '{rights}' are not '-' or some of 'KQkq'"
+                )
+            }
+            Self::BadEnPassant(square) => {
+                writeln!(
+                    f,
+                    "En passant square '{square}' is not '-' or a square on the 3rd or 6th rank"
+                )
+            }
             Self::MalformedNumber => {
                 writeln!(f, "Turn/halfmove clock characters malformed")
             }
+            Self::IncompleteBoard => {
+                writeln!(
+                    f,
+                    "State section does not describe every rank of a board size there is"
+                )
+            }
+            Self::TooManyParts => {
+                writeln!(f, "FEN string has more than six parts")
+            }
+            Self::KingCount(team, count) => {
+                writeln!(f, "{team:?} has {count} kings instead of one")
+            }
+            Self::PawnOnBackRank(square) => {
+                writeln!(f, "Pawn on {square}, where it would already have promoted")
+            }
+            Self::OpponentInCheck(team) => {
+                writeln!(f, "{team:?} is in check without being the side to move")
+            }
+        }
+    }
+}
+
+pub struct PseudoLegalIter<'a> {
+    board: &'a BoardState,
+    team: Team,
+    square: usize, // The next square to generate moves for, with one step past the board for castling
+    pending: std::vec::IntoIter<Move>,
+}
+impl Iterator for PseudoLegalIter<'_> {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        loop {
+            if let Some(next_move) = self.pending.next() {
+                return Some(next_move);
+            }
+
+            let board_size = self.board.size.squares();
+            if self.square < board_size {
+                if let Some(piece) = self.board.get_piece_at_pos(self.square) {
+                    if piece.team == self.team {
+                        self.pending = self.board.piece_psuedolegal_moves(piece).1.into_iter();
+                    }
+                }
+            } else if self.square == board_size {
+                let team = self.team;
+                let board = self.board;
+                self.pending = board
+                    .castling_moves()
+                    .into_iter()
+                    .filter(|castle| board.get_square_team(castle.start) == team)
+                    .collect::<Vec<Move>>()
+                    .into_iter();
+            } else {
+                return None;
+            }
+            self.square += 1;
+        }
+    }
+}
+
+/*
+    What a team's moves have to respect to keep its king safe, worked out once from the king's square: the pieces
+    giving check, the squares that capture or block a single check (none for a double check), the team's pinned
+    pieces, and every square the enemy would attack with the king lifted off the board
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KingSafety {
+    pub king: usize,
+    pub checkers: Bitboard,
+    pub check_mask: Bitboard,
+    pub pinned: Bitboard,
+    pub danger: Bitboard,
+}
+
+/*
+    The rules a game is played by. King of the Hill also ends when a king reaches one of the four centre squares, and
+    Three-check when a side gives its third check. In Atomic every capture explodes, taking the capturer and every
+    piece but a pawn around it off the board, and blowing up the enemy king wins. Horde pits a kingless wall of white
+    pawns against a full black army that wins by taking them all, and in Racing Kings nobody may give check and the
+    first king to the eighth rank wins. Capablanca is played by the usual rules on a 10x8 board, with an archbishop
+    and a chancellor each
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    KingOfTheHill,
+    ThreeCheck,
+    Atomic,
+    Horde,
+    RacingKings,
+    Capablanca,
+}
+impl Variant {
+    pub const ALL: [Variant; 7] = [
+        Variant::Standard,
+        Variant::KingOfTheHill,
+        Variant::ThreeCheck,
+        Variant::Atomic,
+        Variant::Horde,
+        Variant::RacingKings,
+        Variant::Capablanca,
+    ];
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "standard" | "chess" => Some(Variant::Standard),
+            "kingofthehill" | "koth" => Some(Variant::KingOfTheHill),
+            "threecheck" | "3check" => Some(Variant::ThreeCheck),
+            "atomic" => Some(Variant::Atomic),
+            "horde" => Some(Variant::Horde),
+            "racingkings" => Some(Variant::RacingKings),
+            "capablanca" | "capa" => Some(Variant::Capablanca),
+            _ => None,
+        }
+    }
+    // Where a game of the variant starts from, which is the usual position for all but the asymmetric ones and Capablanca
+    pub fn start_position(&self) -> &'static str {
+        match self {
+            Variant::Horde => HORDE_START_POS,
+            Variant::RacingKings => RACING_KINGS_START_POS,
+            Variant::Capablanca => CAPABLANCA_START_POS,
+            _ => crate::START_POS_CHESS,
+        }
+    }
+}
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Standard => write!(f, "Standard"),
+            Self::KingOfTheHill => write!(f, "King of the Hill"),
+            Self::ThreeCheck => write!(f, "Three-check"),
+            Self::Atomic => write!(f, "Atomic"),
+            Self::Horde => write!(f, "Horde"),
+            Self::RacingKings => write!(f, "Racing Kings"),
+            Self::Capablanca => write!(f, "Capablanca"),
+        }
+    }
+}
+// The four squares in the middle of the board, d4, e4, d5 and e5 on a standard one, where a king wins King of the Hill
+pub fn hill_squares(size: BoardSize) -> Bitboard {
+    let (file, rank) = (size.files() / 2 - 1, size.ranks() / 2 - 1);
+    [(0, 0), (1, 0), (0, 1), (1, 1)]
+        .into_iter()
+        .map(|(files, ranks)| Bitboard::from_square(size.square(file + files, rank + ranks)))
+        .fold(Bitboard::default(), |hill, square| hill | square)
+}
+// Thirty-six white pawns, four of them already out on the fifth rank, against the usual black army
+pub const HORDE_START_POS: &str =
+    "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1";
+pub const RACING_KINGS_START_POS: &str = "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1";
+// The king starts on f1 and castles three squares over to i1, or to c1 on the long side
+pub const CAPABLANCA_START_POS: &str =
+    "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1";
+
+// Whether the game goes on from a position, or how it has ended. A win carries the team that won it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameStatus {
+    #[default]
+    Ongoing,
+    Checkmate(Team),
+    KingOfTheHill(Team),
+    ThirdCheck(Team),
+    KingExploded(Team),
+    HordeCaptured(Team),
+    RaceWon(Team),
+    TimeForfeit(Team),
+    Resignation(Team),
+    Stalemate,
+    DrawByRepetition,
+    DrawByFiftyMove,
+    DrawByMaterial,
+    DrawByRace,
+    DrawByTimeout,
+    DrawByAgreement,
+}
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+    pub fn winner(&self) -> Option<Team> {
+        match self {
+            GameStatus::Checkmate(winner)
+            | GameStatus::KingOfTheHill(winner)
+            | GameStatus::ThirdCheck(winner)
+            | GameStatus::KingExploded(winner)
+            | GameStatus::HordeCaptured(winner)
+            | GameStatus::RaceWon(winner)
+            | GameStatus::TimeForfeit(winner)
+            | GameStatus::Resignation(winner) => Some(*winner),
+            _ => None,
+        }
+    }
+    // The result as PGN writes it, for a game that's over
+    pub fn result(&self) -> Option<&'static str> {
+        match (self, self.winner()) {
+            (GameStatus::Ongoing, _) => None,
+            (_, Some(Team::Black)) => Some("0-1"),
+            (_, Some(_)) => Some("1-0"),
+            (_, None) => Some("1/2-1/2"),
+        }
+    }
+}
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ongoing => write!(f, "The game goes on"),
+            Self::Checkmate(winner) => write!(f, "Checkmate - {winner:?} wins"),
+            Self::KingOfTheHill(winner) => write!(f, "King on the hill - {winner:?} wins"),
+            Self::ThirdCheck(winner) => write!(f, "Third check - {winner:?} wins"),
+            Self::KingExploded(winner) => write!(f, "King exploded - {winner:?} wins"),
+            Self::HordeCaptured(winner) => write!(f, "The horde is gone - {winner:?} wins"),
+            Self::RaceWon(winner) => write!(f, "King reached the eighth rank - {winner:?} wins"),
+            Self::TimeForfeit(winner) => write!(
+                f,
+                "{:?} ran out of time - {winner:?} wins",
+                winner.opponent()
+            ),
+            Self::Resignation(winner) => {
+                write!(f, "{:?} resigns - {winner:?} wins", winner.opponent())
+            }
+            Self::Stalemate => write!(f, "Stalemate"),
+            Self::DrawByRepetition => write!(f, "Draw by threefold repetition"),
+            Self::DrawByFiftyMove => write!(f, "Draw by the fifty-move rule"),
+            Self::DrawByMaterial => write!(f, "Draw, neither side has the material to mate"),
+            Self::DrawByRace => write!(f, "Draw, both kings reached the eighth rank"),
+            Self::DrawByTimeout => write!(f, "Draw, out of time against a lone king"),
+            Self::DrawByAgreement => write!(f, "Draw agreed"),
         }
     }
 }
 
+/*
+    Everything a move changes that can't be worked back out from the move itself, handed back by make_move so
+    unmake_move can put the position back exactly: what moved and what it took, then the rights, clocks, hash and
+    attack maps from before
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoInfo {
+    pub played: Move,
+    pub moved: PieceType,
+    pub captured: Option<Piece>,
+    pub castling_rights: u8,
+    pub fifty_move_clock: i64,
+    pub en_passant_square: Option<usize>,
+    pub turn_clock: i64,
+    pub ply_clock: i64,
+    pub active_team: Team,
+    pub capture_bitboard: [Bitboard; 2],
+    pub checks_given: [u8; 2],
+    pub exploded: [Option<Piece>; 8], // What an Atomic capture blew off the squares around it
+    pub zobrist: u64,
+}
+
+/*
+    The moves made since a board was set up, oldest first, each with the position it was made from. It's kept apart
+    from BoardState so boards stay cheap to copy, and belongs to whatever plays the moves out: the game on screen, an
+    arena game, or a search. Moves made through it are remembered and taking them back forgets them again. Each move
+    made also counts how often the position it leads to has come up, so a third repetition is known as it happens
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct History {
+    pub undos: Vec<UndoInfo>,
+    pub repetitions: Vec<usize>, // How many times the position after each move had come up by then, that one included
+}
+impl History {
+    /*
+        Makes the move on `board` and remembers it, along with how often the position it leads to has come up. A
+        pawn move or capture can't be undone, so only the positions since the last one are looked through
+    */
+    pub fn make_move(
+        &mut self,
+        board: &mut BoardState,
+        played: Move,
+    ) -> Result<UndoInfo, MoveError> {
+        let undo = board.make_move(played)?;
+        self.undos.push(undo);
+        let reversible = board.fifty_move_clock.max(0) as usize;
+        let seen = self
+            .undos
+            .iter()
+            .rev()
+            .take(reversible)
+            .filter(|earlier| earlier.zobrist == board.zobrist)
+            .count();
+        self.repetitions.push(1 + seen);
+        Ok(undo)
+    }
+    // Undoes the last move made through here, which `undo` is from
+    pub fn unmake_move(&mut self, board: &mut BoardState, undo: UndoInfo) -> Result<(), MoveError> {
+        board.unmake_move(undo)?;
+        self.undos.pop();
+        self.repetitions.pop();
+        Ok(())
+    }
+    // Takes back the last move, if there is one, and gives it back
+    pub fn take_back(&mut self, board: &mut BoardState) -> Option<Move> {
+        let undo = *self.undos.last()?;
+        self.unmake_move(board, undo).ok().map(|_| undo.played)
+    }
+    // The first `plies` moves, as they were when the last of them was made
+    pub fn truncated(&self, plies: usize) -> History {
+        History {
+            undos: self.undos[..plies].to_vec(),
+            repetitions: self.repetitions[..plies].to_vec(),
+        }
+    }
+    pub fn last_move(&self) -> Option<Move> {
+        self.undos.last().map(|undo| undo.played)
+    }
+    // The moves in the order they were played
+    pub fn moves_played(&self) -> impl Iterator<Item = Move> + '_ {
+        self.undos.iter().map(|undo| undo.played)
+    }
+    // How many times the position the last move led to has come up, including now
+    pub fn repetition_count(&self) -> usize {
+        self.repetitions.last().copied().unwrap_or(1)
+    }
+    // What the last move made of the game by repetition alone, a draw if it brought a position up for the third time
+    pub fn repetition_status(&self) -> GameStatus {
+        if self.repetition_count() >= 3 {
+            GameStatus::DrawByRepetition
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+    // BoardState::rule_draw, along with a third repetition
+    pub fn rule_draw(&self, board: &BoardState) -> GameStatus {
+        match self.repetition_status() {
+            GameStatus::Ongoing => board.rule_draw(),
+            repeated => repeated,
+        }
+    }
+    // BoardState::status, along with a third repetition
+    pub fn status(&self, board: &BoardState) -> GameStatus {
+        match board.status() {
+            GameStatus::Ongoing => self.rule_draw(board),
+            status => status,
+        }
+    }
+}
+
+/*
+    Only the position: bitboards, the piece list, clocks and rights, about 500 bytes that copy as plain data. The
+    moves that led to it are kept apart in a History. The lookup tables movegen uses are statics shared by every board
+    of the same size, see MoveTables
+*/
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct BoardState {
-    pub board_pieces: [[Bitboard; 7]; 3],
+    pub board_pieces: [[Bitboard; PIECE_TYPES]; 2], // White's and black's, a Bitboard for each piece type
     pub castling_rights: u8, // Using queen, king, and each side as booleans, there are 4 bits of castling rights that can be expressed as a number
     pub fifty_move_clock: i64,
     pub en_passant_square: Option<usize>,
     pub turn_clock: i64,
     pub ply_clock: i64,
-    pub active_team_checkmate: bool,
-    pub piece_list: [PieceType; 64],
-    pub edge_compute: [[usize; 8]; 64],
-    pub king_compute: [[Bitboard; 64]; 2],
+    pub piece_list: [PieceType; MAX_SQUARES], // Only the first size.squares() are on the board
     pub capture_bitboard: [Bitboard; 2],
-    pub en_passant_turn: Option<i64>,
     pub active_team: Team,
-    pub pawn_attack_compute: [[Bitboard; 64]; 2],
-    pub pawn_push_compute: [[Bitboard; 64]; 2],
-    pub knight_compute: [Bitboard; 64],
+    pub zobrist: u64, // Kept up to date move by move, see [ZobristKeys]
+    pub variant: Variant,
+    pub checks_given: [u8; 2], // By white and by black. Only counted in Three-check
+    pub size: BoardSize,
 }
 impl Default for BoardState {
     fn default() -> Self {
         BoardState {
-            board_pieces: [[Bitboard { state: 0 }; 7]; 3],
+            board_pieces: [[Bitboard { state: 0 }; PIECE_TYPES]; 2],
             castling_rights: 0,
             fifty_move_clock: 0,
             ply_clock: 0,
             turn_clock: 1,
             en_passant_square: None,
-            en_passant_turn: None,
-            active_team_checkmate: false,
-            piece_list: [PieceType::None; 64], // TODO: Make this compatible with any amount of squares/any size of map. Maybe as a type argument to the board state?
-            edge_compute: compute_edges(),
-            king_compute: precalc_king_attack::<64>(),
-            knight_compute: precalc_knight_attack::<64>(),
-            pawn_attack_compute: precalc_pawn_attack::<64>(),
-            pawn_push_compute: precalc_pawn_push::<64>(),
+            piece_list: [PieceType::None; MAX_SQUARES],
             capture_bitboard: [Bitboard { state: 0 }; 2],
             active_team: Team::White,
+            zobrist: 0,
+            variant: Variant::Standard,
+            checks_given: [0; 2],
+            size: BoardSize::Standard,
         }
     }
 }
 
+fn piece_key(team: Team, piece_type: PieceType, square: usize) -> u64 {
+    ZOBRIST.pieces[team as usize][piece_type as usize][square]
+}
+// How many squares a rank of a FEN's placement covers, counting each run of empty squares as its whole number
+fn rank_width(rank_text: &str) -> usize {
+    let mut width = 0;
+    let mut empty_spaces = 0;
+    for char in rank_text.chars() {
+        match char.to_digit(10) {
+            Some(digit) => empty_spaces = empty_spaces * 10 + digit as usize,
+            None => width += std::mem::take(&mut empty_spaces) + 1,
+        }
+    }
+    width + empty_spaces
+}
+/*
+    Where the rook starts and ends up for a castling king landing on `king_target`. Whatever the board's width, kings
+    castle two files from the corner on the king's side and to the c file on the queen's, with the rook jumping
+    over to the square beside them
+*/
+fn castling_rook_squares(size: BoardSize, king_target: usize) -> Option<(usize, usize)> {
+    let last_file = size.files() - 1;
+    let rank = size.rank(king_target);
+    if rank != 0 && rank != size.last_rank() {
+        return None;
+    }
+    match size.file(king_target) {
+        file if file == last_file - 1 => Some((
+            size.square(last_file, rank),
+            size.square(last_file - 2, rank),
+        )),
+        2 => Some((size.square(0, rank), size.square(3, rank))),
+        _ => None,
+    }
+}
+
 impl BoardState {
     /*
         Constructs a board state from a FEN string
@@ -111,132 +596,74 @@ impl BoardState {
     pub fn from_fen(fen: String) -> Result<Self, FENErr> {
         let mut fen_part_idx = 0;
 
-        let mut rank = 7;
-        let mut file = ChessFile::A;
-
         let mut result_obj = BoardState::default();
 
         for fen_part in fen.split(" ") {
             fen_part_idx += 1;
             match fen_part_idx {
                 1 => {
-                    for char in fen_part.chars() {
-                        let team = if char.is_ascii_uppercase() {
-                            Team::White
-                        } else {
-                            Team::Black
-                        };
-
-                        let square: usize = ((rank as usize) * 8) + file as usize;
-                        match char.to_ascii_lowercase() {
-                            'k' => {
-                                result_obj.board_pieces[team as usize][PieceType::King as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                                result_obj.board_pieces[Team::Both as usize]
-                                    [PieceType::King as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                            }
-                            'q' => {
-                                result_obj.board_pieces[team as usize][PieceType::Queen as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                                result_obj.board_pieces[Team::Both as usize]
-                                    [PieceType::Queen as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                            }
-                            'p' => {
-                                result_obj.board_pieces[team as usize][PieceType::Pawn as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                                result_obj.board_pieces[Team::Both as usize]
-                                    [PieceType::Pawn as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                            }
-                            'b' => {
-                                result_obj.board_pieces[team as usize][PieceType::Bishop as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                                result_obj.board_pieces[Team::Both as usize]
-                                    [PieceType::Bishop as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                            }
-                            'r' => {
-                                result_obj.board_pieces[team as usize][PieceType::Rook as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                                result_obj.board_pieces[Team::Both as usize]
-                                    [PieceType::Rook as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                            }
-                            'n' => {
-                                result_obj.board_pieces[team as usize][PieceType::Knight as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                                result_obj.board_pieces[Team::Both as usize]
-                                    [PieceType::Knight as usize]
-                                    .state
-                                    .view_bits_mut::<Lsb0>()
-                                    .set(square, true);
-                            }
-                            '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' => {
-                                if let Some(empty_spaces) = char.to_digit(10) {
-                                    if char != '8' && (file as usize + empty_spaces as usize) != 8 {
-                                        file =
-                                            CHESS_FILE_ARRAY[file as usize + empty_spaces as usize]
-                                    } else {
-                                        // do nothing and skip to the next rank
-                                        file = ChessFile::H;
-                                    }
+                    let ranks: Vec<&str> = fen_part.split(SPLITTER).collect();
+                    /*
+                        The board's size is however many ranks there are by however wide the first of them is. A first
+                        rank that fits no size is read against the narrowest board with that many ranks, so the error
+                        points at the rank
+                    */
+                    let files = ranks.first().map_or(0, |rank_text| rank_width(rank_text));
+                    let size = BoardSize::from_dimensions(files, ranks.len())
+                        .or_else(|| {
+                            BOARD_SIZES
+                                .into_iter()
+                                .find(|size| size.ranks() == ranks.len())
+                        })
+                        .ok_or(FENErr::IncompleteBoard)?;
+                    result_obj.size = size;
+                    // Ranks are written from black's side of the board down
+                    for (rank, rank_text) in ranks.into_iter().rev().enumerate() {
+                        let mut file = 0;
+                        let mut empty_spaces = 0;
+                        for char in rank_text.chars() {
+                            // A run of empty squares can take more than one digit on a wide board, e.g. 10
+                            if let Some(digit) = char.to_digit(10) {
+                                empty_spaces = empty_spaces * 10 + digit as usize;
+                                if empty_spaces == 0 || empty_spaces > size.files() {
+                                    return Err(FENErr::BadPiece(char));
                                 }
+                                continue;
                             }
-                            SPLITTER => {
-                                if file != ChessFile::H {
-                                    return Err(FENErr::BadState);
-                                }
-                                rank -= 1;
-                                file = ChessFile::A;
+                            file += std::mem::take(&mut empty_spaces);
+                            let piece_type =
+                                piece_from_letter(char).ok_or(FENErr::BadPiece(char))?;
+                            if file >= size.files() {
+                                return Err(FENErr::BadRank(rank + 1));
                             }
-                            _ => return Err(FENErr::BadState),
-                        };
-
-                        if LIST_OF_PIECES.contains(char) && (file as i32 + 1) < 8 {
-                            if file as usize + 1 == 8 {
-                                rank -= 1;
-                                file = ChessFile::A;
+                            let team = if char.is_ascii_uppercase() {
+                                Team::White
                             } else {
-                                file = CHESS_FILE_ARRAY[(file as usize) + 1]
-                            }
+                                Team::Black
+                            };
+                            result_obj.board_pieces[team as usize][piece_type as usize]
+                                .set_bit(size.square(file, rank), true);
+                            file += 1;
+                        }
+                        file += empty_spaces;
+                        if file != size.files() {
+                            return Err(FENErr::BadRank(rank + 1));
                         }
                     }
                 }
-                2 => {
-                    if fen_part.contains("b") {
-                        result_obj.active_team = Team::Black
-                    } else if fen_part.contains("w") {
+                2 => match fen_part {
+                    "b" => result_obj.active_team = Team::Black,
+                    "w" => {
                         result_obj.active_team = Team::White;
                         result_obj.ply_clock += 1;
-                    } else {
-                        return Err(FENErr::BadTeam);
                     }
-                }
+                    _ => return Err(FENErr::BadTeam),
+                },
                 3 => {
+                    let known = fen_part.chars().all(|char| "KQkq".contains(char));
+                    if fen_part.is_empty() || (fen_part != "-" && !known) {
+                        return Err(FENErr::BadCastling(fen_part.to_string()));
+                    }
                     let mut rights: u8 = 0;
                     if fen_part.contains('K') {
                         rights.view_bits_mut::<Lsb0>().set(0, true);
@@ -251,39 +678,50 @@ impl BoardState {
                         rights.view_bits_mut::<Lsb0>().set(3, true);
                     }
 
-                    if fen_part.contains('-') && rights > 0 { // TODO: Throw an error if we hit the 'else' arm and rights is not 0
-                    }
                     result_obj.castling_rights = rights;
                 }
                 4 => {
-                    if fen_part.len() < 2 {
-                        // Not enough to count this
+                    if fen_part == "-" {
                         result_obj.en_passant_square = None;
                     } else {
-                        result_obj.en_passant_square = Bitboard::al_notation_to_bit_idx(fen_part)
+                        // Only a pawn that just moved two squares leaves one behind it, on the 3rd or 6th rank
+                        let size = result_obj.size;
+                        let square = size
+                            .square_at(fen_part)
+                            .filter(|square| [2, size.ranks() - 3].contains(&size.rank(*square)))
+                            .ok_or(FENErr::BadEnPassant(fen_part.to_string()))?;
+                        result_obj.en_passant_square = Some(square);
                     }
                 }
                 5 => {
-                    if let Ok(hm_turn_clk) = fen_part.parse::<i64>() {
+                    if let Some(hm_turn_clk) =
+                        fen_part.parse::<i64>().ok().filter(|clock| *clock >= 0)
+                    {
                         result_obj.fifty_move_clock = hm_turn_clk
                     } else {
                         return Err(FENErr::MalformedNumber);
                     }
                 }
                 6 => {
-                    if let Ok(turn_clk) = fen_part.parse::<i64>() {
+                    // Kept small enough that the ply clock can't overflow counting from it
+                    if let Some(turn_clk) = fen_part
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|clock| (0..i64::MAX / 4).contains(clock))
+                    {
                         result_obj.turn_clock = turn_clk;
                         result_obj.ply_clock += turn_clk * 2;
                     } else {
                         return Err(FENErr::MalformedNumber);
                     }
                 }
-                _ => unreachable!("FEN data has more than seven parts"),
+                _ => return Err(FENErr::TooManyParts),
             }
         }
 
         result_obj.init_piece_list();
         result_obj.update_capture_bitboards();
+        result_obj.zobrist = result_obj.compute_zobrist();
         Ok(result_obj)
     }
 
@@ -297,115 +735,116 @@ impl BoardState {
         {
             let piece_type = PIECE_TYPE_ARRAY[piece_type];
 
-            // Iterate over each bit and table it
-            let mut bit_index = 0;
-            for black_bit in blackboard.state.view_bits::<Lsb0>() {
-                let square = black_bit.then(|| piece_type);
-                if let Some(square_piece) = square {
-                    self.piece_list[bit_index] = square_piece;
-                }
-                bit_index += 1;
-            }
-
-            let mut bit_index = 0;
-            for white_bit in whiteboard.state.view_bits::<Lsb0>() {
-                let square = white_bit.then(|| piece_type);
-                if let Some(square_piece) = square {
-                    self.piece_list[bit_index] = square_piece;
-                }
-                bit_index += 1;
+            // Table each set bit
+            for square in blackboard.iter_ones().chain(whiteboard.iter_ones()) {
+                self.piece_list[square] = piece_type;
             }
         }
     }
 
     fn move_piece(&mut self, square_team: Team, moving_piece_type: PieceType, r#move: Move) {
+        let captured_team = self.get_square_team(r#move.target);
+        if captured_team != Team::None {
+            self.zobrist ^= piece_key(captured_team, self.piece_list[r#move.target], r#move.target);
+        }
+        self.zobrist ^= piece_key(square_team, moving_piece_type, r#move.start)
+            ^ piece_key(square_team, moving_piece_type, r#move.target);
+
         let board_pieces = &mut self.board_pieces;
 
         if square_team == Team::White {
             board_pieces[Team::White as usize][moving_piece_type as usize]
-                .state
-                .view_bits_mut::<Lsb0>()
-                .set(r#move.start, false);
+                .set_bit(r#move.start, false);
             board_pieces[Team::White as usize][moving_piece_type as usize]
-                .state
-                .view_bits_mut::<Lsb0>()
-                .set(r#move.target, true);
+                .set_bit(r#move.target, true);
 
             board_pieces[Team::Black as usize]
                 .iter_mut()
                 .for_each(|bb| {
-                    bb.state.view_bits_mut::<Lsb0>().set(r#move.target, false);
+                    bb.set_bit(r#move.target, false);
                 });
         } else {
             board_pieces[Team::Black as usize][moving_piece_type as usize]
-                .state
-                .view_bits_mut::<Lsb0>()
-                .set(r#move.start, false);
+                .set_bit(r#move.start, false);
             board_pieces[Team::Black as usize][moving_piece_type as usize]
-                .state
-                .view_bits_mut::<Lsb0>()
-                .set(r#move.target, true);
+                .set_bit(r#move.target, true);
 
             // Clear the slot for the piece - this resembles a capture
             board_pieces[Team::White as usize]
                 .iter_mut()
                 .for_each(|bb| {
-                    bb.state.view_bits_mut::<Lsb0>().set(r#move.target, false);
+                    bb.set_bit(r#move.target, false);
                 });
         }
 
-        // Update castling rights
-        if r#move.start == 56 || r#move.target == 56 {
+        // Update castling rights. A rook taking a rook in the corner costs both sides theirs, so each square is looked
+        // at on its own
+        self.drop_castling_rights(r#move.start);
+        self.drop_castling_rights(r#move.target);
+
+        self.piece_list[r#move.start] = PieceType::None;
+        self.piece_list[r#move.target] = moving_piece_type;
+    }
+    // Takes away the castling rights that need a king or rook on `square`, once something leaves or lands there
+    fn drop_castling_rights(&mut self, square: usize) {
+        let size = self.size;
+        let (last_file, last_rank, king_file) =
+            (size.files() - 1, size.last_rank(), size.files() / 2);
+        if square == size.square(0, last_rank) {
             // Black queenside rook
             tracing::debug!("Lost queenside castling (black) through rook movement");
             self.castling_rights.view_bits_mut::<Lsb0>().set(3, false);
-        } else if r#move.start == 0 || r#move.target == 0 {
+        }
+        if square == size.square(0, 0) {
             // White queenside rook
             tracing::debug!("Lost queenside castling (white) through rook movement");
             self.castling_rights.view_bits_mut::<Lsb0>().set(1, false);
-        } else if r#move.start == 7 || r#move.target == 7 {
+        }
+        if square == size.square(last_file, 0) {
             // White kingside rook
             tracing::debug!("Lost kingside castling (white) through rook movement");
             self.castling_rights.view_bits_mut::<Lsb0>().set(0, false);
-        } else if r#move.start == 63 || r#move.target == 63 {
+        }
+        if square == size.square(last_file, last_rank) {
             // Black kingside rook
             tracing::debug!("Lost kingside castling (black) through rook movement");
             self.castling_rights.view_bits_mut::<Lsb0>().set(2, false);
-        } else if r#move.start == 4 || r#move.target == 4 {
+        }
+        if square == size.square(king_file, 0) {
+            // White king
+            tracing::debug!("Lost castling (white) through king movement");
+            self.castling_rights.view_bits_mut::<Lsb0>().set(0, false);
+            self.castling_rights.view_bits_mut::<Lsb0>().set(1, false);
+        }
+        if square == size.square(king_file, last_rank) {
             // Black king
             tracing::debug!("Lost castling (black) through king movement");
             self.castling_rights.view_bits_mut::<Lsb0>().set(2, false);
             self.castling_rights.view_bits_mut::<Lsb0>().set(3, false);
-        } else if r#move.start == 60 || r#move.target == 60 {
-            // White king
-            tracing::debug!("Lost castling (black) through king movement");
-            self.castling_rights.view_bits_mut::<Lsb0>().set(0, false);
-            self.castling_rights.view_bits_mut::<Lsb0>().set(1, false);
         }
-
-        self.piece_list[r#move.start] = PieceType::None;
-        self.piece_list[r#move.target] = moving_piece_type;
     }
     fn update_capture_bitboards(&mut self) {
-        for team_id in 0..=Team::Black as usize {
-            let mut capture_bitboard = Bitboard::default();
-            let legals = self.get_psuedolegal_moves();
-
-            for (square, (bitboard, _legal_moves)) in legals.iter().enumerate().take(64) {
-                if self.get_square_team(square) as usize == team_id {
-                    let piece_type = self.piece_list[square];
-                    if piece_type == PieceType::Pawn {
-                        capture_bitboard |= *bitboard & !self.pawn_push_compute[team_id][square]
-                    } else {
-                        capture_bitboard |= *bitboard;
-                    }
-                }
+        let mut capture_bitboards = [Bitboard::default(); 2];
+
+        for square in 0..self.size.squares() {
+            if let Some(piece) = self.get_piece_at_pos(square) {
+                capture_bitboards[piece.team as usize] |= compute_attacks(self, piece);
             }
-            self.capture_bitboard[team_id] = capture_bitboard;
         }
+        self.capture_bitboard = capture_bitboards;
+    }
+    // Every square `team` attacks, including the ones its own pieces stand on
+    pub fn attack_map(&self, team: Team) -> Bitboard {
+        self.capture_bitboard[team as usize]
     }
-    pub fn render_piece_list(pl: Vec<PieceType>) {
-        print!("  a b c d e f g h");
+    pub fn is_attacked_by(&self, square: usize, team: Team) -> bool {
+        self.attack_map(team).get_bit(square)
+    }
+    pub fn render_piece_list(size: BoardSize, pl: Vec<PieceType>) {
+        print!(" ");
+        for letter in &FILE_LETTERS[..size.files()] {
+            print!(" {letter}");
+        }
 
         let display_map = HashMap::from([
             (PieceType::None, "O"),
@@ -416,17 +855,17 @@ impl BoardState {
             (PieceType::Queen, "♕"),
             (PieceType::King, "♔"),
         ]);
-        for rank in (0..8).rev() {
+        for rank in (0..size.ranks()).rev() {
             print!("\n{} ", rank + 1);
 
-            for file in 0..8 {
-                let bit_opt = pl[rank * 8 + file];
-                print!(
-                    "{} ",
-                    display_map
-                        .get(&bit_opt)
-                        .expect("Exception while rendering piece list: slot doesn't exist")
+            for file in 0..size.files() {
+                let bit_opt = pl[size.square(file, rank)];
+                // The fairy pieces have no symbols, so they're written with their letters
+                let glyph = display_map.get(&bit_opt).map_or_else(
+                    || definition(bit_opt).letter.to_string(),
+                    |glyph| glyph.to_string(),
                 );
+                print!("{glyph} ");
             }
         }
         println!();
@@ -442,51 +881,43 @@ impl BoardState {
         result
     }
     pub fn get_psuedolegal_moves(&self) -> Vec<(Bitboard, Vec<Move>)> {
-        let pl = self.piece_list;
         let mut move_list: Vec<(Bitboard, Vec<Move>)> = Vec::new(); // The bitboard is used for highlighting moves the selected square has
 
-        pl.iter().enumerate().for_each(|(index, piece_type)| {
-            let default_push = (Bitboard::default(), vec![Move::default()]);
-
-            let team = self.get_square_team(index);
-            if team != Team::None {
-                let piece_obj = Piece {
-                    piece_type: *piece_type,
-                    position: index,
-                    team,
-                };
-                let (psuedo_bitboard, psuedo_moves) = match piece_type {
-                    PieceType::Bishop | PieceType::Rook | &PieceType::Queen => {
-                        compute_slider(self, piece_obj)
-                    }
-                    PieceType::King => get_precomputed_king(self, piece_obj),
-                    PieceType::Knight => get_precomputed_knight(self, piece_obj),
-                    PieceType::Pawn => {
-                        let mut pre_computed_moves = get_precomputed_pawn(self, piece_obj);
-
-                        if pre_computed_moves.0.get_bit::<Lsb0>(index+8) == false {
-                            let _ = pre_computed_moves.1.extract_if(0..pre_computed_moves.1.len(), |pawn_move| {
-				pawn_move.target == index + 16 && pawn_move.start == index
-			    });
-                        }
-  			if pre_computed_moves.0.get_bit::<Lsb0>(index-8) == false {
-                            let _ = pre_computed_moves.1.extract_if(0..pre_computed_moves.1.len(), |pawn_move| {
-				pawn_move.target == index - 16 && pawn_move.start == index
-			    });
-                        }
-                        pre_computed_moves
-                    },
-                    _ => default_push,
-                };
+        for square in 0..self.size.squares() {
+            if let Some(piece) = self.get_piece_at_pos(square) {
+                move_list.push(self.piece_psuedolegal_moves(piece));
+            } else {
+                move_list.push((Bitboard::default(), Vec::new()));
+            }
+        }
 
-                move_list.push((psuedo_bitboard, psuedo_moves));
+        for castle in self.castling_moves() {
+            let (bitboard, move_vec) = &mut move_list[castle.start];
+            bitboard.set_bit(castle.target, true);
+            move_vec.push(castle);
+        }
 
-            } else {
-                move_list.push(default_push);
+        move_list
+    }
+    // The psuedolegal moves of a single piece, not counting castling
+    pub fn piece_psuedolegal_moves(&self, piece: Piece) -> (Bitboard, Vec<Move>) {
+        match piece.piece_type {
+            PieceType::Bishop | PieceType::Rook | PieceType::Queen => compute_slider(self, piece),
+            PieceType::King => get_precomputed_king(self, piece),
+            PieceType::Knight => get_precomputed_knight(self, piece),
+            PieceType::Pawn => get_precomputed_pawn(self, piece),
+            PieceType::None => (Bitboard::default(), Vec::new()),
+            // The fairy pieces move however their definitions say
+            PieceType::Archbishop | PieceType::Chancellor | PieceType::Nightrider => {
+                compute_fairy(self, piece)
             }
-        });
+        }
+    }
+    // Castling moves for both teams
+    fn castling_moves(&self) -> Vec<Move> {
+        let pl = self.piece_list;
+        let mut castles: Vec<Move> = Vec::new();
 
-        // Add castling
         // K, Q, k q
         let white_check = self.is_team_checked(Team::White);
         let black_check = self.is_team_checked(Team::Black);
@@ -504,53 +935,64 @@ impl BoardState {
                     black_check
                 })
             {
-                // Update bitboard for this square
-                let king_square = if castling_move < 2 { 4 } else { 60 };
+                let size = self.size;
+                let rank = if castling_move < 2 {
+                    0
+                } else {
+                    size.last_rank()
+                };
+                let king_square = size.square(size.files() / 2, rank);
+                let enemy_team = if castling_move < 2 {
+                    Team::Black
+                } else {
+                    Team::White
+                };
                 if self.piece_list[king_square] != PieceType::King {
                     continue;
                 };
-                let (bitboard, move_vec) = &mut move_list[king_square];
 
-                if (castling_move == 0 || castling_move == 2)
-                    && pl[king_square + 2] == PieceType::None
-                    && pl[king_square + 1] == PieceType::None
-                    && !self.opponent_attacking_square(king_square + 1)
-                    && !self.opponent_attacking_square(king_square + 2)
-                {
-                    bitboard
-                        .state
-                        .view_bits_mut::<Lsb0>()
-                        .set(king_square + 2, true);
-                    move_vec.push(Move {
-                        start: king_square,
-                        target: king_square + 2,
-                        captures: None,
-                        is_pawn_double: false,
-                        is_castle: true,
-                    });
-                } else if pl[king_square - 2] == PieceType::None
-                    && pl[king_square - 1] == PieceType::None
-                    && pl[king_square - 3] == PieceType::None
-                    && !self.opponent_attacking_square(king_square - 1)
-                    && !self.opponent_attacking_square(king_square - 2)
-                    && !self.opponent_attacking_square(king_square - 3)
-                {
-                    bitboard
-                        .state
-                        .view_bits_mut::<Lsb0>()
-                        .set(king_square - 2, true);
-                    move_vec.push(Move {
+                // Kingside goes to the file beside the corner, queenside to the c file
+                let (target, rook_square) = if castling_move % 2 == 0 {
+                    (
+                        size.square(size.files() - 2, rank),
+                        size.square(size.files() - 1, rank),
+                    )
+                } else {
+                    (size.square(2, rank), size.square(0, rank))
+                };
+                let clear = (king_square.min(rook_square) + 1..king_square.max(rook_square))
+                    .all(|square| pl[square] == PieceType::None);
+                // The king never crosses the squares between where it lands and the rook, so those only have to be
+                // empty
+                let safe = (king_square.min(target)..=king_square.max(target))
+                    .filter(|square| *square != king_square)
+                    .all(|square| !self.is_attacked_by(square, enemy_team));
+                if clear && safe {
+                    castles.push(Move {
                         start: king_square,
-                        target: king_square - 2,
+                        target,
                         captures: None,
                         is_pawn_double: false,
                         is_castle: true,
+                        promotion: None,
                     });
                 }
             }
         }
 
-        move_list
+        castles
+    }
+    /*
+        Yields `team`'s psuedolegal moves a piece at a time instead of generating the whole board up front, so callers
+        that only need the first few can stop early
+    */
+    pub fn pseudo_legal_iter(&self, team: Team) -> PseudoLegalIter<'_> {
+        PseudoLegalIter {
+            board: self,
+            team,
+            square: 0,
+            pending: Vec::new().into_iter(),
+        }
     }
     pub fn dump_positions(&self) {
         for (square, _) in self.piece_list.iter().enumerate() {
@@ -559,71 +1001,391 @@ impl BoardState {
                     "{:?} {:?} @ {:?} ({})",
                     piece.team,
                     piece.piece_type,
-                    Bitboard::bit_idx_to_al_notation(square),
+                    self.size.notation(square),
                     square
                 )
             }
         }
     }
     pub fn is_team_checked(&self, team: Team) -> bool {
-        let enemy_capture_bitboard = self.capture_bitboard[Team::White as usize]
-            | self.capture_bitboard[Team::Black as usize];
-
-        let in_check =
-            enemy_capture_bitboard & self.board_pieces[team as usize][PieceType::King as usize];
+        if self.variant == Variant::Atomic {
+            return self.is_atomic_check(team);
+        }
+        let in_check = self.attack_map(team.opponent())
+            & self.board_pieces[team as usize][PieceType::King as usize];
 
         in_check.state > 0
     }
-    pub fn get_legal_moves(&self) -> Vec<(Bitboard, Vec<Move>)> {
-        let pl_moves = self.get_psuedolegal_moves();
-        let mut legal_moves: Vec<(Bitboard, Vec<Move>)> = Vec::new();
+    /*
+        Check in Atomic only comes from pieces that could take the king. The enemy king never can, and kings standing
+        side by side can't be checked at all, since taking one would blow up the other
+    */
+    fn is_atomic_check(&self, team: Team) -> bool {
+        let enemy = team.opponent();
+        let king = self.board_pieces[team as usize][PieceType::King as usize];
+        let enemy_king = self.board_pieces[enemy as usize][PieceType::King as usize];
+        if king.state == 0
+            || (self.tables().king_attacks[team as usize][king.state.trailing_zeros() as usize]
+                & enemy_king)
+                .state
+                != 0
+        {
+            return false;
+        }
+        let occupied = self.get_team_coverage(team) | self.get_team_coverage(enemy);
+        self.get_team_coverage(enemy)
+            .iter_ones()
+            .filter(|square| self.piece_list[*square] != PieceType::King)
+            .any(|square| {
+                (attacks_from(self.size, self.piece_list[square], enemy, square, occupied) & king)
+                    .state
+                    != 0
+            })
+    }
+    /*
+        Whether `r#move` puts the other team in check. Only the piece that moves, the rook it castles with and
+        sliders looking through the square it leaves can give a new check, so only those are looked at instead
+        of making the move and rebuilding both attack maps
+    */
+    pub fn gives_check(&self, r#move: Move) -> bool {
+        let team = self.get_square_team(r#move.start);
+        if team == Team::None {
+            return false;
+        }
+        let king = self.board_pieces[team.opponent() as usize][PieceType::King as usize];
+        let mut after = *self;
+        let mut moved = vec![Piece {
+            piece_type: self.piece_list[r#move.start],
+            team,
+            position: r#move.target,
+        }];
+        after.piece_list[r#move.start] = PieceType::None;
+        after.piece_list[r#move.target] = self.piece_list[r#move.start];
+
+        let mut rook_start = None;
+        if let Some((start, target)) =
+            castling_rook_squares(self.size, r#move.target).filter(|_| r#move.is_castle)
+        {
+            after.piece_list[start] = PieceType::None;
+            after.piece_list[target] = PieceType::Rook;
+            moved.push(Piece {
+                piece_type: PieceType::Rook,
+                team,
+                position: target,
+            });
+            rook_start = Some(start);
+        }
 
-        // This is a list of what moves are available from what square, let's cut that down by active team
-        for (mut bitboard, move_vector) in pl_moves {
-            // Check
-            let mut lm_vector: Vec<Move> = Vec::new();
+        let sliders = PIECE_TYPE_ARRAY
+            .into_iter()
+            .filter(|piece_type| !definition(*piece_type).rides.is_empty())
+            .flat_map(|piece_type| {
+                self.board_pieces[team as usize][piece_type as usize]
+                    .iter_ones()
+                    .map(move |position| Piece {
+                        piece_type,
+                        team,
+                        position,
+                    })
+            })
+            .filter(|slider| {
+                slider.position != r#move.start && Some(slider.position) != rook_start
+            });
 
-            move_vector.iter().for_each(|available_move| {
-                let mut testing_board = *self; // EXPENSIVE? TODO: Decide whether or not to keep this
-                let team_moving = testing_board.get_square_team(available_move.start);
-                let move_att = testing_board.make_move(*available_move);
-
-                if move_att.is_ok() {
-                    if testing_board.is_team_checked(team_moving) {
-                        bitboard
-                            .state
-                            .view_bits_mut::<Lsb0>()
-                            .set(available_move.target, false);
-                    } else {
-                        lm_vector.push(*available_move);
-                    }
+        moved
+            .into_iter()
+            .chain(sliders)
+            .any(|piece| (compute_attacks(&after, piece) & king).state != 0)
+    }
+    pub fn get_legal_moves(&self) -> Vec<(Bitboard, Vec<Move>)> {
+        let _movegen = tracing::trace_span!("movegen", team = ?self.active_team).entered();
+        let safety = [self.king_safety(Team::White), self.king_safety(Team::Black)];
+        let mut legal_moves = self.get_psuedolegal_moves();
+
+        // This is a list of what moves are available from what square, let's cut that down to the ones keeping
+        // the mover's king safe
+        for (square, (bitboard, move_vector)) in legal_moves.iter_mut().enumerate() {
+            let team = self.get_square_team(square);
+            if team == Team::None {
+                continue;
+            }
+            move_vector.retain(|available_move| {
+                let legal = self.keeps_king_safe(*available_move, safety[team as usize].as_ref());
+                if !legal {
+                    bitboard.set_bit(available_move.target, false);
                 }
+                legal
             });
-
-            legal_moves.push((bitboard, lm_vector))
         }
 
         legal_moves
     }
-    pub fn prune_moves_for_team_mut(
-        &mut self,
-        move_list: Vec<(Bitboard, Vec<Move>)>,
-        team: Team,
-    ) -> Vec<Move> {
-        let mut pruned_list: Vec<Move> = Vec::new();
-        move_list.iter().for_each(|(_, move_vector)| {
-            move_vector.iter().for_each(|available_move| {
-                let team_moving = self.get_square_team(available_move.start);
-                if team_moving == team {
-                    pruned_list.push(*available_move);
+    /*
+        The checks, pins and attacked squares `team`'s king has to be kept out of. None when the team has no king,
+        which only set-up positions manage
+    */
+    pub fn king_safety(&self, team: Team) -> Option<KingSafety> {
+        let king_bits = self.board_pieces[team as usize][PieceType::King as usize];
+        if king_bits.state == 0 {
+            return None;
+        }
+        let king = king_bits.state.trailing_zeros() as usize;
+        let enemy = team.opponent();
+        let own = self.get_team_coverage(team);
+        let occupied = own | self.get_team_coverage(enemy);
+
+        let mut checkers = Bitboard::default();
+        let mut pinned = Bitboard::default();
+        let mut danger = Bitboard::default();
+        for square in self.get_team_coverage(enemy).iter_ones() {
+            let piece_type = self.piece_list[square];
+            // Squares behind the king along a check are still attacked once it steps back along the line
+            danger |= attacks_from(self.size, piece_type, enemy, square, occupied & !king_bits);
+
+            if attacks_from(self.size, piece_type, enemy, square, occupied).get_bit(king) {
+                checkers.set_bit(square, true);
+            } else if attacks_from(self.size, piece_type, enemy, square, Bitboard::default())
+                .get_bit(king)
+            {
+                // A rider lined up on the king with exactly one of the king's own pieces in the way pins it
+                let blockers = between(self.size, square, king) & occupied;
+                if blockers.state.count_ones() == 1 && (blockers & own).state != 0 {
+                    pinned |= blockers;
                 }
-            })
-        });
+            }
+        }
+
+        let check_mask = match checkers.state.count_ones() {
+            0 => self.size.all(),
+            1 => checkers | between(self.size, checkers.state.trailing_zeros() as usize, king),
+            _ => Bitboard::default(), // Only the king can answer a double check
+        };
+        Some(KingSafety {
+            king,
+            checkers,
+            check_mask,
+            pinned,
+            danger,
+        })
+    }
+    /*
+        Whether a psuedolegal move keeps its team's king out of check, judged from the king's safety masks instead of
+        playing the move out. A team without a king has nothing to keep safe
+    */
+    pub fn keeps_king_safe(&self, available_move: Move, safety: Option<&KingSafety>) -> bool {
+        // Explosions take pieces off more than the squares a move touches, which the masks can't follow
+        if self.variant == Variant::Atomic {
+            return self.explodes_safely(available_move);
+        }
+        if self.variant == Variant::RacingKings && self.gives_check(available_move) {
+            return false;
+        }
+        let Some(safety) = safety else {
+            return true;
+        };
+        if available_move.start == safety.king {
+            // Castling already kept the king's path out of check when it was generated
+            return available_move.is_castle || !safety.danger.get_bit(available_move.target);
+        }
 
-        if pruned_list.is_empty() && self.is_team_checked(self.active_team) {
-            self.active_team_checkmate = true;
+        // En passant takes a pawn off a different square than the one it lands on, which can open a line the masks
+        // don't see, so it's still played out
+        if self.piece_list[available_move.start] == PieceType::Pawn
+            && self.size.file(available_move.start) != self.size.file(available_move.target)
+            && self.piece_list[available_move.target] == PieceType::None
+        {
+            return self.plays_out_safely(available_move);
         }
-        pruned_list
+
+        safety.check_mask.get_bit(available_move.target)
+            && (!safety.pinned.get_bit(available_move.start)
+                || pin_line(self.size, safety.king, available_move.start)
+                    .get_bit(available_move.target))
+    }
+    // Makes the move on a copy of the board and looks at the king, for the moves the masks can't judge
+    fn plays_out_safely(&self, available_move: Move) -> bool {
+        let mut testing_board = *self;
+        let team_moving = testing_board.get_square_team(available_move.start);
+
+        testing_board.make_move(available_move).is_ok()
+            && !testing_board.is_team_checked(team_moving)
+    }
+    /*
+        Atomic legality, played out on a copy. A king can't capture, since it would go up in its own blast, and no move
+        may blow up its own king. Blowing up the enemy king wins outright, even with the mover's king left in check
+    */
+    fn explodes_safely(&self, available_move: Move) -> bool {
+        let team = self.get_square_team(available_move.start);
+        let has_king = |board: &BoardState, team: Team| {
+            board.board_pieces[team as usize][PieceType::King as usize].state != 0
+        };
+        if self.piece_list[available_move.start] == PieceType::King
+            && self.get_square_team(available_move.target) == team.opponent()
+        {
+            return false;
+        }
+
+        let mut after = *self;
+        if after.make_move(available_move).is_err()
+            || (has_king(self, team) && !has_king(&after, team))
+        {
+            return false;
+        }
+        !has_king(&after, team.opponent()) || !after.is_team_checked(team)
+    }
+    // A psuedolegal move is legal as long as it doesn't leave the mover's own king attacked
+    pub fn is_move_legal(&self, available_move: Move) -> bool {
+        let team_moving = self.get_square_team(available_move.start);
+        self.keeps_king_safe(available_move, self.king_safety(team_moving).as_ref())
+    }
+    /*
+        `team`'s legal moves a piece at a time, with the king's safety worked out once up front. Callers that only
+        need the first few can stop early
+    */
+    pub fn legal_iter(&self, team: Team) -> impl Iterator<Item = Move> + '_ {
+        let safety = self.king_safety(team);
+        self.pseudo_legal_iter(team)
+            .filter(move |available_move| self.keeps_king_safe(*available_move, safety.as_ref()))
+    }
+    /*
+        `team`'s legal moves packed into a [MoveList], read straight off each piece's target squares and the king's
+        safety masks without allocating. get_legal_moves is still there for the per-square bitboards the board
+        display highlights from
+    */
+    pub fn legal_moves(&self, team: Team) -> MoveList {
+        let mut moves = MoveList::default();
+        // The variants with rules of their own go through keeps_king_safe, one move at a time
+        if matches!(self.variant, Variant::Atomic | Variant::RacingKings) {
+            for legal in self.legal_iter(team) {
+                moves.push(PackedMove::from(legal));
+            }
+            return moves;
+        }
+        let safety = self.king_safety(team);
+        let own = self.get_team_coverage(team);
+        let occupied = own | self.get_team_coverage(team.opponent());
+
+        for start in own.iter_ones() {
+            let piece_type = self.piece_list[start];
+            let mut targets = match piece_type {
+                PieceType::Pawn => pawn_targets(
+                    self,
+                    Piece {
+                        piece_type,
+                        team,
+                        position: start,
+                    },
+                ),
+                PieceType::King => {
+                    self.tables().king_attacks[team as usize][start]
+                        & !own
+                        & !self.capture_bitboard[team.opponent() as usize]
+                }
+                _ => attacks_from(self.size, piece_type, team, start, occupied) & !own,
+            };
+            // En passant can uncover the king along the rank both pawns leave, which the masks don't follow
+            let en_passant = self
+                .en_passant_target(team)
+                .filter(|square| piece_type == PieceType::Pawn && targets.get_bit(*square));
+            if let Some(square) = en_passant {
+                targets.set_bit(square, false);
+                let capture = PackedMove::new(start, square, false, false, None).unpack(self);
+                if self.keeps_king_safe(capture, safety.as_ref()) {
+                    moves.push(PackedMove::from(capture));
+                }
+            }
+            if let Some(safety) = &safety {
+                if start == safety.king {
+                    targets &= !safety.danger;
+                } else {
+                    targets &= safety.check_mask;
+                    if safety.pinned.get_bit(start) {
+                        targets &= pin_line(self.size, safety.king, start);
+                    }
+                }
+            }
+
+            for target in targets.iter_ones() {
+                if piece_type == PieceType::Pawn && promotes_on(self.size, team, target) {
+                    for &promotion in promotion_choices(self.size) {
+                        moves.push(PackedMove::new(
+                            start,
+                            target,
+                            false,
+                            false,
+                            Some(promotion),
+                        ));
+                    }
+                    continue;
+                }
+                let is_pawn_double = piece_type == PieceType::Pawn
+                    && start.abs_diff(target) == 2 * self.size.files();
+                moves.push(PackedMove::new(start, target, is_pawn_double, false, None));
+            }
+        }
+        for castle in self.castling_moves() {
+            if self.get_square_team(castle.start) == team {
+                moves.push(PackedMove::from(castle));
+            }
+        }
+        moves
+    }
+    /*
+        Whether `team` has a legal move at all. This stops at the first one it finds, so checking for mate or
+        stalemate doesn't need every legal move generated and filtered
+    */
+    pub fn has_legal_move(&self, team: Team) -> bool {
+        self.legal_iter(team).next().is_some()
+    }
+    /*
+        Counts the positions `depth` legal moves from here, the standard check of a move generator against
+        published totals. Depth 0 is this position alone
+    */
+    pub fn perft(&self, depth: u32) -> u64 {
+        let mut board = *self;
+        board.count_leaves(depth)
+    }
+    // Perft split by the first move, for narrowing down which move a wrong total comes from
+    pub fn perft_divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        let mut board = *self;
+        board
+            .legal_moves(board.active_team)
+            .iter()
+            .map(|packed| {
+                let available_move = packed.unpack(&board);
+                let undo = board
+                    .make_move(available_move)
+                    .expect("Legal moves can be made");
+                let nodes = board.count_leaves(depth - 1);
+                board.unmake_move(undo).expect("Made moves can be unmade");
+                (available_move, nodes)
+            })
+            .collect()
+    }
+    // Perft proper, making and unmaking every move on the one board
+    fn count_leaves(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves(self.active_team);
+        // The last ply only needs counting, not playing
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .iter()
+            .map(|packed| {
+                let undo = self
+                    .make_move(packed.unpack(self))
+                    .expect("Legal moves can be made");
+                let nodes = self.count_leaves(depth - 1);
+                self.unmake_move(undo).expect("Made moves can be unmade");
+                nodes
+            })
+            .sum()
     }
     pub fn prune_moves_for_team(
         &self,
@@ -647,20 +1409,10 @@ impl BoardState {
         let black_check = self.get_team_coverage(Team::Black);
 
         {
-            let white_bitcheck = white_check
-                .state
-                .view_bits::<Lsb0>()
-                .get(square_idx)
-                .expect("Index was not within bitboard")
-                .then(|| Team::White);
+            let white_bitcheck = white_check.get_bit(square_idx).then_some(Team::White);
 
             if white_bitcheck.is_none() {
-                let black_bitcheck = black_check
-                    .state
-                    .view_bits::<Lsb0>()
-                    .get(square_idx)
-                    .expect("Index was not within bitboard")
-                    .then(|| Team::Black);
+                let black_bitcheck = black_check.get_bit(square_idx).then_some(Team::Black);
 
                 if let Some(_bbc) = black_bitcheck {
                     Team::Black
@@ -673,7 +1425,7 @@ impl BoardState {
             }
         }
     }
-    pub fn make_move(&mut self, r#move: Move) -> Result<(), MoveError> {
+    pub fn make_move(&mut self, r#move: Move) -> Result<UndoInfo, MoveError> {
         // Update out of the target positions
         let moving_piece_type = self.piece_list[r#move.start];
         let square_team = self.get_square_team(r#move.start);
@@ -687,88 +1439,143 @@ impl BoardState {
                 return Err(MoveError::AttackedAlly);
             }
             tracing::debug!("{square_team:?} {moving_piece_type:?} {move:?}");
+            let state_key_before = self.state_key();
+            let mut undo = UndoInfo {
+                played: r#move,
+                moved: moving_piece_type,
+                captured: self.captured_by(r#move.start, r#move.target),
+                castling_rights: self.castling_rights,
+                fifty_move_clock: self.fifty_move_clock,
+                en_passant_square: self.en_passant_square,
+                turn_clock: self.turn_clock,
+                ply_clock: self.ply_clock,
+                active_team: self.active_team,
+                capture_bitboard: self.capture_bitboard,
+                checks_given: self.checks_given,
+                exploded: [None; 8],
+                zobrist: self.zobrist,
+            };
 
-            self.move_piece(square_team, moving_piece_type, r#move);
+            // Pawn moves and captures can't be undone, so they restart the fifty-move count
+            if moving_piece_type == PieceType::Pawn || target_team != Team::None {
+                self.fifty_move_clock = 0;
+            } else {
+                self.fifty_move_clock += 1;
+            }
 
-            // Move the rook for castlings
-            // White
+            // En passant takes the pawn from beside the capturer, not from where it lands
+            if let Some(victim) = undo
+                .captured
+                .filter(|victim| victim.position != r#move.target)
+            {
+                self.set_piece(victim.team, PieceType::Pawn, victim.position, false);
+                self.zobrist ^= piece_key(victim.team, PieceType::Pawn, victim.position);
+            }
 
-            if r#move.is_castle && r#move.target == 6 {
-                // Rook to a5
-                self.move_piece(square_team, PieceType::Rook, {
-                    Move {
-                        start: 7,
-                        target: 5,
-                        captures: None,
-                        is_pawn_double: false,
-                        is_castle: true,
-                    }
-                });
-            } else if r#move.is_castle && r#move.target == 2 {
-                // Rook to a3
-                self.move_piece(square_team, PieceType::Rook, {
-                    Move {
-                        start: 0,
-                        target: 3,
-                        captures: None,
-                        is_pawn_double: false,
-                        is_castle: true,
-                    }
-                });
-            } else if r#move.is_castle && r#move.target == 58 {
-                // Rook to a3
-                self.move_piece(square_team, PieceType::Rook, {
-                    Move {
-                        start: 56,
-                        target: 59,
-                        captures: None,
-                        is_pawn_double: false,
-                        is_castle: true,
-                    }
-                });
-            } else if r#move.is_castle && r#move.target == 62 {
-                // Rook to a3
+            self.move_piece(square_team, moving_piece_type, r#move);
+
+            // Move the rook for castlings, from its corner to the square the king passed over
+            if let Some((rook_start, rook_target)) =
+                castling_rook_squares(self.size, r#move.target).filter(|_| r#move.is_castle)
+            {
                 self.move_piece(square_team, PieceType::Rook, {
                     Move {
-                        start: 63,
-                        target: 61,
+                        start: rook_start,
+                        target: rook_target,
                         captures: None,
                         is_pawn_double: false,
                         is_castle: true,
+                        promotion: None,
                     }
                 });
             }
 
-            self.en_passant_square = if r#move.is_pawn_double {
-                Some(r#move.target)
-            } else {
-                self.en_passant_square
-            };
-            self.en_passant_turn = Some(self.turn_clock);
-
-            // Crudely handle promotions by queening any pawns that finished
+            // A pawn's double step can be taken en passant on the next move only, on the square it passed over
+            let size = self.size;
+            let passed_over = (r#move.start + r#move.target) / 2;
+            self.en_passant_square = (moving_piece_type == PieceType::Pawn
+                && r#move.start.abs_diff(r#move.target) == 2 * size.files()
+                && [2, size.ranks() - 3].contains(&size.rank(passed_over)))
+            .then_some(passed_over);
+
+            // The pawn has already been moved, so it's swapped for what it promotes to where it landed
+            if let Some(promoted) = r#move
+                .promotion
+                .filter(|_| moving_piece_type == PieceType::Pawn)
+            {
+                self.set_piece(square_team, PieceType::Pawn, r#move.target, false);
+                self.set_piece(square_team, promoted, r#move.target, true);
+                self.zobrist ^= piece_key(square_team, PieceType::Pawn, r#move.target)
+                    ^ piece_key(square_team, promoted, r#move.target);
+            }
+            if self.variant == Variant::Atomic && undo.captured.is_some() {
+                undo.exploded = self.explode(square_team, r#move.target);
+            }
 
             self.update_capture_bitboards();
 
             if self.active_team == Team::Black {
                 self.active_team = Team::White;
                 self.turn_clock += 1;
-                self.en_passant_square = None;
             } else {
-                self.active_team = Team::Black // TODO: Account for three turn order with red before white
+                self.active_team = Team::Black // Red's turn comes in three-player chess, which three_player.rs plays
             }
             self.ply_clock += 1;
+            if self.variant == Variant::ThreeCheck && self.is_team_checked(self.active_team) {
+                self.checks_given[square_team as usize] += 1;
+            }
+            self.zobrist ^= state_key_before ^ self.state_key();
+            Ok(undo)
         } else {
-            return Err(MoveError::NoUnit);
+            Err(MoveError::NoUnit)
         }
-
-        Ok(())
+    }
+    // Positions are the same when the pieces, side to move, castling rights and en passant square all match
+    pub fn position_key(&self) -> String {
+        self.as_fen()
+            .split(' ')
+            .take(4)
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+    pub fn position_hash(&self) -> u64 {
+        self.zobrist
+    }
+    // The part of the hash that isn't pieces: castling rights, en passant square, side to move and checks given
+    fn state_key(&self) -> u64 {
+        let mut key = ZOBRIST.castling[(self.castling_rights & 0b1111) as usize];
+        if let Some(square) = self.en_passant_square {
+            key ^= ZOBRIST.en_passant[square];
+        }
+        for (team, checks) in self
+            .checks_given
+            .iter()
+            .enumerate()
+            .filter(|(_, checks)| **checks > 0)
+        {
+            key ^= ZOBRIST.checks[team][(*checks as usize).min(3)];
+        }
+        if self.active_team == Team::Black {
+            key ^= ZOBRIST.black_to_move;
+        }
+        key
+    }
+    // The hash worked out from scratch, which the one kept up to date by moves should always equal
+    pub fn compute_zobrist(&self) -> u64 {
+        let mut key = self.state_key();
+        for square in 0..self.size.squares() {
+            let team = self.get_square_team(square);
+            if team != Team::None {
+                key ^= piece_key(team, self.piece_list[square], square);
+            }
+        }
+        key
     }
     pub fn as_fen(&self) -> String {
         let mut castling_rights = String::from(if self.castling_rights > 0 { "" } else { "-" });
         let en_passant_square = {
             if let Some(eps) = self.en_passant_square {
-                if let Some(eps_str) = Bitboard::bit_idx_to_al_notation(eps) {
+                if let Some(eps_str) = self.size.notation(eps) {
                     eps_str
                 } else {
                     String::from("-")
@@ -777,7 +1584,7 @@ impl BoardState {
                 String::from("-")
             }
         };
-        let half_move_clock = "0"; // TODO
+        let half_move_clock = self.fifty_move_clock;
         let full_move_clock = self.turn_clock;
         let active_color = if self.active_team == Team::White {
             "w"
@@ -806,26 +1613,19 @@ impl BoardState {
 
         let mut empty_square_head = 0; // Add to this for every empty square, reset on every filled square
 
-        let mut rank = 0;
-        // Write pieces
-        for rank_of_pieces in self.piece_list.iter().enumerate().rev().array_chunks::<8>() {
-            for (square, piece_type) in rank_of_pieces.iter().rev() {
-                let team = self.get_square_team(*square);
-
-                let mut piece_char = match *(*piece_type) {
-                    PieceType::None => '0',
-                    PieceType::Pawn => 'p',
-                    PieceType::Rook => 'r',
-                    PieceType::Bishop => 'b',
-                    PieceType::Knight => 'n',
-                    PieceType::Queen => 'q',
-                    PieceType::King => 'k',
-                };
+        // Write pieces, from black's side of the board down
+        for rank in (0..self.size.ranks()).rev() {
+            for file in 0..self.size.files() {
+                let square = self.size.square(file, rank);
+                let piece_type = self.piece_list[square];
+                let team = self.get_square_team(square);
 
-                if team == Team::White {
-                    piece_char = piece_char.to_ascii_uppercase()
+                let mut piece_char = definition(piece_type).letter;
+
+                if team != Team::White {
+                    piece_char = piece_char.to_ascii_lowercase()
                 }
-                if *piece_type == &PieceType::None || team == Team::None {
+                if piece_type == PieceType::None || team == Team::None {
                     empty_square_head += 1;
                 } else {
                     if empty_square_head != 0 {
@@ -837,13 +1637,12 @@ impl BoardState {
                 }
             }
 
-            rank += 1;
             if empty_square_head != 0 {
                 // Append empty squares if there is nothing here
                 piece_placement.push_str(&(empty_square_head).to_string())
             }
             empty_square_head = 0;
-            if rank != 8 {
+            if rank != 0 {
                 // Append a splitter
                 piece_placement.push('/')
             }
@@ -852,127 +1651,307 @@ impl BoardState {
             "{piece_placement} {active_color} {castling_rights} {en_passant_square} {half_move_clock} {full_move_clock}"
         )
     }
-    pub fn unmake_move(&mut self, r#move: Move) -> Result<(), MoveError> {
-        let moving_piece_type = self.piece_list[r#move.target];
-        let square_team = self.get_square_team(r#move.target);
-        let target_team = self.get_square_team(r#move.start);
-
-        if r#move.start == r#move.target {
+    /*
+        Takes back the move `undo` came from, which has to be the last one made on this board. The pieces are put back
+        where they stood and everything else is restored from before the move, so the position comes back exactly
+    */
+    pub fn unmake_move(&mut self, undo: UndoInfo) -> Result<(), MoveError> {
+        let played = undo.played;
+        let team = undo.active_team;
+        // An Atomic capture leaves nothing on the square it was made on
+        let exploded = self.variant == Variant::Atomic && undo.captured.is_some();
+        if played.start == played.target {
             return Err(MoveError::NotAMove);
         }
-        if square_team != Team::None {
-            if target_team == square_team {
-                return Err(MoveError::AttackedAlly);
-            }
+        if self.get_square_team(played.target) != team && !exploded {
+            return Err(MoveError::NoUnit);
+        }
 
-            self.move_piece(
-                square_team,
-                moving_piece_type,
-                Move {
-                    start: r#move.target,
-                    target: r#move.start,
-                    captures: r#move.captures,
-                    is_pawn_double: false,
-                    is_castle: false,
-                },
-            );
+        if !exploded {
+            self.set_piece(team, self.piece_list[played.target], played.target, false);
+        }
+        self.set_piece(team, undo.moved, played.start, true);
+        if let Some(captured) = undo.captured {
+            self.set_piece(captured.team, captured.piece_type, captured.position, true);
+        }
+        for piece in undo.exploded.iter().flatten() {
+            self.set_piece(piece.team, piece.piece_type, piece.position, true);
+        }
+        if let Some((rook_start, rook_target)) =
+            castling_rook_squares(self.size, played.target).filter(|_| played.is_castle)
+        {
+            self.set_piece(team, PieceType::Rook, rook_target, false);
+            self.set_piece(team, PieceType::Rook, rook_start, true);
+        }
 
-            if let Some(fallen_piece) = r#move.captures {
-                self.piece_list[r#move.target] = fallen_piece.piece_type;
-                self.board_pieces[fallen_piece.team as usize][fallen_piece.piece_type as usize]
-                    .state
-                    .view_bits_mut::<Lsb0>()
-                    .set(r#move.target, true);
-            }
-            if r#move.is_castle {
-                let is_queenside = r#move.target < r#move.start;
-                let is_kingside = r#move.target > r#move.start;
-                let rights_index = square_team as usize;
-                if is_kingside {
-                    self.castling_rights
-                        .view_bits_mut::<Lsb0>()
-                        .set(rights_index, true);
-                } else if is_queenside {
-                    self.castling_rights
-                        .view_bits_mut::<Lsb0>()
-                        .set(rights_index + 1, true);
+        self.castling_rights = undo.castling_rights;
+        self.fifty_move_clock = undo.fifty_move_clock;
+        self.en_passant_square = undo.en_passant_square;
+        self.turn_clock = undo.turn_clock;
+        self.ply_clock = undo.ply_clock;
+        self.active_team = undo.active_team;
+        self.capture_bitboard = undo.capture_bitboard;
+        self.checks_given = undo.checks_given;
+        self.zobrist = undo.zobrist;
+        Ok(())
+    }
+    // The same position played by `variant`'s rules
+    pub fn with_variant(mut self, variant: Variant) -> BoardState {
+        self.variant = variant;
+        self
+    }
+    /*
+        Why a position that parsed could still not be played from: a side without exactly one king, a pawn that should
+        already have promoted, or the side that just moved left in check. Horde's white army has no king and starts
+        with pawns on the first rank
+    */
+    pub fn playable(&self) -> Result<(), FENErr> {
+        for team in [Team::White, Team::Black] {
+            if self.variant == Variant::Horde && team == Team::White {
+                continue;
+            }
+            let kings = self.board_pieces[team as usize][PieceType::King as usize]
+                .state
+                .count_ones() as usize;
+            if kings != 1 {
+                return Err(FENErr::KingCount(team, kings));
+            }
+        }
+        let first_rank = self.size.rank_mask(0);
+        let last_rank = self.size.rank_mask(self.size.last_rank());
+        let white_back_ranks = if self.variant == Variant::Horde {
+            last_rank
+        } else {
+            first_rank | last_rank
+        };
+        let stranded = (self.board_pieces[Team::White as usize][PieceType::Pawn as usize]
+            & white_back_ranks)
+            | (self.board_pieces[Team::Black as usize][PieceType::Pawn as usize]
+                & (first_rank | last_rank));
+        if let Some(square) = stranded.iter_ones().next() {
+            return Err(FENErr::PawnOnBackRank(
+                self.size.notation(square).unwrap_or_default(),
+            ));
+        }
+        let waiting = self.active_team.opponent();
+        if self.is_team_checked(waiting) {
+            return Err(FENErr::OpponentInCheck(waiting));
+        }
+        Ok(())
+    }
+    /*
+        How the game stands for the team to move. A variant's win comes first, since the move that won it ended the
+        game there. Then running out of moves, since a mate or stalemate ends the game even on a move that would also
+        draw it by rule
+    */
+    pub fn status(&self) -> GameStatus {
+        let variant_win = self.variant_win();
+        if variant_win.is_over() {
+            return variant_win;
+        }
+        if !self.has_legal_move(self.active_team) {
+            return if self.is_team_checked(self.active_team) {
+                GameStatus::Checkmate(self.active_team.opponent())
+            } else {
+                GameStatus::Stalemate
+            };
+        }
+        self.rule_draw()
+    }
+    // Whether the side that just moved has won by its variant's own rule. Cheap enough to ask at every node
+    pub fn variant_win(&self) -> GameStatus {
+        let mover = self.active_team.opponent();
+        match self.variant {
+            Variant::Standard | Variant::Capablanca => GameStatus::Ongoing,
+            Variant::KingOfTheHill => {
+                if (self.board_pieces[mover as usize][PieceType::King as usize]
+                    & hill_squares(self.size))
+                .state
+                    != 0
+                {
+                    GameStatus::KingOfTheHill(mover)
+                } else {
+                    GameStatus::Ongoing
                 }
-
-                // Unmove rooks
-
-                if r#move.is_castle && r#move.target == 6 {
-                    // Rook to a5
-                    self.move_piece(square_team, PieceType::Rook, {
-                        Move {
-                            start: 5,
-                            target: 7,
-                            captures: None,
-                            is_pawn_double: false,
-                            is_castle: true,
-                        }
-                    });
-                } else if r#move.is_castle && r#move.target == 2 {
-                    // Rook to a3
-                    self.move_piece(square_team, PieceType::Rook, {
-                        Move {
-                            start: 3,
-                            target: 0,
-                            captures: None,
-                            is_pawn_double: false,
-                            is_castle: true,
-                        }
-                    });
-                } else if r#move.is_castle && r#move.target == 58 {
-                    // Rook to a3
-                    self.move_piece(square_team, PieceType::Rook, {
-                        Move {
-                            start: 59,
-                            target: 56,
-                            captures: None,
-                            is_pawn_double: false,
-                            is_castle: true,
-                        }
-                    });
-                } else if r#move.is_castle && r#move.target == 62 {
-                    // Rook to a3
-                    self.move_piece(square_team, PieceType::Rook, {
-                        Move {
-                            start: 61,
-                            target: 63,
-                            captures: None,
-                            is_pawn_double: false,
-                            is_castle: true,
-                        }
-                    });
+            }
+            Variant::ThreeCheck => {
+                if self.checks_given[mover as usize] >= 3 {
+                    GameStatus::ThirdCheck(mover)
+                } else {
+                    GameStatus::Ongoing
                 }
             }
-
-            if self.active_team == Team::Black {
-                self.active_team = Team::White;
-            } else {
-                self.turn_clock -= 1;
-                self.en_passant_square = None;
-                self.active_team = Team::Black // TODO: Account for three turn order with red before white
+            Variant::Atomic => {
+                if self.board_pieces[self.active_team as usize][PieceType::King as usize].state == 0
+                {
+                    GameStatus::KingExploded(mover)
+                } else {
+                    GameStatus::Ongoing
+                }
             }
-            self.ply_clock -= 1;
-            self.update_capture_bitboards();
+            Variant::Horde => {
+                if self.get_team_coverage(Team::White).state == 0 {
+                    GameStatus::HordeCaptured(Team::Black)
+                } else {
+                    GameStatus::Ongoing
+                }
+            }
+            // White moves first, so black gets one more move to draw by reaching the last rank as well
+            Variant::RacingKings => {
+                let finish = self.size.rank_mask(self.size.last_rank());
+                let finished = |team: Team| {
+                    (self.board_pieces[team as usize][PieceType::King as usize] & finish).state != 0
+                };
+                match (finished(Team::White), finished(Team::Black)) {
+                    (true, true) => GameStatus::DrawByRace,
+                    (false, true) => GameStatus::RaceWon(Team::Black),
+                    (true, false) if mover == Team::Black || !self.can_finish_race(Team::Black) => {
+                        GameStatus::RaceWon(Team::White)
+                    }
+                    _ => GameStatus::Ongoing,
+                }
+            }
+        }
+    }
+    // Whether `team`'s king has a legal move onto the last rank
+    fn can_finish_race(&self, team: Team) -> bool {
+        let king = self.board_pieces[team as usize][PieceType::King as usize];
+        self.legal_iter(team).any(|available_move| {
+            king.get_bit(available_move.start)
+                && self.size.rank(available_move.target) == self.size.last_rank()
+        })
+    }
+    /*
+        The draws that don't depend on what moves there are: a hundred plies without a pawn move or capture, or too
+        little material left to mate with. Cheap enough for the search to ask at every node. A third repetition needs
+        the moves that led here, see History::rule_draw
+    */
+    pub fn rule_draw(&self) -> GameStatus {
+        if self.fifty_move_clock >= 100 {
+            GameStatus::DrawByFiftyMove
+        } else if self.is_dead_position() {
+            GameStatus::DrawByMaterial
         } else {
-            return Err(MoveError::NoUnit);
+            GameStatus::Ongoing
         }
-
-        Ok(())
     }
-    pub fn opponent_attacking_square(&self, pos: usize) -> bool {
-        let enemy_capture_bitboard = self.capture_bitboard[self.active_team.opponent() as usize];
-
-        let attacked = enemy_capture_bitboard
-            .state
-            .view_bits::<Lsb0>()
-            .get(pos)
-            .unwrap()
-            .then_some(true)
-            .is_some();
-        attacked
+    // How the game ends when `flagged` runs out of time. A lone king can't win, so running out against one draws
+    pub fn timeout_status(&self, flagged: Team) -> GameStatus {
+        let winner = flagged.opponent();
+        if self.get_team_coverage(winner)
+            == self.board_pieces[winner as usize][PieceType::King as usize]
+        {
+            GameStatus::DrawByTimeout
+        } else {
+            GameStatus::TimeForfeit(winner)
+        }
+    }
+    /*
+        Neither side can win any more. A lone king can still walk to the hill or race up the board, and any piece can
+        still give check or take the horde's last pawn, so the variants only call it a day with less
+    */
+    fn is_dead_position(&self) -> bool {
+        match self.variant {
+            Variant::Standard | Variant::Capablanca => self.has_insufficient_material(),
+            Variant::KingOfTheHill | Variant::Horde | Variant::RacingKings => false,
+            Variant::ThreeCheck | Variant::Atomic => self
+                .piece_list
+                .iter()
+                .all(|piece_type| matches!(piece_type, PieceType::None | PieceType::King)),
+        }
+    }
+    /*
+        Bare kings, a single knight or bishop against a bare king, or only bishops that all stand on squares of one
+        colour. No sequence of moves can mate with any of those
+    */
+    pub fn has_insufficient_material(&self) -> bool {
+        let mut knights = 0;
+        let mut bishop_colours = [false; 2];
+        for (square, piece_type) in self.piece_list.iter().enumerate() {
+            match piece_type {
+                PieceType::None | PieceType::King => {}
+                PieceType::Knight => knights += 1,
+                PieceType::Bishop => {
+                    bishop_colours[(self.size.rank(square) + self.size.file(square)) % 2] = true
+                }
+                _ => return false,
+            }
+        }
+        let bishops_on_one_colour = !(bishop_colours[0] && bishop_colours[1]);
+        match knights {
+            0 => bishops_on_one_colour,
+            1 => !bishop_colours[0] && !bishop_colours[1],
+            _ => false,
+        }
+    }
+    /*
+        Takes the capturing piece off `square` and every piece but a pawn off the squares around it, for an Atomic
+        capture, along with any castling rights that went with them. Gives back what the blast took from around the
+        square so unmake_move can put it back
+    */
+    fn explode(&mut self, capturer_team: Team, square: usize) -> [Option<Piece>; 8] {
+        let capturer = self.piece_list[square];
+        self.set_piece(capturer_team, capturer, square, false);
+        self.zobrist ^= piece_key(capturer_team, capturer, square);
+
+        let mut exploded = [None; 8];
+        let around = self.tables().king_attacks[capturer_team as usize][square];
+        for (slot, neighbour) in around.iter_ones().enumerate() {
+            let Some(piece) = self
+                .get_piece_at_pos(neighbour)
+                .filter(|piece| piece.piece_type != PieceType::Pawn)
+            else {
+                continue;
+            };
+            self.set_piece(piece.team, piece.piece_type, neighbour, false);
+            self.zobrist ^= piece_key(piece.team, piece.piece_type, neighbour);
+            self.drop_castling_rights(neighbour);
+            exploded[slot] = Some(piece);
+        }
+        exploded
+    }
+    // Puts a piece on or takes it off a square, leaving the hash, rights and attack maps for the caller
+    fn set_piece(&mut self, team: Team, piece_type: PieceType, square: usize, present: bool) {
+        self.board_pieces[team as usize][piece_type as usize].set_bit(square, present);
+        self.piece_list[square] = if present { piece_type } else { PieceType::None };
+    }
+    /*
+        The square a pawn just passed over by moving two, which an enemy pawn beside it can capture onto next move.
+        Only the side to move can take it, and only from its own 5th rank
+    */
+    pub fn en_passant_target(&self, team: Team) -> Option<usize> {
+        let capture_rank = if team == Team::White {
+            self.size.ranks() - 3
+        } else {
+            2
+        };
+        self.en_passant_square
+            .filter(|square| self.size.rank(*square) == capture_rank && team == self.active_team)
+    }
+    // The pawn an en passant capture onto `square` takes, one rank behind it from the capturer's side
+    fn en_passant_victim(&self, team: Team, square: usize) -> Option<Piece> {
+        let victim = if team == Team::White {
+            square - self.size.files()
+        } else {
+            square + self.size.files()
+        };
+        self.get_piece_at_pos(victim)
+            .filter(|piece| piece.team == team.opponent() && piece.piece_type == PieceType::Pawn)
+    }
+    // What moving from `start` to `target` takes, which for en passant isn't on the square moved to
+    pub fn captured_by(&self, start: usize, target: usize) -> Option<Piece> {
+        let team = self.get_square_team(start);
+        self.get_piece_at_pos(target).or_else(|| {
+            (self.piece_list[start] == PieceType::Pawn
+                && self.size.file(start) != self.size.file(target)
+                && self.en_passant_target(team) == Some(target))
+            .then(|| self.en_passant_victim(team, target))
+            .flatten()
+        })
+    }
+    // The move tables for this board's size
+    pub fn tables(&self) -> &'static MoveTables {
+        self.size.tables()
     }
     pub fn get_piece_at_pos(&self, pos: usize) -> Option<Piece> {
         let target_piece_type = self.piece_list[pos];
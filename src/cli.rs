@@ -0,0 +1,814 @@
+use std::{
+    fs,
+    hint::black_box,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    analysis::{analyse_moves, annotate_game, pgn_moves},
+    arena::{
+        match_pairings, parse_entrant, play_tournament, read_openings, round_robin, Adjudication,
+        MatchScore,
+    },
+    bitboard::Team,
+    board::{BoardState, History, Variant},
+    clock::{ChessClock, TimeControl},
+    correspondence::{CorrespondenceGame, DEFAULT_DAYS_PER_MOVE},
+    ics::IcsSession,
+    netplay::NetSession,
+    opponents::{
+        rank_moves, static_breakdown, ChessOpponent, EngineOptions, MoveComputer, SearchInfo,
+        MAX_SKILL_LEVEL,
+    },
+    pgn::{parse_games, PgnGame},
+    puzzle::{parse_puzzles, PuzzleSession, PUZZLE_RATING_FILE},
+    repertoire::Repertoire,
+    report::html_report,
+    trainer::{OpeningTrainer, ReviewSchedule, REVIEW_FILE},
+    tune::{fit_scaling, parse_positions, tune, tuning_error, write_constants, Weights},
+    START_POS_CHESS,
+};
+
+const DEFAULT_EVAL_DEPTH: i32 = 3;
+const DEFAULT_ANALYSE_DEPTH: i32 = 2;
+const DEFAULT_TUNE_PASSES: usize = 10;
+const DEFAULT_MATCH_GAMES: usize = 10;
+const DEFAULT_BENCH_DEPTH: i32 = 4;
+// How many times `bench` copies a board and generates moves in each position, enough to time them to the nanosecond
+const BENCH_COPIES: u32 = 1_000_000;
+const BENCH_MOVEGENS: u32 = 10_000;
+// The start, a quiet middlegame and a sharp one, none of them near the end of the game
+const BENCH_POSITIONS: [&str; 3] = [
+    START_POS_CHESS,
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+
+pub const EVAL_USAGE: &str = "Usage: chess-r eval \"<FEN>\" [--depth N | --movetime MS]";
+pub const PERFT_USAGE: &str = "Usage: chess-r perft [\"<FEN>\"] <depth> [--divide]";
+pub const BENCH_USAGE: &str = "Usage: chess-r bench [--depth N]";
+pub const ANALYSE_USAGE: &str = "Usage: chess-r analyse <games.pgn> <annotated.pgn> [--depth N]";
+pub const REPORT_USAGE: &str =
+    "Usage: chess-r report <games.pgn> <report.html> [--game N] [--depth N]";
+pub const TOURNAMENT_USAGE: &str =
+    "Usage: chess-r tournament <games.pgn> <entrant> <entrant>... [--rounds N]\nEntrants: randy, matt:<depth>, ada:<ms>, uci:<path>[:<ms>]";
+
+pub const MATCH_USAGE: &str = "Usage: chess-r match <games.pgn> <entrant> <entrant> [--games N] [--openings <suite.epd>]\nEntrants: randy, matt:<depth>, ada:<ms>, uci:<path>[:<ms>]";
+pub const TUNE_USAGE: &str = "Usage: chess-r tune <positions.epd> <tuned.rs> [--passes N]\nPositions: one FEN a line followed by its result (1-0, 0-1 or 1/2-1/2)";
+
+// Names for the parts of the program whose logging can be turned up on its own, and the modules they cover
+const LOG_SUBSYSTEMS: [(&str, &[&str]); 4] = [
+    ("search", &["chess_r::opponents"]),
+    ("movegen", &["chess_r::board", "chess_r::move"]),
+    ("ui", &["chess_r::ui", "chess_r::audio"]),
+    ("network", &["chess_r::ics", "chess_r::netplay"]),
+];
+const DEFAULT_LOG_LEVEL: &str = "chess_r=warn";
+
+/*
+    Filter directives from `--log-level`, which takes a level for the whole program and levels for subsystems by
+    name, like `info,search=debug`. Anything else is passed on as a directive of its own, so module paths work too
+*/
+pub fn log_directives_from_args(args: &[String]) -> Result<Vec<String>, String> {
+    let Some(flag) = args.iter().position(|arg| arg == "--log-level") else {
+        return Ok(vec![String::from(DEFAULT_LOG_LEVEL)]);
+    };
+    let value = args.get(flag + 1).ok_or("--log-level needs a value")?;
+    let is_level = |level: &str| {
+        ["off", "error", "warn", "info", "debug", "trace"].contains(&level.to_lowercase().as_str())
+    };
+
+    let mut directives = vec![String::from(DEFAULT_LOG_LEVEL)];
+    for part in value
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        match part.split_once('=') {
+            None if is_level(part) => directives.push(format!("chess_r={part}")),
+            None => return Err(format!("{part} isn't a log level")),
+            Some((name, level)) => {
+                if !is_level(level) {
+                    return Err(format!("{level} isn't a log level"));
+                }
+                match LOG_SUBSYSTEMS
+                    .iter()
+                    .find(|(subsystem, _)| *subsystem == name)
+                {
+                    Some((_, modules)) => {
+                        directives.extend(modules.iter().map(|module| format!("{module}={level}")))
+                    }
+                    None => directives.push(part.to_string()),
+                }
+            }
+        }
+    }
+    Ok(directives)
+}
+
+/*
+    `chess-r eval "<FEN>" [--depth N | --movetime MS]`. Searches the position once and describes it, so scripts can
+    use the engine without opening the board. A fixed depth searches like Matt, a move time like Ada
+*/
+pub fn eval_command(args: &[String]) -> Result<String, String> {
+    let fen = args.first().ok_or(EVAL_USAGE)?;
+    let board =
+        BoardState::from_fen(fen.clone()).map_err(|err| format!("Invalid FEN ({err}): {fen}"))?;
+
+    let mut engine = ChessOpponent::Matt(DEFAULT_EVAL_DEPTH);
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .and_then(|value| value.parse::<u64>().ok())
+            .ok_or_else(|| format!("{option} needs a number. {EVAL_USAGE}"))?;
+
+        engine = match option.as_str() {
+            "--depth" => ChessOpponent::Matt(value.max(1) as i32),
+            "--movetime" => {
+                ChessOpponent::ada(EngineOptions::with_move_time(Duration::from_millis(value)))
+            }
+            _ => return Err(format!("Unknown option {option}. {EVAL_USAGE}")),
+        };
+    }
+
+    let mut report = format!("Static eval: {}\n", static_breakdown(&board));
+    let outcome = engine.think(board, &History::default(), true, &mut |_| {});
+
+    match (outcome.best_move, outcome.explanation) {
+        (Some(best_move), Some(explanation)) => {
+            report.push_str(&format!(
+                "Best move: {} ({})\nPV: {}\n",
+                explanation.chosen,
+                best_move.to_uci(),
+                explanation.pv.join(" ")
+            ));
+        }
+        (Some(best_move), None) => {
+            report.push_str(&format!("Best move: {}\n", best_move.to_san(&board)));
+        }
+        (None, _) => report.push_str("No legal moves\n"),
+    }
+    report.push_str(&outcome.info.to_uci());
+    Ok(report)
+}
+
+/*
+    `chess-r perft ["<FEN>"] <depth> [--divide]`. Counts the positions at every depth up to `depth` from the FEN, or
+    the starting position when there isn't one, with how long each took. `--divide` also splits the last count by
+    first move
+*/
+pub fn perft_command(args: &[String]) -> Result<String, String> {
+    let divide = args.iter().any(|arg| arg == "--divide");
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "--divide").collect();
+    let (fen, depth) = match args.as_slice() {
+        [depth] => (START_POS_CHESS, depth),
+        [fen, depth] => (fen.as_str(), depth),
+        _ => return Err(PERFT_USAGE.to_string()),
+    };
+    let depth = depth
+        .parse::<u32>()
+        .map_err(|_| format!("The depth needs to be a number. {PERFT_USAGE}"))?;
+    let board = BoardState::from_fen(fen.to_string())
+        .map_err(|err| format!("Invalid FEN ({err}): {fen}"))?;
+
+    let mut report = String::new();
+    for depth in 1..=depth {
+        let started = Instant::now();
+        let nodes = board.perft(depth);
+        let elapsed = started.elapsed();
+        let nps = (nodes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64;
+        report.push_str(&format!(
+            "Depth {depth}: {nodes} nodes in {elapsed:.2?} ({nps} nps)\n"
+        ));
+    }
+    if divide && depth > 0 {
+        report.push('\n');
+        // Sorted by move and totalled like a reference engine's divide, so the two can be diffed line for line
+        let mut divide: Vec<(String, u64)> = board
+            .perft_divide(depth)
+            .into_iter()
+            .map(|(first_move, nodes)| (first_move.to_uci(), nodes))
+            .collect();
+        divide.sort();
+        for (first_move, nodes) in &divide {
+            report.push_str(&format!("{first_move}: {nodes}\n"));
+        }
+        report.push_str(&format!(
+            "\nNodes searched: {}\n",
+            divide.iter().map(|(_, nodes)| nodes).sum::<u64>()
+        ));
+    }
+    Ok(report)
+}
+
+/*
+    `chess-r bench [--depth N]`. Times what the search leans on hardest over a few fixed positions: copying a board,
+    generating its legal moves, perft 3 and a search `depth` plies deep. Build with --release and run it before and
+    after a change to the board or the search to see what the change cost
+*/
+pub fn bench_command(args: &[String]) -> Result<String, String> {
+    let depth = match args {
+        [] => DEFAULT_BENCH_DEPTH,
+        [option, value] if option == "--depth" => value
+            .parse::<i32>()
+            .map_err(|_| format!("--depth needs a number. {BENCH_USAGE}"))?
+            .max(1),
+        _ => return Err(BENCH_USAGE.to_string()),
+    };
+    let boards = BENCH_POSITIONS
+        .iter()
+        .map(|fen| BoardState::from_fen(fen.to_string()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("A bench position doesn't parse: {err}"))?;
+    let per_call = |elapsed: Duration, calls: u32| {
+        elapsed.as_nanos() as f64 / (calls as usize * boards.len()) as f64
+    };
+    let per_second = |nodes: u64, elapsed: Duration| {
+        (nodes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64
+    };
+    let mut report = format!("BoardState: {} bytes\n", std::mem::size_of::<BoardState>());
+
+    let started = Instant::now();
+    for board in &boards {
+        for _ in 0..BENCH_COPIES {
+            black_box(*black_box(board));
+        }
+    }
+    report.push_str(&format!(
+        "Copy: {:.1} ns\n",
+        per_call(started.elapsed(), BENCH_COPIES)
+    ));
+
+    let started = Instant::now();
+    for board in &boards {
+        for _ in 0..BENCH_MOVEGENS {
+            black_box(black_box(board).get_legal_moves());
+        }
+    }
+    report.push_str(&format!(
+        "get_legal_moves: {:.0} ns\n",
+        per_call(started.elapsed(), BENCH_MOVEGENS)
+    ));
+
+    let started = Instant::now();
+    let nodes: u64 = boards.iter().map(|board| board.perft(3)).sum();
+    let elapsed = started.elapsed();
+    report.push_str(&format!(
+        "Perft 3: {nodes} nodes in {elapsed:.2?} ({} nps)\n",
+        per_second(nodes, elapsed)
+    ));
+
+    let started = Instant::now();
+    let mut info = SearchInfo::default();
+    for board in &boards {
+        rank_moves(board, &History::default(), depth - 1, &mut info);
+    }
+    let elapsed = started.elapsed();
+    report.push_str(&format!(
+        "Search depth {depth}: {} nodes in {elapsed:.2?} ({} nps)",
+        info.nodes,
+        per_second(info.nodes, elapsed)
+    ));
+    Ok(report)
+}
+
+/*
+    `chess-r analyse <games.pgn> <annotated.pgn> [--depth N]`. Annotates every game in a PGN file and writes them all
+    to another. Games that can't be followed are reported and left out. Returns a summary of what was written
+*/
+pub fn analyse_command(args: &[String]) -> Result<String, String> {
+    let [input, output, options @ ..] = args else {
+        return Err(ANALYSE_USAGE.to_string());
+    };
+    let depth = match options {
+        [] => DEFAULT_ANALYSE_DEPTH,
+        [flag, value] if flag == "--depth" => value
+            .parse::<i32>()
+            .map_err(|_| format!("--depth needs a number. {ANALYSE_USAGE}"))?
+            .max(1),
+        _ => return Err(ANALYSE_USAGE.to_string()),
+    };
+
+    let text = fs::read_to_string(input).map_err(|err| format!("Couldn't read {input}: {err}"))?;
+    let games = parse_games(&text);
+    let mut annotated = Vec::new();
+    let mut skipped = 0;
+
+    for (index, game) in games.iter().enumerate() {
+        // Searches past the root move, so depth 1 looks at the moves alone
+        match annotate_game(game, depth - 1) {
+            Ok(pgn) => annotated.push(pgn),
+            Err(err) => {
+                eprintln!("Skipping game {}: {err}", index + 1);
+                skipped += 1;
+            }
+        }
+    }
+
+    fs::write(output, annotated.join("\n"))
+        .map_err(|err| format!("Couldn't write {output}: {err}"))?;
+    Ok(format!(
+        "Annotated {} of {} games into {output}{}",
+        annotated.len(),
+        games.len(),
+        if skipped > 0 {
+            format!(", {skipped} skipped")
+        } else {
+            String::new()
+        }
+    ))
+}
+
+// The rules from `--variant <name>`, which are standard chess when it's left out
+pub fn variant_from_args(args: &[String]) -> Result<Variant, String> {
+    let Some(flag) = args.iter().position(|arg| arg == "--variant") else {
+        return Ok(Variant::Standard);
+    };
+    let name = args.get(flag + 1).map_or("", String::as_str);
+    Variant::from_name(name).ok_or_else(|| {
+        format!("Unknown variant {name}. Try standard, kingofthehill, threecheck, atomic, horde, racingkings or capablanca")
+    })
+}
+
+/*
+    The game clock from `--time <min+sec>` for the player and `--bot-time <min+sec>` for the opponent, so either side
+    can be given odds. Whichever is left out matches the other, and there's no clock when both are
+*/
+pub fn clock_from_args(args: &[String], player_team: Team) -> Result<Option<ChessClock>, String> {
+    let control = |flag: &str| -> Result<Option<TimeControl>, String> {
+        let Some(index) = args.iter().position(|arg| arg == flag) else {
+            return Ok(None);
+        };
+        args.get(index + 1)
+            .and_then(|text| TimeControl::parse(text))
+            .map(Some)
+            .ok_or_else(|| {
+                format!("{flag} takes minutes+seconds, like 5+3, or minutes and a delay, like 5d3")
+            })
+    };
+    let (player, bot) = match (control("--time")?, control("--bot-time")?) {
+        (None, None) => return Ok(None),
+        (player, bot) => (player.or(bot).unwrap(), bot.or(player).unwrap()),
+    };
+
+    Ok(Some(if player_team == Team::White {
+        ChessClock::new(player, bot)
+    } else {
+        ChessClock::new(bot, player)
+    }))
+}
+
+/*
+    Ada's settings from `--movetime MS`, `--hash MB`, `--threads N`, `--move-overhead MS`, `--contempt CP`,
+    `--skill 0-20` or `--elo RATING`, and `--no-book`. Anything left out keeps its default
+*/
+pub fn engine_options_from_args(args: &[String]) -> Result<EngineOptions, String> {
+    let number = |flag: &str| -> Result<Option<i64>, String> {
+        let Some(index) = args.iter().position(|arg| arg == flag) else {
+            return Ok(None);
+        };
+        args.get(index + 1)
+            .and_then(|text| text.parse::<i64>().ok())
+            .map(Some)
+            .ok_or_else(|| format!("{flag} needs a number"))
+    };
+    let mut options = EngineOptions::default();
+    if let Some(millis) = number("--movetime")? {
+        options.move_time = Duration::from_millis(millis.max(1) as u64);
+    }
+    if let Some(megabytes) = number("--hash")? {
+        options.hash_mb = megabytes.max(1) as usize;
+    }
+    if let Some(threads) = number("--threads")? {
+        options.threads = threads.max(1) as usize;
+    }
+    if let Some(millis) = number("--move-overhead")? {
+        options.move_overhead = Duration::from_millis(millis.max(0) as u64);
+    }
+    if let Some(centipawns) = number("--contempt")? {
+        options.contempt = centipawns as i32;
+    }
+    if let Some(level) = number("--skill")? {
+        if !(0..=MAX_SKILL_LEVEL as i64).contains(&level) {
+            return Err(format!("--skill goes from 0 to {MAX_SKILL_LEVEL}"));
+        }
+        options.skill_level = level as i32;
+    }
+    if let Some(elo) = number("--elo")? {
+        options.skill_level = EngineOptions::skill_for_elo(elo as i32);
+    }
+    options.book = !args.iter().any(|arg| arg == "--no-book");
+    Ok(options)
+}
+
+/*
+    The correspondence game from `--correspondence <file> [--days N]`. An existing file is picked up where it was
+    left, otherwise a new game of `variant` is saved there with `player_team` to play and N days a move
+*/
+pub fn correspondence_from_args(
+    args: &[String],
+    player_team: Team,
+    variant: Variant,
+) -> Result<Option<CorrespondenceGame>, String> {
+    let Some(flag) = args.iter().position(|arg| arg == "--correspondence") else {
+        return Ok(None);
+    };
+    let path = PathBuf::from(
+        args.get(flag + 1)
+            .ok_or("--correspondence needs a file to keep the game in")?,
+    );
+    if let Some(game) = CorrespondenceGame::load(path.clone())? {
+        return Ok(Some(game));
+    }
+
+    let days_per_move = match args.iter().position(|arg| arg == "--days") {
+        Some(index) => args
+            .get(index + 1)
+            .and_then(|days| days.parse::<i64>().ok())
+            .filter(|days| *days > 0)
+            .ok_or("--days takes a whole number of days")?,
+        None => DEFAULT_DAYS_PER_MOVE,
+    };
+    let game = CorrespondenceGame::new(path, days_per_move, player_team, variant);
+    game.save()?;
+    Ok(Some(game))
+}
+
+/*
+    `chess-r tournament <games.pgn> <entrant> <entrant>... [--rounds N]`. Plays N round-robins (one by default)
+    where every pair meets with both colours, writes the games to a PGN file and returns the crosstable
+*/
+pub fn tournament_command(args: &[String]) -> Result<String, String> {
+    let [output, rest @ ..] = args else {
+        return Err(TOURNAMENT_USAGE.to_string());
+    };
+    let (specs, cycles) = match rest {
+        [specs @ .., flag, value] if flag == "--rounds" => (
+            specs,
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("--rounds needs a number. {TOURNAMENT_USAGE}"))?
+                .max(1),
+        ),
+        specs => (specs, 1),
+    };
+    if specs.len() < 2 {
+        return Err(TOURNAMENT_USAGE.to_string());
+    }
+
+    let mut entrants = specs
+        .iter()
+        .map(|spec| parse_entrant(spec))
+        .collect::<Result<Vec<_>, String>>()?;
+    let pairings = round_robin(entrants.len(), cycles);
+    let start =
+        BoardState::from_fen(START_POS_CHESS.to_string()).map_err(|err| format!("{err}"))?;
+
+    let (crosstable, pgn) = play_tournament(
+        &mut entrants,
+        &pairings,
+        &[start],
+        Adjudication::default(),
+        &mut |pairing, game| {
+            println!(
+                "Round {}: {} - {} {}",
+                pairing.round, specs[pairing.white], specs[pairing.black], game.result
+            )
+        },
+    );
+    fs::write(output, pgn).map_err(|err| format!("Couldn't write {output}: {err}"))?;
+    Ok(format!(
+        "{crosstable}\n{} games saved to {output}",
+        pairings.len()
+    ))
+}
+
+/*
+    `chess-r match <games.pgn> <entrant> <entrant> [--games N] [--openings <suite.epd>]`. Plays N games between two
+    engines with colours alternating, each opening of the suite from both sides when there is one, writes the games
+    to a PGN file and returns the score with the Elo difference it suggests
+*/
+pub fn match_command(args: &[String]) -> Result<String, String> {
+    let [output, first, second, options @ ..] = args else {
+        return Err(MATCH_USAGE.to_string());
+    };
+    let mut games = DEFAULT_MATCH_GAMES;
+    let mut openings =
+        vec![BoardState::from_fen(START_POS_CHESS.to_string()).map_err(|err| format!("{err}"))?];
+    for option in options.chunks(2) {
+        let [flag, value] = option else {
+            return Err(MATCH_USAGE.to_string());
+        };
+        match flag.as_str() {
+            "--games" => {
+                games = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("--games needs a number. {MATCH_USAGE}"))?
+                    .max(1)
+            }
+            "--openings" => {
+                let text = fs::read_to_string(value)
+                    .map_err(|err| format!("Couldn't read {value}: {err}"))?;
+                openings = read_openings(&text)?;
+                if openings.is_empty() {
+                    return Err(format!("{value} has no openings in it"));
+                }
+            }
+            _ => return Err(format!("Unknown option {flag}. {MATCH_USAGE}")),
+        }
+    }
+
+    let mut entrants = vec![parse_entrant(first)?, parse_entrant(second)?];
+    let mut score = MatchScore::new([entrants[0].name.clone(), entrants[1].name.clone()]);
+    let (_, pgn) = play_tournament(
+        &mut entrants,
+        &match_pairings(games),
+        &openings,
+        Adjudication::default(),
+        &mut |pairing, game| {
+            score.record(pairing, game.result);
+            println!(
+                "Game {}: {} - {} {}",
+                pairing.round, score.names[pairing.white], score.names[pairing.black], game.result
+            )
+        },
+    );
+    fs::write(output, pgn).map_err(|err| format!("Couldn't write {output}: {err}"))?;
+    Ok(format!("{score}\n{games} games saved to {output}"))
+}
+
+/*
+    `chess-r tune <positions.epd> <tuned.rs> [--passes N]`. Tunes the piece values and placement tables against
+    how the games the positions came from ended, and writes the tuned ones out as Rust constants
+*/
+pub fn tune_command(args: &[String]) -> Result<String, String> {
+    let passes = match args {
+        [_, _] => DEFAULT_TUNE_PASSES,
+        [_, _, flag, value] if flag == "--passes" => value
+            .parse::<usize>()
+            .map_err(|_| format!("--passes needs a number. {TUNE_USAGE}"))?
+            .max(1),
+        _ => return Err(TUNE_USAGE.to_string()),
+    };
+    let (input, output) = (&args[0], &args[1]);
+
+    let text = fs::read_to_string(input).map_err(|err| format!("Couldn't read {input}: {err}"))?;
+    let positions = parse_positions(&text)?;
+    if positions.is_empty() {
+        return Err(format!("{input} has no positions in it"));
+    }
+    let mut weights = Weights::default();
+    let scaling = fit_scaling(&positions, &weights);
+    let start_error = tuning_error(&positions, &weights, scaling);
+    println!(
+        "Tuning on {} positions with scaling {scaling:.2}, starting error {start_error:.6}",
+        positions.len()
+    );
+    let error = tune(
+        &positions,
+        &mut weights,
+        scaling,
+        passes,
+        &mut |pass, error| println!("Pass {pass}: error {error:.6}"),
+    );
+
+    fs::write(output, write_constants(&weights))
+        .map_err(|err| format!("Couldn't write {output}: {err}"))?;
+    Ok(format!(
+        "Error went from {start_error:.6} to {error:.6}. The tuned weights are in {output}"
+    ))
+}
+
+/*
+    A chess server session from `--ics <host:port> [--handle NAME] [--password PW]`, followed by one of
+    `--seek <min+sec>`, `--accept N` or `--observe N` to get a game going. Without a handle we log in as a guest
+*/
+pub fn ics_from_args(args: &[String]) -> Result<Option<IcsSession>, String> {
+    let value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .map(|index| args.get(index + 1).ok_or(format!("{flag} needs a value")))
+            .transpose()
+    };
+    let Some(address) = value("--ics")? else {
+        return Ok(None);
+    };
+    let number = |flag: &str| {
+        value(flag)?
+            .map(|text| {
+                text.parse::<u32>()
+                    .map_err(|_| format!("{flag} takes a number"))
+            })
+            .transpose()
+    };
+
+    let handle = value("--handle")?.map_or("guest", String::as_str);
+    let mut session =
+        IcsSession::connect(address, handle, value("--password")?.map(String::as_str))?;
+    println!("Logged in as {}", session.handle);
+
+    if let Some(text) = value("--seek")? {
+        let control = TimeControl::parse(text).ok_or("--seek takes minutes+seconds, like 5+3")?;
+        session.seek(control.base.as_secs() / 60, control.increment.as_secs())?;
+    } else if let Some(offer) = number("--accept")? {
+        session.accept(offer)?;
+    } else if let Some(game_number) = number("--observe")? {
+        session.observe(game_number)?;
+    }
+    Ok(Some(session))
+}
+
+/*
+    A game against another player over the network, either hosting it with `--host <port>` or joining one with
+    `--join <host:port>`. The host's side, variant and clock are the ones played with
+*/
+pub fn netplay_from_args(args: &[String]) -> Result<Option<NetSession>, String> {
+    let value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .map(|index| args.get(index + 1).ok_or(format!("{flag} needs a value")))
+            .transpose()
+    };
+    match (value("--host")?, value("--join")?) {
+        (Some(_), Some(_)) => Err(String::from("Either --host or --join a game, not both")),
+        (Some(port), None) => {
+            let port = port
+                .parse()
+                .map_err(|_| String::from("--host takes a port number"))?;
+            let session = NetSession::host(port)?;
+            println!("Waiting for the other player on port {port}");
+            Ok(Some(session))
+        }
+        (None, Some(address)) => {
+            let session = NetSession::join(address)?;
+            println!("Joined the game at {address}");
+            Ok(Some(session))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/*
+    The engine from `--spectate <engine>` that plays the player's side while the player watches: `randy`,
+    `matt:<depth>`, `ada`, which plays with the rest of the command line's engine options, or `uci:<path>`
+*/
+pub fn spectate_from_args(
+    args: &[String],
+    engine_options: EngineOptions,
+) -> Result<Option<ChessOpponent>, String> {
+    let Some(flag) = args.iter().position(|arg| arg == "--spectate") else {
+        return Ok(None);
+    };
+    let spec = args
+        .get(flag + 1)
+        .ok_or("--spectate needs the engine to watch")?;
+    let (kind, setting) = spec.split_once(':').unwrap_or((spec, ""));
+    let engine = match kind.to_lowercase().as_str() {
+        "randy" => ChessOpponent::Randy,
+        "matt" => ChessOpponent::Matt(
+            setting
+                .parse::<i32>()
+                .map_err(|_| format!("{spec} needs a depth after the colon"))?
+                .max(1),
+        ),
+        "ada" => ChessOpponent::ada(engine_options),
+        "uci" if !setting.is_empty() => ChessOpponent::uci(PathBuf::from(setting)),
+        _ => {
+            return Err(format!(
+                "Unknown engine {spec}, try randy, matt:<depth>, ada or uci:<path>"
+            ))
+        }
+    };
+    Ok(Some(engine))
+}
+
+/*
+    Puzzles from `--puzzles <puzzles.csv>`, with the rating kept in PUZZLE_RATING_FILE unless `--puzzle-rating <file>`
+    says otherwise
+*/
+pub fn puzzles_from_args(args: &[String]) -> Result<Option<PuzzleSession>, String> {
+    let Some(flag) = args.iter().position(|arg| arg == "--puzzles") else {
+        return Ok(None);
+    };
+    let path = args
+        .get(flag + 1)
+        .ok_or("--puzzles needs a CSV file of puzzles")?;
+    let csv = fs::read_to_string(path).map_err(|err| format!("Couldn't read {path}: {err}"))?;
+    let rating_path = match args.iter().position(|arg| arg == "--puzzle-rating") {
+        Some(index) => PathBuf::from(
+            args.get(index + 1)
+                .ok_or("--puzzle-rating needs a file to keep the rating in")?,
+        ),
+        None => PathBuf::from(PUZZLE_RATING_FILE),
+    };
+    PuzzleSession::new(parse_puzzles(&csv)?, rating_path).map(Some)
+}
+
+/*
+    Drills the `--repertoire` from one side with `--train <white|black>`, keeping what's due for review in
+    REVIEW_FILE unless `--review-file <file>` says otherwise
+*/
+pub fn trainer_from_args(
+    args: &[String],
+    repertoire: Option<&Repertoire>,
+) -> Result<Option<OpeningTrainer>, String> {
+    let Some(flag) = args.iter().position(|arg| arg == "--train") else {
+        return Ok(None);
+    };
+    let team = match args
+        .get(flag + 1)
+        .map(|side| side.to_lowercase())
+        .as_deref()
+    {
+        Some("white") => Team::White,
+        Some("black") => Team::Black,
+        _ => {
+            return Err(String::from(
+                "--train takes the side to drill, white or black",
+            ))
+        }
+    };
+    let repertoire = repertoire.ok_or("--train needs a --repertoire to drill")?;
+    let review_path = match args.iter().position(|arg| arg == "--review-file") {
+        Some(index) => PathBuf::from(
+            args.get(index + 1)
+                .ok_or("--review-file needs a file to keep the reviews in")?,
+        ),
+        None => PathBuf::from(REVIEW_FILE),
+    };
+    OpeningTrainer::new(repertoire, team, ReviewSchedule::load(review_path)?).map(Some)
+}
+
+/*
+    The game to step through from `--replay <games.pgn> [--game N]`, the first in the file unless told otherwise
+*/
+pub fn replay_from_args(args: &[String]) -> Result<Option<PgnGame>, String> {
+    let Some(flag) = args.iter().position(|arg| arg == "--replay") else {
+        return Ok(None);
+    };
+    let path = args
+        .get(flag + 1)
+        .ok_or("--replay needs a PGN file to read the game from")?;
+    let game_number = match args.iter().position(|arg| arg == "--game") {
+        Some(index) => args
+            .get(index + 1)
+            .and_then(|number| number.parse::<usize>().ok())
+            .filter(|number| *number > 0)
+            .ok_or("--game takes the game's number in the file, counting from 1")?,
+        None => 1,
+    };
+
+    let text = fs::read_to_string(path).map_err(|err| format!("Couldn't read {path}: {err}"))?;
+    let mut games = parse_games(&text);
+    if game_number > games.len() {
+        return Err(format!("{path} has {} games", games.len()));
+    }
+    Ok(Some(games.swap_remove(game_number - 1)))
+}
+
+/*
+    `chess-r report <games.pgn> <report.html> [--game N] [--depth N]`. Analyses one game from a PGN file (the first
+    unless told otherwise) and writes it out as a web page that steps through the positions
+*/
+pub fn report_command(args: &[String]) -> Result<String, String> {
+    let [input, output, options @ ..] = args else {
+        return Err(REPORT_USAGE.to_string());
+    };
+    let mut depth = DEFAULT_ANALYSE_DEPTH;
+    let mut game_number = 1;
+    for option in options.chunks(2) {
+        let [flag, value] = option else {
+            return Err(REPORT_USAGE.to_string());
+        };
+        let value = value
+            .parse::<usize>()
+            .map_err(|_| format!("{flag} needs a number. {REPORT_USAGE}"))?
+            .max(1);
+        match flag.as_str() {
+            "--game" => game_number = value,
+            "--depth" => depth = value as i32,
+            _ => return Err(format!("Unknown option {flag}. {REPORT_USAGE}")),
+        }
+    }
+
+    let text = fs::read_to_string(input).map_err(|err| format!("Couldn't read {input}: {err}"))?;
+    let games = parse_games(&text);
+    let game = games
+        .get(game_number - 1)
+        .ok_or_else(|| format!("{input} has {} games", games.len()))?;
+    let start = game.start_board()?;
+    // Searches past the root move, so depth 1 looks at the moves alone
+    let analysed = analyse_moves(start, &pgn_moves(game)?, depth - 1)?;
+
+    fs::write(
+        output,
+        html_report(&game.tags, start, &analysed, &game.result),
+    )
+    .map_err(|err| format!("Couldn't write {output}: {err}"))?;
+    Ok(format!(
+        "Wrote a report on {} moves to {output}",
+        analysed.len()
+    ))
+}
@@ -0,0 +1,71 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// ggez can't see the system clipboard, so we borrow whichever clipboard tool the platform ships with.
+// Each entry is a program and the arguments that make it print the clipboard to stdout
+#[cfg(target_os = "windows")]
+const PASTE_COMMANDS: &[(&str, &[&str])] =
+    &[("powershell", &["-NoProfile", "-Command", "Get-Clipboard"])];
+#[cfg(target_os = "macos")]
+const PASTE_COMMANDS: &[(&str, &[&str])] = &[("pbpaste", &[])];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const PASTE_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("xsel", &["--clipboard", "--output"]),
+];
+// And the ones that put what they're given on stdin on the clipboard
+#[cfg(target_os = "windows")]
+const COPY_COMMANDS: &[(&str, &[&str])] = &[(
+    "powershell",
+    &["-NoProfile", "-Command", "$input | Set-Clipboard"],
+)];
+#[cfg(target_os = "macos")]
+const COPY_COMMANDS: &[(&str, &[&str])] = &[("pbcopy", &[])];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const COPY_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard", "-i"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+/*
+    Reads text from the system clipboard, or None if no clipboard tool could be run
+*/
+pub fn paste() -> Option<String> {
+    PASTE_COMMANDS.iter().find_map(|(program, args)| {
+        let output = Command::new(program).args(*args).output().ok()?;
+
+        if output.status.success() {
+            String::from_utf8(output.stdout).ok()
+        } else {
+            None
+        }
+    })
+}
+
+/*
+    Puts `text` on the system clipboard, returning whether any clipboard tool took it
+*/
+pub fn copy(text: &str) -> bool {
+    COPY_COMMANDS.iter().any(|(program, args)| {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            return false;
+        };
+        // Dropping stdin closes it, which is how the tool knows the text is complete
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+
+        child.wait().is_ok_and(|status| status.success()) && written
+    })
+}
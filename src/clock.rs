@@ -0,0 +1,148 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use crate::bitboard::Team;
+
+// Roughly how many more moves the bot expects to make, when splitting up what's left on its clock
+const EXPECTED_MOVES_LEFT: u32 = 30;
+const MIN_MOVE_BUDGET: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration, // Added after every move
+    pub delay: Duration,     // How long each move can take before the clock starts running down
+}
+impl TimeControl {
+    /*
+        Reads the usual minutes+seconds notation, so 5+3 is five minutes with three seconds a move.
+        5d3 is five minutes with a three second delay instead
+    */
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        let (minutes, seconds, is_delay) = match (text.split_once('+'), text.split_once('d')) {
+            (Some((minutes, seconds)), _) => (minutes, seconds, false),
+            (None, Some((minutes, seconds))) => (minutes, seconds, true),
+            (None, None) => (text, "0", false),
+        };
+        let seconds = Duration::from_secs(seconds.trim().parse().ok()?);
+        // This comes from other programs and saved files too, where "inf" or "NaN" must not panic the clock
+        let minutes = minutes
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|minutes| !minutes.is_nan())?;
+
+        Some(TimeControl {
+            base: Duration::try_from_secs_f32(minutes.max(0.0) * 60.0).ok()?,
+            increment: if is_delay { Duration::ZERO } else { seconds },
+            delay: if is_delay { seconds } else { Duration::ZERO },
+        })
+    }
+}
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.delay.is_zero() {
+            write!(
+                f,
+                "{}+{}",
+                self.base.as_secs_f32() / 60.0,
+                self.increment.as_secs()
+            )
+        } else {
+            write!(
+                f,
+                "{}d{}",
+                self.base.as_secs_f32() / 60.0,
+                self.delay.as_secs()
+            )
+        }
+    }
+}
+
+/*
+    A clock for each side, which can run on different time controls to give one side odds.
+    Only the side to move has its time running
+*/
+#[derive(Debug, Clone)]
+pub struct ChessClock {
+    controls: [TimeControl; 2],
+    remaining: [Duration; 2], // As of when the running side's turn started
+    running: Option<(Team, Instant)>,
+}
+impl ChessClock {
+    pub fn new(white: TimeControl, black: TimeControl) -> ChessClock {
+        ChessClock {
+            controls: [white, black],
+            remaining: [white.base, black.base],
+            running: None,
+        }
+    }
+    pub fn control(&self, team: Team) -> TimeControl {
+        self.controls[team as usize]
+    }
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+    pub fn start(&mut self, team: Team) {
+        self.running = Some((team, Instant::now()));
+    }
+    // Stops both clocks where they are, once the game is over
+    pub fn stop(&mut self) {
+        if let Some((team, _)) = self.running {
+            self.remaining[team as usize] = self.remaining(team);
+            self.running = None;
+        }
+    }
+    // Puts `team`'s clock at `remaining`, as another player's clock reported it, and runs it from there if it's running
+    pub fn set_remaining(&mut self, team: Team, remaining: Duration) {
+        self.remaining[team as usize] = remaining;
+        if let Some((running_team, started)) = self.running.as_mut() {
+            if *running_team == team {
+                *started = Instant::now();
+            }
+        }
+    }
+    // `team` finished its move, so it gets its increment and the other side's time starts
+    pub fn press(&mut self, team: Team) {
+        let left = self.remaining(team);
+        self.remaining[team as usize] = left + self.control(team).increment;
+        self.start(team.opponent());
+    }
+    // The time `team` has left. A delay is used up before any of it is
+    pub fn remaining(&self, team: Team) -> Duration {
+        match self.running {
+            Some((running_team, started)) if running_team == team => self.remaining[team as usize]
+                .saturating_sub(started.elapsed().saturating_sub(self.control(team).delay)),
+            _ => self.remaining[team as usize],
+        }
+    }
+    // The side whose time has run out, if either has
+    pub fn flagged(&self) -> Option<Team> {
+        [Team::White, Team::Black]
+            .into_iter()
+            .find(|team| self.remaining(*team).is_zero())
+    }
+    /*
+        How long `team` can afford to think about its next move: an even share of what's left over the moves still
+        to come, plus most of the increment it gets back, but never more than half its time. The delay is free on
+        top of that
+    */
+    pub fn move_budget(&self, team: Team) -> Duration {
+        let remaining = self.remaining(team);
+        let share = remaining / EXPECTED_MOVES_LEFT + self.control(team).increment * 3 / 4;
+
+        (share.min(remaining / 2) + self.control(team).delay).max(MIN_MOVE_BUDGET)
+    }
+}
+
+// Clock times as m:ss, with tenths once under a minute
+pub fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs_f32();
+    if seconds < 60.0 {
+        format!("0:{seconds:04.1}")
+    } else {
+        format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+    }
+}
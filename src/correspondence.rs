@@ -0,0 +1,144 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    bitboard::{BoardSize, Team},
+    board::{BoardState, Variant},
+    r#move::Move,
+};
+
+// Without a time budget the opponent searches this deep, however long that takes
+pub const CORRESPONDENCE_SEARCH_DEPTH: i32 = 4;
+pub const DEFAULT_DAYS_PER_MOVE: i64 = 3;
+
+/*
+    A game played over days instead of minutes. It's written to disk after every move so the app can be closed
+    between moves and picked up again later. The file is plain text, one `key value` per line
+*/
+#[derive(Debug, Clone)]
+pub struct CorrespondenceGame {
+    pub path: PathBuf,
+    pub days_per_move: i64,
+    pub player_team: Team,
+    pub variant: Variant,
+    pub start_fen: String,
+    pub moves: Vec<String>, // In UCI, so they don't depend on how SAN was disambiguated
+    pub last_move_at: DateTime<Utc>,
+}
+impl CorrespondenceGame {
+    // A game from the start of `variant`
+    pub fn new(path: PathBuf, days_per_move: i64, player_team: Team, variant: Variant) -> Self {
+        CorrespondenceGame {
+            path,
+            days_per_move,
+            player_team,
+            variant,
+            start_fen: variant.start_position().to_string(),
+            moves: Vec::new(),
+            last_move_at: Utc::now(),
+        }
+    }
+    // Picks the game back up from its file, or None when there isn't one yet
+    pub fn load(path: PathBuf) -> Result<Option<Self>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("Couldn't read {}: {err}", path.display()))?;
+        Self::parse(path, &text).map(Some)
+    }
+    pub fn parse(path: PathBuf, text: &str) -> Result<Self, String> {
+        let field = |key: &str| {
+            text.lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+                .map(str::trim)
+                .ok_or_else(|| format!("{} has no {key} line", path.display()))
+        };
+
+        let player_team = match field("player")? {
+            "White" => Team::White,
+            "Black" => Team::Black,
+            other => return Err(format!("Unknown player team {other}")),
+        };
+        // Games saved before the variant was written down were all standard chess
+        let variant = match field("variant") {
+            Ok(variant) => {
+                Variant::from_name(variant).ok_or_else(|| format!("Unknown variant {variant}"))?
+            }
+            Err(_) => Variant::Standard,
+        };
+        let game = CorrespondenceGame {
+            days_per_move: field("days")?
+                .parse()
+                .map_err(|_| String::from("days should be a whole number"))?,
+            player_team,
+            variant,
+            start_fen: field("start")?.to_string(),
+            moves: field("moves")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            last_move_at: field("last_move")?
+                .parse()
+                .map_err(|err| format!("Bad last_move time: {err}"))?,
+            path,
+        };
+        // Fails early on a file that can't be replayed, rather than partway into the game
+        game.board()?;
+        Ok(game)
+    }
+    pub fn to_text(&self) -> String {
+        format!(
+            "days {}\nplayer {:?}\nvariant {}\nstart {}\nmoves {}\nlast_move {}\n",
+            self.days_per_move,
+            self.player_team,
+            self.variant,
+            self.start_fen,
+            self.moves.join(" "),
+            self.last_move_at.to_rfc3339()
+        )
+    }
+    pub fn save(&self) -> Result<(), String> {
+        fs::write(&self.path, self.to_text())
+            .map_err(|err| format!("Couldn't save {}: {err}", self.path.display()))
+    }
+    pub fn start_board(&self) -> Result<BoardState, String> {
+        BoardState::from_fen(self.start_fen.clone())
+            .map(|board| board.with_variant(self.variant))
+            .map_err(|err| format!("Invalid start FEN ({err}): {}", self.start_fen))
+    }
+    // The start position with every move so far played on it
+    pub fn board(&self) -> Result<BoardState, String> {
+        let mut board = self.start_board()?;
+        for uci in &self.moves {
+            let played = Move::from_uci(&board, uci)
+                .ok_or_else(|| format!("Illegal move {uci} in {}", board.as_fen()))?;
+            board
+                .make_move(played)
+                .map_err(|err| format!("Couldn't play {uci}: {err:?}"))?;
+        }
+        Ok(board)
+    }
+    pub fn record_move(&mut self, played: Move, size: BoardSize) -> Result<(), String> {
+        self.moves.push(played.to_uci_on(size));
+        self.last_move_at = Utc::now();
+        self.save()
+    }
+    // Starts the file over from a new position, keeping the time control
+    pub fn restart(&mut self, start: &BoardState) -> Result<(), String> {
+        self.variant = start.variant;
+        self.start_fen = start.as_fen();
+        self.moves.clear();
+        self.last_move_at = Utc::now();
+        self.save()
+    }
+    // The side to move has until then to reply
+    pub fn deadline(&self) -> DateTime<Utc> {
+        self.last_move_at + Duration::days(self.days_per_move)
+    }
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+        now > self.deadline()
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    process::Command,
+    sync::mpsc::{self, Receiver},
+};
+
+use crate::{
+    bitboard::{PieceType, Team},
+    board::BoardState,
+    r#move::Move,
+};
+
+// Commands the board understands, each a single byte
+const DGT_SEND_RESET: u8 = 0x40;
+const DGT_SEND_BRD: u8 = 0x42;
+const DGT_SEND_UPDATE_BRD: u8 = 0x44;
+// Messages from the board have their top bit set, which is how the start of one is found
+const MESSAGE_BIT: u8 = 0x80;
+const DGT_BOARD_DUMP: u8 = MESSAGE_BIT | 0x06;
+const DGT_FIELD_UPDATE: u8 = MESSAGE_BIT | 0x0E;
+const MESSAGE_HEADER_LENGTH: usize = 3;
+const BAUD_RATE: &str = "9600";
+
+// What stands on each square of the physical board, indexed like the board's own squares
+pub type Placement = [Option<(PieceType, Team)>; 64];
+
+// The board numbers its pieces white pawn, rook, knight, bishop, king, queen and then the same for black
+pub fn piece_from_code(code: u8) -> Option<(PieceType, Team)> {
+    const PIECES: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Rook,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::King,
+        PieceType::Queen,
+    ];
+    match code {
+        1..=6 => Some((PIECES[code as usize - 1], Team::White)),
+        7..=12 => Some((PIECES[code as usize - 7], Team::Black)),
+        _ => None,
+    }
+}
+
+// The board counts its fields from a8 across to h8 and down to h1
+fn square_from_field(field: usize) -> usize {
+    (7 - field / 8) * 8 + field % 8
+}
+
+pub fn placement_of(board: &BoardState) -> Placement {
+    std::array::from_fn(|square| {
+        board
+            .get_piece_at_pos(square)
+            .map(|piece| (piece.piece_type, piece.team))
+    })
+}
+
+/*
+    Takes every whole message off the front of `received` and applies it to `placement`. A board dump replaces
+    the lot while a field update changes one square. True if anything changed
+*/
+pub fn read_messages(received: &mut Vec<u8>, placement: &mut Placement) -> bool {
+    let mut changed = false;
+    loop {
+        // Anything before the start of a message is noise from the line
+        let Some(start) = received.iter().position(|byte| byte & MESSAGE_BIT != 0) else {
+            received.clear();
+            return changed;
+        };
+        received.drain(..start);
+        if received.len() < MESSAGE_HEADER_LENGTH {
+            return changed;
+        }
+        let length = ((received[1] as usize) << 7) | received[2] as usize;
+        if length < MESSAGE_HEADER_LENGTH {
+            received.drain(..1);
+            continue;
+        }
+        if received.len() < length {
+            return changed;
+        }
+
+        let message: Vec<u8> = received.drain(..length).collect();
+        let body = &message[MESSAGE_HEADER_LENGTH..];
+        let before = *placement;
+        match message[0] {
+            DGT_BOARD_DUMP if body.len() == 64 => {
+                for (field, code) in body.iter().enumerate() {
+                    placement[square_from_field(field)] = piece_from_code(*code);
+                }
+            }
+            DGT_FIELD_UPDATE if body.len() == 2 && body[0] < 64 => {
+                placement[square_from_field(body[0] as usize)] = piece_from_code(body[1]);
+            }
+            _ => {}
+        }
+        changed |= *placement != before;
+    }
+}
+
+/*
+    The move that turns the game's position into what is standing on the physical board, if there is a legal
+    one. Pieces being lifted or halfway through a capture don't match any move, so they are waited out
+*/
+pub fn move_from_placement(board: &BoardState, placement: &Placement) -> Option<Move> {
+    board.legal_iter(board.active_team).find(|candidate| {
+        let mut after = *board;
+        after.make_move(*candidate).is_ok() && placement_of(&after) == *placement
+    })
+}
+
+/*
+    An electronic board plugged in over serial or USB. It is put into update mode, where it reports every piece
+    that is lifted or set down, and what's on it is read on its own thread and picked up with `poll`
+*/
+pub struct DgtBoard {
+    pub path: String,
+    placements: Receiver<Placement>,
+}
+impl DgtBoard {
+    pub fn open(path: &str) -> Result<DgtBoard, String> {
+        // The port has to be raw so none of the message bytes get eaten as line editing
+        let configured = Command::new("stty")
+            .args(["-F", path, BAUD_RATE, "raw", "-echo"])
+            .status();
+        if !configured.is_ok_and(|status| status.success()) {
+            tracing::warn!("Couldn't set up {path} with stty, reading it as it is");
+        }
+        let mut port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|err| format!("Couldn't open the board at {path}: {err}"))?;
+        port.write_all(&[DGT_SEND_RESET, DGT_SEND_BRD, DGT_SEND_UPDATE_BRD])
+            .map_err(|err| format!("Couldn't talk to the board at {path}: {err}"))?;
+
+        let (placement_tx, placements) = mpsc::channel();
+        std::thread::spawn(move || read_placements(port, placement_tx));
+
+        Ok(DgtBoard {
+            path: path.to_string(),
+            placements,
+        })
+    }
+    // What's on the board now, if it has changed since the last call
+    pub fn poll(&self) -> Option<Placement> {
+        self.placements.try_iter().last()
+    }
+}
+
+// Sends on what's on the board each time it changes, until the board is unplugged
+fn read_placements(mut port: File, placement_tx: mpsc::Sender<Placement>) {
+    let mut received = Vec::new();
+    let mut placement: Placement = [None; 64];
+    let mut buffer = [0; 256];
+
+    while let Ok(read) = port.read(&mut buffer) {
+        if read == 0 {
+            break;
+        }
+        received.extend_from_slice(&buffer[..read]);
+        if read_messages(&mut received, &mut placement) && placement_tx.send(placement).is_err() {
+            return;
+        }
+    }
+    eprintln!("The electronic board stopped responding");
+}
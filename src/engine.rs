@@ -0,0 +1,249 @@
+use std::{
+    io::{self, BufRead},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    bitboard::Team,
+    board::{BoardState, History},
+    clock::{ChessClock, TimeControl},
+    opponents::{transposition::SharedTable, ChessOpponent, EngineOptions, MAX_SKILL_LEVEL},
+    r#move::Move,
+    START_POS_CHESS,
+};
+
+const ENGINE_NAME: &str = "chess-r";
+const ENGINE_AUTHOR: &str = "3500pts";
+// `go infinite` and `go depth N` run until stopped, but a search left behind shouldn't hold a thread forever
+const UNLIMITED_SEARCH_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Where the engine's replies go: stdout when a GUI is listening, anywhere else in tests
+pub type UciOutput = Arc<dyn Fn(String) + Send + Sync>;
+
+/*
+    Ada speaking the Universal Chess Interface, so GUIs and tournament managers can play her against other engines.
+    Each `go` searches on a thread of its own, so `stop` and `isready` are still answered while she thinks
+*/
+pub struct UciSession {
+    board: BoardState,
+    history: History, // The moves `position` gave after its FEN
+    options: EngineOptions,
+    table: SharedTable,
+    output: UciOutput,
+    search: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+impl UciSession {
+    pub fn new(options: EngineOptions, output: UciOutput) -> UciSession {
+        UciSession {
+            board: BoardState::from_fen(START_POS_CHESS.to_string())
+                .expect("The start position is valid"),
+            history: History::default(),
+            options,
+            table: SharedTable::default(),
+            output,
+            search: None,
+        }
+    }
+    // Acts on one command from the GUI. False once it says to quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => self.identify(),
+            Some("isready") => (self.output)(String::from("readyok")),
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.table = SharedTable::default();
+            }
+            Some("position") => {
+                if let Err(err) = self.set_position(&words[1..]) {
+                    (self.output)(format!("info string {err}"));
+                }
+            }
+            Some("setoption") => {
+                if let Err(err) = self.set_option(&words[1..]) {
+                    (self.output)(format!("info string {err}"));
+                }
+            }
+            Some("go") => self.go(&words[1..]),
+            Some("stop") => self.stop_search(),
+            Some("quit") => {
+                self.stop_search();
+                return false;
+            }
+            // The protocol has unknown commands ignored
+            _ => {}
+        }
+        true
+    }
+    fn identify(&self) {
+        let defaults = self.options;
+        for line in [
+            format!("id name {ENGINE_NAME}"),
+            format!("id author {ENGINE_AUTHOR}"),
+            format!(
+                "option name Hash type spin default {} min 1 max 4096",
+                defaults.hash_mb
+            ),
+            format!(
+                "option name Threads type spin default {} min 1 max 64",
+                defaults.threads
+            ),
+            format!(
+                "option name Move Overhead type spin default {} min 0 max 5000",
+                defaults.move_overhead.as_millis()
+            ),
+            format!(
+                "option name Contempt type spin default {} min -1000 max 1000",
+                defaults.contempt
+            ),
+            format!(
+                "option name Skill Level type spin default {} min 0 max {MAX_SKILL_LEVEL}",
+                defaults.skill_level
+            ),
+            String::from("uciok"),
+        ] {
+            (self.output)(line);
+        }
+    }
+    // `position startpos|fen <FEN> [moves <move>...]`
+    fn set_position(&mut self, words: &[&str]) -> Result<(), String> {
+        let moves_at = words
+            .iter()
+            .position(|word| *word == "moves")
+            .unwrap_or(words.len());
+        let fen = match words.first().copied() {
+            Some("startpos") => START_POS_CHESS.to_string(),
+            Some("fen") => words[1..moves_at].join(" "),
+            _ => return Err(format!("Unknown position: {}", words.join(" "))),
+        };
+        let mut board = BoardState::from_fen(fen.clone())
+            .map_err(|err| format!("Invalid FEN ({err}): {fen}"))?;
+        let mut history = History::default();
+        for uci in words.iter().skip(moves_at + 1) {
+            let played = Move::from_uci(&board, uci)
+                .ok_or_else(|| format!("Illegal move {uci} in {}", board.as_fen()))?;
+            history
+                .make_move(&mut board, played)
+                .map_err(|err| format!("Couldn't play {uci}: {err:?}"))?;
+        }
+        self.board = board;
+        self.history = history;
+        Ok(())
+    }
+    // `setoption name <name> value <value>`, where names can have spaces in them
+    fn set_option(&mut self, words: &[&str]) -> Result<(), String> {
+        let value_at = words
+            .iter()
+            .position(|word| *word == "value")
+            .unwrap_or(words.len());
+        let name = words.get(1..value_at).unwrap_or_default().join(" ");
+        let value = words.get(value_at + 1..).unwrap_or_default().join(" ");
+        let number = value
+            .parse::<i64>()
+            .map_err(|_| format!("{name} needs a number, not {value}"))?;
+
+        match name.to_lowercase().as_str() {
+            "hash" => self.options.hash_mb = number.max(1) as usize,
+            "threads" => self.options.threads = number.max(1) as usize,
+            "move overhead" => {
+                self.options.move_overhead = Duration::from_millis(number.max(0) as u64)
+            }
+            "contempt" => self.options.contempt = number as i32,
+            "skill level" => {
+                self.options.skill_level = number.clamp(0, MAX_SKILL_LEVEL as i64) as i32
+            }
+            _ => return Err(format!("Unknown option {name}")),
+        }
+        Ok(())
+    }
+    /*
+        The options a `go` searches with. A fixed move time wins over the clocks, and a depth or `infinite` with
+        neither searches until it gets there or is stopped
+    */
+    fn go_options(&self, words: &[&str]) -> EngineOptions {
+        let mut options = self.options;
+        let value = |name: &str| {
+            words
+                .iter()
+                .position(|word| *word == name)
+                .and_then(|index| words.get(index + 1))
+                .and_then(|number| number.parse::<u64>().ok())
+        };
+        let millis = |name: &str| value(name).map(Duration::from_millis);
+
+        let (time, increment) = match self.board.active_team {
+            Team::Black => (millis("btime"), millis("binc")),
+            _ => (millis("wtime"), millis("winc")),
+        };
+        if let Some(move_time) = millis("movetime") {
+            options.move_time = move_time;
+        } else if let Some(base) = time {
+            let control = TimeControl {
+                base,
+                increment: increment.unwrap_or_default(),
+                delay: Duration::ZERO,
+            };
+            options.move_time =
+                ChessClock::new(control, control).move_budget(self.board.active_team);
+        } else if words.contains(&"infinite") || value("depth").is_some() {
+            options.move_time = UNLIMITED_SEARCH_TIME;
+        }
+        options.max_depth = value("depth").map(|depth| depth.max(1) as i32);
+        options
+    }
+    fn go(&mut self, words: &[&str]) {
+        self.stop_search();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut ada = ChessOpponent::Ada(self.go_options(words), self.table.clone());
+        let board = self.board;
+        let history = self.history.clone();
+        let output = self.output.clone();
+        let stop_flag = stop.clone();
+        let handle = thread::spawn(move || {
+            let outcome = ada.think_until(board, &history, stop_flag, &mut |info| {
+                output(info.to_uci())
+            });
+            // Stopped before her first iteration finished, she still has to name a move
+            let best_move = outcome.best_move.or_else(|| {
+                let legals = board.legal_moves(board.active_team);
+                legals.first().map(|packed| packed.unpack(&board))
+            });
+            output(match best_move {
+                Some(best_move) => format!("bestmove {}", best_move.to_uci()),
+                None => String::from("bestmove 0000"),
+            });
+        });
+        self.search = Some((stop, handle));
+    }
+    // Stops the search that's running, if there is one, once it has sent its best move
+    fn stop_search(&mut self) {
+        if let Some((stop, handle)) = self.search.take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = handle.join();
+        }
+    }
+}
+impl Drop for UciSession {
+    fn drop(&mut self) {
+        self.stop_search();
+    }
+}
+
+// Answers a GUI on stdin and stdout until it says to quit or goes away
+pub fn run_uci(options: EngineOptions) {
+    let mut session = UciSession::new(options, Arc::new(|line| println!("{line}")));
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !session.handle(&line) {
+            break;
+        }
+    }
+}
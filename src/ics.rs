@@ -0,0 +1,284 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use crate::{bitboard::Team, r#move::Move};
+
+const PROMPT: &str = "fics% ";
+// How long to wait for the server during login before giving up on it
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(20);
+
+// A board update in the server's style 12 format, which it sends after every move of a game we're in or watching
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style12 {
+    pub fen: String,
+    pub game_number: u32,
+    pub white: String,
+    pub black: String,
+    pub relation: i32, // 1 when it's our move, -1 when it's the opponent's, 0 when we're observing
+    pub white_time: Duration,
+    pub black_time: Duration,
+    pub last_move: Option<String>, // In SAN, None before the first move
+}
+impl Style12 {
+    pub fn is_observed(&self) -> bool {
+        self.relation == 0
+    }
+    // The team we play in this game, if we play in it
+    pub fn our_team(&self) -> Option<Team> {
+        let to_move = if self.fen.split(' ').nth(1) == Some("w") {
+            Team::White
+        } else {
+            Team::Black
+        };
+        match self.relation {
+            1 => Some(to_move),
+            -1 => Some(to_move.opponent()),
+            _ => None,
+        }
+    }
+}
+
+/*
+    Reads a `<12>` line. The board comes rank 8 first with dashes for empty squares, followed by the side to move,
+    the en passant file, castling flags, clocks, game number, players, our relation to the game and the last move
+*/
+pub fn parse_style12(line: &str) -> Option<Style12> {
+    let fields: Vec<&str> = line.trim().strip_prefix("<12> ")?.split(' ').collect();
+    if fields.len() < 30 {
+        return None;
+    }
+
+    let mut placement = Vec::new();
+    for rank in &fields[0..8] {
+        let mut row = String::new();
+        let mut empty = 0;
+        for square in rank.chars() {
+            if square == '-' {
+                empty += 1;
+                continue;
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+                empty = 0;
+            }
+            row.push(square);
+        }
+        if empty > 0 {
+            row.push_str(&empty.to_string());
+        }
+        placement.push(row);
+    }
+
+    let white_to_move = fields[8] == "W";
+    let en_passant = match fields[9].parse::<i32>().ok()? {
+        file @ 0..=7 => format!(
+            "{}{}",
+            (b'a' + file as u8) as char,
+            if white_to_move { 6 } else { 3 }
+        ),
+        _ => String::from("-"),
+    };
+    let castling: String = ["K", "Q", "k", "q"]
+        .iter()
+        .zip(&fields[10..14])
+        .filter(|(_, flag)| **flag == "1")
+        .map(|(right, _)| *right)
+        .collect();
+    let seconds =
+        |field: &str| Duration::from_secs(field.parse::<i64>().unwrap_or(0).max(0) as u64);
+
+    Some(Style12 {
+        fen: format!(
+            "{} {} {} {} {} {}",
+            placement.join("/"),
+            if white_to_move { "w" } else { "b" },
+            if castling.is_empty() { "-" } else { &castling },
+            en_passant,
+            fields[14],
+            fields[25]
+        ),
+        game_number: fields[15].parse().ok()?,
+        white: fields[16].to_string(),
+        black: fields[17].to_string(),
+        relation: fields[18].parse().ok()?,
+        white_time: seconds(fields[23]),
+        black_time: seconds(fields[24]),
+        last_move: Some(fields[28].to_string()).filter(|san| san != "none"),
+    })
+}
+
+// Anything the server tells us that the board cares about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcsEvent {
+    Board(Style12),
+    GameOver {
+        game_number: u32,
+        reason: String,
+        result: String,
+    },
+    Text(String), // Everything else, for the console
+    Disconnected,
+}
+
+// `{Game 12 (GuestA vs. GuestB) GuestA resigns} 0-1`
+pub fn parse_game_over(line: &str) -> Option<IcsEvent> {
+    let rest = line.trim().strip_prefix("{Game ")?;
+    let (game_number, rest) = rest.split_once(' ')?;
+    let (_players, rest) = rest.split_once(") ")?;
+    let (reason, result) = rest.split_once("} ")?;
+    let result = result.trim();
+    if !["1-0", "0-1", "1/2-1/2", "*"].contains(&result) {
+        return None;
+    }
+
+    Some(IcsEvent::GameOver {
+        game_number: game_number.parse().ok()?,
+        reason: reason.to_string(),
+        result: result.to_string(),
+    })
+}
+
+fn parse_line(line: &str) -> IcsEvent {
+    parse_style12(line)
+        .map(IcsEvent::Board)
+        .or_else(|| parse_game_over(line))
+        .unwrap_or_else(|| IcsEvent::Text(line.to_string()))
+}
+
+/*
+    A logged in session with a FICS-compatible chess server. Commands go straight out over the connection, while
+    what the server sends back is read on its own thread and picked up with `poll`
+*/
+pub struct IcsSession {
+    pub handle: String, // The name the server gave us, which for guests isn't the one we asked for
+    stream: TcpStream,
+    events: Receiver<IcsEvent>,
+}
+impl IcsSession {
+    /*
+        Logs in as `handle`. Registered handles need their password, anything else is let in as a guest. Board
+        updates are switched to style 12 before the session is handed back
+    */
+    pub fn connect(
+        address: &str,
+        handle: &str,
+        password: Option<&str>,
+    ) -> Result<IcsSession, String> {
+        let mut stream = TcpStream::connect(address)
+            .map_err(|err| format!("Couldn't reach {address}: {err}"))?;
+        let fail = |err: std::io::Error| format!("Lost {address} while logging in: {err}");
+        stream.set_read_timeout(Some(LOGIN_TIMEOUT)).map_err(fail)?;
+
+        read_until_any(&mut stream, &["login: "]).map_err(fail)?;
+        send_line(&mut stream, handle).map_err(fail)?;
+        let (asked, _) =
+            read_until_any(&mut stream, &["password: ", "\":", PROMPT]).map_err(fail)?;
+        let mut greeting = String::new();
+        if asked != PROMPT {
+            // Guests are asked to press return, everyone else for their password
+            send_line(
+                &mut stream,
+                password.filter(|_| asked == "password: ").unwrap_or(""),
+            )
+            .map_err(fail)?;
+            greeting = read_until_any(&mut stream, &[PROMPT, "login: "])
+                .map_err(fail)?
+                .1;
+            if greeting.ends_with("login: ") {
+                return Err(format!("{address} turned down the login for {handle}"));
+            }
+        }
+        let logged_in_as = greeting
+            .split_once("Starting FICS session as ")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            // Guests get an unregistered marker on the end of their name
+            .map(|name| name.split('(').next().unwrap_or(name))
+            .unwrap_or(handle)
+            .to_string();
+
+        for setting in ["set style 12", "set bell 0", "set seek 0"] {
+            send_line(&mut stream, setting).map_err(fail)?;
+        }
+        stream.set_read_timeout(None).map_err(fail)?;
+        let reader = stream.try_clone().map_err(fail)?;
+        let (event_tx, events) = mpsc::channel();
+        std::thread::spawn(move || read_events(reader, event_tx));
+
+        Ok(IcsSession {
+            handle: logged_in_as,
+            stream,
+            events,
+        })
+    }
+    pub fn send(&mut self, command: &str) -> Result<(), String> {
+        send_line(&mut self.stream, command).map_err(|err| format!("Lost the server: {err}"))
+    }
+    // Offers a game at this many minutes with this many seconds a move
+    pub fn seek(&mut self, minutes: u64, increment: u64) -> Result<(), String> {
+        self.send(&format!("seek {minutes} {increment}"))
+    }
+    // Takes up someone else's offer of a game, by its number in the seek list
+    pub fn accept(&mut self, offer: u32) -> Result<(), String> {
+        self.send(&format!("play {offer}"))
+    }
+    pub fn observe(&mut self, game_number: u32) -> Result<(), String> {
+        self.send(&format!("observe {game_number}"))
+    }
+    // The server takes moves in coordinate notation
+    pub fn send_move(&mut self, played: Move) -> Result<(), String> {
+        self.send(&played.to_uci())
+    }
+    // Everything the server has sent since the last call
+    pub fn poll(&self) -> Vec<IcsEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+fn send_line(stream: &mut TcpStream, line: &str) -> std::io::Result<()> {
+    stream.write_all(format!("{line}\n").as_bytes())
+}
+
+/*
+    Reads until the text ends with one of `endings`, which is how prompts that don't end their line are spotted.
+    Returns the ending that turned up and everything read
+*/
+fn read_until_any(stream: &mut TcpStream, endings: &[&str]) -> std::io::Result<(String, String)> {
+    let mut text = Vec::new();
+    let mut byte = [0; 1];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        text.push(byte[0]);
+        let read = String::from_utf8_lossy(&text);
+        if let Some(ending) = endings.iter().find(|ending| read.ends_with(**ending)) {
+            return Ok((ending.to_string(), read.into_owned()));
+        }
+    }
+}
+
+// Turns the server's output into events until it hangs up. Lines end in \n\r and prompts are stripped off them
+fn read_events(mut stream: TcpStream, event_tx: mpsc::Sender<IcsEvent>) {
+    let mut pending = String::new();
+    let mut buffer = [0; 4096];
+
+    while let Ok(read) = stream.read(&mut buffer) {
+        if read == 0 {
+            break;
+        }
+        pending.push_str(&String::from_utf8_lossy(&buffer[..read]));
+        while let Some(end) = pending.find('\n') {
+            let line: String = pending.drain(..=end).collect();
+            let line = line.trim_matches(|c: char| c == '\r' || c == '\n');
+            let line = line.trim_start_matches(PROMPT).trim();
+            if !line.is_empty() && event_tx.send(parse_line(line)).is_err() {
+                return;
+            }
+        }
+    }
+    let _ = event_tx.send(IcsEvent::Disconnected);
+}
@@ -1,28 +1,50 @@
 #![feature(int_roundings)]
-#![feature(try_find)]
-#![feature(mpmc_channel)]
-#![feature(string_remove_matches)]
-#![feature(iter_array_chunks)]
 //#![windows_subsystem = "windows"]
 
+pub mod analysis;
+pub mod arena;
+pub mod audio;
+pub mod autosave;
 pub mod bitboard;
 pub mod board;
+pub mod cli;
+pub mod clipboard;
+pub mod clock;
+pub mod correspondence;
+pub mod dgt;
+pub mod engine;
+pub mod ics;
 pub mod r#move;
+pub mod netplay;
+pub mod openings;
 pub mod opponents;
+pub mod pgn;
+pub mod pieces;
+pub mod puzzle;
+pub mod repertoire;
+pub mod report;
 pub mod rules;
+pub mod settings;
+pub mod tactics;
+pub mod three_player;
+pub mod trainer;
+pub mod tune;
 pub mod ui;
+pub mod voice;
 const START_POS_CHESS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
-use std::time::Duration;
-
+use arena::Adjudication;
 use bitboard::Team;
 use board::BoardState;
+use dgt::DgtBoard;
 use ggez::conf::{WindowMode, WindowSetup};
 use ggez::event;
 use opponents::*;
 use rand::random_range;
+use repertoire::Repertoire;
 use tracing_subscriber::EnvFilter;
 use ui::MainState;
+use voice::VoiceInput;
 
 #[tokio::main]
 async fn main() {
@@ -32,21 +54,179 @@ async fn main() {
         Team::White
     };
 
-    let board_full_test = BoardState::from_fen(String::from(START_POS_CHESS))
-        .expect("Failed to create board from FEN");
+    let args: Vec<String> = std::env::args().collect();
+
+    // `--variant <name>` plays one of the variants instead of standard chess
+    let variant = cli::variant_from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+    let board_full_test = BoardState::from_fen(String::from(variant.start_position()))
+        .expect("Failed to create board from FEN")
+        .with_variant(variant);
 
-    let filter = EnvFilter::builder()
+    // `--log-level <levels>` turns logging up or down, for everything or subsystem by subsystem
+    let directives = cli::log_directives_from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+    let mut filter = EnvFilter::builder()
         .from_env()
-        .expect("Failed to build envfilter")
-        .add_directive(
-            "chess_r=warn"
-                .parse()
-                .expect("Failed to parse tracing directive"),
-        );
+        .expect("Failed to build envfilter");
+    for directive in directives {
+        match directive.parse() {
+            Ok(directive) => filter = filter.add_directive(directive),
+            Err(err) => {
+                eprintln!("Bad log level {directive}: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
 
     let sub_builder = tracing_subscriber::fmt().with_env_filter(filter);
 
-    sub_builder.compact().init();
+    // A GUI reads the engine's stdout, so in UCI mode the logs have to keep out of it
+    if args.iter().any(|arg| arg == "--uci") {
+        sub_builder.with_writer(std::io::stderr).compact().init();
+    } else {
+        sub_builder.compact().init();
+    }
+
+    let command = match args.get(1).map(String::as_str) {
+        Some("eval") => Some(cli::eval_command as fn(&[String]) -> Result<String, String>),
+        Some("perft") => Some(cli::perft_command as fn(&[String]) -> Result<String, String>),
+        Some("bench") => Some(cli::bench_command as fn(&[String]) -> Result<String, String>),
+        Some("analyse") => Some(cli::analyse_command as fn(&[String]) -> Result<String, String>),
+        Some("report") => Some(cli::report_command as fn(&[String]) -> Result<String, String>),
+        Some("tournament") => {
+            Some(cli::tournament_command as fn(&[String]) -> Result<String, String>)
+        }
+        Some("match") => Some(cli::match_command as fn(&[String]) -> Result<String, String>),
+        Some("tune") => Some(cli::tune_command as fn(&[String]) -> Result<String, String>),
+        _ => None,
+    };
+    if let Some(command) = command {
+        match command(&args[2..]) {
+            Ok(report) => println!("{report}"),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Plays the bots against each other in the terminal instead of opening the board
+    if args.iter().any(|arg| arg == "--selfplay") {
+        let game = arena::play_game(
+            &mut ChessOpponent::ada(EngineOptions::default()),
+            &mut ChessOpponent::Matt(2),
+            board_full_test,
+            Adjudication::default(),
+        );
+        println!(
+            "{} {}{}",
+            game.moves.join(" "),
+            game.result,
+            if game.adjudicated {
+                " (adjudicated)"
+            } else {
+                ""
+            }
+        );
+        for (team, stats) in [Team::White, Team::Black].iter().zip(game.stats) {
+            println!("{team:?} engine:\n{stats}");
+        }
+        return;
+    }
+
+    // `--repertoire <file.pgn>` has the opponent play the file's lines and shows them while in them
+    let repertoire = match args.iter().position(|arg| arg == "--repertoire") {
+        Some(flag) => {
+            let path = args.get(flag + 1).map(String::as_str).unwrap_or_default();
+            let loaded = std::fs::read_to_string(path)
+                .map_err(|err| format!("Couldn't read repertoire {path}: {err}"))
+                .and_then(|text| Repertoire::from_pgn(&text));
+            match loaded {
+                Ok(repertoire) if repertoire.is_empty() => {
+                    eprintln!("Repertoire {path} has no moves in it");
+                    None
+                }
+                Ok(repertoire) => {
+                    println!(
+                        "Loaded a repertoire covering {} positions",
+                        repertoire.len()
+                    );
+                    Some(repertoire)
+                }
+                Err(err) => {
+                    eprintln!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    // `--train <white|black>` drills the repertoire's lines for that side, each coming back as it's due for review
+    let trainer = cli::trainer_from_args(&args, repertoire.as_ref()).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    // `--movetime`, `--hash`, `--skill` and the rest tune how Ada plays
+    let engine_options = cli::engine_options_from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    // `--uci` hands Ada to a GUI or tournament manager that speaks UCI on stdin and stdout
+    if args.iter().any(|arg| arg == "--uci") {
+        engine::run_uci(engine_options);
+        return;
+    }
+
+    let clock = cli::clock_from_args(&args, player_team).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    // `--correspondence <file>` plays a days-per-move game that is saved after every move
+    let correspondence =
+        cli::correspondence_from_args(&args, player_team, variant).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            std::process::exit(1);
+        });
+
+    // `--ics <host:port>` plays and watches games on a FICS-compatible server instead of against our opponents
+    let ics = cli::ics_from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    // `--host <port>` and `--join <host:port>` play against another player running the program elsewhere
+    let netplay = cli::netplay_from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    // `--replay <games.pgn> [--game N]` opens a finished game to step through instead of starting a new one
+    let replay = cli::replay_from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    // `--puzzles <puzzles.csv>` plays through puzzles in lichess's layout instead of games
+    let puzzles = cli::puzzles_from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    // `--spectate <engine>` hands the player's side to an engine too, so the two play while the player watches
+    let spectated = cli::spectate_from_args(&args, engine_options).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
 
     let cb = ggez::ContextBuilder::new("chess-r", "3500pts")
         .window_setup(WindowSetup {
@@ -57,19 +237,127 @@ async fn main() {
             vsync: true,
         })
         .window_mode(
+            // The board, the panel beside it and the move list after that, scaled to fit if the window is resized
             WindowMode::default()
-                .resizable(false)
-                .max_dimensions(800.0, 800.0),
+                .dimensions(ui::WINDOW_WIDTH, ui::WINDOW_HEIGHT)
+                .resizable(true)
+                .min_dimensions(ui::WINDOW_WIDTH / 2.0, ui::WINDOW_HEIGHT / 2.0),
         );
 
     let (mut ctx, event_loop) = cb.build().unwrap();
 
-    let state: MainState = MainState::new(
-        board_full_test,
-        &mut ctx,
-        player_team,
-        ChessOpponent::Ada(Duration::from_millis(400)),
-    )
-    .unwrap();
+    // `--engine <path>` plays against another engine, like Stockfish, instead of Ada,
+    // and `--hot-seat` against a second player at the same machine
+    let opponent = match args
+        .iter()
+        .position(|arg| arg == "--engine")
+        .map(|flag| args.get(flag + 1))
+    {
+        Some(Some(path)) => ChessOpponent::uci(std::path::PathBuf::from(path)),
+        Some(None) => {
+            eprintln!("--engine needs the path to a UCI engine");
+            std::process::exit(1);
+        }
+        None if args.iter().any(|arg| arg == "--hot-seat") => ChessOpponent::Human,
+        None => ChessOpponent::ada(engine_options),
+    };
+
+    let mut state: MainState =
+        MainState::new(board_full_test, &mut ctx, player_team, opponent).unwrap();
+    state.repertoire = repertoire;
+    state.game.clock = clock;
+    state.ics = ics;
+    state.netplay = netplay;
+    state.game.spectated = spectated;
+    state.puzzles = puzzles;
+    state.trainer = trainer;
+    // The board colours and piece set picked on the settings menu last time
+    if let Err(err) = state.load_settings(&mut ctx) {
+        eprintln!("{err}");
+    }
+    // `--report <file.html>` writes an analysed report of the game when it ends
+    state.report_path = args
+        .iter()
+        .position(|arg| arg == "--report")
+        .and_then(|flag| args.get(flag + 1))
+        .map(std::path::PathBuf::from);
+    // `--eval-graph <file.svg>` writes the graph of the game's eval shown when it ends as an image
+    state.eval_graph_path = args
+        .iter()
+        .position(|arg| arg == "--eval-graph")
+        .and_then(|flag| args.get(flag + 1))
+        .map(std::path::PathBuf::from);
+    // `--sound-pack <name>` plays the sounds from resources/sounds/<name> instead of the default ones
+    if let Some(pack) = args
+        .iter()
+        .position(|arg| arg == "--sound-pack")
+        .and_then(|flag| args.get(flag + 1))
+    {
+        state.audio.load_pack(&mut ctx, pack);
+    }
+    // `--dgt <port>` reads the player's moves off an electronic board plugged in at that serial port
+    if let Some(port) = args
+        .iter()
+        .position(|arg| arg == "--dgt")
+        .and_then(|flag| args.get(flag + 1))
+    {
+        match DgtBoard::open(port) {
+            Ok(board) => state.dgt = Some(board),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+    // `--voice "<recogniser>"` plays moves that are spoken, as heard by a program that writes out each phrase
+    if let Some(command) = args
+        .iter()
+        .position(|arg| arg == "--voice")
+        .and_then(|flag| args.get(flag + 1))
+    {
+        match VoiceInput::launch(command) {
+            Ok(voice) => state.voice = Some(voice),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+    // A new game is set up on the menu first, starting from whatever the command line chose, unless there's one
+    // left unfinished last time to pick back up. Resumed and replayed games, and games on a server or over the
+    // network, already know how they're played
+    if correspondence.is_none()
+        && replay.is_none()
+        && state.ics.is_none()
+        && state.netplay.is_none()
+        && state.puzzles.is_none()
+        && state.trainer.is_none()
+    {
+        state.offer_resume();
+    }
+    // `--three-player` goes straight to a three-player game instead, which is never saved to be picked back up
+    if args.iter().any(|arg| arg == "--three-player") {
+        state.start_three_player();
+    }
+    // A run of puzzles or a drill starts straight into the first one
+    if let Err(err) = state
+        .next_puzzle()
+        .and_then(|()| state.next_training_line())
+    {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+    if let Some(correspondence) = correspondence {
+        if let Err(err) = state.resume_correspondence(correspondence) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+    if let Some(game) = replay {
+        if let Err(err) = state.start_replay(game) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
     event::run(ctx, event_loop, state);
 }
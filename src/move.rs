@@ -1,13 +1,16 @@
 use std::{
-    fmt::{Display, Formatter},
-    ops::{Add, Sub},
+    fmt::{Debug, Display, Formatter},
+    ops::{Deref, DerefMut},
 };
 
 use bitvec::{order::Lsb0, slice::BitSlice, view::BitView};
 
 use crate::{
-    bitboard::{Bitboard, PieceType, Team},
-    board::BoardState,
+    bitboard::{Bitboard, BoardSize, PieceType, Team, BOARD_SIZES, MAX_SQUARES},
+    board::{compute_edges, BoardState, GameStatus, Variant},
+    pieces::{
+        definition, definition_attacks, is_fairy, piece_from_letter, KING_STEPS, KNIGHT_STEPS,
+    },
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -24,6 +27,45 @@ pub struct Move {
     pub captures: Option<Piece>,
     pub is_pawn_double: bool, // en passant tracker
     pub is_castle: bool,
+    pub promotion: Option<PieceType>, // What a pawn reaching the last rank becomes
+}
+
+/*
+    What a pawn can promote to, best first so looking a move up by its squares alone finds the queen. Capablanca's
+    chancellor and archbishop come last, as only a board wide enough for them offers them, see promotion_choices
+*/
+pub const PROMOTION_CHOICES: [PieceType; 6] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Chancellor,
+    PieceType::Archbishop,
+];
+// The pieces a pawn can promote to on a `size` board
+pub fn promotion_choices(size: BoardSize) -> &'static [PieceType] {
+    match size {
+        BoardSize::Standard => &PROMOTION_CHOICES[..4],
+        BoardSize::TenByEight => &PROMOTION_CHOICES,
+    }
+}
+
+// Whether a `team` pawn arriving on `square` of a `size` board promotes there
+pub fn promotes_on(size: BoardSize, team: Team, square: usize) -> bool {
+    match team {
+        Team::White => size.rank(square) == size.last_rank(),
+        Team::Black => size.rank(square) == 0,
+        _ => false,
+    }
+}
+// The letter SAN writes after the = of a promotion, lowercased for UCI
+fn promotion_letter(piece_type: PieceType) -> Option<char> {
+    PROMOTION_CHOICES
+        .contains(&piece_type)
+        .then(|| definition(piece_type).letter)
+}
+fn promotion_from_letter(letter: char) -> Option<PieceType> {
+    piece_from_letter(letter).filter(|piece_type| PROMOTION_CHOICES.contains(piece_type))
 }
 impl Move {
     fn set_start(&self, pos: usize) -> Self {
@@ -31,6 +73,154 @@ impl Move {
         clone.start = pos;
         clone
     }
+
+    // Long algebraic coordinates as UCI expects them, e.g. g1f3, with the piece a pawn promotes to after, e.g. e7e8q
+    pub fn to_uci(&self) -> String {
+        self.to_uci_on(BoardSize::Standard)
+    }
+    // The same coordinates for a move on a `size` board, where the files run on past h
+    pub fn to_uci_on(&self, size: BoardSize) -> String {
+        let mut uci = format!(
+            "{}{}",
+            size.notation(self.start).unwrap_or_default(),
+            size.notation(self.target).unwrap_or_default()
+        );
+        if let Some(letter) = self.promotion.and_then(promotion_letter) {
+            uci.push(letter.to_ascii_lowercase());
+        }
+        uci
+    }
+
+    /*
+        Writes the move in standard algebraic notation as it would be played from `board`
+    */
+    pub fn to_san(&self, board: &BoardState) -> String {
+        let piece_type = board.piece_list[self.start];
+        let mut san = String::new();
+
+        if self.is_castle {
+            san.push_str(if self.target < self.start {
+                "O-O-O"
+            } else {
+                "O-O"
+            });
+        } else {
+            let start_notation = board.size.notation(self.start).unwrap_or_default();
+            let (start_file, start_rank) = start_notation.split_at(1);
+
+            if !matches!(piece_type, PieceType::Pawn | PieceType::None) {
+                san.push(definition(piece_type).letter);
+            }
+
+            if piece_type == PieceType::Pawn {
+                if self.captures.is_some() {
+                    san.push_str(start_file);
+                }
+            } else {
+                // Only disambiguate against pieces of the same type that can legally reach the same square
+                let team_moves = board.prune_moves_for_team(
+                    board.get_legal_moves(),
+                    board.get_square_team(self.start),
+                );
+                let rivals: Vec<&Move> = team_moves
+                    .iter()
+                    .filter(|other| {
+                        other.target == self.target
+                            && other.start != self.start
+                            && board.piece_list[other.start] == piece_type
+                    })
+                    .collect();
+
+                if !rivals.is_empty() {
+                    let shares_file = rivals
+                        .iter()
+                        .any(|other| board.size.file(other.start) == board.size.file(self.start));
+                    let shares_rank = rivals
+                        .iter()
+                        .any(|other| board.size.rank(other.start) == board.size.rank(self.start));
+
+                    if !shares_file {
+                        san.push_str(start_file);
+                    } else if !shares_rank {
+                        san.push_str(start_rank);
+                    } else {
+                        san.push_str(&start_notation);
+                    }
+                }
+            }
+
+            if self.captures.is_some() {
+                san.push('x');
+            }
+            san.push_str(&board.size.notation(self.target).unwrap_or_default());
+            if let Some(letter) = self.promotion.and_then(promotion_letter) {
+                san.push('=');
+                san.push(letter);
+            }
+        }
+
+        let mut board_after = *board;
+        if board_after.make_move(*self).is_ok()
+            && board_after.is_team_checked(board_after.active_team)
+        {
+            san.push(if let GameStatus::Checkmate(_) = board_after.status() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+
+        san
+    }
+
+    /*
+        Finds the legal move for the active team written in UCI coordinates, e.g. g1f3. A promotion without its
+        fifth letter is taken as a queen
+    */
+    pub fn from_uci(board: &BoardState, uci: &str) -> Option<Move> {
+        let uci = uci.trim();
+        if !(4..=5).contains(&uci.len()) || !uci.is_ascii() {
+            return None;
+        }
+        let start = board.size.square_at(&uci[0..2])?;
+        let target = board.size.square_at(&uci[2..4])?;
+        let promotion = match uci[4..].chars().next() {
+            Some(letter) => Some(promotion_from_letter(letter)?),
+            None => None,
+        };
+
+        board
+            .prune_moves_for_team(board.get_legal_moves(), board.active_team)
+            .into_iter()
+            .find(|legal_move| {
+                legal_move.start == start
+                    && legal_move.target == target
+                    && (promotion.is_none() || legal_move.promotion == promotion)
+            })
+    }
+
+    /*
+        Finds the legal move for the active team written in standard algebraic notation, e.g. Nf3.
+        Check marks, annotations and the = before a promotion are optional, and castling can be written with zeros
+    */
+    pub fn from_san(board: &BoardState, san: &str) -> Option<Move> {
+        let strip = |notation: &str| {
+            notation
+                .trim()
+                .trim_end_matches(['+', '#', '!', '?'])
+                .replace('0', "O")
+                .replace('=', "")
+        };
+        let wanted = strip(san);
+        if wanted.is_empty() {
+            return None;
+        }
+
+        board
+            .prune_moves_for_team(board.get_legal_moves(), board.active_team)
+            .into_iter()
+            .find(|legal_move| strip(&legal_move.to_san(board)) == wanted)
+    }
 }
 impl Display for Move {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -41,28 +231,356 @@ impl Display for Move {
         if let Some(alno) = Bitboard::bit_idx_to_al_notation(self.target) {
             f.write_str(&alno)?;
         }
+        if let Some(letter) = self.promotion.and_then(promotion_letter) {
+            write!(f, "={letter}")?;
+        }
         Ok(())
     }
 }
+/*
+    A move packed into 32 bits: the start square in the low seven, the target in the next seven, so a 10x8 board's
+    80 squares fit, then the flags. A pawn's double step and castling have a bit each, and a promotion sets its own
+    bit and keeps its index into PROMOTION_CHOICES in the three above it. What it captures is left for the board to
+    say when it's unpacked
+*/
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct PackedMove(u32);
+
+const SQUARE_BITS: u32 = 0b111_1111;
+const TARGET_SHIFT: u32 = 7;
+const PAWN_DOUBLE_FLAG: u32 = 1 << 14;
+const CASTLE_FLAG: u32 = 1 << 15;
+const PROMOTION_FLAG: u32 = 1 << 16;
+const PROMOTION_SHIFT: u32 = 17;
+
+impl PackedMove {
+    pub fn new(
+        start: usize,
+        target: usize,
+        is_pawn_double: bool,
+        is_castle: bool,
+        promotion: Option<PieceType>,
+    ) -> Self {
+        let mut packed = start as u32 | (target as u32) << TARGET_SHIFT;
+        if is_pawn_double {
+            packed |= PAWN_DOUBLE_FLAG;
+        }
+        if is_castle {
+            packed |= CASTLE_FLAG;
+        }
+        if let Some(index) = promotion.and_then(|promoted| {
+            PROMOTION_CHOICES
+                .iter()
+                .position(|choice| *choice == promoted)
+        }) {
+            packed |= PROMOTION_FLAG | (index as u32) << PROMOTION_SHIFT;
+        }
+        PackedMove(packed)
+    }
+    pub fn start(self) -> usize {
+        (self.0 & SQUARE_BITS) as usize
+    }
+    pub fn target(self) -> usize {
+        (self.0 >> TARGET_SHIFT & SQUARE_BITS) as usize
+    }
+    pub fn is_pawn_double(self) -> bool {
+        self.0 & PAWN_DOUBLE_FLAG != 0
+    }
+    pub fn is_castle(self) -> bool {
+        self.0 & CASTLE_FLAG != 0
+    }
+    pub fn promotion(self) -> Option<PieceType> {
+        (self.0 & PROMOTION_FLAG != 0)
+            .then(|| PROMOTION_CHOICES[(self.0 >> PROMOTION_SHIFT & 0b111) as usize])
+    }
+    // Whether it takes a piece on `board`, the position it was generated for
+    pub fn is_capture(self, board: &BoardState) -> bool {
+        !self.is_castle() && board.piece_list[self.target()] != PieceType::None
+    }
+    // The full move as it would be played on `board`, the position it was generated for
+    pub fn unpack(self, board: &BoardState) -> Move {
+        Move {
+            start: self.start(),
+            target: self.target(),
+            captures: board.captured_by(self.start(), self.target()),
+            is_pawn_double: self.is_pawn_double(),
+            is_castle: self.is_castle(),
+            promotion: self.promotion(),
+        }
+    }
+}
+impl From<Move> for PackedMove {
+    fn from(r#move: Move) -> Self {
+        PackedMove::new(
+            r#move.start,
+            r#move.target,
+            r#move.is_pawn_double,
+            r#move.is_castle,
+            r#move.promotion,
+        )
+    }
+}
+
+// Standard chess tops out at 218 moves in a position, and made-up positions full of compound pieces can go past this
+pub const MAX_MOVES: usize = 256;
+
+/*
+    A position's moves in a fixed-size array, so generating them doesn't allocate. The rare position with more moves
+    than that has them all moved onto the heap once the array is full. It derefs to a slice of the moves pushed so far
+*/
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [PackedMove; MAX_MOVES],
+    len: usize,
+    spilled: Vec<PackedMove>, // Every move, once there are more than MAX_MOVES. Empty until then
+}
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList {
+            moves: [PackedMove::default(); MAX_MOVES],
+            len: 0,
+            spilled: Vec::new(),
+        }
+    }
+}
+impl MoveList {
+    pub fn push(&mut self, packed: PackedMove) {
+        if self.len < MAX_MOVES {
+            self.moves[self.len] = packed;
+        } else {
+            if self.spilled.is_empty() {
+                self.spilled.extend_from_slice(&self.moves);
+            }
+            self.spilled.push(packed);
+        }
+        self.len += 1;
+    }
+}
+impl Deref for MoveList {
+    type Target = [PackedMove];
+
+    fn deref(&self) -> &[PackedMove] {
+        if self.len > MAX_MOVES {
+            &self.spilled
+        } else {
+            &self.moves[..self.len]
+        }
+    }
+}
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [PackedMove] {
+        if self.len > MAX_MOVES {
+            &mut self.spilled
+        } else {
+            &mut self.moves[..self.len]
+        }
+    }
+}
+impl Debug for MoveList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a PackedMove;
+    type IntoIter = std::slice::Iter<'a, PackedMove>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum MoveError {
     AttackedAlly,
     NoUnit,
     NotAMove,
 }
-// Should match [compute_edges] from board.rs exactly in direction
-const DIRECTION_OFFSETS: [i32; 8] = [
+
+// Why a move the player tried isn't allowed, so it can be explained instead of just refused
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IllegalMoveReason {
+    NotYourTurn,
+    NotYourPiece,
+    NoPiece,
+    CannotMoveThere,
+    PiecePinned { pinned_by: usize },
+    KingInCheck,         // The king is in check and the move doesn't get it out
+    KingWouldBeAttacked, // The king would walk into an attack
+    CastlingRightsLost,
+    CastlingBlocked,
+    CastlingOutOfCheck,
+    CastlingPathAttacked { square: usize },
+}
+impl IllegalMoveReason {
+    // The reason as a sentence, naming any square in question by where it is on a `size` board
+    pub fn describe(&self, size: BoardSize) -> String {
+        let square_name = |square: usize| size.notation(square).unwrap_or_default();
+
+        match self {
+            IllegalMoveReason::NotYourTurn => String::from("It's not your turn"),
+            IllegalMoveReason::NotYourPiece => String::from("That's not your piece"),
+            IllegalMoveReason::NoPiece => String::from("There's no piece there"),
+            IllegalMoveReason::CannotMoveThere => String::from("That piece can't move there"),
+            IllegalMoveReason::PiecePinned { pinned_by } => {
+                format!(
+                    "That piece is pinned to the king by {}",
+                    square_name(*pinned_by)
+                )
+            }
+            IllegalMoveReason::KingInCheck => String::from("Your king would still be in check"),
+            IllegalMoveReason::KingWouldBeAttacked => {
+                String::from("Your king would be in check there")
+            }
+            IllegalMoveReason::CastlingRightsLost => {
+                String::from("You can't castle that way any more")
+            }
+            IllegalMoveReason::CastlingBlocked => {
+                String::from("There are pieces in the way of castling")
+            }
+            IllegalMoveReason::CastlingOutOfCheck => String::from("You can't castle out of check"),
+            IllegalMoveReason::CastlingPathAttacked { square } => {
+                format!("You can't castle through check on {}", square_name(*square))
+            }
+        }
+    }
+}
+impl Display for IllegalMoveReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(BoardSize::Standard))
+    }
+}
+/*
+    Works out why moving the piece on `start` to `target` isn't legal for the side to move, or None if it is.
+    Attempts to move the king from its home square to where it castles to are taken as castling
+*/
+pub fn explain_illegal_move(
+    board: &BoardState,
+    start: usize,
+    target: usize,
+) -> Option<IllegalMoveReason> {
+    let Some(piece) = board.get_piece_at_pos(start) else {
+        return Some(IllegalMoveReason::NoPiece);
+    };
+    if piece.team != board.active_team {
+        return Some(IllegalMoveReason::NotYourTurn);
+    }
+    let legal = board
+        .prune_moves_for_team(board.get_legal_moves(), board.active_team)
+        .into_iter()
+        .any(|legal_move| legal_move.start == start && legal_move.target == target);
+    if legal {
+        return None;
+    }
+
+    let enemy = piece.team.opponent();
+    let in_check = board.is_team_checked(piece.team);
+    let size = board.size;
+    let home_rank = if piece.team == Team::White {
+        0
+    } else {
+        size.last_rank()
+    };
+    let home_square = size.square(size.files() / 2, home_rank);
+    let castle_targets = [
+        size.square(size.files() - 2, home_rank),
+        size.square(2, home_rank),
+    ];
+    if piece.piece_type == PieceType::King
+        && start == home_square
+        && castle_targets.contains(&target)
+    {
+        let kingside = target > start;
+        let right = (piece.team != Team::White) as usize * 2 + (!kingside) as usize;
+        // Every square the king crosses first, then the ones only the rook passes over
+        let path: Vec<usize> = if kingside {
+            (start + 1..size.square(size.files() - 1, home_rank)).collect()
+        } else {
+            (size.square(1, home_rank)..start).rev().collect()
+        };
+        let crossed = start.abs_diff(target);
+
+        return Some(if in_check {
+            IllegalMoveReason::CastlingOutOfCheck
+        } else if !board.castling_rights.view_bits::<Lsb0>()[right] {
+            IllegalMoveReason::CastlingRightsLost
+        } else if path
+            .iter()
+            .any(|square| board.piece_list[*square] != PieceType::None)
+        {
+            IllegalMoveReason::CastlingBlocked
+        } else {
+            // The squares past where the king lands only have to be empty, they can be attacked
+            match path[..crossed]
+                .iter()
+                .find(|square| board.is_attacked_by(**square, enemy))
+            {
+                Some(square) => IllegalMoveReason::CastlingPathAttacked { square: *square },
+                None => IllegalMoveReason::CannotMoveThere,
+            }
+        });
+    }
+
+    // The king's own moves already leave out attacked squares, so any step onto a square without a friendly piece
+    // was refused for walking into an attack
+    if piece.piece_type == PieceType::King {
+        let reachable = board.tables().king_attacks[piece.team as usize][start]
+            & !board.get_team_coverage(piece.team);
+        return Some(if !reachable.get_bit(target) {
+            IllegalMoveReason::CannotMoveThere
+        } else if in_check {
+            IllegalMoveReason::KingInCheck
+        } else {
+            IllegalMoveReason::KingWouldBeAttacked
+        });
+    }
+    let Some(attempted) = board
+        .piece_psuedolegal_moves(piece)
+        .1
+        .into_iter()
+        .find(|pseudo_legal| pseudo_legal.target == target)
+    else {
+        return Some(IllegalMoveReason::CannotMoveThere);
+    };
+    if in_check {
+        return Some(IllegalMoveReason::KingInCheck);
+    }
+
+    // Nothing was attacking the king, so whatever does once the piece moves was pinning it
+    let mut after = *board;
+    if after.make_move(attempted).is_err() {
+        return Some(IllegalMoveReason::CannotMoveThere);
+    }
+    let king = after.board_pieces[piece.team as usize][PieceType::King as usize]
+        .state
+        .trailing_zeros() as usize;
+    let pinned_by = (0..size.squares()).find(|square| {
+        board.tables().between[*square][king].get_bit(start)
+            && after
+                .get_piece_at_pos(*square)
+                .filter(|pinner| pinner.team == enemy)
+                .is_some_and(|pinner| compute_attacks(&after, pinner).get_bit(king))
+    });
+    Some(match pinned_by {
+        Some(pinned_by) => IllegalMoveReason::PiecePinned { pinned_by },
+        None => IllegalMoveReason::KingInCheck,
+    })
+}
+// Should match [compute_edges] from board.rs exactly in direction. Steps as (files, ranks)
+const DIRECTION_STEPS: [(i32, i32); 8] = [
     // Rook moves are 0-4
-    8,  // n
-    -8, // s
-    1,  // e
-    -1, // w
+    (0, 1),  // n
+    (0, -1), // s
+    (1, 0),  // e
+    (-1, 0), // w
     // Bishop moves are 4-7
-    9,  // ne
-    -7, // se
-    7,  // nw
-    -9, // sw
+    (1, 1),   // ne
+    (1, -1),  // se
+    (-1, 1),  // nw
+    (-1, -1), // sw
 ];
+// The index in DIRECTION_STEPS of the direction pointing back the other way
+const OPPOSITE_DIRECTIONS: [usize; 8] = [1, 0, 3, 2, 7, 6, 5, 4];
 
 fn is_square_attackable(board: &BoardState, piece: Piece, possible_target: usize) -> bool {
     let target_team = board.get_square_team(possible_target);
@@ -86,121 +604,223 @@ fn is_square_attackable(board: &BoardState, piece: Piece, possible_target: usize
     }
 }
 
-pub fn precalc_pawn_attack<const S: usize>() -> [[Bitboard; S]; 2] {
-    let mut array = [[Bitboard::default(); S]; 2];
-    for index in Team::White as usize..=Team::Black as usize {
-        let mut square_target: i32 = 0;
-
-        for square_bb in &mut array[index] {
-            let rank_advance_diff: i32 = if index == 0 {
-                S.isqrt() as i32
-            } else {
-                -(S.isqrt() as i32)
-            };
-
-            let target = square_target + rank_advance_diff - (rank_advance_diff.signum());
-            let target2 = square_target + rank_advance_diff + (rank_advance_diff.signum());
-
-            let current_file = square_target % rank_advance_diff.abs();
-            let target_file = target % rank_advance_diff.abs();
-            let target2_file = target2 % rank_advance_diff.abs();
-
-            let is_file_jumping = target.is_negative() || target_file.abs_diff(current_file) > 3;
-            let is_file_jumping2 = target2.is_negative() || target2_file.abs_diff(current_file) > 3;
+/*
+    The tables movegen looks squares up in, built at compile time for each BoardSize so that copying a board only
+    copies the position. A board finds its own through BoardState::tables
+*/
+pub struct MoveTables {
+    pub edges: [[usize; 8]; MAX_SQUARES],
+    pub direction_offsets: [i32; 8], // How far a step along each of DIRECTION_STEPS moves a square's index
+    pub pawn_attacks: [[Bitboard; MAX_SQUARES]; 2],
+    pub pawn_pushes: [[Bitboard; MAX_SQUARES]; 2],
+    pub knight_attacks: [Bitboard; MAX_SQUARES],
+    pub king_attacks: [[Bitboard; MAX_SQUARES]; 2],
+    pub rays: [[Bitboard; 8]; MAX_SQUARES],
+    pub between: [[Bitboard; MAX_SQUARES]; MAX_SQUARES],
+    pub line: [[Bitboard; MAX_SQUARES]; MAX_SQUARES],
+}
+impl MoveTables {
+    pub const fn build(size: BoardSize) -> MoveTables {
+        MoveTables {
+            edges: compute_edges(size),
+            direction_offsets: direction_offsets(size),
+            pawn_attacks: precalc_pawn_attack(size),
+            pawn_pushes: precalc_pawn_push(size),
+            knight_attacks: precalc_knight_attack(size),
+            king_attacks: precalc_king_attack(size),
+            rays: precalc_rays(size),
+            between: precalc_between(size),
+            line: precalc_line(size),
+        }
+    }
+}
+// In the order of BOARD_SIZES
+pub static MOVE_TABLES: [MoveTables; BOARD_SIZES.len()] = [
+    MoveTables::build(BOARD_SIZES[0]),
+    MoveTables::build(BOARD_SIZES[1]),
+];
+impl BoardSize {
+    pub fn tables(self) -> &'static MoveTables {
+        &MOVE_TABLES[self as usize]
+    }
+}
 
-            square_bb.set_bit::<Lsb0>(target as usize, !is_file_jumping);
-            square_bb.set_bit::<Lsb0>(target2 as usize, !is_file_jumping2);
+pub const fn direction_offsets(size: BoardSize) -> [i32; 8] {
+    let mut offsets = [0; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let (files, ranks) = DIRECTION_STEPS[direction];
+        offsets[direction] = ranks * size.files() as i32 + files;
+        direction += 1;
+    }
+    offsets
+}
+// The squares `steps` take a piece on `square` to, leaving out any that would run off a side of the board
+const fn step_targets(size: BoardSize, square: usize, steps: &[(i32, i32)]) -> Bitboard {
+    let mut targets = Bitboard { state: 0 };
+    let mut index = 0;
+    while index < steps.len() {
+        if let Some(target) = size.offset(square, steps[index]) {
+            targets.state |= 1 << target;
+        }
+        index += 1;
+    }
+    targets
+}
 
-            square_target += 1;
+pub const fn precalc_pawn_attack(size: BoardSize) -> [[Bitboard; MAX_SQUARES]; 2] {
+    let mut array = [[Bitboard { state: 0 }; MAX_SQUARES]; 2];
+    let mut square = 0;
+    while square < size.squares() {
+        array[Team::White as usize][square] = step_targets(size, square, &[(-1, 1), (1, 1)]);
+        array[Team::Black as usize][square] = step_targets(size, square, &[(-1, -1), (1, -1)]);
+        square += 1;
+    }
+    array
+}
+pub const fn precalc_pawn_push(size: BoardSize) -> [[Bitboard; MAX_SQUARES]; 2] {
+    let mut array = [[Bitboard { state: 0 }; MAX_SQUARES]; 2];
+    let mut square = 0;
+    while square < size.squares() {
+        let rank = size.rank(square);
+        // Pawns never stand on either back rank, so they get no pushes there
+        if rank != 0 && rank != size.last_rank() {
+            array[Team::White as usize][square] = step_targets(size, square, &[(0, 1)]);
+            array[Team::Black as usize][square] = step_targets(size, square, &[(0, -1)]);
+        }
+        // From their own second rank they can step twice
+        if rank == 1 {
+            array[Team::White as usize][square].state |=
+                step_targets(size, square, &[(0, 2)]).state;
+        }
+        if rank == size.last_rank() - 1 {
+            array[Team::Black as usize][square].state |=
+                step_targets(size, square, &[(0, -2)]).state;
         }
+        square += 1;
     }
     array
 }
-pub fn precalc_pawn_push<const S: usize>() -> [[Bitboard; S]; 2] {
-    let mut array = [[Bitboard::default(); S]; 2];
-    for index in Team::White as usize..=Team::Black as usize {
-        let mut square_target: i32 = 0;
-
-        for square_bb in &mut array[index] {
-            let rank_advance_diff: i32 = if (index == 0) {
-                S.isqrt() as i32
-            } else {
-                -(S.isqrt() as i32)
-            };
-            let is_ranked_out = square_target.div_floor(rank_advance_diff.abs())
-                == rank_advance_diff.abs() - 1
-                || square_target.div_floor(rank_advance_diff.abs()) == 0;
-            let is_at_start = if (index == 0) {
-                square_target.div_floor(8) == 1
-            } else {
-                square_target.div_floor(8) == 6
-            };
 
-            square_bb.set_bit::<Lsb0>((square_target + rank_advance_diff) as usize, !is_ranked_out);
-            square_bb.set_bit::<Lsb0>(
-                (square_target + rank_advance_diff + rank_advance_diff) as usize,
-                is_at_start,
-            );
+pub const fn precalc_knight_attack(size: BoardSize) -> [Bitboard; MAX_SQUARES] {
+    let mut array = [Bitboard { state: 0 }; MAX_SQUARES];
+    let mut square = 0;
+    while square < size.squares() {
+        array[square] = step_targets(size, square, KNIGHT_STEPS);
+        square += 1;
+    }
+    array
+}
+// Kings attack the same way on either team, the table is split by team to match the pawn tables
+pub const fn precalc_king_attack(size: BoardSize) -> [[Bitboard; MAX_SQUARES]; 2] {
+    let mut array = [[Bitboard { state: 0 }; MAX_SQUARES]; 2];
+    let mut square = 0;
+    while square < size.squares() {
+        let attacks = step_targets(size, square, KING_STEPS);
+        array[Team::White as usize][square] = attacks;
+        array[Team::Black as usize][square] = attacks;
+        square += 1;
+    }
+    array
+}
 
-            square_target += 1;
+// Every square from a square to the edge of the board in each direction of DIRECTION_STEPS, on an empty board
+pub const fn precalc_rays(size: BoardSize) -> [[Bitboard; 8]; MAX_SQUARES] {
+    let edges = compute_edges(size);
+    let offsets = direction_offsets(size);
+    let mut array = [[Bitboard { state: 0 }; 8]; MAX_SQUARES];
+    let mut square = 0;
+    while square < size.squares() {
+        let mut direction = 0;
+        while direction < 8 {
+            let mut step = 1;
+            while step <= edges[square][direction] {
+                let target = square as i32 + (step as i32 * offsets[direction]);
+                array[square][direction].state |= 1 << target;
+                step += 1;
+            }
+            direction += 1;
         }
+        square += 1;
     }
     array
 }
-
-pub fn precalc_knight_attack<const S: usize>() -> [Bitboard; S] {
-    let mut array = [Bitboard::default(); S];
-    let knight_moves: [i32; 8] = [10, 17, -10, -17, 15, -15, 6, -6];
-
-    let mut square_target = 0;
-
-    for square_bb in &mut array {
-        for knight_square in knight_moves {
-            let target = (square_target + knight_square);
-            let target_file = target % 8;
-            let valid_move = target_file.abs_diff(square_target % 8) <= 3;
-
-            square_bb.set_bit::<Lsb0>(target as usize, valid_move);
+// The squares strictly between two squares that share a line, or nothing when they don't share one
+pub const fn precalc_between(size: BoardSize) -> [[Bitboard; MAX_SQUARES]; MAX_SQUARES] {
+    let edges = compute_edges(size);
+    let offsets = direction_offsets(size);
+    let mut array = [[Bitboard { state: 0 }; MAX_SQUARES]; MAX_SQUARES];
+    let mut square = 0;
+    while square < size.squares() {
+        let mut direction = 0;
+        while direction < 8 {
+            let mut passed: u128 = 0;
+            let mut step = 1;
+            while step <= edges[square][direction] {
+                let target = (square as i32 + (step as i32 * offsets[direction])) as usize;
+                array[square][target].state = passed;
+                passed |= 1 << target;
+                step += 1;
+            }
+            direction += 1;
         }
-        square_target += 1;
+        square += 1;
     }
     array
 }
-pub fn precalc_king_attack<const S: usize>() -> [[Bitboard; S]; 2] {
-    let mut array = [[Bitboard::default(); S]; 2];
-    for index in Team::White as usize..=Team::Black as usize {
-        let mut square_target: i32 = 0;
-
-        for square_bb in &mut array[index] {
-            let rank_advance_diff: i32 = if (index == 0) {
-                S.isqrt() as i32
-            } else {
-                -(S.isqrt() as i32)
-            };
-
-            let is_a_file = square_target % rank_advance_diff.abs() == 0;
-            let is_h_file = square_target % (rank_advance_diff.abs() - 1) == 0;
-
-            let bit_slice = square_bb.state.view_bits_mut::<Lsb0>();
-
-            square_bb.set_bit::<Lsb0>((square_target + rank_advance_diff - 1) as usize, !is_a_file);
-            square_bb.set_bit::<Lsb0>((square_target + rank_advance_diff + 1) as usize, !is_h_file);
-
-            square_bb.set_bit::<Lsb0>((square_target - 1) as usize, !is_a_file);
-            square_bb.set_bit::<Lsb0>((square_target + 1) as usize, !is_h_file);
-
-            square_bb.set_bit::<Lsb0>((square_target + rank_advance_diff) as usize, true);
-            square_bb.set_bit::<Lsb0>((square_target - rank_advance_diff) as usize, true);
-
-            square_bb.set_bit::<Lsb0>((square_target - rank_advance_diff - 1) as usize, !is_a_file);
-            square_bb.set_bit::<Lsb0>((square_target - rank_advance_diff + 1) as usize, !is_h_file);
 
-            square_target += 1;
+/*
+    The whole line through two squares that share one, edge to edge and including both squares, or nothing when they
+    don't share one. A piece is pinned to its king only along `line[king][piece]`, and a check can only be blocked or
+    a pinned piece moved on it
+*/
+pub const fn precalc_line(size: BoardSize) -> [[Bitboard; MAX_SQUARES]; MAX_SQUARES] {
+    let rays = precalc_rays(size);
+    let edges = compute_edges(size);
+    let offsets = direction_offsets(size);
+    let mut array = [[Bitboard { state: 0 }; MAX_SQUARES]; MAX_SQUARES];
+    let mut square = 0;
+    while square < size.squares() {
+        let mut direction = 0;
+        while direction < 8 {
+            let line = (1 << square)
+                | rays[square][direction].state
+                | rays[square][OPPOSITE_DIRECTIONS[direction]].state;
+            let mut step = 1;
+            while step <= edges[square][direction] {
+                let target = (square as i32 + (step as i32 * offsets[direction])) as usize;
+                array[square][target].state = line;
+                step += 1;
+            }
+            direction += 1;
         }
+        square += 1;
     }
     array
 }
 
+// The DIRECTION_STEPS a piece can ride along, empty for pieces that only step or leap
+pub fn ray_directions(piece_type: PieceType) -> std::ops::Range<usize> {
+    match piece_type {
+        PieceType::Bishop | PieceType::Archbishop => 4..8,
+        PieceType::Rook | PieceType::Chancellor => 0..4,
+        PieceType::Queen => 0..8,
+        _ => 0..0,
+    }
+}
+
+// A pawn's move onto the last rank once for each piece it can promote to, and any other move as it is
+fn with_promotions(size: BoardSize, piece: Piece, cmove: Move) -> impl Iterator<Item = Move> {
+    let promotes =
+        piece.piece_type == PieceType::Pawn && promotes_on(size, piece.team, cmove.target);
+    promotion_choices(size)
+        .iter()
+        .copied()
+        .map(Some)
+        .filter(move |_| promotes)
+        .chain(std::iter::once(None).filter(move |_| !promotes))
+        .map(move |promotion| Move { promotion, ..cmove })
+}
 fn psuedolegalize_move(
     move_list: &mut Vec<Move>,
     bitboard: &mut Bitboard,
@@ -210,10 +830,7 @@ fn psuedolegalize_move(
     if condition {
         move_list.push(cmove);
     }
-    bitboard
-        .state
-        .view_bits_mut::<Lsb0>()
-        .set(cmove.target, condition);
+    bitboard.set_bit(cmove.target, condition);
 }
 /*
     Gets psuedolegal moves for the pawns.
@@ -221,9 +838,10 @@ fn psuedolegalize_move(
 pub fn compute_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
     let mut bitboard = Bitboard::default();
     let mut computed_moves: Vec<Move> = Vec::new();
+    let files = board.size.files() as i32;
     let forward_direction: i32 = match piece.team {
-        Team::Black => -8,
-        Team::White => 8, // making this 7 makes for an interesting diagonal pawn...
+        Team::Black => -files,
+        Team::White => files, // making this one less makes for an interesting diagonal pawn...
         _ => {
             panic!("Pawn movements for unconventional teams are unhandled"); // TODO: Dont forget to fix this if you add other teams
         }
@@ -232,8 +850,8 @@ pub fn compute_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
     let pawn_view_range = forward_direction.signum();
 
     let far_edge_dist = match piece.team {
-        Team::Black => board.edge_compute[piece.position][1],
-        Team::White => board.edge_compute[piece.position][0],
+        Team::Black => board.tables().edges[piece.position][1],
+        Team::White => board.tables().edges[piece.position][0],
         _ => {
             unreachable!()
         }
@@ -244,7 +862,11 @@ pub fn compute_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
     }
 
     let mut offset_index = 0;
-    let step_length = if far_edge_dist == 6 { 2 } else { 1 }; // Do we award initial advances from any start position? It is an nteresting question, but for now we just assume normal start
+    let step_length = if far_edge_dist == board.size.ranks() - 2 {
+        2
+    } else {
+        1
+    }; // Do we award initial advances from any start position? It is an nteresting question, but for now we just assume normal start
 
     let of_start = (forward_direction - pawn_view_range).min(forward_direction + pawn_view_range);
     let of_end = (forward_direction - pawn_view_range).max(forward_direction + pawn_view_range);
@@ -252,11 +874,11 @@ pub fn compute_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
     for offset in of_start..=of_end {
         'step_ray: for step in 1..=step_length {
             let possible_target = (piece.position as i32 + (offset * step)) as usize;
-            if !(0..board.piece_list.len()).contains(&possible_target) {
+            if !(0..board.size.squares()).contains(&possible_target) {
                 continue;
             };
-            let target_file = possible_target % 8;
-            let start_file = piece.position % 8;
+            let target_file = board.size.file(possible_target);
+            let start_file = board.size.file(piece.position);
 
             if target_file.abs_diff(start_file) > 3 {
                 continue;
@@ -271,6 +893,7 @@ pub fn compute_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
                 is_pawn_double: step == 2,
                 captures: target_piece,
                 is_castle: false,
+                promotion: None,
             };
             if target_piece_type == PieceType::None {
                 psuedolegalize_move(
@@ -295,10 +918,9 @@ pub fn compute_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
     }
 
     // en passant
-    if let Some(en_pass) = board.en_passant_square {
-        if en_pass.abs_diff(piece.position) == 1 {
-            let target_piece_type = board.piece_list[en_pass];
-            let target_piece = board.get_piece_at_pos(en_pass);
+    if let Some(en_pass) = board.en_passant_target(piece.team) {
+        if board.tables().pawn_attacks[piece.team as usize][piece.position].get_bit(en_pass) {
+            let target_piece = board.captured_by(piece.position, en_pass);
 
             let resulting_move = Move {
                 start: piece.position,
@@ -306,19 +928,22 @@ pub fn compute_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
                 is_pawn_double: false,
                 captures: target_piece,
                 is_castle: false,
+                promotion: None,
             };
 
             psuedolegalize_move(
                 &mut computed_moves,
                 &mut bitboard,
                 resulting_move,
-                is_square_attackable(board, piece, en_pass)
-                    && board.en_passant_turn.unwrap() == board.turn_clock
-                    && target_piece_type == PieceType::Pawn,
+                target_piece.is_some(),
             );
         }
     }
 
+    let computed_moves = computed_moves
+        .into_iter()
+        .flat_map(|computed_move| with_promotions(board.size, piece, computed_move))
+        .collect();
     (bitboard, computed_moves)
 }
 
@@ -346,13 +971,15 @@ pub fn compute_slider(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>)
     let square_bit_index = piece.position;
 
     // index is a direction
-    for (index, direction_add) in DIRECTION_OFFSETS
+    let tables = board.tables();
+    for (index, direction_add) in tables
+        .direction_offsets
         .iter()
         .enumerate()
         .take(index_end)
         .skip(index_start)
     {
-        let mut indexed_direction = board.edge_compute[square_bit_index][index];
+        let mut indexed_direction = tables.edges[square_bit_index][index];
 
         if indexed_direction >= 1 {
             tracing::debug!(
@@ -360,7 +987,7 @@ pub fn compute_slider(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>)
                 piece.team,
                 piece.piece_type,
                 piece.position,
-                DIRECTION_OFFSETS[index]
+                direction_add
             );
         } else {
             // We are against the edge in this direction
@@ -380,7 +1007,7 @@ pub fn compute_slider(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>)
             let possible_target =
                 (square_bit_index as i32 + (raycast as i32 * direction_add)) as usize;
 
-            if !(0..board.piece_list.len()).contains(&possible_target) {
+            if !(0..board.size.squares()).contains(&possible_target) {
                 break 'raycast_check;
             };
 
@@ -393,6 +1020,7 @@ pub fn compute_slider(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>)
                 is_pawn_double: false,
                 captures: target_piece,
                 is_castle: false,
+                promotion: None,
             };
             psuedolegalize_move(
                 &mut computed_moves,
@@ -410,16 +1038,115 @@ pub fn compute_slider(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>)
     (bitboard, computed_moves)
 }
 
-// For nightrider, we could do this recursively until we get 0 results
+/*
+    Computes psuedolegals for the fairy pieces, straight from the leaps and rides in their definitions. That covers
+    the compound pieces, which ride like a bishop (archbishop) or rook (chancellor) and also leap like a knight
+*/
+pub fn compute_fairy(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
+    let targets = definition_attacks(
+        definition(piece.piece_type),
+        board.size,
+        piece.position,
+        |square| board.piece_list[square] != PieceType::None,
+    ) & !board.get_team_coverage(piece.team);
+    (targets, bitboard_to_movelist(board, piece, targets))
+}
+
+/*
+    Every square `piece` attacks. Unlike the move generators this includes squares held by its own team, since a
+    defended piece is still attacked, and it never looks at the attack maps themselves so it can build them.
+*/
+pub fn compute_attacks(board: &BoardState, piece: Piece) -> Bitboard {
+    if is_fairy(piece.piece_type) {
+        return definition_attacks(
+            definition(piece.piece_type),
+            board.size,
+            piece.position,
+            |square| board.piece_list[square] != PieceType::None,
+        );
+    }
+    let mut attacks = Bitboard::default();
+    let tables = board.tables();
+
+    let directions = ray_directions(piece.piece_type);
+    for (index, direction_add) in tables
+        .direction_offsets
+        .iter()
+        .enumerate()
+        .take(directions.end)
+        .skip(directions.start)
+    {
+        for raycast in 1..=tables.edges[piece.position][index] {
+            let target = (piece.position as i32 + (raycast as i32 * direction_add)) as usize;
+            attacks.set_bit(target, true);
+
+            if board.piece_list[target] != PieceType::None {
+                break;
+            }
+        }
+    }
+
+    match piece.piece_type {
+        PieceType::Pawn => attacks |= tables.pawn_attacks[piece.team as usize][piece.position],
+        PieceType::Knight => attacks |= tables.knight_attacks[piece.position],
+        PieceType::King => attacks |= tables.king_attacks[piece.team as usize][piece.position],
+        _ => {}
+    }
+    attacks
+}
+
+/*
+    Every square a `piece_type` on `square` of a `size` board attacks when only the pieces in `occupied` block it. It
+    works from the bitboards alone, so legality can ask what would be attacked with a piece lifted off without copying
+    the board
+*/
+pub fn attacks_from(
+    size: BoardSize,
+    piece_type: PieceType,
+    team: Team,
+    square: usize,
+    occupied: Bitboard,
+) -> Bitboard {
+    if is_fairy(piece_type) {
+        return definition_attacks(definition(piece_type), size, square, |blocker| {
+            occupied.get_bit(blocker)
+        });
+    }
+    let mut attacks = Bitboard::default();
+    let tables = size.tables();
+
+    for direction in ray_directions(piece_type) {
+        let mut ray = tables.rays[square][direction];
+        let blockers = ray & occupied;
+        if blockers.state != 0 {
+            // The nearest blocker is the lowest bit on rays running up the board and the highest on ones running down
+            let nearest = if tables.direction_offsets[direction] > 0 {
+                blockers.state.trailing_zeros()
+            } else {
+                u128::BITS - 1 - blockers.state.leading_zeros()
+            } as usize;
+            ray &= !tables.rays[nearest][direction];
+        }
+        attacks |= ray;
+    }
+
+    match piece_type {
+        PieceType::Pawn => attacks |= tables.pawn_attacks[team as usize][square],
+        PieceType::Knight => attacks |= tables.knight_attacks[square],
+        PieceType::King => attacks |= tables.king_attacks[team as usize][square],
+        _ => {}
+    }
+    attacks
+}
+
 // compute_knight
 pub fn compute_knight(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
-    let knight_moves: [i32; 8] = [10, 17, -10, -17, 15, -15, 6, -6];
     let mut computed_moves: Vec<Move> = Vec::new();
     let mut bitboard = Bitboard::default();
 
-    for knight_square in knight_moves {
-        let possible_target = ((piece.position as i32) + knight_square) as usize;
-        if !(0..board.piece_list.len()).contains(&possible_target) {
+    // Steps are taken by file and rank, so none of them can loop around the side of the board
+    for knight_step in KNIGHT_STEPS {
+        let Some(possible_target) = board.size.offset(piece.position, *knight_step) else {
             continue;
         };
         let target_piece = board.get_piece_at_pos(possible_target);
@@ -429,19 +1156,14 @@ pub fn compute_knight(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>)
             is_pawn_double: false,
             captures: target_piece,
             is_castle: false,
+            promotion: None,
         };
 
-        let target_file = possible_target % 8;
-        let start_file = piece.position % 8;
-
-        // Disable stuff that lets you loop around the board, which seems to only happen laterally.
-        // Do this by ignoring anything that is on file A/B if you're on H/G and vice versa
         psuedolegalize_move(
             &mut computed_moves,
             &mut bitboard,
             resulting_move,
-            is_square_attackable(board, piece, possible_target)
-                && target_file.abs_diff(start_file) <= 2,
+            is_square_attackable(board, piece, possible_target),
         );
     }
 
@@ -451,25 +1173,26 @@ pub fn compute_knight(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>)
 fn bitboard_to_movelist(board: &BoardState, piece: Piece, bitboard: Bitboard) -> Vec<Move> {
     let mut computed_moves: Vec<Move> = Vec::new();
 
-    let bit_slice = bitboard.state.view_bits::<Lsb0>();
-    for index in bit_slice.iter_ones() {
+    for index in bitboard.iter_ones() {
         let far_edge_dist_for_pawns = match piece.team {
-            Team::Black => board.edge_compute[piece.position][1],
-            Team::White => board.edge_compute[piece.position][0],
+            Team::Black => board.tables().edges[piece.position][1],
+            Team::White => board.tables().edges[piece.position][0],
             _ => {
                 unreachable!()
             }
         };
 
-        computed_moves.push(Move {
+        let resulting_move = Move {
             start: piece.position,
             target: index,
-            captures: board.get_piece_at_pos(index),
-            is_pawn_double: far_edge_dist_for_pawns == 6
+            captures: board.captured_by(piece.position, index),
+            is_pawn_double: far_edge_dist_for_pawns == board.size.ranks() - 2
                 && piece.piece_type == PieceType::Pawn
-                && index.abs_diff(piece.position) == 16,
+                && index.abs_diff(piece.position) == 2 * board.size.files(),
             is_castle: false,
-        });
+            promotion: None,
+        };
+        computed_moves.extend(with_promotions(board.size, piece, resulting_move));
     }
 
     computed_moves
@@ -481,7 +1204,7 @@ pub fn get_precomputed_king(board: &BoardState, piece: Piece) -> (Bitboard, Vec<
 
     let cap_bits = board.capture_bitboard[piece.team.opponent() as usize];
 
-    let king_bit = board.king_compute[piece.team as usize][piece.position];
+    let king_bit = board.tables().king_attacks[piece.team as usize][piece.position];
 
     bitboard |= king_bit & !team_cov & !cap_bits;
 
@@ -489,45 +1212,59 @@ pub fn get_precomputed_king(board: &BoardState, piece: Piece) -> (Bitboard, Vec<
 }
 
 pub fn get_precomputed_pawn(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
+    let pawn_bits = pawn_targets(board, piece);
+    (pawn_bits, bitboard_to_movelist(board, piece, pawn_bits))
+}
+// The squares a pawn can push or capture to
+pub fn pawn_targets(board: &BoardState, piece: Piece) -> Bitboard {
     let team_cov =
         board.get_team_coverage(piece.team) | board.get_team_coverage(piece.team.opponent());
     let enemy_cov = board.get_team_coverage(piece.team.opponent());
 
-    let pawn_step_1 = match piece.team {
-        Team::Black => piece.position.sub(8),
-        Team::White => piece.position.add(8),
-        _ => unreachable!(),
-    };
-
-    let pawn_step_2 = match piece.team {
-        Team::Black => piece.position.sub(16),
-        Team::White => piece.position.add(16),
-        _ => unreachable!(),
-    };
-
+    let tables = board.tables();
+    let files = board.size.files();
     let far_edge_dist_for_pawns = match piece.team {
-        Team::Black => board.edge_compute[piece.position][1],
-        Team::White => board.edge_compute[piece.position][0],
+        Team::Black => tables.edges[piece.position][1],
+        Team::White => tables.edges[piece.position][0],
         _ => unreachable!(),
     };
 
-    let mut push_bit = board.pawn_push_compute[piece.team as usize][piece.position];
+    let mut push_bit = tables.pawn_pushes[piece.team as usize][piece.position] & !team_cov;
 
-    if far_edge_dist_for_pawns == 6 && piece.piece_type == PieceType::Pawn {
-        let slider_block_state = push_bit.get_bit::<Lsb0>(pawn_step_1);
-        push_bit.set_bit::<Lsb0>(pawn_step_2, slider_block_state);
+    // The double step passes over the single one, so anything standing there blocks both
+    if far_edge_dist_for_pawns == board.size.ranks() - 2 {
+        let passed_over = match piece.team {
+            Team::Black => piece.position - files,
+            Team::White => piece.position + files,
+            _ => unreachable!(),
+        };
+        if team_cov.get_bit(passed_over) {
+            push_bit = Bitboard::default();
+        }
     }
 
-    push_bit &= !team_cov;
-
-    let push_attack = board.pawn_attack_compute[piece.team as usize][piece.position] & enemy_cov;
+    // Horde's white pawns start as far back as the first rank, and can step once or twice from there
+    if board.variant == Variant::Horde && piece.team == Team::White && piece.position < files {
+        let first = piece.position + files;
+        if !team_cov.get_bit(first) {
+            push_bit.set_bit(first, true);
+            push_bit.set_bit(first + files, !team_cov.get_bit(first + files));
+        }
+    }
 
-    let pawn_bits = push_attack | push_bit;
+    let attacks = tables.pawn_attacks[piece.team as usize][piece.position];
+    let mut push_attack = attacks & enemy_cov;
+    // En passant lands on an empty square, so it's added on its own
+    if let Some(en_pass) = board.en_passant_target(piece.team) {
+        if attacks.get_bit(en_pass) && board.captured_by(piece.position, en_pass).is_some() {
+            push_attack.set_bit(en_pass, true);
+        }
+    }
 
-    (pawn_bits, bitboard_to_movelist(board, piece, pawn_bits))
+    push_attack | push_bit
 }
 pub fn get_precomputed_knight(board: &BoardState, piece: Piece) -> (Bitboard, Vec<Move>) {
     let team_cov = board.get_team_coverage(piece.team);
-    let knight_bits = board.knight_compute[piece.position] & !team_cov;
+    let knight_bits = board.tables().knight_attacks[piece.position] & !team_cov;
     (knight_bits, bitboard_to_movelist(board, piece, knight_bits))
 }
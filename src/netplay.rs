@@ -0,0 +1,382 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{bitboard::Team, board::Variant, clock::TimeControl};
+
+// How long the joining side waits between tries at getting back to a host it lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+// How often the host looks for a guest, which is also how long its port can stay taken once the session is gone
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+// How long a guest that connects has to say which game it's there for
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*
+    What one side of a network game tells the other, one message to a line. A guest opens with the game it's
+    coming back to, if it has been told one. The host says how the game is played whenever the guest connects, and
+    both sides send what they've played so far, so either can catch up after a dropped connection. Hashes are the
+    zobrist hash of the position, for spotting boards that have drifted apart
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetMessage {
+    Hello {
+        game: Option<u64>,
+    },
+    Start {
+        game: u64, // Made up by the host, for the guest to name when it comes back
+        guest_team: Team,
+        variant: Variant,
+        clock: Option<(TimeControl, TimeControl)>, // White's and black's
+        fen: String,
+    },
+    History(Vec<String>), // Every move of the game so far, in UCI
+    Move {
+        ply: usize, // How many moves the game has once this one is played
+        uci: String,
+        time_left: Option<Duration>, // On the mover's clock as they moved, before any increment
+        hash: u64,
+    },
+    DrawOffer,
+    DrawAccept,
+    Resign,
+    Final {
+        hash: u64,
+        fen: String,
+    },
+}
+impl NetMessage {
+    pub fn parse(line: &str) -> Option<NetMessage> {
+        let mut words = line.split_whitespace();
+        let rest = |words: std::str::SplitWhitespace| words.collect::<Vec<&str>>().join(" ");
+        let team = |letter: &str| match letter {
+            "w" => Some(Team::White),
+            "b" => Some(Team::Black),
+            _ => None,
+        };
+        let time_control = |text: &str| match text {
+            "-" => Some(None),
+            text => TimeControl::parse(text).map(Some),
+        };
+
+        match words.next()? {
+            "HELLO" => Some(NetMessage::Hello {
+                game: match words.next()? {
+                    "-" => None,
+                    game => Some(u64::from_str_radix(game, 16).ok()?),
+                },
+            }),
+            "START" => {
+                let game = u64::from_str_radix(words.next()?, 16).ok()?;
+                let guest_team = team(words.next()?)?;
+                let variant = Variant::from_name(words.next()?)?;
+                let clock = match (time_control(words.next()?)?, time_control(words.next()?)?) {
+                    (Some(white), Some(black)) => Some((white, black)),
+                    (None, None) => None,
+                    _ => return None,
+                };
+                Some(NetMessage::Start {
+                    game,
+                    guest_team,
+                    variant,
+                    clock,
+                    fen: rest(words),
+                })
+            }
+            "HISTORY" => Some(NetMessage::History(words.map(String::from).collect())),
+            "MOVE" => Some(NetMessage::Move {
+                ply: words.next()?.parse().ok()?,
+                uci: words.next()?.to_string(),
+                time_left: match words.next()? {
+                    "-" => None,
+                    millis => Some(Duration::from_millis(millis.parse().ok()?)),
+                },
+                hash: u64::from_str_radix(words.next()?, 16).ok()?,
+            }),
+            "DRAW" => match words.next()? {
+                "offer" => Some(NetMessage::DrawOffer),
+                "accept" => Some(NetMessage::DrawAccept),
+                _ => None,
+            },
+            "RESIGN" => Some(NetMessage::Resign),
+            "FINAL" => Some(NetMessage::Final {
+                hash: u64::from_str_radix(words.next()?, 16).ok()?,
+                fen: rest(words),
+            }),
+            _ => None,
+        }
+    }
+}
+impl Display for NetMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            NetMessage::Hello { game } => write!(
+                f,
+                "HELLO {}",
+                game.map_or(String::from("-"), |game| format!("{game:016x}"))
+            ),
+            NetMessage::Start {
+                game,
+                guest_team,
+                variant,
+                clock,
+                fen,
+            } => {
+                let (white, black) = clock
+                    .map_or((String::from("-"), String::from("-")), |(white, black)| {
+                        (white.to_string(), black.to_string())
+                    });
+                write!(
+                    f,
+                    "START {game:016x} {} {} {white} {black} {fen}",
+                    if *guest_team == Team::White { "w" } else { "b" },
+                    variant.to_string().replace(' ', "")
+                )
+            }
+            NetMessage::History(moves) => write!(f, "HISTORY {}", moves.join(" ")),
+            NetMessage::Move {
+                ply,
+                uci,
+                time_left,
+                hash,
+            } => write!(
+                f,
+                "MOVE {ply} {uci} {} {hash:016x}",
+                time_left.map_or(String::from("-"), |time| time.as_millis().to_string())
+            ),
+            NetMessage::DrawOffer => write!(f, "DRAW offer"),
+            NetMessage::DrawAccept => write!(f, "DRAW accept"),
+            NetMessage::Resign => write!(f, "RESIGN"),
+            NetMessage::Final { hash, fen } => write!(f, "FINAL {hash:016x} {fen}"),
+        }
+    }
+}
+
+// What the connection thread hands over to the board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetEvent {
+    Connected,
+    Message(NetMessage),
+    Unreadable(String),
+    Disconnected,
+}
+
+// The connection in use, numbered so a reader that outlived its connection can't mark its replacement as gone
+type Connection = Arc<Mutex<Option<(usize, TcpStream)>>>;
+
+/*
+    A game against another copy of the program over TCP. The host listens for the guest and lets a guest that
+    dropped out connect again, as long as it names the game, while the guest keeps trying to get back to a host it
+    lost. Messages are read on their own threads and picked up with `poll`
+*/
+pub struct NetSession {
+    pub hosting: bool,
+    pub connected: bool,
+    pub draw_offered: bool, // We've offered a draw the other player hasn't answered yet
+    pub peer_offers_draw: bool, // and the other way round
+    pub peer_final: Option<(u64, String)>, // The position the other player's game ended in, as a hash and FEN
+    connection: Connection,
+    events: Receiver<NetEvent>,
+    closed: Arc<AtomicBool>,
+    game: Arc<AtomicU64>, // The game's id, 0 until a guest has been told it
+}
+impl NetSession {
+    /*
+        Waits for a guest on `port`, without holding up the board. The listener is only polled, so it's let go of
+        soon after the session is, and the port can be hosted on again
+    */
+    pub fn host(port: u16) -> Result<NetSession, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|err| format!("Couldn't listen on port {port}: {err}"))?;
+        let (session, event_tx) = NetSession::new(true);
+        let (connection, closed) = (session.connection.clone(), session.closed.clone());
+        let game = rand::random_range(1..=u64::MAX);
+        session.game.store(game, Ordering::Relaxed);
+        let joined = Arc::new(AtomicBool::new(false));
+
+        std::thread::spawn(move || {
+            let mut id = 0;
+            while !closed.load(Ordering::Relaxed) {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        std::thread::sleep(ACCEPT_POLL);
+                        continue;
+                    }
+                    Err(_) => continue,
+                };
+                id += 1;
+                // A guest connecting again takes over from the connection it lost
+                let (connection, event_tx, joined) =
+                    (connection.clone(), event_tx.clone(), joined.clone());
+                std::thread::spawn(move || {
+                    if let Some(reader) = admit_guest(stream, game, &joined) {
+                        read_messages(id, reader, &connection, &event_tx);
+                    }
+                });
+            }
+        });
+        Ok(session)
+    }
+    // Connects to the host at `address`, which has to be there now, and reconnects whenever it's lost after that
+    pub fn join(address: &str) -> Result<NetSession, String> {
+        let first = TcpStream::connect(address)
+            .map_err(|err| format!("Couldn't reach {address}: {err}"))?;
+        let (session, event_tx) = NetSession::new(false);
+        let (connection, closed) = (session.connection.clone(), session.closed.clone());
+        let game = session.game.clone();
+        let address = address.to_string();
+
+        std::thread::spawn(move || {
+            let mut stream = Some(first);
+            for id in 0.. {
+                // The host only lets a guest back in that names the game it was told about
+                let hello = NetMessage::Hello {
+                    game: Some(game.load(Ordering::Relaxed)).filter(|game| *game != 0),
+                };
+                if let Some(mut stream) = stream.take() {
+                    if stream.write_all(format!("{hello}\n").as_bytes()).is_ok() {
+                        read_messages(id, BufReader::new(stream), &connection, &event_tx);
+                    }
+                }
+                if closed.load(Ordering::Relaxed) {
+                    return;
+                }
+                std::thread::sleep(RECONNECT_DELAY);
+                stream = TcpStream::connect(&address).ok();
+            }
+        });
+        Ok(session)
+    }
+    // A session with nothing connected yet, and the sender its connection threads report through
+    fn new(hosting: bool) -> (NetSession, Sender<NetEvent>) {
+        let (event_tx, events) = mpsc::channel();
+        let session = NetSession {
+            hosting,
+            connected: false,
+            draw_offered: false,
+            peer_offers_draw: false,
+            peer_final: None,
+            connection: Arc::default(),
+            events,
+            closed: Arc::default(),
+            game: Arc::default(),
+        };
+        (session, event_tx)
+    }
+    // The id of the game being played, once there is one
+    pub fn game(&self) -> Option<u64> {
+        Some(self.game.load(Ordering::Relaxed)).filter(|game| *game != 0)
+    }
+    pub fn send(&mut self, message: &NetMessage) -> Result<(), String> {
+        let mut connection = self.connection.lock().map_err(|err| err.to_string())?;
+        let Some((_, stream)) = connection.as_mut() else {
+            return Err(String::from("Not connected to the other player"));
+        };
+        stream
+            .write_all(format!("{message}\n").as_bytes())
+            .map_err(|err| format!("Lost the other player: {err}"))
+    }
+    // Everything that has come in since the last call
+    pub fn poll(&mut self) -> Vec<NetEvent> {
+        let events: Vec<NetEvent> = self.events.try_iter().collect();
+        for event in &events {
+            match event {
+                NetEvent::Connected => self.connected = true,
+                NetEvent::Disconnected => self.connected = false,
+                // The guest remembers the game for when it has to connect again
+                NetEvent::Message(NetMessage::Start { game, .. }) if !self.hosting => {
+                    self.game.store(*game, Ordering::Relaxed)
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+impl Drop for NetSession {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Ok(Some((_, stream))) = self.connection.lock().as_deref() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/*
+    Reads the hello of a guest that just connected, giving back the connection if it can play. The first guest to
+    connect joins the game, and after that only one naming the game can take over, so nobody else can cut in on it
+*/
+fn admit_guest(stream: TcpStream, game: u64, joined: &AtomicBool) -> Option<BufReader<TcpStream>> {
+    stream.set_nonblocking(false).ok()?;
+    stream.set_read_timeout(Some(HELLO_TIMEOUT)).ok()?;
+    let mut reader = BufReader::new(stream);
+    let mut hello = String::new();
+    reader.read_line(&mut hello).ok()?;
+
+    let admitted = match NetMessage::parse(hello.trim()) {
+        Some(NetMessage::Hello { game: Some(named) }) => named == game,
+        Some(NetMessage::Hello { game: None }) => joined
+            .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok(),
+        _ => false,
+    };
+    if !admitted {
+        let _ = reader.get_ref().shutdown(Shutdown::Both);
+        return None;
+    }
+    reader.get_ref().set_read_timeout(None).ok()?;
+    Some(reader)
+}
+
+// Makes `reader`'s stream the connection in use and turns what comes over it into events until it closes
+fn read_messages(
+    id: usize,
+    reader: BufReader<TcpStream>,
+    connection: &Connection,
+    event_tx: &Sender<NetEvent>,
+) {
+    let Ok(writer) = reader.get_ref().try_clone() else {
+        return;
+    };
+    if let Ok(mut connection) = connection.lock() {
+        if let Some((_, old)) = connection.replace((id, writer)) {
+            let _ = old.shutdown(Shutdown::Both);
+        }
+    }
+    let _ = event_tx.send(NetEvent::Connected);
+
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let event = NetMessage::parse(line)
+            .map(NetEvent::Message)
+            .unwrap_or_else(|| NetEvent::Unreadable(line.to_string()));
+        if event_tx.send(event).is_err() {
+            return;
+        }
+    }
+    // Only the connection still in use going away counts, not one that's already been replaced
+    if let Ok(mut connection) = connection.lock() {
+        if connection
+            .as_ref()
+            .is_some_and(|(current, _)| *current == id)
+        {
+            *connection = None;
+            let _ = event_tx.send(NetEvent::Disconnected);
+        }
+    }
+}
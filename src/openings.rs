@@ -0,0 +1,245 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{
+    board::{BoardState, Variant},
+    r#move::Move,
+};
+
+// A named opening and the moves that reach it from the usual start, in SAN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+    pub moves: &'static str,
+}
+impl Opening {
+    // The position the opening's moves reach from the usual start, or None if one of them isn't legal
+    pub fn board(&self) -> Option<BoardState> {
+        let mut board = BoardState::from_fen(String::from(crate::START_POS_CHESS)).ok()?;
+        for san in self.moves.split_whitespace() {
+            let played = Move::from_san(&board, san)?;
+            board.make_move(played).ok()?;
+        }
+        Some(board)
+    }
+}
+
+// The openings that get named, loosely after the ECO classification. Deeper lines come after the ones they grow from
+#[rustfmt::skip]
+pub const OPENINGS: &[Opening] = &[
+    Opening { eco: "A00", name: "Polish Opening", moves: "b4" },
+    Opening { eco: "A00", name: "Grob Opening", moves: "g4" },
+    Opening { eco: "A00", name: "Hungarian Opening", moves: "g3" },
+    Opening { eco: "A00", name: "Van't Kruijs Opening", moves: "e3" },
+    Opening { eco: "A00", name: "Mieses Opening", moves: "d3" },
+    Opening { eco: "A01", name: "Nimzo-Larsen Attack", moves: "b3" },
+    Opening { eco: "A02", name: "Bird Opening", moves: "f4" },
+    Opening { eco: "A02", name: "Bird Opening: From's Gambit", moves: "f4 e5" },
+    Opening { eco: "A03", name: "Bird Opening: Dutch Variation", moves: "f4 d5" },
+    Opening { eco: "A04", name: "Zukertort Opening", moves: "Nf3" },
+    Opening { eco: "A04", name: "Zukertort Opening: Sicilian Invitation", moves: "Nf3 c5" },
+    Opening { eco: "A05", name: "Zukertort Opening", moves: "Nf3 Nf6" },
+    Opening { eco: "A06", name: "Zukertort Opening", moves: "Nf3 d5" },
+    Opening { eco: "A07", name: "King's Indian Attack", moves: "Nf3 d5 g3" },
+    Opening { eco: "A09", name: "Reti Opening", moves: "Nf3 d5 c4" },
+    Opening { eco: "A10", name: "English Opening", moves: "c4" },
+    Opening { eco: "A13", name: "English Opening: Agincourt Defense", moves: "c4 e6" },
+    Opening { eco: "A15", name: "English Opening: Anglo-Indian Defense", moves: "c4 Nf6" },
+    Opening { eco: "A20", name: "English Opening: King's English Variation", moves: "c4 e5" },
+    Opening { eco: "A21", name: "English Opening: Reversed Sicilian", moves: "c4 e5 Nc3" },
+    Opening { eco: "A22", name: "English Opening: Two Knights Variation", moves: "c4 e5 Nc3 Nf6" },
+    Opening { eco: "A25", name: "English Opening: Reversed Closed Sicilian", moves: "c4 e5 Nc3 Nc6" },
+    Opening { eco: "A30", name: "English Opening: Symmetrical Variation", moves: "c4 c5" },
+    Opening { eco: "A40", name: "Queen's Pawn Game", moves: "d4" },
+    Opening { eco: "A40", name: "Englund Gambit", moves: "d4 e5" },
+    Opening { eco: "A43", name: "Old Benoni Defense", moves: "d4 c5" },
+    Opening { eco: "A45", name: "Indian Defense", moves: "d4 Nf6" },
+    Opening { eco: "A45", name: "Trompowsky Attack", moves: "d4 Nf6 Bg5" },
+    Opening { eco: "A46", name: "Indian Defense: Knights Variation", moves: "d4 Nf6 Nf3" },
+    Opening { eco: "A46", name: "Torre Attack", moves: "d4 Nf6 Nf3 e6 Bg5" },
+    Opening { eco: "A48", name: "London System", moves: "d4 Nf6 Nf3 g6 Bf4" },
+    Opening { eco: "A50", name: "Indian Defense: Normal Variation", moves: "d4 Nf6 c4" },
+    Opening { eco: "A51", name: "Budapest Defense", moves: "d4 Nf6 c4 e5" },
+    Opening { eco: "A53", name: "Old Indian Defense", moves: "d4 Nf6 c4 d6" },
+    Opening { eco: "A56", name: "Benoni Defense", moves: "d4 Nf6 c4 c5" },
+    Opening { eco: "A57", name: "Benko Gambit", moves: "d4 Nf6 c4 c5 d5 b5" },
+    Opening { eco: "A60", name: "Benoni Defense: Modern Variation", moves: "d4 Nf6 c4 c5 d5 e6" },
+    Opening { eco: "A80", name: "Dutch Defense", moves: "d4 f5" },
+    Opening { eco: "A86", name: "Dutch Defense: Leningrad Variation", moves: "d4 f5 c4 Nf6 g3 g6" },
+    Opening { eco: "B00", name: "King's Pawn Game", moves: "e4" },
+    Opening { eco: "B00", name: "Nimzowitsch Defense", moves: "e4 Nc6" },
+    Opening { eco: "B00", name: "Owen Defense", moves: "e4 b6" },
+    Opening { eco: "B01", name: "Scandinavian Defense", moves: "e4 d5" },
+    Opening { eco: "B01", name: "Scandinavian Defense: Mieses-Kotroc Variation", moves: "e4 d5 exd5 Qxd5" },
+    Opening { eco: "B01", name: "Scandinavian Defense: Modern Variation", moves: "e4 d5 exd5 Nf6" },
+    Opening { eco: "B02", name: "Alekhine Defense", moves: "e4 Nf6" },
+    Opening { eco: "B03", name: "Alekhine Defense: Four Pawns Attack", moves: "e4 Nf6 e5 Nd5 d4 d6 c4 Nb6 f4" },
+    Opening { eco: "B04", name: "Alekhine Defense: Modern Variation", moves: "e4 Nf6 e5 Nd5 d4 d6 Nf3" },
+    Opening { eco: "B06", name: "Modern Defense", moves: "e4 g6" },
+    Opening { eco: "B07", name: "Pirc Defense", moves: "e4 d6 d4 Nf6 Nc3 g6" },
+    Opening { eco: "B09", name: "Pirc Defense: Austrian Attack", moves: "e4 d6 d4 Nf6 Nc3 g6 f4" },
+    Opening { eco: "B10", name: "Caro-Kann Defense", moves: "e4 c6" },
+    Opening { eco: "B12", name: "Caro-Kann Defense: Advance Variation", moves: "e4 c6 d4 d5 e5" },
+    Opening { eco: "B13", name: "Caro-Kann Defense: Exchange Variation", moves: "e4 c6 d4 d5 exd5 cxd5" },
+    Opening { eco: "B15", name: "Caro-Kann Defense: Main Line", moves: "e4 c6 d4 d5 Nc3" },
+    Opening { eco: "B17", name: "Caro-Kann Defense: Karpov Variation", moves: "e4 c6 d4 d5 Nc3 dxe4 Nxe4 Nd7" },
+    Opening { eco: "B18", name: "Caro-Kann Defense: Classical Variation", moves: "e4 c6 d4 d5 Nc3 dxe4 Nxe4 Bf5" },
+    Opening { eco: "B20", name: "Sicilian Defense", moves: "e4 c5" },
+    Opening { eco: "B21", name: "Sicilian Defense: Smith-Morra Gambit", moves: "e4 c5 d4 cxd4 c3" },
+    Opening { eco: "B22", name: "Sicilian Defense: Alapin Variation", moves: "e4 c5 c3" },
+    Opening { eco: "B23", name: "Sicilian Defense: Closed", moves: "e4 c5 Nc3" },
+    Opening { eco: "B23", name: "Sicilian Defense: Grand Prix Attack", moves: "e4 c5 Nc3 Nc6 f4" },
+    Opening { eco: "B27", name: "Sicilian Defense", moves: "e4 c5 Nf3" },
+    Opening { eco: "B30", name: "Sicilian Defense: Old Sicilian", moves: "e4 c5 Nf3 Nc6" },
+    Opening { eco: "B30", name: "Sicilian Defense: Rossolimo Variation", moves: "e4 c5 Nf3 Nc6 Bb5" },
+    Opening { eco: "B32", name: "Sicilian Defense: Open", moves: "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4" },
+    Opening { eco: "B33", name: "Sicilian Defense: Sveshnikov Variation", moves: "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 Nf6 Nc3 e5" },
+    Opening { eco: "B34", name: "Sicilian Defense: Accelerated Dragon", moves: "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 g6" },
+    Opening { eco: "B40", name: "Sicilian Defense: French Variation", moves: "e4 c5 Nf3 e6" },
+    Opening { eco: "B41", name: "Sicilian Defense: Kan Variation", moves: "e4 c5 Nf3 e6 d4 cxd4 Nxd4 a6" },
+    Opening { eco: "B44", name: "Sicilian Defense: Taimanov Variation", moves: "e4 c5 Nf3 e6 d4 cxd4 Nxd4 Nc6" },
+    Opening { eco: "B50", name: "Sicilian Defense: Modern Variations", moves: "e4 c5 Nf3 d6" },
+    Opening { eco: "B51", name: "Sicilian Defense: Moscow Variation", moves: "e4 c5 Nf3 d6 Bb5+" },
+    Opening { eco: "B53", name: "Sicilian Defense: Chekhover Variation", moves: "e4 c5 Nf3 d6 d4 cxd4 Qxd4" },
+    Opening { eco: "B54", name: "Sicilian Defense: Modern Variations, Main Line", moves: "e4 c5 Nf3 d6 d4 cxd4 Nxd4" },
+    Opening { eco: "B56", name: "Sicilian Defense: Classical Variation", moves: "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 Nc6" },
+    Opening { eco: "B70", name: "Sicilian Defense: Dragon Variation", moves: "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 g6" },
+    Opening { eco: "B80", name: "Sicilian Defense: Scheveningen Variation", moves: "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 e6" },
+    Opening { eco: "B90", name: "Sicilian Defense: Najdorf Variation", moves: "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6" },
+    Opening { eco: "B90", name: "Sicilian Defense: Najdorf Variation, English Attack", moves: "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 Be3" },
+    Opening { eco: "C00", name: "French Defense", moves: "e4 e6" },
+    Opening { eco: "C01", name: "French Defense: Exchange Variation", moves: "e4 e6 d4 d5 exd5" },
+    Opening { eco: "C02", name: "French Defense: Advance Variation", moves: "e4 e6 d4 d5 e5" },
+    Opening { eco: "C03", name: "French Defense: Tarrasch Variation", moves: "e4 e6 d4 d5 Nd2" },
+    Opening { eco: "C10", name: "French Defense: Rubinstein Variation", moves: "e4 e6 d4 d5 Nc3 dxe4" },
+    Opening { eco: "C11", name: "French Defense: Classical Variation", moves: "e4 e6 d4 d5 Nc3 Nf6" },
+    Opening { eco: "C15", name: "French Defense: Winawer Variation", moves: "e4 e6 d4 d5 Nc3 Bb4" },
+    Opening { eco: "C20", name: "King's Pawn Game", moves: "e4 e5" },
+    Opening { eco: "C21", name: "Center Game", moves: "e4 e5 d4" },
+    Opening { eco: "C21", name: "Danish Gambit", moves: "e4 e5 d4 exd4 c3" },
+    Opening { eco: "C23", name: "Bishop's Opening", moves: "e4 e5 Bc4" },
+    Opening { eco: "C25", name: "Vienna Game", moves: "e4 e5 Nc3" },
+    Opening { eco: "C26", name: "Vienna Game: Falkbeer Variation", moves: "e4 e5 Nc3 Nf6" },
+    Opening { eco: "C29", name: "Vienna Game: Vienna Gambit", moves: "e4 e5 Nc3 Nf6 f4" },
+    Opening { eco: "C30", name: "King's Gambit", moves: "e4 e5 f4" },
+    Opening { eco: "C31", name: "King's Gambit Declined: Falkbeer Countergambit", moves: "e4 e5 f4 d5" },
+    Opening { eco: "C33", name: "King's Gambit Accepted", moves: "e4 e5 f4 exf4" },
+    Opening { eco: "C40", name: "King's Knight Opening", moves: "e4 e5 Nf3" },
+    Opening { eco: "C40", name: "Latvian Gambit", moves: "e4 e5 Nf3 f5" },
+    Opening { eco: "C41", name: "Philidor Defense", moves: "e4 e5 Nf3 d6" },
+    Opening { eco: "C42", name: "Petrov's Defense", moves: "e4 e5 Nf3 Nf6" },
+    Opening { eco: "C42", name: "Petrov's Defense: Stafford Gambit", moves: "e4 e5 Nf3 Nf6 Nxe5 Nc6" },
+    Opening { eco: "C44", name: "King's Knight Opening: Normal Variation", moves: "e4 e5 Nf3 Nc6" },
+    Opening { eco: "C44", name: "Ponziani Opening", moves: "e4 e5 Nf3 Nc6 c3" },
+    Opening { eco: "C44", name: "Scotch Game", moves: "e4 e5 Nf3 Nc6 d4" },
+    Opening { eco: "C44", name: "Scotch Gambit", moves: "e4 e5 Nf3 Nc6 d4 exd4 Bc4" },
+    Opening { eco: "C45", name: "Scotch Game: Main Line", moves: "e4 e5 Nf3 Nc6 d4 exd4 Nxd4" },
+    Opening { eco: "C46", name: "Three Knights Opening", moves: "e4 e5 Nf3 Nc6 Nc3" },
+    Opening { eco: "C47", name: "Four Knights Game", moves: "e4 e5 Nf3 Nc6 Nc3 Nf6" },
+    Opening { eco: "C48", name: "Four Knights Game: Spanish Variation", moves: "e4 e5 Nf3 Nc6 Nc3 Nf6 Bb5" },
+    Opening { eco: "C50", name: "Italian Game", moves: "e4 e5 Nf3 Nc6 Bc4" },
+    Opening { eco: "C50", name: "Italian Game: Hungarian Defense", moves: "e4 e5 Nf3 Nc6 Bc4 Be7" },
+    Opening { eco: "C50", name: "Italian Game: Giuoco Piano", moves: "e4 e5 Nf3 Nc6 Bc4 Bc5" },
+    Opening { eco: "C50", name: "Italian Game: Giuoco Pianissimo", moves: "e4 e5 Nf3 Nc6 Bc4 Bc5 d3" },
+    Opening { eco: "C51", name: "Italian Game: Evans Gambit", moves: "e4 e5 Nf3 Nc6 Bc4 Bc5 b4" },
+    Opening { eco: "C53", name: "Italian Game: Classical Variation", moves: "e4 e5 Nf3 Nc6 Bc4 Bc5 c3" },
+    Opening { eco: "C55", name: "Italian Game: Two Knights Defense", moves: "e4 e5 Nf3 Nc6 Bc4 Nf6" },
+    Opening { eco: "C57", name: "Italian Game: Two Knights Defense, Traxler Counterattack", moves: "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5 Bc5" },
+    Opening { eco: "C57", name: "Italian Game: Two Knights Defense, Fried Liver Attack", moves: "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5 d5 exd5 Nxd5 Nxf7" },
+    Opening { eco: "C58", name: "Italian Game: Two Knights Defense, Polerio Defense", moves: "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5 d5 exd5 Na5" },
+    Opening { eco: "C60", name: "Ruy Lopez", moves: "e4 e5 Nf3 Nc6 Bb5" },
+    Opening { eco: "C62", name: "Ruy Lopez: Steinitz Defense", moves: "e4 e5 Nf3 Nc6 Bb5 d6" },
+    Opening { eco: "C63", name: "Ruy Lopez: Schliemann Defense", moves: "e4 e5 Nf3 Nc6 Bb5 f5" },
+    Opening { eco: "C64", name: "Ruy Lopez: Classical Variation", moves: "e4 e5 Nf3 Nc6 Bb5 Bc5" },
+    Opening { eco: "C65", name: "Ruy Lopez: Berlin Defense", moves: "e4 e5 Nf3 Nc6 Bb5 Nf6" },
+    Opening { eco: "C68", name: "Ruy Lopez: Exchange Variation", moves: "e4 e5 Nf3 Nc6 Bb5 a6 Bxc6" },
+    Opening { eco: "C70", name: "Ruy Lopez: Morphy Defense", moves: "e4 e5 Nf3 Nc6 Bb5 a6" },
+    Opening { eco: "C80", name: "Ruy Lopez: Open", moves: "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Nxe4" },
+    Opening { eco: "C84", name: "Ruy Lopez: Closed", moves: "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7" },
+    Opening { eco: "C89", name: "Ruy Lopez: Marshall Attack", moves: "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7 Re1 b5 Bb3 O-O c3 d5" },
+    Opening { eco: "D00", name: "Queen's Pawn Game", moves: "d4 d5" },
+    Opening { eco: "D00", name: "Blackmar-Diemer Gambit", moves: "d4 d5 e4" },
+    Opening { eco: "D00", name: "Queen's Pawn Game: Accelerated London System", moves: "d4 d5 Bf4" },
+    Opening { eco: "D02", name: "Queen's Pawn Game: London System", moves: "d4 d5 Nf3 Nf6 Bf4" },
+    Opening { eco: "D04", name: "Queen's Pawn Game: Colle System", moves: "d4 d5 Nf3 Nf6 e3" },
+    Opening { eco: "D06", name: "Queen's Gambit", moves: "d4 d5 c4" },
+    Opening { eco: "D07", name: "Queen's Gambit Declined: Chigorin Defense", moves: "d4 d5 c4 Nc6" },
+    Opening { eco: "D08", name: "Queen's Gambit Declined: Albin Countergambit", moves: "d4 d5 c4 e5" },
+    Opening { eco: "D10", name: "Slav Defense", moves: "d4 d5 c4 c6" },
+    Opening { eco: "D11", name: "Slav Defense: Modern Line", moves: "d4 d5 c4 c6 Nf3" },
+    Opening { eco: "D17", name: "Slav Defense: Czech Variation", moves: "d4 d5 c4 c6 Nf3 Nf6 Nc3 dxc4 a4 Bf5" },
+    Opening { eco: "D20", name: "Queen's Gambit Accepted", moves: "d4 d5 c4 dxc4" },
+    Opening { eco: "D30", name: "Queen's Gambit Declined", moves: "d4 d5 c4 e6" },
+    Opening { eco: "D31", name: "Queen's Gambit Declined: Queen's Knight Variation", moves: "d4 d5 c4 e6 Nc3" },
+    Opening { eco: "D32", name: "Tarrasch Defense", moves: "d4 d5 c4 e6 Nc3 c5" },
+    Opening { eco: "D35", name: "Queen's Gambit Declined: Exchange Variation", moves: "d4 d5 c4 e6 Nc3 Nf6 cxd5" },
+    Opening { eco: "D37", name: "Queen's Gambit Declined: Three Knights Variation", moves: "d4 d5 c4 e6 Nc3 Nf6 Nf3" },
+    Opening { eco: "D43", name: "Semi-Slav Defense", moves: "d4 d5 c4 e6 Nc3 Nf6 Nf3 c6" },
+    Opening { eco: "D80", name: "Grunfeld Defense", moves: "d4 Nf6 c4 g6 Nc3 d5" },
+    Opening { eco: "D85", name: "Grunfeld Defense: Exchange Variation", moves: "d4 Nf6 c4 g6 Nc3 d5 cxd5 Nxd5" },
+    Opening { eco: "E00", name: "Catalan Opening", moves: "d4 Nf6 c4 e6 g3" },
+    Opening { eco: "E11", name: "Bogo-Indian Defense", moves: "d4 Nf6 c4 e6 Nf3 Bb4+" },
+    Opening { eco: "E12", name: "Queen's Indian Defense", moves: "d4 Nf6 c4 e6 Nf3 b6" },
+    Opening { eco: "E20", name: "Nimzo-Indian Defense", moves: "d4 Nf6 c4 e6 Nc3 Bb4" },
+    Opening { eco: "E32", name: "Nimzo-Indian Defense: Classical Variation", moves: "d4 Nf6 c4 e6 Nc3 Bb4 Qc2" },
+    Opening { eco: "E40", name: "Nimzo-Indian Defense: Rubinstein Variation", moves: "d4 Nf6 c4 e6 Nc3 Bb4 e3" },
+    Opening { eco: "E60", name: "King's Indian Defense", moves: "d4 Nf6 c4 g6" },
+    Opening { eco: "E61", name: "King's Indian Defense", moves: "d4 Nf6 c4 g6 Nc3 Bg7" },
+    Opening { eco: "E80", name: "King's Indian Defense: Samisch Variation", moves: "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 f3" },
+    Opening { eco: "E90", name: "King's Indian Defense: Normal Variation", moves: "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 Nf3" },
+    Opening { eco: "E92", name: "King's Indian Defense: Classical Variation", moves: "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 Nf3 O-O Be2 e5" },
+];
+
+// Each opening's position against where it is in OPENINGS, built the first time it's needed
+static POSITIONS: OnceLock<HashMap<String, usize>> = OnceLock::new();
+
+/*
+    What a position is looked up by: the pieces, side to move and castling rights. The en passant square is left
+    out, since the board keeps it after a double step whether or not a pawn could take, and that would split
+    transpositions that end on different pawn moves
+*/
+fn position_key(board: &BoardState) -> String {
+    board
+        .as_fen()
+        .split(' ')
+        .take(3)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+fn positions() -> &'static HashMap<String, usize> {
+    POSITIONS.get_or_init(|| {
+        let mut positions = HashMap::new();
+        for (index, opening) in OPENINGS.iter().enumerate() {
+            if let Some(board) = opening.board() {
+                positions.entry(position_key(&board)).or_insert(index);
+            }
+        }
+        positions
+    })
+}
+
+/*
+    Names the opening a game from `start` went into with `moves`. Positions are matched rather than move orders, so
+    a line that transposes into a known one is named after it, and the last known position the game passed through
+    is the one that counts
+*/
+pub fn identify(
+    start: &BoardState,
+    moves: impl IntoIterator<Item = Move>,
+) -> Option<&'static Opening> {
+    if start.variant != Variant::Standard {
+        return None;
+    }
+    let positions = positions();
+    let mut board = *start;
+    let mut opening = None;
+    for played in moves {
+        if board.make_move(played).is_err() {
+            break;
+        }
+        if let Some(index) = positions.get(&position_key(&board)) {
+            opening = Some(&OPENINGS[*index]);
+        }
+    }
+    opening
+}
@@ -2,20 +2,58 @@
 // TODO: Add a timer that is passed to the opponent
 
 use std::{
-    cmp::Ordering,
+    cmp::Reverse,
     fmt::{self, Display, Formatter},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 
 use rand::{seq::IndexedRandom, Rng};
 
 use crate::{
-    bitboard::{Bitboard, PieceType, Team},
-    board::BoardState,
-    r#move::{self, Move, MoveError, Piece},
+    bitboard::{PieceType, Team, MAX_SQUARES, PIECE_TYPES},
+    board::{hill_squares, BoardState, GameStatus, History, Variant},
+    r#move::{self, Move, MoveError, PackedMove},
 };
 
-const SCORES: [(PieceType, i32); 7] = [
+pub mod tapered;
+pub mod transposition;
+pub mod uci;
+
+use transposition::{
+    entries_for_megabytes, Bound, SharedTable, TranspositionTable, DEFAULT_TT_ENTRIES,
+};
+use uci::{SharedEngine, UciTime, DEFAULT_UCI_MOVE_TIME};
+
+// Castling is scored on the move that makes it, so positions reached by castling are stored apart from the rest
+const CASTLED_KEY: u64 = 0x0C45_71ED_0C45_71ED;
+// Late move reductions start after this many moves at a node, and only with this much depth left to cut into
+const LMR_FULL_DEPTH_MOVES: usize = 3;
+const LMR_MIN_BUDGET: i32 = 3;
+// Checks searched a ply deeper along any one line, so a long run of them can't keep a search from finishing
+const MAX_CHECK_EXTENSIONS: i32 = 4;
+// What a king is worth in King of the Hill by how many king moves it is from the centre
+const HILL_DISTANCE_SCORES: [i32; 4] = [0, 150, 60, 20];
+// What having given 0, 1 and 2 checks is worth in Three-check. The third wins outright
+const CHECKS_GIVEN_SCORES: [i32; 3] = [0, 120, 400];
+// What each rank a king has climbed is worth in Racing Kings
+const RACE_RANK_SCORE: i32 = 50;
+/*
+    Being mated is worse than any material loss. A mate `n` plies from the root scores MATE_SCORE - n for the side
+    giving it, so quicker mates score higher, and anything within MAX_MATE_PLY of MATE_SCORE is a mate. Scores stay
+    clear of i32::MIN so they can always be negated
+*/
+pub const MATE_SCORE: i32 = 100000000;
+const MAX_MATE_PLY: i32 = 1000;
+const INFINITE_SCORE: i32 = i32::MAX;
+// Reading the clock at every node would cost more than it saves, so it's read once per this many
+const STOP_CHECK_NODES: u64 = 1024;
+
+pub const SCORES: [(PieceType, i32); PIECE_TYPES] = [
     (PieceType::None, 0),
     (PieceType::Pawn, 100),
     (PieceType::Knight, 300),
@@ -23,15 +61,9 @@ const SCORES: [(PieceType, i32); 7] = [
     (PieceType::Rook, 500),
     (PieceType::Queen, 900),
     (PieceType::King, 1000000),
-];
-const SAC_SCORES: [(PieceType, i32); 7] = [
-    (PieceType::None, 0),
-    (PieceType::Pawn, 50),
-    (PieceType::Knight, 150),
-    (PieceType::Bishop, 150),
-    (PieceType::Rook, 350),
-    (PieceType::Queen, 1100),
-    (PieceType::King, 1000000),
+    (PieceType::Archbishop, 825),
+    (PieceType::Chancellor, 875),
+    (PieceType::Nightrider, 550),
 ];
 #[derive(Debug, Copy, Clone)]
 struct NegamaxEval {
@@ -56,376 +88,1520 @@ impl Display for EvaluationList {
         Ok(())
     }
 }
-#[derive(Debug, Copy, Clone)]
-pub enum ChessOpponent {
-    Randy,
-    Matt(i32),
-    Ada(Duration),
-}
+// How many of the runner-up root moves get their refutation spelled out in a search explanation
+const EXPLAINED_ALTERNATIVES: usize = 3;
 
-fn pick_random_move(board: BoardState) -> Option<Move> {
-    let legals = board.prune_moves_for_team(board.get_legal_moves(), board.active_team);
-    legals.choose(&mut rand::rng()).copied()
+#[derive(Debug, Copy, Clone, Default)]
+pub struct EvalBreakdown {
+    pub material: i32,
+    pub placement: i32, // Kings and pawns, weighted between the middlegame and endgame by what's left
+    pub castling: i32,
+    pub mate: i32,
+    pub forks: i32,
+    pub variant: i32, // How close each side is to winning by its variant's own rule
 }
-
-fn handle_move_result(
-    result_type: &str,
-    result: Result<(), MoveError>,
-    ava_move: Move,
-    search_budget: i32,
-    virtual_board: &BoardState,
-) {
-    if let Err(vm_err) = result {
-        tracing::info!(
-            "RECURSIVE {result_type} at search budget {search_budget}: {vm_err:?}; MOVE: {ava_move}"
-        );
-        BoardState::render_piece_list(virtual_board.piece_list.to_vec());
-        tracing::info!("{}", virtual_board.get_team_coverage(Team::White))
+impl EvalBreakdown {
+    pub fn total(&self) -> i32 {
+        self.material + self.placement + self.castling + self.mate + self.forks + self.variant
+    }
+    fn signed(self, sign: i32) -> Self {
+        EvalBreakdown {
+            material: self.material * sign,
+            placement: self.placement * sign,
+            castling: self.castling * sign,
+            mate: self.mate * sign,
+            forks: self.forks * sign,
+            variant: self.variant * sign,
+        }
     }
 }
-fn eval_max(
-    board: &mut BoardState,
-    ava_move: Move,
-    search_budget: i32,
-    mut best_white: i32,
-    mut best_black: i32,
-) {
+impl Display for EvalBreakdown {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "material {}, placement {}, castling {}, mate {}, forks {}",
+            self.material, self.placement, self.castling, self.mate, self.forks
+        )?;
+        if self.variant != 0 {
+            write!(f, ", variant {}", self.variant)?;
+        }
+        Ok(())
+    }
 }
-fn evaluate_move(
-    board: &mut BoardState,
-    ava_move: Move,
-    search_budget: i32,
-    mut best_white: i32,
-    mut best_black: i32,
-) -> i32 {
-    // SUPER EXPENSIVE to recurse over it
-    let virtual_board = board;
-    let who_to_play = if virtual_board.active_team == Team::White {
-        1
-    } else {
-        -1
-    };
-
-    let risky = virtual_board.opponent_attacking_square(ava_move.target);
-
-    let cap_score_idx = SCORES.iter().position(|(piece_type, _scre)| {
-        piece_type
-            == &ava_move
-                .captures
-                .unwrap_or(Piece {
-                    piece_type: PieceType::None,
-                    position: ava_move.target,
-                    team: virtual_board.active_team.opponent(),
-                })
-                .piece_type
-    });
-
-    let capture_score = SCORES[cap_score_idx.unwrap()].1;
-    let piece_score = {
-        let score_pt = SCORES.iter().position(|(piece_type, _scre)| {
-            piece_type == &virtual_board.piece_list[ava_move.start]
-        });
-        SCORES[score_pt.unwrap()].1 * who_to_play
-    };
 
-    let good_trade = capture_score - piece_score > 0;
+#[derive(Debug, Clone)]
+pub struct RefutedAlternative {
+    pub san: String,
+    pub eval: i32,
+    pub refutation: Option<String>,
+}
 
-    let sacrifice_score = {
-        let score_pt = SAC_SCORES.iter().position(|(piece_type, _scre)| {
-            piece_type == &virtual_board.piece_list[ava_move.start]
-        });
-        SAC_SCORES[score_pt.unwrap()].1 * who_to_play
-    };
+// Post-search report on why the engine picked its move. Evals are from the mover's point of view.
+#[derive(Debug, Clone)]
+pub struct SearchExplanation {
+    pub chosen: String,
+    pub eval: i32,
+    pub depth: i32,
+    pub breakdown: EvalBreakdown,
+    pub alternatives: Vec<RefutedAlternative>,
+    pub pv: Vec<String>,
+}
+impl SearchExplanation {
+    // `pv` is the line the search found, which is used over working one out afresh when it starts with `chosen`
+    fn new(
+        board: &BoardState,
+        chosen: NegamaxEval,
+        ranked: &EvaluationList,
+        depth: i32,
+        pv: &[Move],
+    ) -> Self {
+        let sign = if board.active_team == Team::White {
+            1
+        } else {
+            -1
+        };
 
-    let mut eval_score = 0;
+        let mut board_after = *board;
+        let breakdown = if board_after.make_move(chosen.legal_move).is_ok() {
+            let status = board_after.status();
+            score_terms(&board_after, status, chosen.legal_move.is_castle, sign).signed(sign)
+        } else {
+            EvalBreakdown::default()
+        };
 
-    handle_move_result(
-        "MOVE",
-        virtual_board.make_move(ava_move),
-        ava_move,
-        search_budget,
-        virtual_board,
-    );
-    let legals_all = virtual_board.get_legal_moves();
-    let legals = virtual_board.prune_moves_for_team(legals_all.clone(), virtual_board.active_team);
+        let alternatives = ranked
+            .0
+            .iter()
+            .filter(|alternative| alternative.legal_move != chosen.legal_move)
+            .take(EXPLAINED_ALTERNATIVES)
+            .map(|alternative| {
+                let mut reply_board = *board;
+                let refutation = reply_board
+                    .make_move(alternative.legal_move)
+                    .ok()
+                    .and_then(|_| best_reply(&reply_board))
+                    .map(|reply| reply.to_san(&reply_board));
 
-    eval_score += evaluate(virtual_board, legals_all);
+                RefutedAlternative {
+                    san: alternative.legal_move.to_san(board),
+                    eval: alternative.eval,
+                    refutation,
+                }
+            })
+            .collect();
 
-    if risky && !good_trade {
-        //eval_score -= sacrifice_score
+        SearchExplanation {
+            chosen: chosen.legal_move.to_san(board),
+            eval: chosen.eval,
+            depth,
+            breakdown,
+            alternatives,
+            pv: if pv.first() == Some(&chosen.legal_move) {
+                line_to_san(board, pv)
+            } else {
+                expected_line(board, chosen.legal_move, depth)
+            },
+        }
+    }
+}
+impl Display for SearchExplanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} ({}, depth {})",
+            self.chosen,
+            format_score(self.eval),
+            self.depth
+        )?;
+        writeln!(f, "{}", self.breakdown)?;
+        writeln!(f, "PV: {}", self.pv.join(" "))?;
+        for alternative in &self.alternatives {
+            write!(
+                f,
+                "Not {} ({})",
+                alternative.san,
+                format_score(alternative.eval)
+            )?;
+            if let Some(refutation) = &alternative.refutation {
+                write!(f, ": {refutation}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
+}
 
-    if ava_move.is_castle {
-        eval_score += 1200 * who_to_play
+// Progress figures reported while a search runs, mirroring the fields of a UCI info line
+#[derive(Debug, Clone, Default)]
+pub struct SearchInfo {
+    pub depth: i32,
+    pub seldepth: i32, // Deepest ply any line reached
+    pub score: i32,
+    pub nodes: u64,
+    pub hashfull: u32, // Permille of the transposition table in use
+    // Transposition table lookups and how many of them found an entry
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub cutoffs: u64, // Positions where a move failed high and the rest of the moves went unsearched
+    pub elapsed: Duration,
+    pub best_move: Option<Move>,
+    pub pv: Vec<Move>, // The line the search expects, starting with the best move. Empty if it doesn't track one
+}
+impl SearchInfo {
+    // Nodes searched per second of thinking
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.elapsed.as_secs_f64().max(0.001)) as u64
     }
+    pub fn to_uci(&self) -> String {
+        let mut line = format!(
+            "info depth {} seldepth {} score {} nodes {} nps {} hashfull {} time {}",
+            self.depth,
+            self.seldepth,
+            match mate_in(self.score) {
+                Some(moves) => format!("mate {moves}"),
+                None => format!("cp {}", self.score),
+            },
+            self.nodes,
+            self.nps(),
+            self.hashfull,
+            self.elapsed.as_millis()
+        );
+        if !self.pv.is_empty() {
+            let pv: Vec<String> = self.pv.iter().map(|pv_move| pv_move.to_uci()).collect();
+            line.push_str(&format!(" pv {}", pv.join(" ")));
+        } else if let Some(best_move) = self.best_move {
+            line.push_str(&format!(" pv {}", best_move.to_uci()));
+        }
+        line
+    }
+}
 
-    if virtual_board.active_team_checkmate {
-        eval_score -= 100000000 * who_to_play;
+// Everything an opponent hands back when it's done thinking
+#[derive(Debug, Clone, Default)]
+pub struct SearchOutcome {
+    pub best_move: Option<Move>,
+    pub explanation: Option<SearchExplanation>,
+    pub info: SearchInfo,
+}
+
+// Running totals of an engine's searches over a whole game, for the summary once it's over
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SearchStats {
+    pub moves: u32,
+    pub total_depth: u64,
+    pub total_nodes: u64,
+    pub total_time: Duration,
+    pub ponder_hits: u32, // Replies the engine had already been thinking about while the player moved
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+}
+impl SearchStats {
+    // Adds one finished search, along with how long the engine took over the move
+    pub fn record(&mut self, info: &SearchInfo, time: Duration) {
+        self.moves += 1;
+        self.total_depth += info.depth.max(0) as u64;
+        self.total_nodes += info.nodes;
+        self.total_time += time;
+        self.tt_probes += info.tt_probes;
+        self.tt_hits += info.tt_hits;
     }
-    let center_control_bits = Bitboard {
-        state: 0x1818000000,
-    };
-    let center_control =
-        virtual_board.get_team_coverage(virtual_board.active_team) & center_control_bits;
-    if center_control.state > 0 {
-        // For some reason negatively attributing it makes it focus on the center
-        //  eval_score -= (center_control.state.count_ones() as i32) * who_to_play * 3;
+    pub fn average_depth(&self) -> f32 {
+        self.total_depth as f32 / self.moves.max(1) as f32
+    }
+    pub fn average_nodes(&self) -> u64 {
+        self.total_nodes / self.moves.max(1) as u64
+    }
+    pub fn average_time(&self) -> Duration {
+        self.total_time / self.moves.max(1)
+    }
+    // Share of transposition table lookups that found an entry, from 0 to 1
+    pub fn tt_hit_rate(&self) -> f32 {
+        self.tt_hits as f32 / self.tt_probes.max(1) as f32
+    }
+}
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Engine moves: {}", self.moves)?;
+        writeln!(f, "Average depth: {:.1}", self.average_depth())?;
+        writeln!(f, "Average nodes: {}", self.average_nodes())?;
+        writeln!(
+            f,
+            "Average time per move: {:.2}s",
+            self.average_time().as_secs_f32()
+        )?;
+        writeln!(f, "Ponder hits: {}", self.ponder_hits)?;
+        write!(f, "TT hit rate: {:.1}%", self.tt_hit_rate() * 100.0)
     }
+}
+
+#[derive(Debug, Clone)]
+pub enum ChessOpponent {
+    Randy,
+    Matt(i32),
+    Ada(EngineOptions, SharedTable), // Ada remembers what she searched last move, so she needn't start over
+    Uci(PathBuf, UciTime, SharedEngine), // Another program, like Stockfish, that plays over UCI
+    Human, // A second player at the same machine, who makes their moves on the board like the first
+}
 
-    let forking = virtual_board.capture_bitboard[virtual_board.active_team as usize]
-        & virtual_board.get_team_coverage(virtual_board.active_team.opponent());
+pub const MAX_SKILL_LEVEL: i32 = 20;
+// Below full strength, each skill level lost takes this much off how good a move has to be for Ada to play it
+const SKILL_MARGIN_STEP: i32 = 8;
+// and makes her this much likelier to settle for a move well short of her best
+const SKILL_INACCURACY_STEP: f64 = 0.02;
+const INACCURACY_MARGIN_SCALE: i32 = 3;
+// Ratings that map onto the ends of the skill levels
+const MIN_SKILL_ELO: i32 = 800;
+const MAX_SKILL_ELO: i32 = 2400;
+// How close to level the opponent's own evals have to have stayed, in centipawns, for it to take a draw offer
+const DRAW_ACCEPT_SCORE: i32 = 30;
+// and for how many of its moves in a row
+const DRAW_ACCEPT_MOVES: usize = 3;
 
-    if forking.state.count_ones() > 1 {
-        eval_score += 50 * (forking.state.count_ones() as i32) * who_to_play;
+// Everything about how Ada plays that can be changed from the command line
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EngineOptions {
+    pub move_time: Duration,     // How long she thinks about each move
+    pub hash_mb: usize,          // Size of her transposition table
+    pub threads: usize,          // Ada searches on one thread for now, whatever this says
+    pub move_overhead: Duration, // Held back from every move's time for it to reach the board
+    pub contempt: i32, // Centipawns she counts a draw as losing by, so she plays on against weaker players
+    pub skill_level: i32, // From 0 up to MAX_SKILL_LEVEL, which is her full strength. Lower levels play weaker moves
+    pub book: bool,       // Play the loaded repertoire's moves while the game is still in it
+    pub max_depth: Option<i32>, // Stop after this many iterations, however much time is left
+}
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions {
+            move_time: DEFAULT_ADA_TIME,
+            hash_mb: 8,
+            threads: 1,
+            move_overhead: Duration::from_millis(10),
+            contempt: 0,
+            skill_level: MAX_SKILL_LEVEL,
+            book: true,
+            max_depth: None,
+        }
     }
+}
+impl EngineOptions {
+    pub fn with_move_time(move_time: Duration) -> EngineOptions {
+        EngineOptions {
+            move_time,
+            ..Default::default()
+        }
+    }
+    // The skill level for playing at about `elo`, from MIN_SKILL_ELO and below up to MAX_SKILL_ELO and above
+    pub fn skill_for_elo(elo: i32) -> i32 {
+        let elo = elo.clamp(MIN_SKILL_ELO, MAX_SKILL_ELO);
+        (elo - MIN_SKILL_ELO) * MAX_SKILL_LEVEL / (MAX_SKILL_ELO - MIN_SKILL_ELO)
+    }
+    // How many iterations deep Ada may search, with no limit at full strength unless one was asked for
+    fn depth_cap(&self) -> Option<i32> {
+        let skill_cap = (self.skill_level < MAX_SKILL_LEVEL).then_some(1 + self.skill_level / 3);
+        skill_cap.into_iter().chain(self.max_depth).min()
+    }
+    /*
+        The move Ada plays out of `ranked`, best first. At full strength that's the best one. Below it she picks at
+        random among the moves close enough to the best, and now and then from ones a good deal further off
+    */
+    fn skill_choice(&self, ranked: &EvaluationList) -> Option<NegamaxEval> {
+        let best = *ranked.0.first()?;
+        let levels_lost = MAX_SKILL_LEVEL - self.skill_level.clamp(0, MAX_SKILL_LEVEL);
+        if levels_lost == 0 {
+            return Some(best);
+        }
+        let mut rng = rand::rng();
+        let mut margin = levels_lost * SKILL_MARGIN_STEP;
+        if rng.random_bool(levels_lost as f64 * SKILL_INACCURACY_STEP) {
+            margin *= INACCURACY_MARGIN_SCALE;
+        }
+        let playable: Vec<NegamaxEval> = ranked
+            .0
+            .iter()
+            .copied()
+            .filter(|evaluated| evaluated.eval >= best.eval.saturating_sub(margin))
+            .collect();
+        playable.choose(&mut rng).copied()
+    }
+}
 
-    let jiggle = 0; //rand::rng().random_range(-1..1);
+// How far a single strength adjustment moves each opponent
+const ADA_TIME_STEP: Duration = Duration::from_millis(200);
+const ADA_MIN_TIME: Duration = Duration::from_millis(100);
+const MATT_MAX_DEPTH: i32 = 6;
 
-    if virtual_board.ply_clock > 6 {
-        //jiggle = rand::rng().random_range(-70..70);
+// Settings an opponent starts with when it is swapped in partway through a game
+const DEFAULT_MATT_DEPTH: i32 = 3;
+const DEFAULT_ADA_TIME: Duration = Duration::from_millis(400);
+// Pondering runs until the player moves, but there's nothing to gain from searching a single reply for longer
+const PONDER_TIME_LIMIT: Duration = Duration::from_secs(600);
+
+impl ChessOpponent {
+    // Ada with a table of her own to keep between moves
+    pub fn ada(options: EngineOptions) -> ChessOpponent {
+        ChessOpponent::Ada(options, SharedTable::default())
     }
-    if search_budget == 0 {
-        handle_move_result(
-            "UNMOVE",
-            virtual_board.unmake_move(ava_move),
-            ava_move,
-            search_budget,
-            virtual_board,
-        );
-        return eval_score + jiggle;
+    // The engine at `path`, which gets started when it's first asked for a move
+    pub fn uci(path: PathBuf) -> ChessOpponent {
+        ChessOpponent::Uci(
+            path,
+            UciTime::MoveTime(DEFAULT_UCI_MOVE_TIME),
+            SharedEngine::default(),
+        )
     }
+    // The next kind of opponent in line, for cycling through them
+    pub fn next_kind(&self) -> ChessOpponent {
+        match self {
+            ChessOpponent::Randy => ChessOpponent::Matt(DEFAULT_MATT_DEPTH),
+            ChessOpponent::Matt(_) => ChessOpponent::ada(EngineOptions::default()),
+            ChessOpponent::Ada(..) | ChessOpponent::Uci(..) => ChessOpponent::Human,
+            ChessOpponent::Human => ChessOpponent::Randy,
+        }
+    }
+    /*
+        Lets the opponent think longer (or search deeper) for positive steps and less for negative ones.
+        Randy picks at random and a human plays as well as they play, so there is nothing to adjust for either
+    */
+    pub fn adjust_strength(&mut self, steps: i32) {
+        match self {
+            ChessOpponent::Randy | ChessOpponent::Human => {}
+            ChessOpponent::Matt(search_budget) => {
+                *search_budget = (*search_budget + steps).clamp(1, MATT_MAX_DEPTH)
+            }
+            ChessOpponent::Ada(options, _) => {
+                let change = ADA_TIME_STEP * steps.unsigned_abs();
+                options.move_time = if steps >= 0 {
+                    options.move_time.saturating_add(change)
+                } else {
+                    options.move_time.saturating_sub(change).max(ADA_MIN_TIME)
+                };
+            }
+            // An engine on the clock runs its own time, so only a fixed move time can be changed
+            ChessOpponent::Uci(_, time, _) => {
+                if let UciTime::MoveTime(move_time) = time {
+                    let change = ADA_TIME_STEP * steps.unsigned_abs();
+                    *move_time = if steps >= 0 {
+                        move_time.saturating_add(change)
+                    } else {
+                        move_time.saturating_sub(change).max(ADA_MIN_TIME)
+                    };
+                }
+            }
+        }
+    }
+    /*
+        Thinks about `board` like `think`, but Ada also gives up as soon as `stop` is set and plays the best move
+        she has by then. The other opponents are quick enough to just finish
+    */
+    pub fn think_until(
+        &mut self,
+        board: BoardState,
+        history: &History,
+        stop: Arc<AtomicBool>,
+        progress: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchOutcome {
+        match self {
+            ChessOpponent::Ada(options, table) => {
+                ada_outcome(board, history, *options, table, false, stop, progress)
+            }
+            ChessOpponent::Matt(_)
+            | ChessOpponent::Randy
+            | ChessOpponent::Uci(..)
+            | ChessOpponent::Human => self.think(board, history, false, progress),
+        }
+    }
+    /*
+        Whether the opponent takes a draw offer, going by the scores its searches reported over the game, from its
+        own point of view. It only agrees once the game has looked level to it for its last few moves. Randy never
+        looks at the position, so he plays on. A human at the same machine has already agreed by the time it's offered
+    */
+    pub fn accepts_draw(&self, scores: &[i32]) -> bool {
+        match self {
+            ChessOpponent::Randy => false,
+            ChessOpponent::Human => true,
+            ChessOpponent::Matt(_) | ChessOpponent::Ada(..) | ChessOpponent::Uci(..) => {
+                scores.len() >= DRAW_ACCEPT_MOVES
+                    && scores[scores.len() - DRAW_ACCEPT_MOVES..]
+                        .iter()
+                        .all(|score| score.abs() <= DRAW_ACCEPT_SCORE)
+            }
+        }
+    }
+    // Whether the opponent plays a loaded repertoire's moves before thinking for itself
+    pub fn uses_book(&self) -> bool {
+        match self {
+            ChessOpponent::Ada(options, _) => options.book,
+            ChessOpponent::Human => false,
+            ChessOpponent::Randy | ChessOpponent::Matt(_) | ChessOpponent::Uci(..) => true,
+        }
+    }
+}
 
-    if virtual_board.active_team == Team::White {
-        let mut max = i32::MIN;
+// Moves until mate for a mate score, negative when it's the side to move being mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    let plies = MATE_SCORE - score.abs();
+    (0..=MAX_MATE_PLY)
+        .contains(&plies)
+        .then(|| plies.div_ceil(2) * score.signum())
+}
+// A score for people to read: centipawns like the rest of the game shows them, or how far off a mate is
+pub fn format_score(score: i32) -> String {
+    match mate_in(score) {
+        Some(moves) if moves > 0 => format!("mate in {moves}"),
+        Some(moves) => format!("mated in {}", -moves),
+        None => format!("{score}e"),
+    }
+}
+/*
+    Mate scores count plies from the root, but a position can be reached at any ply, so the table keeps them
+    counted from the position itself and they're put back when found
+*/
+fn score_to_table(score: i32, ply: i32) -> i32 {
+    match mate_in(score) {
+        Some(moves) if moves > 0 => score.saturating_add(ply),
+        Some(_) => score.saturating_sub(ply),
+        None => score,
+    }
+}
+fn score_from_table(score: i32, ply: i32) -> i32 {
+    match mate_in(score) {
+        Some(moves) if moves > 0 => score - ply,
+        Some(_) => score + ply,
+        None => score,
+    }
+}
+
+// What a piece is worth in centipawns
+pub fn piece_value(piece_type: PieceType) -> i32 {
+    SCORES
+        .iter()
+        .find(|(scored_type, _score)| *scored_type == piece_type)
+        .map_or(0, |(_scored_type, score)| *score)
+}
 
-        for legal_move in legals {
-            let move_score = evaluate_move(
-                virtual_board,
+// How many of the best root moves a search reports a line for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MultiPv(pub usize);
+
+// One root move as a search saw it, with the line it expects to follow. Evals are from the mover's point of view
+#[derive(Debug, Clone)]
+pub struct AnalysisLine {
+    pub root: Move,
+    pub eval: i32,
+    pub pv: Vec<Move>,
+}
+
+/*
+    Scores every legal move for the side to play, from their point of view, searching `search_budget` plies past
+    each one. Best moves come first
+*/
+pub fn rank_moves(
+    board: &BoardState,
+    history: &History,
+    search_budget: i32,
+    info: &mut SearchInfo,
+) -> Vec<(Move, i32)> {
+    rank_lines(board, history, search_budget, info)
+        .into_iter()
+        .map(|line| (line.root, line.eval))
+        .collect()
+}
+/*
+    The best `lines` root moves and what the search expects after each of them, best first. Every root move is
+    searched with the full window, so each one's score is exact and not just a bound
+*/
+pub fn multi_pv(
+    board: &BoardState,
+    history: &History,
+    search_budget: i32,
+    MultiPv(lines): MultiPv,
+    info: &mut SearchInfo,
+) -> Vec<AnalysisLine> {
+    let mut ranked = rank_lines(board, history, search_budget, info);
+    ranked.truncate(lines);
+    ranked
+}
+fn rank_lines(
+    board: &BoardState,
+    history: &History,
+    search_budget: i32,
+    info: &mut SearchInfo,
+) -> Vec<AnalysisLine> {
+    let legals = board.legal_moves(board.active_team);
+    let _search = tracing::debug_span!("search", depth = search_budget + 1).entered();
+    let mut search = SearchContext::new(DEFAULT_TT_ENTRIES);
+    search.played = history.clone();
+    search.info = std::mem::take(info);
+
+    let mut ranked: Vec<AnalysisLine> = legals
+        .iter()
+        .map(|packed| {
+            let legal_move = packed.unpack(board);
+            let _root = tracing::trace_span!("root_move", root = %legal_move).entered();
+            let eval = search.search_root_move(
+                &mut board.clone(),
                 legal_move,
-                search_budget - 1,
-                best_white,
-                best_black,
+                search_budget,
+                -INFINITE_SCORE,
+                INFINITE_SCORE,
             );
-            max = max.max(best_white);
-            //println!("W{best_black}, {best_white} {search_budget}");
-            if move_score >= best_black {
+            tracing::trace!(eval, nodes = search.info.nodes, "Root move searched");
+            AnalysisLine {
+                root: legal_move,
+                eval,
+                pv: search.line_from(legal_move, 1),
+            }
+        })
+        .collect();
+    ranked.sort_by_key(|line| Reverse(line.eval));
+    *info = std::mem::take(&mut search.info);
+    info.hashfull = search.tt.hashfull();
+    info.pv = ranked
+        .first()
+        .map(|line| line.pv.clone())
+        .unwrap_or_default();
+    tracing::debug!(
+        nodes = info.nodes,
+        tt_hits = info.tt_hits,
+        cutoffs = info.cutoffs,
+        score = ranked.first().map(|line| line.eval),
+        pv = %line_to_uci(&info.pv),
+        "Search finished"
+    );
+    ranked
+}
+
+fn pick_random_move(board: BoardState) -> Option<Move> {
+    let legals = board.legal_moves(board.active_team);
+    legals
+        .choose(&mut rand::rng())
+        .map(|packed| packed.unpack(&board))
+}
+
+// The side to move's best reply by a shallow look, used to annotate lines after the real search is done
+fn best_reply(board: &BoardState) -> Option<Move> {
+    let replies = board.legal_moves(board.active_team);
+
+    // A single ply never meets the same position twice, so there's nothing for a table to do
+    let mut search = SearchContext::new(0);
+    replies
+        .iter()
+        .map(|packed| packed.unpack(board))
+        .max_by_key(|reply| {
+            let mut reply_board = *board;
+            search.search_root_move(&mut reply_board, *reply, 0, -INFINITE_SCORE, INFINITE_SCORE)
+        })
+}
+// Follows the best replies after `first_move` to give the line the engine is expecting, in SAN
+pub fn expected_line(board: &BoardState, first_move: Move, depth: i32) -> Vec<String> {
+    let mut line_board = *board;
+    let mut line = vec![first_move.to_san(&line_board)];
+
+    if line_board.make_move(first_move).is_err() {
+        return line;
+    }
+    for _ in 0..depth {
+        if let Some(reply) = best_reply(&line_board) {
+            line.push(reply.to_san(&line_board));
+            if line_board.make_move(reply).is_err() {
                 break;
             }
-            best_white = best_white.max(move_score);
-        }
-        handle_move_result(
-            "UNMOVE",
-            virtual_board.unmake_move(ava_move),
-            ava_move,
-            search_budget,
-            virtual_board,
+        } else {
+            break;
+        }
+    }
+    line
+}
+
+// A line of moves played out from `board`, in SAN. Stops early if a move doesn't fit the position
+pub fn line_to_san(board: &BoardState, line: &[Move]) -> Vec<String> {
+    let mut line_board = *board;
+    let mut sans = Vec::new();
+    for line_move in line {
+        sans.push(line_move.to_san(&line_board));
+        if line_board.make_move(*line_move).is_err() {
+            break;
+        }
+    }
+    sans
+}
+fn line_to_uci(line: &[Move]) -> String {
+    line.iter()
+        .map(|line_move| line_move.to_uci())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn handle_move_result<T>(
+    result_type: &str,
+    result: Result<T, MoveError>,
+    ava_move: Move,
+    search_budget: i32,
+    virtual_board: &BoardState,
+) -> Option<T> {
+    result
+        .map_err(|vm_err| {
+            tracing::error!(
+                result_type,
+                search_budget,
+                error = ?vm_err,
+                played = %ava_move,
+                white = %virtual_board.get_team_coverage(Team::White),
+                "The search couldn't play a move it generated"
+            );
+            BoardState::render_piece_list(virtual_board.size, virtual_board.piece_list.to_vec());
+        })
+        .ok()
+}
+/*
+    What a search keeps between the positions it visits: the transposition table, and the quiet moves that have
+    caused cutoffs, as two killer moves for each ply and a history score for every start and target square. It
+    lasts a whole search so each iteration orders its moves by what the last ones learned, and tries the line the
+    last iteration settled on before anything else
+*/
+pub struct SearchContext {
+    tt: TranspositionTable,
+    killers: Vec<[Option<Move>; 2]>,
+    history: Box<[[i32; MAX_SQUARES]; MAX_SQUARES]>,
+    // The best line found below each ply so far, built back up from the leaves as each position finishes
+    lines: Vec<Vec<Move>>,
+    previous_pv: Vec<Move>,
+    // Once set, every position still being searched gives up straight away and its score means nothing
+    stop: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    // Checks extended along the line being searched right now
+    check_extensions: i32,
+    // How far below even the side to move at the root counts a draw. Positive when it expects to be the stronger side
+    pub contempt: i32,
+    // The game's moves up to the root and then the line being searched, for spotting a repetition
+    pub played: History,
+    // What the search has counted and found so far, for whoever started it to read back
+    pub info: SearchInfo,
+}
+impl SearchContext {
+    pub fn new(tt_entries: usize) -> SearchContext {
+        SearchContext::with_table(TranspositionTable::with_capacity(tt_entries))
+    }
+    // A search that starts out knowing whatever `tt` already holds
+    pub fn with_table(tt: TranspositionTable) -> SearchContext {
+        SearchContext {
+            tt,
+            killers: Vec::new(),
+            history: Box::new([[0; MAX_SQUARES]; MAX_SQUARES]),
+            lines: Vec::new(),
+            previous_pv: Vec::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            deadline: None,
+            check_extensions: 0,
+            contempt: 0,
+            played: History::default(),
+            info: SearchInfo::default(),
+        }
+    }
+    // Sets the stop flag by itself once `time_limit` from now has passed
+    pub fn stop_after(&mut self, time_limit: Duration) {
+        self.deadline = Some(Instant::now() + time_limit);
+    }
+    // For stopping the search from elsewhere, like another thread
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+    /*
+        What a finished game is worth to the side to move `ply` plies from the root, or None while it goes on.
+        Mates sooner are worse to be on the end of, so the search prefers the quickest one it can find
+    */
+    fn status_score(&self, status: GameStatus, to_move: Team, ply: i32) -> Option<i32> {
+        match status.winner() {
+            _ if status == GameStatus::Ongoing => None,
+            // A game is nearly always won with the move just played, but a Racing Kings king that couldn't be caught
+            // up with wins on the loser's move
+            Some(winner) if winner == to_move => Some(MATE_SCORE - ply),
+            Some(_) => Some(-MATE_SCORE + ply),
+            None => Some(self.draw_score(ply)),
+        }
+    }
+    // A draw for whoever is to move `ply` plies below the root, which is the root's side on even plies
+    fn draw_score(&self, ply: i32) -> i32 {
+        if ply % 2 == 0 {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+    fn check_stop(&self, nodes: u64) -> bool {
+        if nodes.is_multiple_of(STOP_CHECK_NODES)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        self.stopped()
+    }
+    // The line to try first from now on, which is the one the last finished iteration expects
+    pub fn follow_pv(&mut self, pv: &[Move]) {
+        self.previous_pv = pv.to_vec();
+    }
+    // `first` followed by the best line the search found after it, once `first` has been searched at `ply`
+    pub fn line_from(&self, first: Move, ply: i32) -> Vec<Move> {
+        let mut line = vec![first];
+        line.extend(self.lines.get(ply as usize).into_iter().flatten());
+        line
+    }
+    fn line_at(&mut self, ply: i32) -> &mut Vec<Move> {
+        let ply = ply as usize;
+        if self.lines.len() <= ply + 1 {
+            self.lines.resize(ply + 2, Vec::new());
+        }
+        &mut self.lines[ply]
+    }
+    // `best` beat everything else tried at `ply`, so the line from here is it and whatever the search expects after
+    fn improve_line(&mut self, best: Move, ply: i32) {
+        let line = self.line_from(best, ply + 1);
+        *self.line_at(ply) = line;
+    }
+    /*
+        Puts the last iteration's move for this ply first, then captures, most valuable victim first and least
+        valuable attacker first among those, since they're the likeliest to cause a cutoff. After them come this
+        ply's killer moves and then the other quiet moves by their history, with ties left in the order they were
+        generated
+    */
+    pub fn order_moves(&self, board: &BoardState, moves: &mut [PackedMove], ply: i32) {
+        let killers = self
+            .killers
+            .get(ply as usize)
+            .copied()
+            .unwrap_or_default()
+            .map(|killer| killer.map(PackedMove::from));
+        let pv_move = self
+            .previous_pv
+            .get(ply as usize)
+            .copied()
+            .map(PackedMove::from);
+        moves.sort_by_key(|candidate| {
+            Reverse((
+                pv_move == Some(*candidate),
+                candidate.is_capture(board).then(|| {
+                    (
+                        piece_value(board.piece_list[candidate.target()]),
+                        -piece_value(board.piece_list[candidate.start()]),
+                    )
+                }),
+                candidate.promotion().map(piece_value),
+                killers.contains(&Some(*candidate)),
+                self.history[candidate.start()][candidate.target()],
+            ))
+        });
+    }
+    // A quiet move that refuted its position is worth trying early in the positions around it
+    pub fn record_cutoff(&mut self, cutoff: Move, ply: i32, search_budget: i32) {
+        if cutoff.captures.is_some() {
+            return;
+        }
+        let ply = ply as usize;
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(cutoff) {
+            killers[1] = killers[0];
+            killers[0] = Some(cutoff);
+        }
+        // Cutoffs far from the leaves prune the most, so they count for more
+        let history = &mut self.history[cutoff.start][cutoff.target];
+        *history = history.saturating_add(search_budget * search_budget);
+    }
+    /*
+        How many plies shallower to look at the `index`th move tried here. Moves this far down the order rarely
+        matter, so quiet ones get a ply less unless they are killers, give check or get out of one
+    */
+    pub fn late_move_reduction(
+        &self,
+        board: &BoardState,
+        candidate: Move,
+        index: usize,
+        ply: i32,
+        search_budget: i32,
+    ) -> i32 {
+        if index < LMR_FULL_DEPTH_MOVES
+            || search_budget < LMR_MIN_BUDGET
+            || candidate.captures.is_some()
+            || board.is_team_checked(board.active_team)
+            || self
+                .killers
+                .get(ply as usize)
+                .is_some_and(|killers| killers.contains(&Some(candidate)))
+        {
+            return 0;
+        }
+        if board.gives_check(candidate) {
+            0
+        } else {
+            1
+        }
+    }
+    /*
+        Fail-soft negamax: what `board` is worth to the side to move, looking `depth` plies ahead. Scores outside
+        the window `alpha` to `beta` only bound the real score, from below when they reach `beta` and from above
+        when they don't get past `alpha`. `ply` counts the moves already made since the search started
+    */
+    pub fn search(
+        &mut self,
+        board: &mut BoardState,
+        depth: i32,
+        alpha: i32,
+        beta: i32,
+        ply: i32,
+    ) -> i32 {
+        self.negamax(board, None, depth, alpha, beta, ply)
+    }
+    // What `root` is worth to the side playing it from `board`, searching `depth` plies after it
+    pub fn search_root_move(
+        &mut self,
+        board: &mut BoardState,
+        root: Move,
+        depth: i32,
+        alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        let extension = i32::from(board.gives_check(root));
+        let undo = handle_move_result(
+            "MOVE",
+            self.played.make_move(board, root),
+            root,
+            depth,
+            board,
         );
-        max
-    } else {
-        let mut min = i32::MAX;
-        for legal_move in legals {
-            let move_score = evaluate_move(
-                virtual_board,
+        self.check_extensions += extension;
+        let score = -self.negamax(board, Some(root), depth + extension, -beta, -alpha, 1);
+        self.check_extensions -= extension;
+        if let Some(undo) = undo {
+            handle_move_result(
+                "UNMOVE",
+                self.played.unmake_move(board, undo),
+                root,
+                depth,
+                board,
+            );
+        }
+        score
+    }
+    // The search proper, which also knows the move that reached `board` since castling is scored on the move
+    fn negamax(
+        &mut self,
+        board: &mut BoardState,
+        played: Option<Move>,
+        depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: i32,
+    ) -> i32 {
+        self.info.seldepth = self.info.seldepth.max(ply);
+        self.info.nodes += 1;
+        if self.check_stop(self.info.nodes) {
+            return 0;
+        }
+        let to_play = if board.active_team == Team::White {
+            1
+        } else {
+            -1
+        };
+        let castled = played.is_some_and(|played| played.is_castle);
+
+        // A variant's win or a draw by rule ends the game however the table remembers the position scoring
+        if let Some(score) = self
+            .status_score(board.variant_win(), board.active_team, ply)
+            .or_else(|| self.status_score(self.played.rule_draw(board), board.active_team, ply))
+        {
+            return score;
+        }
+        let key = board.zobrist ^ if castled { CASTLED_KEY } else { 0 };
+        // Stored scores and leaves have no line after them
+        self.line_at(ply).clear();
+        self.info.tt_probes += 1;
+        let window = (score_to_table(alpha, ply), score_to_table(beta, ply));
+        if let Some(stored) = self.tt.probe(key, depth, window.0, window.1) {
+            self.info.tt_hits += 1;
+            return score_from_table(stored, ply);
+        }
+
+        if depth <= 0 {
+            // The terms are from white's point of view and favour whoever just moved
+            let status = board.status();
+            let score = self
+                .status_score(status, board.active_team, ply)
+                .unwrap_or_else(|| score_terms(board, status, castled, -to_play).total() * to_play);
+            self.tt
+                .store(key, 0, score_to_table(score, ply), Bound::Exact);
+            return score;
+        }
+        let mut legals = board.legal_moves(board.active_team);
+        if legals.is_empty() {
+            return self
+                .status_score(board.status(), board.active_team, ply)
+                .expect("A side without moves has been mated or stalemated");
+        }
+        self.order_moves(board, &mut legals, ply);
+
+        let original_alpha = alpha;
+        let mut best = -INFINITE_SCORE;
+        for (index, packed) in legals.iter().enumerate() {
+            let legal_move = packed.unpack(board);
+            let reduction = self.late_move_reduction(board, legal_move, index, ply, depth);
+            // A check is looked at a ply further, so the reply that answers it isn't cut off just before it
+            let extension = i32::from(
+                self.check_extensions < MAX_CHECK_EXTENSIONS && board.gives_check(legal_move),
+            );
+            self.check_extensions += extension;
+            let undo = handle_move_result(
+                "MOVE",
+                self.played.make_move(board, legal_move),
                 legal_move,
-                search_budget - 1,
-                best_white,
-                best_black,
+                depth,
+                board,
             );
-            min = min.min(best_black);
-            // println!("B{best_white}, {best_black} {search_budget}");
-            if move_score <= best_white {
+            let mut score = -self.negamax(
+                board,
+                Some(legal_move),
+                depth - 1 - reduction + extension,
+                -beta,
+                -alpha,
+                ply + 1,
+            );
+            // A reduced move that raises alpha after all is worth the full depth
+            if reduction > 0 && score > alpha && !self.stopped() {
+                score = -self.negamax(
+                    board,
+                    Some(legal_move),
+                    depth - 1 + extension,
+                    -beta,
+                    -alpha,
+                    ply + 1,
+                );
+            }
+            self.check_extensions -= extension;
+            if let Some(undo) = undo {
+                handle_move_result(
+                    "UNMOVE",
+                    self.played.unmake_move(board, undo),
+                    legal_move,
+                    depth,
+                    board,
+                );
+            }
+            // An unfinished search mustn't be stored or learned from
+            if self.stopped() {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                self.improve_line(legal_move, ply);
+            }
+            if score >= beta {
+                self.info.cutoffs += 1;
+                self.record_cutoff(legal_move, ply, depth);
                 break;
             }
-            best_black = best_black.min(move_score);
-        }
-        handle_move_result(
-            "UNMOVE",
-            virtual_board.unmake_move(ava_move),
-            ava_move,
-            search_budget,
-            virtual_board,
+            alpha = alpha.max(score);
+        }
+        self.tt.store(
+            key,
+            depth,
+            score_to_table(best, ply),
+            window_bound(best, original_alpha, beta),
         );
-        min
+        best
+    }
+}
+// What a score found searching with the window alpha to beta says about the real one
+fn window_bound(score: i32, alpha: i32, beta: i32) -> Bound {
+    if score <= alpha {
+        Bound::Upper
+    } else if score >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    }
+}
+/*
+    Scores the position reached after the last move term by term, from white's point of view. `status` is the
+    board's, which the caller has usually worked out already
+*/
+fn score_terms(
+    board: &BoardState,
+    status: GameStatus,
+    castled: bool,
+    who_to_play: i32,
+) -> EvalBreakdown {
+    let mut breakdown = EvalBreakdown {
+        material: evaluate(board),
+        placement: tapered::placement_score(board),
+        ..Default::default()
+    };
+
+    if castled {
+        breakdown.castling = 1200 * who_to_play
     }
+
+    // The side left to move has lost, which is as good as it gets for whoever played the last move
+    if status.winner().is_some() {
+        breakdown.mate = MATE_SCORE * who_to_play;
+    }
+    breakdown.variant = variant_score(board);
+    let center_control = board.get_team_coverage(board.active_team) & hill_squares(board.size);
+    if center_control.state > 0 {
+        // For some reason negatively attributing it makes it focus on the center
+        //  eval_score -= (center_control.state.count_ones() as i32) * who_to_play * 3;
+    }
+
+    let forking = board.capture_bitboard[board.active_team as usize]
+        & board.get_team_coverage(board.active_team.opponent());
+
+    if forking.state.count_ones() > 1 {
+        breakdown.forks = 50 * (forking.state.count_ones() as i32) * who_to_play;
+    }
+
+    breakdown
 }
-fn evaluate_team(board: &BoardState, team: Team, available_moves: Vec<Move>) -> i32 {
+/*
+    The variants' own ways to win, from white's point of view. In King of the Hill a king is worth more the fewer
+    king moves it is from the centre, in Three-check every check given is worth more than the one before, and in
+    Racing Kings a king is worth more the further up the board it has got
+*/
+fn variant_score(board: &BoardState) -> i32 {
+    let mut score = 0;
+    for (team, sign) in [(Team::White, 1), (Team::Black, -1)] {
+        score += sign
+            * match board.variant {
+                Variant::Standard | Variant::Atomic | Variant::Horde | Variant::Capablanca => 0,
+                Variant::RacingKings => {
+                    let king = board.board_pieces[team as usize][PieceType::King as usize];
+                    king.iter_ones()
+                        .next()
+                        .map_or(0, |square| RACE_RANK_SCORE * board.size.rank(square) as i32)
+                }
+                Variant::KingOfTheHill => {
+                    let king = board.board_pieces[team as usize][PieceType::King as usize];
+                    if let Some(square) = king.iter_ones().next() {
+                        let size = board.size;
+                        let (file, rank) = (size.file(square) as i32, size.rank(square) as i32);
+                        // Squares from the nearest of the four in the middle, d4, e4, d5 and e5 on a standard board
+                        let (centre_file, centre_rank) =
+                            (size.files() as i32 / 2, size.ranks() as i32 / 2);
+                        let distance = (file - file.clamp(centre_file - 1, centre_file))
+                            .abs()
+                            .max((rank - rank.clamp(centre_rank - 1, centre_rank)).abs());
+                        HILL_DISTANCE_SCORES
+                            .get(distance as usize)
+                            .copied()
+                            .unwrap_or_default()
+                    } else {
+                        0
+                    }
+                }
+                Variant::ThreeCheck => {
+                    CHECKS_GIVEN_SCORES[(board.checks_given[team as usize] as usize)
+                        .min(CHECKS_GIVEN_SCORES.len() - 1)]
+                }
+            };
+    }
+    score
+}
+fn evaluate_team(board: &BoardState, team: Team) -> i32 {
+    // Mobility would need both teams' moves generated at every leaf, which costs more than it tells
+    team_material(board, team, &SCORES)
+}
+// What `team`'s pieces are worth by `scores`
+pub fn team_material(
+    board: &BoardState,
+    team: Team,
+    scores: &[(PieceType, i32); PIECE_TYPES],
+) -> i32 {
     let mut material = 0;
     for (idx, piece) in board.piece_list.iter().enumerate() {
         if board.get_square_team(idx) == team {
-            let score_pt = SCORES
+            let score_pt = scores
                 .iter()
                 .position(|(piece_type, _scre)| piece_type == piece);
 
-            material += SCORES[score_pt.unwrap()].1;
+            material += scores[score_pt.unwrap()].1;
         }
     }
-
-    // Rewards mobility, but kind of expensive
     material
 }
-fn evaluate(board: &BoardState, all_moves: Vec<(Bitboard, Vec<Move>)>) -> i32 {
-    let wl = board.prune_moves_for_team(all_moves.clone(), Team::White);
-    let bl = board.prune_moves_for_team(all_moves, Team::Black);
-    let white_eval = evaluate_team(board, Team::White, wl);
-    let black_eval = evaluate_team(board, Team::Black, bl);
+/*
+    What the position is worth as it stands, from white's point of view. Castling is only scored on the move that
+    makes it, so it never shows up here
+*/
+pub fn static_breakdown(board: &BoardState) -> EvalBreakdown {
+    let sign = if board.active_team == Team::White {
+        1
+    } else {
+        -1
+    };
+    let mut breakdown = EvalBreakdown {
+        material: evaluate(board),
+        placement: tapered::placement_score(board),
+        ..Default::default()
+    };
+
+    if board.status().winner().is_some() {
+        breakdown.mate = -MATE_SCORE * sign;
+    }
+    breakdown.variant = variant_score(board);
+    let forking = board.capture_bitboard[board.active_team as usize]
+        & board.get_team_coverage(board.active_team.opponent());
+    if forking.state.count_ones() > 1 {
+        breakdown.forks = 50 * (forking.state.count_ones() as i32) * sign;
+    }
+    breakdown
+}
+fn evaluate(board: &BoardState) -> i32 {
+    let white_eval = evaluate_team(board, Team::White);
+    let black_eval = evaluate_team(board, Team::Black);
 
     white_eval - black_eval
 }
 impl fmt::Display for ChessOpponent {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            // The move time is what tells one Ada from another at a glance
+            ChessOpponent::Ada(options, _) if options.skill_level < MAX_SKILL_LEVEL => write!(
+                f,
+                "Ada({:?}, skill {})",
+                options.move_time, options.skill_level
+            ),
+            ChessOpponent::Ada(options, _) => write!(f, "Ada({:?})", options.move_time),
+            // The engine's own name once it has given it, or the program's until then
+            ChessOpponent::Uci(path, _, engine) => match engine.name() {
+                Some(name) => write!(f, "{name}"),
+                None => write!(
+                    f,
+                    "{}",
+                    path.file_name()
+                        .unwrap_or(path.as_os_str())
+                        .to_string_lossy()
+                ),
+            },
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
-pub trait MoveComputer {
-    fn get_move(&mut self, board: BoardState) -> Option<Move>;
-}
+/*
+    Ada's iterative deepening over the root moves until her move time runs out or `stop` is set. Returns the chosen
+    move, the root moves ranked best-first and the search depth reached. `progress` hears about every finished
+    iteration.
+*/
+fn search_ada(
+    board: &mut BoardState,
+    history: &History,
+    options: EngineOptions,
+    table: &SharedTable,
+    stop: Arc<AtomicBool>,
+    info: &mut SearchInfo,
+    progress: &mut dyn FnMut(&SearchInfo),
+) -> (Option<NegamaxEval>, EvaluationList, i32) {
+    let mut legals = board.prune_moves_for_team(board.get_legal_moves(), board.active_team);
+    let mut current_best: Option<NegamaxEval> = None;
+    let current_worst: Option<NegamaxEval> = None;
+    let start_time = Instant::now();
+    let time_limit = options.move_time.saturating_sub(options.move_overhead);
 
-impl MoveComputer for ChessOpponent {
-    fn get_move(&mut self, board: BoardState) -> Option<Move> {
-        let mut board = board;
-        let result = match self {
-            ChessOpponent::Randy => pick_random_move(board),
-            ChessOpponent::Ada(time_limit) => {
-                let mut legals =
-                    board.prune_moves_for_team_mut(board.get_legal_moves(), board.active_team);
-                let mut current_best: Option<NegamaxEval> = None;
-                let current_worst: Option<NegamaxEval> = None;
-                let start_time = Instant::now();
+    if legals.is_empty() {
+        return (None, EvaluationList(Vec::new()), 0);
+    }
+    if legals.len() == 1 {
+        let only_move = NegamaxEval {
+            eval: 0,
+            legal_move: legals[0],
+        };
+        return (Some(only_move), EvaluationList(vec![only_move]), 0);
+    }
+    let mut search_budget = 0;
+    let mut mapped_legals = EvaluationList(Vec::new());
+    // Deeper iterations use what shallower ones stored and learned, so the context lasts the whole search
+    let mut search = SearchContext::with_table(table.take(entries_for_megabytes(options.hash_mb)));
+    search.stop = stop;
+    search.contempt = options.contempt;
+    search.played = history.clone();
+    search.info = std::mem::take(info);
+    let _search = tracing::debug_span!("search", time_limit = ?time_limit).entered();
+    // A root move from an unfinished iteration that beat the line it started from, or the best of the first one
+    let mut improved: Option<NegamaxEval> = None;
+    loop {
+        let mut evals: EvaluationList = EvaluationList(Vec::new());
+        let _iteration = tracing::debug_span!("iteration", depth = search_budget + 1).entered();
 
-                if board.active_team_checkmate {
-                    return None;
-                }
-                if legals.len() == 1 {
-                    return Some(legals[0]);
-                }
-                let mut search_budget = 0;
-                let mut mapped_legals = EvaluationList(Vec::new());
-                loop {
-                    let mut evals: EvaluationList = EvaluationList(Vec::new());
-
-                    let mut will_break = false;
-                    /* Check the current best first
-                    legals.sort_by(|c_a, c_b| {
-                        if let Some(cb) = current_best {
-                            if cb.legal_move == *c_a && cb.legal_move != *c_b {
-                                Ordering::Less
-                            } else {
-                                Ordering::Equal
-                            }
-                        } else {
-                            Ordering::Equal
-                        }
-                    });*/
-                    let (best_white, best_black) = (i32::MIN, i32::MAX);
-                    'legal_check: for legal_move in &legals {
-                        // Preset the AB pruning with the eval we already have
-
-                        if Instant::now().duration_since(start_time) > *time_limit {
-                            will_break = true;
-                            break 'legal_check;
-                        };
-
-                        let eval = evaluate_move(
-                            &mut board,
-                            *legal_move,
-                            search_budget,
-                            best_white,
-                            best_black,
-                        ) * if board.active_team == Team::White {
-                            1
-                        } else {
-                            -1
-                        };
-
-                        evals.0.push(NegamaxEval {
-                            eval: eval + rand::rng().random_range(-2..=2),
-                            legal_move: *legal_move,
-                        })
-                    }
-                    if will_break {
-                        break;
-                    };
-                    mapped_legals = evals;
-                    search_budget += 1;
-                }
+        let mut will_break = false;
+        // Start from the last iteration's line, which is the likeliest to still be best
+        let pv = search.info.pv.clone();
+        search.follow_pv(&pv);
+        if let Some(&pv_move) = search.info.pv.first() {
+            legals.sort_by_key(|legal_move| *legal_move != pv_move);
+        }
+        let mut lines = Vec::new();
+        'legal_check: for legal_move in &legals {
+            let _root = tracing::trace_span!("root_move", root = %legal_move).entered();
+            let eval = search.search_root_move(
+                board,
+                *legal_move,
+                search_budget,
+                -INFINITE_SCORE,
+                INFINITE_SCORE,
+            );
+            if search.stopped() {
+                will_break = true;
+                break 'legal_check;
+            }
 
-                mapped_legals.0.sort_by(|a, b| b.eval.cmp(&a.eval));
-                if !mapped_legals.0.is_empty() {
-                    if let Some(current_best_move) = current_best {
-                        current_best = if current_best_move.eval < mapped_legals.0[0].eval {
-                            Some(mapped_legals.0[0])
-                        } else {
-                            current_best
-                        };
-                    } else {
-                        current_best = Some(mapped_legals.0[0]);
-                    }
+            tracing::trace!(eval, nodes = search.info.nodes, "Root move searched");
+            lines.push(search.line_from(*legal_move, 1));
+            // A little noise varies Ada's play, but mates have to stay exact to be told apart
+            let jiggle = if mate_in(eval).is_some() {
+                0
+            } else {
+                rand::rng().random_range(-2..=2)
+            };
+            evals.0.push(NegamaxEval {
+                eval: eval + jiggle,
+                legal_move: *legal_move,
+            })
+        }
+        if will_break {
+            /*
+                The last line goes first, so anything here that beat it is better at this depth too. Stopped before
+                any iteration finished, the best root move searched so far is all there is to go on
+            */
+            let nothing_finished = mapped_legals.0.is_empty();
+            improved = evals
+                .0
+                .iter()
+                .copied()
+                .max_by_key(|evaluated| evaluated.eval)
+                .filter(|evaluated| nothing_finished || evals.0[0].eval < evaluated.eval);
+            break;
+        };
+        mapped_legals = evals;
 
-                    /*if let Some(current_worst_move) = current_worst {
-                        current_worst =
-                            if current_worst_move.eval > mapped_legals.0.last().unwrap().eval {
-                                mapped_legals.0.last().copied()
-                            } else {
-                                current_best
-                            };
-                    } else {
-                        current_worst = mapped_legals.0.last().copied();
-                    }*/
+        if let Some((iteration_best, line)) = mapped_legals
+            .0
+            .iter()
+            .zip(lines)
+            .max_by_key(|(evaluated, _)| evaluated.eval)
+        {
+            search.info.score = iteration_best.eval;
+            search.info.best_move = Some(iteration_best.legal_move);
+            search.info.pv = line;
+        }
+        search.info.depth = search_budget + 1;
+        search.info.elapsed = start_time.elapsed();
+        search.info.hashfull = search.tt.hashfull();
+        tracing::debug!(
+            nodes = search.info.nodes,
+            nps = search.info.nps(),
+            tt_hits = search.info.tt_hits,
+            cutoffs = search.info.cutoffs,
+            score = search.info.score,
+            mate_in = mate_in(search.info.score),
+            best = search.info.best_move.map(|best| best.to_uci()),
+            pv = %line_to_uci(&search.info.pv),
+            "Iteration finished"
+        );
+        progress(&search.info);
 
-                    tracing::warn!(
-                        "\n Best move ply {search_budget}: {:?}",
-                        mapped_legals.0[0].eval
-                    );
-                    tracing::warn!("Mapped legals ply {search_budget}: {}", mapped_legals);
-                } else if let Some(current_best_move) = current_best {
-                    mapped_legals.0.push(current_best_move);
-                }
+        search_budget += 1;
+        if options
+            .depth_cap()
+            .is_some_and(|depth_cap| search_budget >= depth_cap)
+        {
+            break;
+        }
+        // The first iteration always runs to the end so there's a move to play however short the time
+        if start_time.elapsed() >= time_limit {
+            break;
+        }
+        search.stop_after(time_limit.saturating_sub(start_time.elapsed()));
+    }
 
-                if current_best.is_some() {
-                    tracing::warn!(
-                        "Within limit of {:?} Ada got to ply {search_budget} eval: {}",
-                        time_limit,
-                        current_best.unwrap()
-                    );
-                    Some(current_best.unwrap().legal_move)
+    mapped_legals.0.sort_by(|a, b| b.eval.cmp(&a.eval));
+    if let Some(improved) = improved {
+        tracing::debug!(best = %improved.legal_move.to_uci(), "Took a better move from the unfinished iteration");
+        mapped_legals
+            .0
+            .retain(|evaluated| evaluated.legal_move != improved.legal_move);
+        mapped_legals.0.insert(0, improved);
+        search.info.best_move = Some(improved.legal_move);
+        search.info.score = improved.eval;
+        search.info.pv = search.line_from(improved.legal_move, 1);
+    }
+    if !mapped_legals.0.is_empty() {
+        if let Some(current_best_move) = current_best {
+            current_best = if current_best_move.eval < mapped_legals.0[0].eval {
+                Some(mapped_legals.0[0])
+            } else {
+                current_best
+            };
+        } else {
+            current_best = Some(mapped_legals.0[0]);
+        }
+
+        /*if let Some(current_worst_move) = current_worst {
+            current_worst =
+                if current_worst_move.eval > mapped_legals.0.last().unwrap().eval {
+                    mapped_legals.0.last().copied()
                 } else {
-                    None
-                }
-            }
+                    current_best
+                };
+        } else {
+            current_worst = mapped_legals.0.last().copied();
+        }*/
+
+        tracing::trace!(ranked = %mapped_legals, "Root moves after the last iteration");
+        // A weakened Ada may play something other than what she found best
+        if let Some(chosen) = options
+            .skill_choice(&mapped_legals)
+            .filter(|chosen| Some(chosen.legal_move) != current_best.map(|best| best.legal_move))
+        {
+            tracing::debug!(chosen = %chosen.legal_move.to_uci(), skill = options.skill_level, "Played below her best");
+            current_best = Some(chosen);
+            search.info.best_move = Some(chosen.legal_move);
+            search.info.score = chosen.eval;
+            search.info.pv = vec![chosen.legal_move];
+        }
+    } else if let Some(current_best_move) = current_best {
+        mapped_legals.0.push(current_best_move);
+    }
+
+    // Nodes of an iteration that didn't finish still count towards the speed
+    search.info.elapsed = start_time.elapsed();
+    if let Some(best) = current_best {
+        tracing::debug!(
+            depth = search_budget,
+            nodes = search.info.nodes,
+            nps = search.info.nps(),
+            cutoffs = search.info.cutoffs,
+            score = best.eval,
+            best = %best.legal_move.to_uci(),
+            "Search finished"
+        );
+    }
+    *info = std::mem::take(&mut search.info);
+    table.put_back(search.tt);
+    (current_best, mapped_legals, search_budget)
+}
+pub trait MoveComputer {
+    // `history` is the moves that led to `board`, so the opponents that search can steer for or clear of a repetition
+    fn get_move(&mut self, board: BoardState, history: &History) -> Option<Move>;
+
+    /*
+        Like get_move, but reports search progress after every finished iteration and, when asked and the
+        opponent is able to, explains why the move was chosen.
+    */
+    fn think(
+        &mut self,
+        board: BoardState,
+        history: &History,
+        _explain: bool,
+        _progress: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchOutcome {
+        SearchOutcome {
+            best_move: self.get_move(board, history),
+            ..Default::default()
+        }
+    }
+
+    /*
+        Thinks about `board` on the other side's time, until `stop` is set, for when the reply the opponent
+        expected gets played. None if the opponent doesn't ponder
+    */
+    fn ponder(
+        &mut self,
+        _board: BoardState,
+        _history: &History,
+        _explain: bool,
+        _stop: Arc<AtomicBool>,
+    ) -> Option<SearchOutcome> {
+        None
+    }
+}
+
+// Ada's search of `board` wrapped up as an outcome, explained when asked
+fn ada_outcome(
+    board: BoardState,
+    history: &History,
+    options: EngineOptions,
+    table: &SharedTable,
+    explain: bool,
+    stop: Arc<AtomicBool>,
+    progress: &mut dyn FnMut(&SearchInfo),
+) -> SearchOutcome {
+    let mut search_board = board;
+    let mut info = SearchInfo::default();
+    let (best, ranked, depth) = search_ada(
+        &mut search_board,
+        history,
+        options,
+        table,
+        stop,
+        &mut info,
+        progress,
+    );
+    let explanation = best
+        .filter(|_| explain)
+        .map(|chosen| SearchExplanation::new(&board, chosen, &ranked, depth, &info.pv));
+
+    SearchOutcome {
+        best_move: best.map(|chosen| chosen.legal_move),
+        explanation,
+        info,
+    }
+}
+
+impl MoveComputer for ChessOpponent {
+    fn get_move(&mut self, board: BoardState, history: &History) -> Option<Move> {
+        let mut board = board;
+        let result = match self {
+            ChessOpponent::Randy => pick_random_move(board),
+            // A human's moves come from the board, never from here
+            ChessOpponent::Human => None,
+            ChessOpponent::Uci(..) => self.think(board, history, false, &mut |_| {}).best_move,
+            ChessOpponent::Ada(options, table) => search_ada(
+                &mut board,
+                history,
+                *options,
+                table,
+                Arc::default(),
+                &mut SearchInfo::default(),
+                &mut |_| {},
+            )
+            .0
+            .map(|best| best.legal_move),
             ChessOpponent::Matt(search_budget) => {
-                let legals = board.prune_moves_for_team(board.get_legal_moves(), board.active_team);
-                let mut mapped_legals: EvaluationList = EvaluationList(Vec::new());
+                let legals = board.legal_moves(board.active_team);
                 if legals.len() == 1 {
-                    return Some(legals[0]);
+                    return Some(legals[0].unpack(&board));
                 }
                 // expensive...
-                let (best_white, best_black) = (i32::MIN, i32::MAX);
-
-                for legal_move in legals {
-                    let eval = evaluate_move(
-                        &mut board.clone(),
-                        legal_move,
-                        *search_budget - 1,
-                        best_white,
-                        best_black,
-                    ) * if board.active_team == Team::White {
-                        1
-                    } else {
-                        -1
-                    };
-
-                    mapped_legals.0.push(NegamaxEval { eval, legal_move })
-                }
-
-                mapped_legals.0.sort_by(|a, b| b.eval.cmp(&a.eval));
-                tracing::debug!(
-                    "Evaled to: {} and {}",
-                    mapped_legals.0[0].eval,
-                    mapped_legals.0[mapped_legals.0.len() - 1].eval
+                let ranked = rank_moves(
+                    &board,
+                    history,
+                    *search_budget - 1,
+                    &mut SearchInfo::default(),
                 );
 
-                if !mapped_legals.0.is_empty() {
-                    Some(mapped_legals.0[0].legal_move)
+                if let (Some((best_move, best_eval)), Some((_, worst_eval))) =
+                    (ranked.first(), ranked.last())
+                {
+                    tracing::debug!(
+                        best = best_eval,
+                        worst = worst_eval,
+                        "Ranked the root moves"
+                    );
+                    Some(*best_move)
                 } else {
                     None
                 }
@@ -438,4 +1614,82 @@ impl MoveComputer for ChessOpponent {
             None
         }
     }
+
+    fn think(
+        &mut self,
+        board: BoardState,
+        history: &History,
+        explain: bool,
+        progress: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchOutcome {
+        match self {
+            ChessOpponent::Ada(options, table) => ada_outcome(
+                board,
+                history,
+                *options,
+                table,
+                explain,
+                Arc::default(),
+                progress,
+            ),
+            ChessOpponent::Matt(search_budget) => {
+                let start_time = Instant::now();
+                let mut info = SearchInfo::default();
+                let ranked = EvaluationList(
+                    rank_moves(&board, history, *search_budget - 1, &mut info)
+                        .into_iter()
+                        .map(|(legal_move, eval)| NegamaxEval { eval, legal_move })
+                        .collect(),
+                );
+                let best = ranked.0.first().copied();
+
+                info.depth = *search_budget;
+                info.score = best.map_or(0, |chosen| chosen.eval);
+                info.best_move = best.map(|chosen| chosen.legal_move);
+                info.elapsed = start_time.elapsed();
+                progress(&info);
+
+                SearchOutcome {
+                    best_move: info.best_move,
+                    explanation: best.filter(|_| explain).map(|chosen| {
+                        SearchExplanation::new(&board, chosen, &ranked, *search_budget, &info.pv)
+                    }),
+                    info,
+                }
+            }
+            ChessOpponent::Randy | ChessOpponent::Human => SearchOutcome {
+                best_move: self.get_move(board, history),
+                ..Default::default()
+            },
+            ChessOpponent::Uci(path, time, engine) => engine.think(path, *time, board, progress),
+        }
+    }
+
+    // Only Ada's search can be cut off whenever the player moves, so only Ada ponders
+    fn ponder(
+        &mut self,
+        board: BoardState,
+        history: &History,
+        explain: bool,
+        stop: Arc<AtomicBool>,
+    ) -> Option<SearchOutcome> {
+        match self {
+            ChessOpponent::Ada(options, table) => Some(ada_outcome(
+                board,
+                history,
+                EngineOptions {
+                    move_time: PONDER_TIME_LIMIT,
+                    ..*options
+                },
+                table,
+                explain,
+                stop,
+                &mut |_| {},
+            )),
+            ChessOpponent::Matt(_)
+            | ChessOpponent::Randy
+            | ChessOpponent::Uci(..)
+            | ChessOpponent::Human => None,
+        }
+    }
 }
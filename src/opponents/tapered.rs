@@ -0,0 +1,164 @@
+use crate::{
+    bitboard::{PieceType, Team},
+    board::BoardState,
+};
+
+// How much each piece counts towards the middlegame. With all of them on the board the phase is MAX_PHASE
+const PHASE_WEIGHTS: [(PieceType, i32); 7] = [
+    (PieceType::Knight, 1),
+    (PieceType::Bishop, 1),
+    (PieceType::Rook, 2),
+    (PieceType::Queen, 4),
+    (PieceType::Archbishop, 3),
+    (PieceType::Chancellor, 3),
+    (PieceType::Nightrider, 2),
+];
+pub const MAX_PHASE: i32 = 24;
+
+/*
+    Square tables are laid out the way the board looks from white's side, with rank 8 on the top row, so white
+    looks squares up flipped and black looks them up as they are
+*/
+#[rustfmt::skip]
+const KING_MIDGAME: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+#[rustfmt::skip]
+const KING_ENDGAME: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+#[rustfmt::skip]
+const PAWN_MIDGAME: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const PAWN_ENDGAME: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     80,  80,  80,  80,  80,  80,  80,  80,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     20,  20,  20,  20,  20,  20,  20,  20,
+     10,  10,  10,  10,  10,  10,  10,  10,
+     10,  10,  10,  10,  10,  10,  10,  10,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+// Extra for a pawn no enemy pawn can stop, by how far up the board it has got
+const PASSED_PAWN_MIDGAME: [i32; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+const PASSED_PAWN_ENDGAME: [i32; 8] = [0, 10, 20, 35, 60, 100, 150, 0];
+
+// Every table placement is scored from, each as a middlegame and an endgame pair, so they can be tuned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacementWeights {
+    pub king: [[i32; 64]; 2],
+    pub pawn: [[i32; 64]; 2],
+    pub passed_pawn: [[i32; 8]; 2],
+}
+pub const PLACEMENT_WEIGHTS: PlacementWeights = PlacementWeights {
+    king: [KING_MIDGAME, KING_ENDGAME],
+    pawn: [PAWN_MIDGAME, PAWN_ENDGAME],
+    passed_pawn: [PASSED_PAWN_MIDGAME, PASSED_PAWN_ENDGAME],
+};
+
+// From MAX_PHASE with every piece still on the board down to 0 once only kings and pawns are left
+pub fn game_phase(board: &BoardState) -> i32 {
+    let phase: i32 = [Team::White, Team::Black]
+        .iter()
+        .flat_map(|team| {
+            PHASE_WEIGHTS.iter().map(move |(piece_type, weight)| {
+                board.board_pieces[*team as usize][*piece_type as usize]
+                    .state
+                    .count_ones() as i32
+                    * weight
+            })
+        })
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+// Whether nothing but a pawn's own pawns could stand in its way, on its own file or either side of it
+fn is_passed(board: &BoardState, team: Team, square: usize) -> bool {
+    let size = board.size;
+    let (file, rank) = (size.file(square) as i32, size.rank(square) as i32);
+    let enemy_pawns = board.board_pieces[team.opponent() as usize][PieceType::Pawn as usize];
+    !enemy_pawns.iter_ones().any(|enemy| {
+        let (enemy_file, enemy_rank) = (size.file(enemy) as i32, size.rank(enemy) as i32);
+        let ahead = match team {
+            Team::White => enemy_rank > rank,
+            _ => enemy_rank < rank,
+        };
+        ahead && (enemy_file - file).abs() <= 1
+    })
+}
+
+// A team's middlegame and endgame scores for where its kings and pawns stand
+fn team_placement(board: &BoardState, team: Team, weights: &PlacementWeights) -> (i32, i32) {
+    /*
+        The tables are written from white's side of a standard board down, a8 first. A wider board's files are
+        squeezed onto their eight columns, so the centre still scores as the centre
+    */
+    let size = board.size;
+    let table_rank = |square: usize| match team {
+        Team::White => size.last_rank() - size.rank(square),
+        _ => size.rank(square),
+    };
+    let table_square =
+        |square: usize| table_rank(square) * 8 + size.file(square) * 8 / size.files();
+    let (mut midgame, mut endgame) = (0, 0);
+
+    let kings = board.board_pieces[team as usize][PieceType::King as usize];
+    for square in kings.iter_ones() {
+        midgame += weights.king[0][table_square(square)];
+        endgame += weights.king[1][table_square(square)];
+    }
+    let pawns = board.board_pieces[team as usize][PieceType::Pawn as usize];
+    for square in pawns.iter_ones() {
+        midgame += weights.pawn[0][table_square(square)];
+        endgame += weights.pawn[1][table_square(square)];
+        if is_passed(board, team, square) {
+            let advanced = 7 - table_rank(square);
+            midgame += weights.passed_pawn[0][advanced];
+            endgame += weights.passed_pawn[1][advanced];
+        }
+    }
+    (midgame, endgame)
+}
+
+/*
+    Where the kings and pawns stand, from white's point of view. The middlegame tables keep the king tucked away
+    and the endgame ones bring it to the centre and push passed pawns, and the two are blended by how much
+    material is left
+*/
+pub fn placement_score(board: &BoardState) -> i32 {
+    placement_score_with(board, &PLACEMENT_WEIGHTS)
+}
+// The same with tables other than the ones the engine plays with, for tuning them
+pub fn placement_score_with(board: &BoardState, weights: &PlacementWeights) -> i32 {
+    let (white_midgame, white_endgame) = team_placement(board, Team::White, weights);
+    let (black_midgame, black_endgame) = team_placement(board, Team::Black, weights);
+    let phase = game_phase(board);
+
+    ((white_midgame - black_midgame) * phase
+        + (white_endgame - black_endgame) * (MAX_PHASE - phase))
+        / MAX_PHASE
+}
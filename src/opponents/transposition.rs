@@ -0,0 +1,135 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+// Entries in the table a search starts with, about 8MB
+pub const DEFAULT_TT_ENTRIES: usize = 1 << 18;
+
+// Slots hashfull looks at, since counting every one would take longer than the search it's reporting on
+const HASHFULL_SAMPLE: usize = 1000;
+
+// How many entries fit in a table of this many megabytes
+pub fn entries_for_megabytes(megabytes: usize) -> usize {
+    megabytes * 1024 * 1024 / std::mem::size_of::<Option<TtEntry>>()
+}
+
+// How a stored score relates to the position's real one, given the window it was searched with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower, // The search failed high, so the real score is at least this
+    Upper, // The search failed low, so the real score is at most this
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TtEntry {
+    key: u64,
+    depth: i32,
+    score: i32,
+    bound: Bound,
+    generation: u8, // Which search stored it
+}
+
+/*
+    Scores of positions the search has already been through, by Zobrist hash. Scores are from white's point of
+    view like the rest of the search. Each hash has one slot. Within a search a deeper result keeps its slot, but
+    anything left from an earlier search gives way to whatever the current one stores
+*/
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+    generation: u8,
+}
+impl TranspositionTable {
+    // A table with no entries never finds anything, for quick looks that aren't worth the memory
+    pub fn with_capacity(entries: usize) -> TranspositionTable {
+        TranspositionTable {
+            entries: vec![None; entries],
+            generation: 0,
+        }
+    }
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+    // Ages everything stored so far, which is still probed but no longer kept over what the next search stores
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+    fn slot(&self, key: u64) -> Option<usize> {
+        (!self.entries.is_empty()).then(|| (key % self.entries.len() as u64) as usize)
+    }
+    // The stored score for this position, if it was searched deep enough and its bound settles the window
+    pub fn probe(&self, key: u64, depth: i32, alpha: i32, beta: i32) -> Option<i32> {
+        let entry = self.entries[self.slot(key)?].filter(|entry| entry.key == key)?;
+        if entry.depth < depth {
+            return None;
+        }
+        match entry.bound {
+            Bound::Exact => Some(entry.score),
+            Bound::Lower if entry.score >= beta => Some(entry.score),
+            Bound::Upper if entry.score <= alpha => Some(entry.score),
+            _ => None,
+        }
+    }
+    pub fn store(&mut self, key: u64, depth: i32, score: i32, bound: Bound) {
+        let Some(slot) = self.slot(key) else {
+            return;
+        };
+        if self.entries[slot]
+            .is_some_and(|old| old.generation == self.generation && old.depth > depth)
+        {
+            return;
+        }
+        self.entries[slot] = Some(TtEntry {
+            key,
+            depth,
+            score,
+            bound,
+            generation: self.generation,
+        });
+    }
+    // Permille of the slots holding something from the current search, as UCI reports it
+    pub fn hashfull(&self) -> u32 {
+        let sample = &self.entries[..self.entries.len().min(HASHFULL_SAMPLE)];
+        let current = sample
+            .iter()
+            .flatten()
+            .filter(|entry| entry.generation == self.generation)
+            .count();
+        (current * 1000 / sample.len().max(1)) as u32
+    }
+}
+
+/*
+    A transposition table an opponent keeps from one move to the next. Copies of the opponent share it, so the
+    copy that searches on another thread leaves what it found for the next move's search
+*/
+#[derive(Clone, Default)]
+pub struct SharedTable(Arc<Mutex<Option<TranspositionTable>>>);
+impl SharedTable {
+    /*
+        The kept table aged ready for a new search, or a fresh one if it isn't `entries` big or another search
+        has it
+    */
+    pub fn take(&self, entries: usize) -> TranspositionTable {
+        let kept = self
+            .0
+            .lock()
+            .ok()
+            .and_then(|mut kept| kept.take())
+            .filter(|table| table.capacity() == entries);
+        let mut table = kept.unwrap_or_else(|| TranspositionTable::with_capacity(entries));
+        table.new_search();
+        table
+    }
+    pub fn put_back(&self, table: TranspositionTable) {
+        if let Ok(mut kept) = self.0.lock() {
+            *kept = Some(table);
+        }
+    }
+}
+impl fmt::Debug for SharedTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedTable")
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bitboard::Team,
+    board::{BoardState, History},
+    clock::ChessClock,
+    r#move::Move,
+};
+
+use super::{MoveComputer, SearchInfo, SearchOutcome, MATE_SCORE};
+
+// What an engine gets for each move when there's no clock to go by
+pub const DEFAULT_UCI_MOVE_TIME: Duration = Duration::from_millis(500);
+
+// How long an engine gets for each move: a fixed time, or whatever it makes of both sides' clocks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UciTime {
+    MoveTime(Duration),
+    Clock {
+        wtime: Duration,
+        btime: Duration,
+        winc: Duration,
+        binc: Duration,
+    },
+}
+impl UciTime {
+    // The clocks as they stand, for an engine that runs its own time
+    pub fn from_clock(clock: &ChessClock) -> UciTime {
+        UciTime::Clock {
+            wtime: clock.remaining(Team::White),
+            btime: clock.remaining(Team::Black),
+            winc: clock.control(Team::White).increment,
+            binc: clock.control(Team::Black).increment,
+        }
+    }
+    pub fn go_command(&self) -> String {
+        match self {
+            UciTime::MoveTime(move_time) => format!("go movetime {}", move_time.as_millis()),
+            UciTime::Clock {
+                wtime,
+                btime,
+                winc,
+                binc,
+            } => format!(
+                "go wtime {} btime {} winc {} binc {}",
+                wtime.as_millis(),
+                btime.as_millis(),
+                winc.as_millis(),
+                binc.as_millis()
+            ),
+        }
+    }
+}
+
+/*
+    An engine running in its own process, spoken to over UCI on its stdin and stdout. It gets a fixed time for each
+    move unless given the clocks, and is told to quit when dropped
+*/
+pub struct UciEngine {
+    name: String,
+    pub time: UciTime,
+    process: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+impl UciEngine {
+    // Starts the engine and waits for it to say it's ready
+    pub fn launch(path: &str, move_time: Duration) -> Result<UciEngine, String> {
+        let mut process = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Couldn't start {path}: {err}"))?;
+        let input = process.stdin.take().ok_or("The engine has no stdin")?;
+        let output = BufReader::new(process.stdout.take().ok_or("The engine has no stdout")?);
+
+        let mut engine = UciEngine {
+            name: path.to_string(),
+            time: UciTime::MoveTime(move_time),
+            process,
+            input,
+            output,
+        };
+        engine.send("uci")?;
+        for line in engine.read_until("uciok")? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+        }
+        engine.send("isready")?;
+        engine.read_until("readyok")?;
+        Ok(engine)
+    }
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.input, "{command}")
+            .and_then(|_| self.input.flush())
+            .map_err(|err| format!("{} stopped listening: {err}", self.name))
+    }
+    // Every line the engine writes up to and including the first one starting with `last`
+    fn read_until(&mut self, last: &str) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            match self.output.read_line(&mut line) {
+                Ok(0) | Err(_) => return Err(format!("{} quit before sending {last}", self.name)),
+                Ok(_) => {}
+            }
+            let done = line.starts_with(last);
+            lines.push(line);
+            if done {
+                return Ok(lines);
+            }
+        }
+    }
+}
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.process.wait();
+    }
+}
+impl Display for UciEngine {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+// Fills in whatever an engine's `info` line reports. Mates are scored like our own searches score them
+pub fn read_info_line(line: &str, info: &mut SearchInfo) {
+    let mut words = line.split_whitespace().skip(1);
+
+    while let Some(word) = words.next() {
+        let mut number = || words.next().and_then(|value| value.parse::<i64>().ok());
+        match word {
+            "depth" => info.depth = number().unwrap_or_default() as i32,
+            "seldepth" => info.seldepth = number().unwrap_or_default() as i32,
+            "nodes" => info.nodes = number().unwrap_or_default() as u64,
+            "cp" => info.score = number().unwrap_or_default() as i32,
+            "mate" => {
+                info.score = number().map_or(0, |moves| {
+                    // The engine counts moves, our scores count plies to the mate
+                    let moves = moves as i32;
+                    if moves > 0 {
+                        MATE_SCORE - (2 * moves - 1)
+                    } else {
+                        -MATE_SCORE + 2 * -moves
+                    }
+                })
+            }
+            // Everything after pv is moves, which the info line has no use for
+            "pv" => break,
+            _ => {}
+        }
+    }
+}
+
+impl MoveComputer for UciEngine {
+    fn get_move(&mut self, board: BoardState, history: &History) -> Option<Move> {
+        self.think(board, history, false, &mut |_| {}).best_move
+    }
+    // The engine is only told the position, so it can't know about repetitions from before it
+    fn think(
+        &mut self,
+        board: BoardState,
+        _history: &History,
+        _explain: bool,
+        progress: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchOutcome {
+        let started = Instant::now();
+        let mut info = SearchInfo::default();
+        let command = format!(
+            "position fen {}\n{}",
+            board.as_fen(),
+            self.time.go_command()
+        );
+
+        let lines = match self
+            .send(&command)
+            .and_then(|_| self.read_until("bestmove"))
+        {
+            Ok(lines) => lines,
+            Err(err) => {
+                eprintln!("{err}");
+                return SearchOutcome::default();
+            }
+        };
+        for line in &lines {
+            if line.starts_with("info") && line.contains(" score ") {
+                read_info_line(line, &mut info);
+                progress(&info);
+            }
+        }
+
+        let best_move = lines
+            .last()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|uci| Move::from_uci(&board, uci));
+        info.best_move = best_move;
+        info.elapsed = started.elapsed();
+        SearchOutcome {
+            best_move,
+            explanation: None,
+            info,
+        }
+    }
+}
+
+/*
+    An engine process every copy of an opponent plays through, so it keeps what it learned between moves. It's
+    started the first time one of them needs a move
+*/
+#[derive(Clone, Default)]
+pub struct SharedEngine(Arc<Mutex<Option<UciEngine>>>);
+impl SharedEngine {
+    pub fn think(
+        &self,
+        path: &Path,
+        time: UciTime,
+        board: BoardState,
+        progress: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchOutcome {
+        let Ok(mut kept) = self.0.lock() else {
+            return SearchOutcome::default();
+        };
+        if kept.is_none() {
+            match UciEngine::launch(&path.to_string_lossy(), DEFAULT_UCI_MOVE_TIME) {
+                Ok(engine) => *kept = Some(engine),
+                Err(err) => {
+                    eprintln!("{err}");
+                    return SearchOutcome::default();
+                }
+            }
+        }
+        let Some(engine) = kept.as_mut() else {
+            return SearchOutcome::default();
+        };
+        engine.time = time;
+        engine.think(board, &History::default(), false, progress)
+    }
+    // What the engine calls itself, once it has been started
+    pub fn name(&self) -> Option<String> {
+        let kept = self.0.lock().ok()?;
+        kept.as_ref().map(|engine| engine.name.clone())
+    }
+}
+impl fmt::Debug for SharedEngine {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SharedEngine")
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{board::BoardState, START_POS_CHESS};
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// One game from a PGN file. Comments and NAGs are dropped while reading
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>, // The mainline in SAN, as written
+    pub variations: Vec<PgnVariation>,
+    pub result: String,
+}
+
+// A line played instead of one of the moves of the line it hangs off
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgnVariation {
+    pub branch_ply: usize, // Index of the move it replaces in the parent line
+    pub moves: Vec<String>,
+    pub variations: Vec<PgnVariation>,
+}
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+    // Where the game starts, which is the standard position unless a FEN tag says otherwise
+    pub fn start_board(&self) -> Result<BoardState, String> {
+        let fen = self.tag("FEN").unwrap_or(START_POS_CHESS);
+        BoardState::from_fen(fen.to_string())
+            .map_err(|err| format!("Invalid FEN tag ({err}): {fen}"))
+    }
+    pub fn header(&self) -> String {
+        self.tags
+            .iter()
+            .map(|(name, value)| format!("[{name} \"{value}\"]\n"))
+            .collect()
+    }
+}
+
+/*
+    Reads every game out of a PGN file. A game ends at its result token, or where the next game's tags start
+*/
+pub fn parse_games(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut in_movetext = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if let Some(tag) = line.strip_prefix('[').and_then(|tag| tag.strip_suffix(']')) {
+            if in_movetext {
+                games.push(std::mem::take(&mut game));
+                in_movetext = false;
+            }
+            if let Some((name, value)) = tag.split_once(' ') {
+                game.tags
+                    .push((name.to_string(), value.trim().trim_matches('"').to_string()));
+            }
+        } else if !line.is_empty() && !line.starts_with('%') {
+            in_movetext = true;
+            game.moves.push(line.to_string());
+        }
+    }
+    if in_movetext || !game.tags.is_empty() {
+        games.push(game);
+    }
+
+    // The movetext was gathered line by line so comments and variations can span lines
+    games
+        .into_iter()
+        .flat_map(|game| split_movetext(game.tags, &game.moves.join("\n")))
+        .collect()
+}
+
+enum MovetextToken {
+    Word(String),
+    VariationStart,
+    VariationEnd,
+}
+
+// Splits movetext into words and variation brackets, dropping comments
+fn tokenize(movetext: &str) -> Vec<MovetextToken> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = movetext.chars();
+
+    while let Some(character) = chars.next() {
+        if (character.is_whitespace() || "{;()".contains(character)) && !word.is_empty() {
+            tokens.push(MovetextToken::Word(std::mem::take(&mut word)));
+        }
+        match character {
+            '{' => {
+                for skipped in chars.by_ref() {
+                    if skipped == '}' {
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                for skipped in chars.by_ref() {
+                    if skipped == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => tokens.push(MovetextToken::VariationStart),
+            ')' => tokens.push(MovetextToken::VariationEnd),
+            _ if character.is_whitespace() => {}
+            _ => word.push(character),
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(MovetextToken::Word(word));
+    }
+    tokens
+}
+
+// Turns movetext into moves and variations, starting a new game after every result token
+fn split_movetext(tags: Vec<(String, String)>, movetext: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame {
+        tags,
+        ..Default::default()
+    };
+    // Variations still being read, innermost last
+    let mut open: Vec<PgnVariation> = Vec::new();
+
+    for token in tokenize(movetext) {
+        match token {
+            MovetextToken::VariationStart => {
+                let parent_length = open
+                    .last()
+                    .map_or(game.moves.len(), |line| line.moves.len());
+                open.push(PgnVariation {
+                    branch_ply: parent_length.saturating_sub(1),
+                    ..Default::default()
+                });
+            }
+            MovetextToken::VariationEnd => {
+                if let Some(finished) = open.pop() {
+                    match open.last_mut() {
+                        Some(parent) => parent.variations.push(finished),
+                        None => game.variations.push(finished),
+                    }
+                }
+            }
+            MovetextToken::Word(word) if RESULTS.contains(&word.as_str()) => {
+                if open.is_empty() {
+                    game.result = word;
+                    games.push(std::mem::take(&mut game));
+                }
+            }
+            MovetextToken::Word(word) => {
+                // Move numbers can be glued to the move, as in 12.e4 or 12...Nf6
+                let word = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if !word.is_empty() && !word.starts_with('$') {
+                    match open.last_mut() {
+                        Some(line) => line.moves.push(word.to_string()),
+                        None => game.moves.push(word.to_string()),
+                    }
+                }
+            }
+        }
+    }
+
+    if !game.moves.is_empty() {
+        game.result = String::from("*");
+        games.push(game);
+    }
+    games
+}
+
+/*
+    Lays out movetext the way PGN files expect: move numbers before white's moves (and before black's when it
+    starts), wrapped under 80 columns. Each entry is a move with whatever annotation follows it
+*/
+pub fn write_movetext(first_ply: usize, annotated_moves: &[String], result: &str) -> String {
+    let mut words = Vec::new();
+
+    for (index, annotated) in annotated_moves.iter().enumerate() {
+        let ply = first_ply + index;
+        if ply.is_multiple_of(2) {
+            words.push(format!("{}.", ply / 2 + 1));
+        } else if index == 0 {
+            words.push(format!("{}...", ply / 2 + 1));
+        }
+        words.push(annotated.clone());
+    }
+    words.push(result.to_string());
+
+    let mut text = String::new();
+    let mut line_length = 0;
+    for word in words {
+        for piece in word.split(' ') {
+            if line_length > 0 && line_length + piece.len() + 1 > 79 {
+                text.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                text.push(' ');
+                line_length += 1;
+            }
+            text.push_str(piece);
+            line_length += piece.len();
+        }
+    }
+    text.push('\n');
+    text
+}
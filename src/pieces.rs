@@ -0,0 +1,201 @@
+use crate::bitboard::{Bitboard, BoardSize, PieceType, PIECE_TYPES};
+
+// Steps as (files, ranks), from white's side of the board
+pub const KNIGHT_STEPS: &[(i32, i32)] = &[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const DIAGONAL_STEPS: &[(i32, i32)] = &[(1, 1), (1, -1), (-1, -1), (-1, 1)];
+const ORTHOGONAL_STEPS: &[(i32, i32)] = &[(0, 1), (1, 0), (0, -1), (-1, 0)];
+pub const KING_STEPS: &[(i32, i32)] = &[
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+/*
+    What a kind of piece is: the letter FEN and SAN write it with, how it moves, and the sprite it's drawn with.
+    Leaps are taken once and rides repeat until something stands in the way. The standard pieces keep their own fast
+    generators in move.rs, and pawns and castling their own rules, so these moves are only generated for the fairy
+    pieces. A new one needs a PieceType, an entry here, what it's worth in SCORES, and art in each piece set
+*/
+pub struct PieceDefinition {
+    pub letter: char, // Upper case, as white's pieces are written
+    pub leaps: &'static [(i32, i32)],
+    pub rides: &'static [(i32, i32)],
+    pub sprite: &'static str, // The image in each piece set, after the team's w or b
+}
+
+// Every piece type's definition, in the order of PieceType
+#[rustfmt::skip]
+pub const PIECE_DEFINITIONS: [PieceDefinition; PIECE_TYPES] = [
+    PieceDefinition {
+        letter: ' ',
+        leaps: &[],
+        rides: &[],
+        sprite: "",
+    },
+    PieceDefinition {
+        letter: 'P',
+        leaps: &[],
+        rides: &[],
+        sprite: "p",
+    },
+    PieceDefinition {
+        letter: 'R',
+        leaps: &[],
+        rides: ORTHOGONAL_STEPS,
+        sprite: "r",
+    },
+    PieceDefinition {
+        letter: 'B',
+        leaps: &[],
+        rides: DIAGONAL_STEPS,
+        sprite: "b",
+    },
+    PieceDefinition {
+        letter: 'N',
+        leaps: KNIGHT_STEPS,
+        rides: &[],
+        sprite: "n",
+    },
+    PieceDefinition {
+        letter: 'Q',
+        leaps: &[],
+        rides: KING_STEPS,
+        sprite: "q",
+    },
+    PieceDefinition {
+        letter: 'K',
+        leaps: KING_STEPS,
+        rides: &[],
+        sprite: "k",
+    },
+    // Capablanca's compound pieces, drawn as a knight behind the rider they also move like
+    PieceDefinition {
+        letter: 'A',
+        leaps: KNIGHT_STEPS,
+        rides: DIAGONAL_STEPS,
+        sprite: "a",
+    },
+    PieceDefinition {
+        letter: 'C',
+        leaps: KNIGHT_STEPS,
+        rides: ORTHOGONAL_STEPS,
+        sprite: "c",
+    },
+    // A knight that keeps leaping the same way until something's in the way, drawn as an upside down knight like
+    // problem diagrams do
+    PieceDefinition {
+        letter: 'I',
+        leaps: &[],
+        rides: KNIGHT_STEPS,
+        sprite: "i",
+    },
+];
+
+pub fn definition(piece_type: PieceType) -> &'static PieceDefinition {
+    &PIECE_DEFINITIONS[piece_type as usize]
+}
+// The piece type a FEN letter stands for, whichever case it's in
+pub fn piece_from_letter(letter: char) -> Option<PieceType> {
+    let letter = letter.to_ascii_uppercase();
+    crate::bitboard::PIECE_TYPE_ARRAY
+        .into_iter()
+        .find(|piece_type| {
+            *piece_type != PieceType::None && definition(*piece_type).letter == letter
+        })
+}
+// The pieces that move by their definitions rather than by one of move.rs's generators
+pub fn is_fairy(piece_type: PieceType) -> bool {
+    !matches!(
+        piece_type,
+        PieceType::None
+            | PieceType::Pawn
+            | PieceType::Rook
+            | PieceType::Bishop
+            | PieceType::Knight
+            | PieceType::Queen
+            | PieceType::King
+    )
+}
+
+/*
+    Every square a piece moving by `definition` attacks from `square` on a board of `size`, where `occupied` says which
+    squares end a ride. Steps that would run off a side of the board stop there instead of wrapping round to the other
+*/
+pub fn definition_attacks(
+    definition: &PieceDefinition,
+    size: BoardSize,
+    square: usize,
+    occupied: impl Fn(usize) -> bool,
+) -> Bitboard {
+    let mut attacks = Bitboard::default();
+    for &step in definition.leaps {
+        if let Some(target) = size.offset(square, step) {
+            attacks.set_bit(target, true);
+        }
+    }
+    for &step in definition.rides {
+        let mut from = square;
+        while let Some(target) = size.offset(from, step) {
+            attacks.set_bit(target, true);
+            if occupied(target) {
+                break;
+            }
+            from = target;
+        }
+    }
+    attacks
+}
+
+/*
+    The squares strictly between `from` and `to` along the first ride that leads from one to the other, and that
+    whole ride from `from` to the edge of the board. Rides along ranks, files and diagonals are already in the between
+    and line tables, so this only finds the fairy ones, like a nightrider's
+*/
+fn ride_toward(size: BoardSize, from: usize, to: usize) -> Option<(Bitboard, Bitboard)> {
+    PIECE_DEFINITIONS
+        .iter()
+        .flat_map(|definition| definition.rides)
+        .find_map(|&step| {
+            let mut line = Bitboard::default();
+            let mut between = None;
+            let mut square = from;
+            while let Some(next) = size.offset(square, step) {
+                if next == to {
+                    between = Some(line);
+                }
+                line.set_bit(next, true);
+                square = next;
+            }
+            between.map(|between| (between, line))
+        })
+}
+// The squares a piece could block a check or pin from `from` to `to` on
+pub fn between(size: BoardSize, from: usize, to: usize) -> Bitboard {
+    let tables = size.tables();
+    if tables.line[from][to].state != 0 {
+        return tables.between[from][to];
+    }
+    ride_toward(size, from, to).map_or(Bitboard::default(), |(between, _)| between)
+}
+// The squares a piece on `start` pinned to a king on `king` can still move to, short of taking the pinning piece
+pub fn pin_line(size: BoardSize, king: usize, start: usize) -> Bitboard {
+    let line = size.tables().line[king][start];
+    if line.state != 0 {
+        return line;
+    }
+    ride_toward(size, king, start).map_or(Bitboard::default(), |(_, line)| line)
+}
@@ -0,0 +1,262 @@
+use std::{fs, path::PathBuf};
+
+use crate::{
+    bitboard::Team,
+    board::{BoardState, GameStatus},
+    r#move::Move,
+};
+
+pub const DEFAULT_PUZZLE_RATING: i32 = 1500;
+pub const PUZZLE_RATING_FILE: &str = "puzzle_rating.txt"; // Where the player's rating is kept between runs
+
+// How far one puzzle can move the rating, as in Elo's K-factor
+const PUZZLE_K_FACTOR: f64 = 32.0;
+
+/*
+    A position with the line that solves it. The first move is the opponent's, which sets the puzzle up, and
+    from there the player's moves and the opponent's replies take turns
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    pub moves: Vec<String>, // In UCI
+    pub rating: i32,
+    pub themes: Vec<String>,
+}
+impl Puzzle {
+    // The position before the opponent's move that sets the puzzle
+    pub fn board(&self) -> Result<BoardState, String> {
+        BoardState::from_fen(self.fen.clone()).map_err(|err| {
+            format!(
+                "Puzzle {} has an invalid FEN ({err}): {}",
+                self.id, self.fen
+            )
+        })
+    }
+    // The side the player solves for, which is the one that doesn't move first
+    pub fn solver(&self) -> Result<Team, String> {
+        Ok(self.board()?.active_team.opponent())
+    }
+}
+
+/*
+    Reads puzzles in the lichess database's CSV layout: PuzzleId,FEN,Moves,Rating followed by columns that are
+    skipped apart from Themes, the eighth. A header line is passed over, and so are blank lines
+*/
+pub fn parse_puzzles(csv: &str) -> Result<Vec<Puzzle>, String> {
+    let mut puzzles = Vec::new();
+    for (index, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("PuzzleId") {
+            continue;
+        }
+        let columns: Vec<&str> = line.split(',').collect();
+        let [id, fen, moves, rating, ..] = columns[..] else {
+            return Err(format!("Line {} isn't a puzzle: {line}", index + 1));
+        };
+        let puzzle = Puzzle {
+            id: id.to_string(),
+            fen: fen.to_string(),
+            moves: moves.split_whitespace().map(String::from).collect(),
+            rating: rating
+                .parse()
+                .map_err(|_| format!("Line {} has no rating: {line}", index + 1))?,
+            themes: columns.get(7).map_or(Vec::new(), |themes| {
+                themes.split_whitespace().map(String::from).collect()
+            }),
+        };
+        // A solution needs the setting-up move and at least one for the player
+        if puzzle.moves.len() < 2 {
+            return Err(format!("Puzzle {} has no solution", puzzle.id));
+        }
+        puzzle.board()?;
+        puzzles.push(puzzle);
+    }
+    Ok(puzzles)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PuzzleStep {
+    Correct, // The opponent replies next
+    Solved,
+    Wrong,
+}
+
+// One go at a puzzle, from the setting-up move to the end of the solution
+#[derive(Debug, Clone)]
+pub struct PuzzleAttempt {
+    pub puzzle: Puzzle,
+    pub ply: usize,   // How many of the solution's moves have been played
+    pub failed: bool, // A wrong move or a hint, either of which loses the puzzle for the rating
+    pub hints: usize, // How many hints were asked for
+    recorded: bool,   // Whether the rating has been changed for this puzzle yet
+}
+impl PuzzleAttempt {
+    pub fn new(puzzle: Puzzle) -> PuzzleAttempt {
+        PuzzleAttempt {
+            puzzle,
+            ply: 0,
+            failed: false,
+            hints: 0,
+            recorded: false,
+        }
+    }
+    pub fn is_solved(&self) -> bool {
+        self.ply >= self.puzzle.moves.len()
+    }
+    // The opponent's next move, once the player has found theirs
+    pub fn reply(&self, board: &BoardState) -> Option<Move> {
+        if self.ply % 2 == 1 {
+            return None;
+        }
+        Move::from_uci(board, self.puzzle.moves.get(self.ply)?)
+    }
+    pub fn reply_played(&mut self) {
+        self.ply += 1;
+    }
+    // The move the solution has the player make next
+    pub fn solution(&self, board: &BoardState) -> Option<Move> {
+        if self.ply.is_multiple_of(2) {
+            return None;
+        }
+        Move::from_uci(board, self.puzzle.moves.get(self.ply)?)
+    }
+    /*
+        Checks the player's move against the solution. Any mate on the solution's last move counts too, since a
+        position with two mates in one has no wrong answer between them
+    */
+    pub fn try_move(&mut self, board: &BoardState, played: Move) -> PuzzleStep {
+        let last = self.ply + 1 == self.puzzle.moves.len();
+        let mates = || {
+            let mut after = *board;
+            after.make_move(played).is_ok() && matches!(after.status(), GameStatus::Checkmate(_))
+        };
+        if self.solution(board) == Some(played) || (last && mates()) {
+            self.ply += 1;
+            if self.is_solved() {
+                PuzzleStep::Solved
+            } else {
+                PuzzleStep::Correct
+            }
+        } else {
+            self.failed = true;
+            PuzzleStep::Wrong
+        }
+    }
+    // The solution's next move, which gives the puzzle away as far as the rating goes
+    pub fn hint(&mut self, board: &BoardState) -> Option<Move> {
+        let solution = self.solution(board)?;
+        self.hints += 1;
+        self.failed = true;
+        Some(solution)
+    }
+}
+
+/*
+    The player's puzzle rating, which goes up and down like an Elo rating with each puzzle as the opponent.
+    It's kept on disk as `rating played solved`
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PuzzleRating {
+    pub rating: i32,
+    pub played: u32,
+    pub solved: u32,
+}
+impl Default for PuzzleRating {
+    fn default() -> Self {
+        PuzzleRating {
+            rating: DEFAULT_PUZZLE_RATING,
+            played: 0,
+            solved: 0,
+        }
+    }
+}
+impl PuzzleRating {
+    // The rating saved at `path`, or a fresh one if there isn't one yet
+    pub fn load(path: &PathBuf) -> Result<PuzzleRating, String> {
+        if !path.exists() {
+            return Ok(PuzzleRating::default());
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {err}", path.display()))?;
+        let numbers: Vec<i64> = text
+            .split_whitespace()
+            .map(|word| {
+                word.parse()
+                    .map_err(|_| format!("{} isn't a puzzle rating", path.display()))
+            })
+            .collect::<Result<_, String>>()?;
+        let [rating, played, solved] = numbers[..] else {
+            return Err(format!("{} isn't a puzzle rating", path.display()));
+        };
+        Ok(PuzzleRating {
+            rating: rating as i32,
+            played: played as u32,
+            solved: solved as u32,
+        })
+    }
+    pub fn save(&self, path: &PathBuf) -> Result<(), String> {
+        fs::write(
+            path,
+            format!("{} {} {}\n", self.rating, self.played, self.solved),
+        )
+        .map_err(|err| format!("Couldn't save {}: {err}", path.display()))
+    }
+    // Scores a puzzle of `puzzle_rating` as won or lost, and returns how far the rating moved
+    pub fn record(&mut self, puzzle_rating: i32, solved: bool) -> i32 {
+        let expected = 1.0 / (1.0 + 10f64.powf(f64::from(puzzle_rating - self.rating) / 400.0));
+        let score = if solved { 1.0 } else { 0.0 };
+        let change = (PUZZLE_K_FACTOR * (score - expected)).round() as i32;
+        self.rating += change;
+        self.played += 1;
+        self.solved += u32::from(solved);
+        change
+    }
+}
+
+/*
+    A run of puzzles. Each one comes from the set closest to the player's rating among those not tried yet, and
+    the rating is saved after every result
+*/
+pub struct PuzzleSession {
+    pub puzzles: Vec<Puzzle>,
+    pub tried: Vec<bool>, // Alongside the puzzles
+    pub attempt: Option<PuzzleAttempt>,
+    pub rating: PuzzleRating,
+    pub rating_path: PathBuf,
+}
+impl PuzzleSession {
+    pub fn new(puzzles: Vec<Puzzle>, rating_path: PathBuf) -> Result<PuzzleSession, String> {
+        if puzzles.is_empty() {
+            return Err(String::from("There are no puzzles to play"));
+        }
+        Ok(PuzzleSession {
+            tried: vec![false; puzzles.len()],
+            puzzles,
+            attempt: None,
+            rating: PuzzleRating::load(&rating_path)?,
+            rating_path,
+        })
+    }
+    // Moves on to the next puzzle and returns it, or None once every puzzle has been tried
+    pub fn next_puzzle(&mut self) -> Option<Puzzle> {
+        let rating = self.rating.rating;
+        let index = (0..self.puzzles.len())
+            .filter(|index| !self.tried[*index])
+            .min_by_key(|index| (self.puzzles[*index].rating - rating).abs())?;
+        self.tried[index] = true;
+        self.attempt = Some(PuzzleAttempt::new(self.puzzles[index].clone()));
+        Some(self.puzzles[index].clone())
+    }
+    // Changes the rating for the current puzzle, only the first time it's called. None once it's already counted
+    pub fn record_result(&mut self, solved: bool) -> Option<i32> {
+        let attempt = self.attempt.as_mut().filter(|attempt| !attempt.recorded)?;
+        attempt.recorded = true;
+        let change = self.rating.record(attempt.puzzle.rating, solved);
+        if let Err(err) = self.rating.save(&self.rating_path) {
+            eprintln!("{err}");
+        }
+        Some(change)
+    }
+}
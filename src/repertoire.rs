@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use rand::seq::IndexedRandom;
+
+use crate::{
+    board::BoardState,
+    pgn::{parse_games, PgnVariation},
+    r#move::Move,
+};
+
+/*
+    Prepared opening moves, read from a PGN file with variations. Moves are stored against the position they're
+    played from, so lines that transpose into each other share their continuations
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Repertoire {
+    moves: HashMap<u64, Vec<Move>>,
+    pub lines: Vec<RepertoireLine>, // Every line from the start to where it ends, side lines included
+}
+// One way through the repertoire, from the start of its game to the end of the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepertoireLine {
+    pub start_fen: String,
+    pub moves: Vec<Move>,
+}
+impl RepertoireLine {
+    // What the line is known by, which is its moves in UCI
+    pub fn key(&self) -> String {
+        self.moves
+            .iter()
+            .map(Move::to_uci)
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+impl Repertoire {
+    pub fn from_pgn(text: &str) -> Result<Repertoire, String> {
+        let mut repertoire = Repertoire::default();
+
+        for game in parse_games(text) {
+            let start = game.start_board()?;
+            let start_fen = start.as_fen();
+            repertoire.add_line(&start_fen, start, &[], &game.moves, &game.variations)?;
+        }
+        Ok(repertoire)
+    }
+    // Adds `moves` from `board`, which `before` reached from the start, and the side lines off them
+    fn add_line(
+        &mut self,
+        start_fen: &str,
+        board: BoardState,
+        before: &[Move],
+        moves: &[String],
+        variations: &[PgnVariation],
+    ) -> Result<(), String> {
+        let mut board = board;
+        let mut line = before.to_vec();
+
+        for (ply, san) in moves.iter().enumerate() {
+            // Side lines branch off before the move they replace is played
+            for variation in variations
+                .iter()
+                .filter(|variation| variation.branch_ply == ply)
+            {
+                self.add_line(
+                    start_fen,
+                    board,
+                    &line,
+                    &variation.moves,
+                    &variation.variations,
+                )?;
+            }
+
+            let played = Move::from_san(&board, san)
+                .ok_or_else(|| format!("Illegal repertoire move {san} in {}", board.as_fen()))?;
+            let known = self.moves.entry(board.position_hash()).or_default();
+            if !known.contains(&played) {
+                known.push(played);
+            }
+            board
+                .make_move(played)
+                .map_err(|err| format!("Couldn't play {san}: {err:?}"))?;
+            line.push(played);
+        }
+        if !moves.is_empty() {
+            self.lines.push(RepertoireLine {
+                start_fen: start_fen.to_string(),
+                moves: line,
+            });
+        }
+        Ok(())
+    }
+    // The prepared moves from this position, in the order the file gave them
+    pub fn moves_for(&self, board: &BoardState) -> &[Move] {
+        self.moves
+            .get(&board.position_hash())
+            .map_or(&[], |moves| moves.as_slice())
+    }
+    pub fn pick(&self, board: &BoardState) -> Option<Move> {
+        self.moves_for(board).choose(&mut rand::rng()).copied()
+    }
+    // How many positions have a prepared move
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{
+    analysis::{eval_graph_height, first_ply, AnnotatedMove, MoveQuality},
+    bitboard::{PieceType, Team},
+    board::BoardState,
+    pieces::definition,
+};
+
+const SQUARE_PIXELS: usize = 40;
+const GRAPH_WIDTH: usize = 600;
+const GRAPH_HEIGHT: usize = 200;
+// The board's colours, matching the ones it's drawn with in the app
+const LIGHT_SQUARE: &str = "#f07777";
+const DARK_SQUARE: &str = "#a62424";
+const LAST_MOVE_SQUARE: &str = "#ffcc40";
+const QUALITIES: [MoveQuality; 6] = [
+    MoveQuality::Brilliant,
+    MoveQuality::Best,
+    MoveQuality::Good,
+    MoveQuality::Inaccuracy,
+    MoveQuality::Mistake,
+    MoveQuality::Blunder,
+];
+
+// Frames are shown with :target, so stepping through the game is just following links and needs no scripts
+const STYLE: &str = "body { font-family: sans-serif; background: #333; color: #eee; margin: 2em; }
+a { color: #ffcc40; }
+.report { display: flex; gap: 2em; align-items: flex-start; }
+.frame { display: none; }
+.frame:target, .frames:not(:has(.frame:target)) .frame:first-child { display: block; }
+.frame nav { display: flex; justify-content: space-between; }
+.moves { columns: 2; min-width: 20em; }
+.eval { color: #aaa; font-size: 0.85em; }
+.Inaccuracy { color: #f0e060; } .Mistake { color: #f0a040; } .Blunder { color: #ff6060; } .Brilliant { color: #60d0ff; }
+table { border-collapse: collapse; } td, th { padding: 0.2em 0.8em; border-bottom: 1px solid #555; }";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn piece_glyph(piece_type: PieceType) -> String {
+    match piece_type {
+        PieceType::King => String::from("\u{265A}"),
+        PieceType::Queen => String::from("\u{265B}"),
+        PieceType::Rook => String::from("\u{265C}"),
+        PieceType::Bishop => String::from("\u{265D}"),
+        PieceType::Knight => String::from("\u{265E}"),
+        PieceType::Pawn => String::from("\u{265F}"),
+        PieceType::None => String::new(),
+        // There are no symbols for the fairy pieces, so they get their letters
+        fairy => definition(fairy).letter.to_string(),
+    }
+}
+
+// The board as an inline SVG diagram, with the squares of the last move picked out
+fn board_svg(board: &BoardState, last_move: Option<(usize, usize)>) -> String {
+    let size = board.size;
+    let (width, height) = (SQUARE_PIXELS * size.files(), SQUARE_PIXELS * size.ranks());
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">"
+    );
+
+    for square in 0..size.squares() {
+        let (file, rank) = (size.file(square), size.rank(square));
+        let x = file * SQUARE_PIXELS;
+        let y = (size.last_rank() - rank) * SQUARE_PIXELS;
+        let colour = if last_move.is_some_and(|(start, target)| square == start || square == target)
+        {
+            LAST_MOVE_SQUARE
+        } else if (file + rank) % 2 == 0 {
+            DARK_SQUARE
+        } else {
+            LIGHT_SQUARE
+        };
+        svg.push_str(&format!(
+            "<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE_PIXELS}\" height=\"{SQUARE_PIXELS}\" fill=\"{colour}\"/>"
+        ));
+
+        let piece_type = board.piece_list[square];
+        if piece_type != PieceType::None {
+            let (fill, stroke) = if board.get_square_team(square) == Team::White {
+                ("#fff", "#000")
+            } else {
+                ("#000", "#fff")
+            };
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" fill=\"{fill}\" stroke=\"{stroke}\" stroke-width=\"0.8\">{}</text>",
+                x + SQUARE_PIXELS / 2,
+                y + SQUARE_PIXELS * 4 / 5,
+                SQUARE_PIXELS * 4 / 5,
+                piece_glyph(piece_type)
+            ));
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/*
+    A standalone SVG of how the eval went over the game, white's advantage up and black's down, with the area under
+    the line filled in white. It starts from level at the start position
+*/
+pub fn eval_graph_svg(analysed: &[AnnotatedMove]) -> String {
+    let step = GRAPH_WIDTH as f32 / analysed.len().max(1) as f32;
+    let height = GRAPH_HEIGHT as f32;
+    let points: Vec<String> = std::iter::once(0)
+        .chain(analysed.iter().map(|analysed_move| analysed_move.eval))
+        .enumerate()
+        .map(|(index, eval)| {
+            format!(
+                "{:.1},{:.1}",
+                index as f32 * step,
+                height * (1.0 - eval_graph_height(eval))
+            )
+        })
+        .collect();
+    let line = points.join(" ");
+    let (end, middle) = (analysed.len() as f32 * step, GRAPH_HEIGHT / 2);
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{GRAPH_WIDTH}\" height=\"{GRAPH_HEIGHT}\" viewBox=\"0 0 {GRAPH_WIDTH} {GRAPH_HEIGHT}\">\
+<rect width=\"{GRAPH_WIDTH}\" height=\"{GRAPH_HEIGHT}\" fill=\"#333\"/>\
+<polygon points=\"0,{GRAPH_HEIGHT} {line} {end:.1},{GRAPH_HEIGHT}\" fill=\"#eee\"/>\
+<line x1=\"0\" y1=\"{middle}\" x2=\"{GRAPH_WIDTH}\" y2=\"{middle}\" stroke=\"#888\"/>\
+<polyline points=\"{line}\" fill=\"none\" stroke=\"{LAST_MOVE_SQUARE}\" stroke-width=\"2\"/></svg>\n"
+    )
+}
+
+// How often each side made each grade of move, and how many centipawns they gave up a move on average
+fn summary_table(analysed: &[AnnotatedMove]) -> String {
+    let mut table = String::from("<table><tr><th></th><th>White</th><th>Black</th></tr>");
+    let by_team = |team: Team| {
+        analysed
+            .iter()
+            .filter(move |analysed_move| analysed_move.mover == team)
+    };
+
+    for quality in QUALITIES {
+        let count = |team: Team| {
+            by_team(team)
+                .filter(|analysed_move| analysed_move.quality == quality)
+                .count()
+        };
+        table.push_str(&format!(
+            "<tr><td class=\"{quality}\">{quality}</td><td>{}</td><td>{}</td></tr>",
+            count(Team::White),
+            count(Team::Black)
+        ));
+    }
+    let average_loss = |team: Team| {
+        let moves = by_team(team).count().max(1);
+        by_team(team)
+            .map(|analysed_move| analysed_move.loss)
+            .sum::<i32>()
+            / moves as i32
+    };
+    table.push_str(&format!(
+        "<tr><td>Average loss</td><td>{}</td><td>{}</td></tr></table>",
+        average_loss(Team::White),
+        average_loss(Team::Black)
+    ));
+    table
+}
+
+/*
+    A self-contained HTML page for a game that has been through `analyse_moves`: a diagram for every position
+    that the move list and arrows step through, each move's grade and eval, and a summary of how each side played
+*/
+pub fn html_report(
+    tags: &[(String, String)],
+    start: BoardState,
+    analysed: &[AnnotatedMove],
+    result: &str,
+) -> String {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map_or("?", |(_, value)| value.as_str())
+    };
+    let title = escape(&format!("{} - {} {result}", tag("White"), tag("Black")));
+    let first_ply = first_ply(&start);
+    let move_label = |index: usize| {
+        let ply = first_ply + index;
+        format!(
+            "{}{} {}",
+            ply / 2 + 1,
+            if ply.is_multiple_of(2) { "." } else { "..." },
+            analysed[index].san
+        )
+    };
+
+    let mut frames = String::new();
+    let mut board = start;
+    for frame in 0..=analysed.len() {
+        let last_move = frame
+            .checked_sub(1)
+            .map(|index| (analysed[index].played.start, analysed[index].played.target));
+        let caption = match frame.checked_sub(1) {
+            None => String::from("Start position"),
+            Some(index) => {
+                let analysed_move = &analysed[index];
+                let mut caption = escape(&format!(
+                    "{}{} ({}, {:+.2})",
+                    move_label(index),
+                    analysed_move.quality.pgn_suffix(),
+                    analysed_move.quality,
+                    analysed_move.eval as f32 / 100.0
+                ));
+                if let Some(line) = &analysed_move.better_line {
+                    caption.push_str(&format!("<br>Better was {}", escape(&line.join(" "))));
+                }
+                caption
+            }
+        };
+        let previous = match frame {
+            0 => String::from("<span></span>"),
+            _ => format!("<a href=\"#ply-{}\">&lsaquo; back</a>", frame - 1),
+        };
+        let next = if frame < analysed.len() {
+            format!("<a href=\"#ply-{}\">next &rsaquo;</a>", frame + 1)
+        } else {
+            String::from("<span></span>")
+        };
+
+        frames.push_str(&format!(
+            "<section class=\"frame\" id=\"ply-{frame}\">{}<p>{}</p><nav>{previous}{next}</nav></section>\n",
+            board_svg(&board, last_move),
+            caption
+        ));
+        if let Some(analysed_move) = analysed.get(frame) {
+            if board.make_move(analysed_move.played).is_err() {
+                break;
+            }
+        }
+    }
+
+    let move_list: String = analysed
+        .iter()
+        .enumerate()
+        .map(|(index, analysed_move)| {
+            format!(
+                "<li><a href=\"#ply-{}\" class=\"{}\">{}{}</a> <span class=\"eval\">{:+.2}</span></li>\n",
+                index + 1,
+                analysed_move.quality,
+                escape(&move_label(index)),
+                analysed_move.quality.pgn_suffix(),
+                analysed_move.eval as f32 / 100.0
+            )
+        })
+        .collect();
+    let tag_list: String = tags
+        .iter()
+        .map(|(name, value)| format!("{}: {}<br>\n", escape(name), escape(value)))
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{tag_list}</p>\n<div class=\"report\">\n<div class=\"frames\">\n{frames}</div>\n<ol class=\"moves\">\n{move_list}</ol>\n</div>\n<h2>Summary</h2>\n{}\n</body>\n</html>\n",
+        summary_table(analysed)
+    )
+}
@@ -9,16 +9,10 @@ mod tests {
     fn en_passant() {
         use crate::board::BoardState;
         use crate::r#move::Move;
-        use crate::Team;
-        use bitvec::prelude::Lsb0;
-        use bitvec::view::BitView;
         let mut test_board = BoardState::from_fen(String::from(
-            "rnbqkbnr/4pppp/3p4/2p5/pp6/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1",
+            "rnbqkbnr/4pppp/3p4/2p5/pp6/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
         ))
         .expect("Invalid FEN used in testing");
-        let moves = test_board.get_legal_moves();
-
-        BoardState::render_piece_list(test_board.piece_list.to_vec());
 
         // White to move. Do c2c4 to allow black en passant
         let _ = test_board
@@ -28,19 +22,32 @@ mod tests {
                 captures: None,
                 is_pawn_double: true,
                 is_castle: false,
+                promotion: None,
             })
             .unwrap();
         assert_eq!(
-            moves[Bitboard::al_notation_to_bit_idx("b4").unwrap()]
-                .0
-                .state
-                .view_bits::<Lsb0>()
-                .get(26)
-                .expect("Piece Bitboard did not extend to 25 bits")
-                .then_some(1),
-            Some(1),
-            "En passant test failed"
+            test_board.en_passant_square,
+            Bitboard::al_notation_to_bit_idx("c3")
+        );
+
+        // Black's b4 pawn takes on c3, and the pawn on c4 is what it captures
+        let moves = test_board.get_legal_moves();
+        let capture = moves[Bitboard::al_notation_to_bit_idx("b4").unwrap()]
+            .1
+            .iter()
+            .find(|available_move| available_move.target == 18)
+            .copied()
+            .expect("En passant test failed");
+        assert_eq!(capture.captures.map(|piece| piece.position), Some(26));
+
+        test_board.make_move(capture).unwrap();
+        assert_eq!(
+            test_board.piece_list[26],
+            PieceType::None,
+            "The passed pawn is still there"
         );
+        assert_eq!(test_board.piece_list[18], PieceType::Pawn);
+        assert_eq!(test_board.en_passant_square, None);
     }
 
     #[test]
@@ -48,16 +55,11 @@ mod tests {
     fn en_passant_deferred() {
         use crate::board::BoardState;
         use crate::r#move::Move;
-        use crate::Team;
-        use bitvec::prelude::Lsb0;
-        use bitvec::view::BitView;
         let mut test_board = BoardState::from_fen(String::from(
-            "rnbqkbnr/4pppp/3p4/2p5/pp6/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1",
+            "rnbqkbnr/4pppp/3p4/2p5/pp6/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
         ))
         .expect("Invalid FEN used in testing");
 
-        let moves = test_board.get_legal_moves();
-
         // White to move. Do c2c4 to allow black en passant
         test_board
             .make_move(Move {
@@ -66,15 +68,14 @@ mod tests {
                 captures: None,
                 is_pawn_double: true,
                 is_castle: false,
+                promotion: None,
             })
             .unwrap();
 
+        let moves = test_board.get_legal_moves();
         let ept = moves[Bitboard::al_notation_to_bit_idx("b4").unwrap()]
             .0
-            .state
-            .view_bits::<Lsb0>()
-            .get(26)
-            .expect("Piece Bitboard did not extend to 25 bits")
+            .get_bit(18)
             .then_some(1);
         if ept.is_none() {
             panic!("Prerequisite test failed")
@@ -83,35 +84,34 @@ mod tests {
         test_board
             .make_move(Move {
                 start: 58,
-                target: 32,
+                target: 40,
                 captures: None,
                 is_pawn_double: false,
                 is_castle: false,
+                promotion: None,
             })
             .unwrap();
 
-        // White does something else (h2h3), meaning the opportunity for black to take 24 with a pawn should be lost by this next move
+        // White does something else (h2h3), meaning the opportunity for black to take on c3 should be lost by this next move
         test_board
             .make_move(Move {
-                start: 14,
-                target: 21,
+                start: 15,
+                target: 23,
                 captures: None,
                 is_pawn_double: false,
                 is_castle: false,
+                promotion: None,
             })
             .unwrap();
 
         let moves_after_deferral = test_board.get_legal_moves();
 
-        // Black to move. Check if they can attack square 24 where we just en passanted to
-        // There is no other legal captures on the square besides en passant
+        // Black to move. Check if they can still take en passant on c3
+        // There is no other legal move to the square besides en passant
         assert_eq!(
             moves_after_deferral[Bitboard::al_notation_to_bit_idx("b4").unwrap()]
                 .0
-                .state
-                .view_bits::<Lsb0>()
-                .get(26)
-                .expect("Piece Bitboard did not extend to 25 bits")
+                .get_bit(18)
                 .then_some(1),
             None,
             "En passant test failed - you can still capture after a turn"
@@ -123,8 +123,6 @@ mod tests {
     fn check_castling() {
         use crate::board::BoardState;
         use crate::Team;
-        use bitvec::prelude::Lsb0;
-        use bitvec::view::BitView;
 
         let test_board = BoardState::from_fen(String::from(
             "rnb1kbnr/ppp2ppp/8/3p4/3pP3/3B1N2/PPP2qPP/RNBQK2R w KQkq - 0 1",
@@ -134,10 +132,7 @@ mod tests {
 
         let can_castle = moves[WHITE_KING_POS]
             .0
-            .state
-            .view_bits::<Lsb0>()
-            .get(Bitboard::al_notation_to_bit_idx("g1").unwrap())
-            .expect("Piece Bitboard did not extend to g1 bits")
+            .get_bit(Bitboard::al_notation_to_bit_idx("g1").unwrap())
             .then_some(1);
 
         println!("{}", test_board.capture_bitboard[Team::White as usize]);
@@ -206,7 +201,7 @@ mod tests {
             BoardState::from_fen(String::from("8/7p/8/5r2/P3K2k/1P4p1/2P5/8 w - - 0 40"))
                 .expect("Invalid FEN used in testing");
 
-        let compare_board = start_board.clone();
+        let compare_board = start_board;
 
         let move_to_reverse = Move {
             start: Bitboard::al_notation_to_bit_idx("e4").unwrap(),
@@ -214,12 +209,13 @@ mod tests {
             captures: start_board.get_piece_at_pos(Bitboard::al_notation_to_bit_idx("f5").unwrap()),
             is_pawn_double: false,
             is_castle: false,
+            promotion: None,
         };
 
         start_board.dump_positions();
-        start_board.make_move(move_to_reverse).unwrap();
+        let undo = start_board.make_move(move_to_reverse).unwrap();
         println!("{} COMP {}", start_board.as_fen(), compare_board.as_fen());
-        start_board.unmake_move(move_to_reverse).unwrap();
+        start_board.unmake_move(undo).unwrap();
         assert_eq!(
             start_board.as_fen(),
             compare_board.as_fen(),
@@ -232,8 +228,6 @@ mod tests {
         use crate::bitboard::Bitboard;
         use crate::bitboard::Team;
         use crate::board::BoardState;
-        use bitvec::prelude::Lsb0;
-        use bitvec::view::BitView;
 
         // In this position Qh3 is watching f1, preventing a castle
         let test_board = BoardState::from_fen(String::from(
@@ -244,10 +238,7 @@ mod tests {
 
         let can_castle = moves[WHITE_KING_POS]
             .0
-            .state
-            .view_bits::<Lsb0>()
-            .get(Bitboard::al_notation_to_bit_idx("g1").unwrap())
-            .expect("Piece Bitboard did not extend to 25 bits")
+            .get_bit(Bitboard::al_notation_to_bit_idx("g1").unwrap())
             .then_some(1);
 
         assert_eq!(
@@ -261,8 +252,6 @@ mod tests {
         use crate::bitboard::Bitboard;
         use crate::bitboard::Team;
         use crate::board::BoardState;
-        use bitvec::prelude::Lsb0;
-        use bitvec::view::BitView;
 
         // In this position Qh3 is watching f1, preventing a castle
         let test_board = BoardState::from_fen(String::from(
@@ -274,10 +263,7 @@ mod tests {
 
         let can_castle = moves[WHITE_KING_POS]
             .0
-            .state
-            .view_bits::<Lsb0>()
-            .get(Bitboard::al_notation_to_bit_idx("g1").unwrap())
-            .expect("Piece Bitboard did not extend to g1 bits")
+            .get_bit(Bitboard::al_notation_to_bit_idx("g1").unwrap())
             .then_some(1);
 
         assert_eq!(
@@ -292,35 +278,4353 @@ mod tests {
         use crate::board::BoardState;
         use crate::r#move::Move;
 
-        let mut test_board = BoardState::from_fen(String::from("K1n5/8/8/2q5/8/3k4/8/8 w - - 0 51")).expect("Invalid FEN used in testing");
-        test_board.make_move({
-            Move {
-                start: Bitboard::al_notation_to_bit_idx("c5").unwrap(),
-                target: Bitboard::al_notation_to_bit_idx("a7").unwrap(),
-                captures: None,
-                is_pawn_double: false,
-                is_castle: false
-            }
-        }).unwrap();
-        test_board.prune_moves_for_team_mut(test_board.get_psuedolegal_moves(), crate::bitboard::Team::White);
+        let mut test_board =
+            BoardState::from_fen(String::from("K1n5/8/8/2q5/8/3k4/8/8 b - - 0 51"))
+                .expect("Invalid FEN used in testing");
+        test_board
+            .make_move({
+                Move {
+                    start: Bitboard::al_notation_to_bit_idx("c5").unwrap(),
+                    target: Bitboard::al_notation_to_bit_idx("a7").unwrap(),
+                    captures: None,
+                    is_pawn_double: false,
+                    is_castle: false,
+                    promotion: None,
+                }
+            })
+            .unwrap();
         println!("{test_board:?}");
-        assert!(test_board.active_team_checkmate, "BoardState did not calculate checkmate from position {}, which is mate for black", test_board.as_fen());
+        assert_eq!(
+            test_board.status(),
+            crate::board::GameStatus::Checkmate(crate::bitboard::Team::Black),
+            "BoardState did not calculate checkmate from position {}, which is mate for black",
+            test_board.as_fen()
+        );
     }
 
     #[test]
-    fn pawn_jump() {
-	use crate::bitboard::Bitboard;
+    fn san() {
+        use crate::bitboard::Bitboard;
+        use crate::board::BoardState;
+
+        let test_board = BoardState::from_fen(String::from("4k3/8/8/8/8/8/8/RN2KN2 w - - 0 1"))
+            .expect("Invalid FEN used in testing");
+        let moves = test_board.get_legal_moves();
+        let find_move = |start: &str, target: &str| {
+            let target = Bitboard::al_notation_to_bit_idx(target).unwrap();
+            moves[Bitboard::al_notation_to_bit_idx(start).unwrap()]
+                .1
+                .iter()
+                .find(|legal_move| legal_move.target == target)
+                .copied()
+                .expect("Move used in testing is not legal")
+        };
+
+        assert_eq!(
+            find_move("b1", "d2").to_san(&test_board),
+            "Nbd2",
+            "SAN did not disambiguate between two knights"
+        );
+        assert_eq!(
+            find_move("a1", "a8").to_san(&test_board),
+            "Ra8+",
+            "SAN did not mark a check"
+        );
+    }
+
+    #[test]
+    fn adjudication() {
+        use crate::arena::{Adjudication, Adjudicator, MatchResult};
         use crate::bitboard::Team;
+        use crate::board::{BoardState, Variant};
+
+        let board = BoardState::from_fen(String::from(
+            "4k3/pppppppp/8/8/8/8/PPPPPPPP/QQQQK3 w - - 0 10",
+        ))
+        .expect("Invalid FEN used in testing");
+        let rules = Adjudication {
+            resign_moves: 2,
+            ..Default::default()
+        };
+        let mut adjudicator = Adjudicator::new(rules);
+
+        // Only one engine thinks white is winning, so nothing happens
+        assert_eq!(adjudicator.record(Team::White, Some(2000), &board), None);
+        assert_eq!(adjudicator.record(Team::Black, Some(0), &board), None);
+        for _ in 0..2 {
+            assert_eq!(adjudicator.record(Team::White, Some(2000), &board), None);
+            assert_eq!(
+                adjudicator.record(Team::Black, Some(1500), &board),
+                None,
+                "Resigned too early"
+            );
+        }
+        assert_eq!(
+            adjudicator.record(Team::White, Some(2000), &board),
+            Some(MatchResult::WhiteWins),
+            "Both engines agreed white is winning but the game wasn't called"
+        );
+
+        let bare_kings = BoardState::from_fen(String::from("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"))
+            .expect("Invalid FEN used in testing");
+        assert_eq!(
+            Adjudicator::new(rules).record(Team::White, None, &bare_kings),
+            Some(MatchResult::Draw),
+            "King and knight against king was not called a draw"
+        );
+        assert_eq!(
+            Adjudicator::new(rules).record(
+                Team::White,
+                None,
+                &bare_kings.with_variant(Variant::KingOfTheHill)
+            ),
+            None,
+            "A king that can still walk to the hill was called a draw"
+        );
+    }
+
+    #[test]
+    fn move_quality() {
+        use crate::analysis::{classify, MoveQuality};
         use crate::board::BoardState;
-        use bitvec::prelude::Lsb0;
-        use bitvec::view::BitView;
+        use crate::r#move::Move;
 
-	let mut test_board = BoardState::from_fen(String::from("rnbqkb1r/pppppppp/6N1/8/6n1/8/PPPPPPPP/RNBQKB1R b KQkq - 0 1")).expect("Invalid FEN used in testing");
+        let board = BoardState::from_fen(String::from("1k6/8/8/8/8/8/8/R3K3 w - - 0 1"))
+            .expect("Invalid FEN used in testing");
+        let rook_lift = Move::from_uci(&board, "a1a6").expect("a1a6 should be legal");
+        let rook_slide = Move::from_uci(&board, "a1b1").expect("a1b1 should be legal");
 
-        let moves = test_board.get_legal_moves();
-	
-	let can_jump_knight = moves[Bitboard::al_notation_to_bit_idx("g7").unwrap()].0.get_bit::<Lsb0>(Bitboard::al_notation_to_bit_idx("g5").unwrap());
-	assert!(!can_jump_knight, "Pawn is moving twice with a knight in the way")
-	
+        assert_eq!(classify(&board, rook_lift, rook_lift, 0), MoveQuality::Best);
+        assert_eq!(
+            classify(&board, rook_slide, rook_lift, 20),
+            MoveQuality::Good
+        );
+        assert_eq!(
+            classify(&board, rook_slide, rook_lift, 80),
+            MoveQuality::Inaccuracy
+        );
+        assert_eq!(
+            classify(&board, rook_slide, rook_lift, 200),
+            MoveQuality::Mistake
+        );
+        assert_eq!(
+            classify(&board, rook_slide, rook_lift, 900),
+            MoveQuality::Blunder
+        );
+
+        // Best move, but the rook can be taken for nothing
+        let rook_offer = Move::from_uci(&board, "a1a8").expect("a1a8 should be legal");
+        assert_eq!(
+            classify(&board, rook_offer, rook_offer, 0),
+            MoveQuality::Brilliant
+        );
+
+        // PGN exports mark mistakes and blunders with the standard NAGs
+        assert_eq!(MoveQuality::Mistake.nag(), Some(2));
+        assert_eq!(MoveQuality::Blunder.nag(), Some(4));
+        assert_eq!(MoveQuality::Good.nag(), None);
+    }
+
+    #[test]
+    fn tactics() {
+        use crate::bitboard::{Bitboard, BoardSize, Team};
+        use crate::board::BoardState;
+        use crate::tactics::{find_tactics, Motif};
+
+        let square = |name: &str| Bitboard::al_notation_to_bit_idx(name).unwrap();
+        let between = &BoardSize::Standard.tables().between;
+        assert_eq!(
+            between[square("a1")][square("h8")].state,
+            0x0040201008040200,
+            "Diagonal between a1 and h8 is wrong"
+        );
+        assert_eq!(
+            between[square("a1")][square("b3")].state,
+            0,
+            "Squares off a shared line have something between them"
+        );
+
+        let cases = [
+            (
+                "r3k3/2N5/8/8/8/8/8/4K3 w - - 0 1",
+                Motif::Fork,
+                vec!["a8", "e8"],
+            ),
+            (
+                "4k3/8/2n5/1B6/8/8/8/4K3 w - - 0 1",
+                Motif::Pin,
+                vec!["c6", "e8"],
+            ),
+            (
+                "q7/8/8/k7/8/8/8/R3K3 w - - 0 1",
+                Motif::Skewer,
+                vec!["a5", "a8"],
+            ),
+            (
+                "4k3/8/8/8/8/8/4N3/4R1K1 w - - 0 1",
+                Motif::DiscoveredAttack,
+                vec!["e8"],
+            ),
+        ];
+        for (fen, motif, targets) in cases {
+            let board =
+                BoardState::from_fen(String::from(fen)).expect("Invalid FEN used in testing");
+            let targets: Vec<usize> = targets.into_iter().map(square).collect();
+            let found = find_tactics(&board, Team::White);
+
+            assert!(
+                found
+                    .iter()
+                    .any(|tactic| tactic.motif == motif && tactic.targets == targets),
+                "Missed the {motif:?} in {fen}, found {found:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn eval_command() {
+        use crate::cli::eval_command;
+
+        let args = |line: &[&str]| {
+            line.iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>()
+        };
+
+        let report = eval_command(&args(&[
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+            "--depth",
+            "1",
+        ]))
+        .expect("Eval failed");
+        assert!(
+            report.contains("Best move: Ra8#"),
+            "Missed the back rank mate:\n{report}"
+        );
+        assert!(
+            report.contains("info depth 1"),
+            "No search info in the report:\n{report}"
+        );
+
+        assert!(
+            eval_command(&args(&["not a fen"])).is_err(),
+            "Accepted a bad FEN"
+        );
+        assert!(
+            eval_command(&args(&["6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "--depth"])).is_err(),
+            "Accepted a missing depth"
+        );
+        assert!(
+            eval_command(&args(&[
+                "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+                "--nodes",
+                "5"
+            ]))
+            .is_err(),
+            "Accepted an unknown option"
+        );
+    }
+
+    #[test]
+    fn pgn_analysis() {
+        use crate::analysis::annotate_game;
+        use crate::pgn::parse_games;
+
+        let text = "[Event \"First\"]\n[White \"A\"]\n\n1. e4 {best by test} e5 2.Nf3 (2. f4 exf4) 2...Nc6 $1 1-0\n\n[Event \"Second\"]\n[FEN \"6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 30\"]\n\n30. Ra8# 1/2-1/2\n";
+        let games = parse_games(text);
+
+        assert_eq!(games.len(), 2, "Wrong number of games read");
+        assert_eq!(
+            games[0].moves,
+            vec!["e4", "e5", "Nf3", "Nc6"],
+            "Comments, variations or move numbers leaked into the moves"
+        );
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[1].result, "1/2-1/2");
+
+        let annotated = annotate_game(&games[1], 0).expect("Failed to annotate a legal game");
+        assert!(
+            annotated.starts_with("[Event \"Second\"]"),
+            "Tags were lost:\n{annotated}"
+        );
+        assert!(
+            annotated.contains("30. Ra8#"),
+            "Move numbering or SAN is off:\n{annotated}"
+        );
+        assert!(
+            annotated.trim_end().ends_with("1/2-1/2"),
+            "Result was lost:\n{annotated}"
+        );
+
+        let mut broken = games[0].clone();
+        broken.moves[1] = String::from("Ke3");
+        assert!(
+            annotate_game(&broken, 0).is_err(),
+            "Annotated a game with an illegal move"
+        );
+    }
+
+    #[test]
+    fn repertoire() {
+        use crate::board::BoardState;
+        use crate::pgn::parse_games;
+        use crate::r#move::Move;
+        use crate::repertoire::Repertoire;
+
+        let text = "1. e4 e5 (1... c5 2. Nf3 (2. Nc3) d6) 2. Nf3 Nc6 (2... d6 3. d4) *\n\n1. d4 d5 2. c4 *\n";
+        let games = parse_games(text);
+        assert_eq!(
+            games[0].variations.len(),
+            2,
+            "Side lines weren't read as variations"
+        );
+        assert_eq!(
+            games[0].variations[0].branch_ply, 1,
+            "Variation hangs off the wrong move"
+        );
+        assert_eq!(
+            games[0].variations[1].branch_ply, 3,
+            "Variation hangs off the wrong move"
+        );
+        assert_eq!(
+            games[0].variations[0].variations[0].moves,
+            vec!["Nc3"],
+            "Nested variation was lost"
+        );
+
+        let repertoire = Repertoire::from_pgn(text).expect("Failed to read the repertoire");
+        let mut board = BoardState::from_fen(String::from(crate::START_POS_CHESS))
+            .expect("Invalid FEN used in testing");
+        let san = |board: &BoardState| {
+            let mut moves: Vec<String> = repertoire
+                .moves_for(board)
+                .iter()
+                .map(|book_move| book_move.to_san(board))
+                .collect();
+            moves.sort();
+            moves
+        };
+
+        assert_eq!(
+            san(&board),
+            vec!["d4", "e4"],
+            "Both games should start the repertoire"
+        );
+        board
+            .make_move(Move::from_san(&board, "e4").unwrap())
+            .unwrap();
+        assert_eq!(san(&board), vec!["c5", "e5"]);
+        board
+            .make_move(Move::from_san(&board, "c5").unwrap())
+            .unwrap();
+        assert_eq!(san(&board), vec!["Nc3", "Nf3"]);
+        board
+            .make_move(Move::from_san(&board, "Nf3").unwrap())
+            .unwrap();
+        assert_eq!(san(&board), vec!["d6"]);
+        board
+            .make_move(Move::from_san(&board, "d6").unwrap())
+            .unwrap();
+        assert!(
+            san(&board).is_empty(),
+            "Found repertoire moves past the end of the line"
+        );
+
+        assert!(
+            Repertoire::from_pgn("1. e4 e4 *").is_err(),
+            "Accepted an illegal repertoire"
+        );
+
+        // Every way through the file is a line of its own, side lines from where they branch off
+        let lines: Vec<String> = repertoire.lines.iter().map(|line| line.key()).collect();
+        assert_eq!(
+            lines,
+            [
+                "e2e4 c7c5 b1c3",
+                "e2e4 c7c5 g1f3 d7d6",
+                "e2e4 e7e5 g1f3 d7d6 d2d4",
+                "e2e4 e7e5 g1f3 b8c6",
+                "d2d4 d7d5 c2c4"
+            ]
+        );
+        assert!(repertoire
+            .lines
+            .iter()
+            .all(|line| line.start_fen == crate::START_POS_CHESS));
+    }
+
+    #[test]
+    fn opening_trainer() {
+        use crate::bitboard::Team;
+        use crate::board::BoardState;
+        use crate::r#move::Move;
+        use crate::repertoire::Repertoire;
+        use crate::trainer::{DrillStep, OpeningTrainer, ReviewSchedule};
+        use chrono::{Duration, Utc};
+
+        let repertoire =
+            Repertoire::from_pgn("1. e4 c5 (1... e5 2. Nf3 Nc6) 2. Nf3 d6 *\n\n1. d4 *\n").unwrap();
+        let path = std::env::temp_dir().join(format!("chess-r-review-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut trainer = OpeningTrainer::new(
+            &repertoire,
+            Team::Black,
+            ReviewSchedule::load(path.clone()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            trainer.lines.len(),
+            2,
+            "1. d4 has no move for black to find"
+        );
+        assert!(OpeningTrainer::new(
+            &Repertoire::from_pgn("1. d4 *").unwrap(),
+            Team::Black,
+            ReviewSchedule::load(path.clone()).unwrap()
+        )
+        .is_err());
+
+        // Drills the line with a slip in it, which brings it straight back
+        let now = Utc::now();
+        assert_eq!(trainer.due_count(now), 2);
+        assert_eq!(trainer.next_line(now).key(), "e2e4 e7e5 g1f3 b8c6");
+        let mut board = BoardState::from_fen(String::from(crate::START_POS_CHESS)).unwrap();
+        let play = |board: &mut BoardState, uci: &str| {
+            let played = Move::from_uci(board, uci).unwrap();
+            board.make_move(played).unwrap();
+        };
+        let reply = trainer.expected().unwrap();
+        assert_eq!(reply.to_uci(), "e2e4");
+        trainer.reply_played();
+        play(&mut board, "e2e4");
+        assert_eq!(
+            trainer.try_move(&repertoire, &board, Move::from_uci(&board, "c7c5").unwrap()),
+            DrillStep::OtherLine,
+            "Another prepared move isn't a slip"
+        );
+        assert_eq!(trainer.drill.unwrap().slips, 0);
+        assert_eq!(
+            trainer.try_move(&repertoire, &board, Move::from_uci(&board, "a7a6").unwrap()),
+            DrillStep::Wrong
+        );
+        assert_eq!(
+            trainer.try_move(&repertoire, &board, Move::from_uci(&board, "e7e5").unwrap()),
+            DrillStep::Correct
+        );
+        play(&mut board, "e7e5");
+        trainer.reply_played();
+        play(&mut board, "g1f3");
+        assert_eq!(
+            trainer.hint().map(|hint| hint.to_uci()).as_deref(),
+            Some("b8c6")
+        );
+        assert_eq!(
+            trainer.try_move(&repertoire, &board, Move::from_uci(&board, "b8c6").unwrap()),
+            DrillStep::LineDone
+        );
+        assert!(trainer.is_line_done());
+        assert_eq!(
+            trainer.finish_line(now).map(|card| card.interval_days),
+            Some(0)
+        );
+        assert_eq!(
+            trainer.next_line(now).key(),
+            "e2e4 e7e5 g1f3 b8c6",
+            "A line with slips is due again straight away"
+        );
+
+        // A clean run puts the line off for a day, then twice as long each time after
+        trainer.drill.as_mut().unwrap().ply = 4;
+        assert_eq!(
+            trainer.finish_line(now).map(|card| card.interval_days),
+            Some(1)
+        );
+        assert_eq!(trainer.due_count(now), 1);
+        assert_eq!(
+            trainer.next_line(now).key(),
+            "e2e4 c7c5 g1f3 d7d6",
+            "A line never played comes before one that isn't due"
+        );
+        trainer.drill.as_mut().unwrap().ply = 4;
+        trainer.finish_line(now);
+        assert_eq!(
+            trainer.next_line(now).key(),
+            "e2e4 e7e5 g1f3 b8c6",
+            "With nothing due, the line due soonest comes round early"
+        );
+        trainer.drill.as_mut().unwrap().ply = 4;
+        let card = trainer.finish_line(now).unwrap();
+        assert_eq!(card.interval_days, 2);
+        assert_eq!(card.due, now + Duration::days(2));
+
+        let reloaded = ReviewSchedule::load(path.clone()).unwrap();
+        assert_eq!(
+            reloaded
+                .due("e2e4 e7e5 g1f3 b8c6")
+                .map(|due| due.timestamp()),
+            Some(card.due.timestamp())
+        );
+        assert_eq!(
+            reloaded
+                .due("e2e4 c7c5 g1f3 d7d6")
+                .map(|due| due.timestamp()),
+            Some((now + Duration::days(1)).timestamp())
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn time_odds_clock() {
+        use crate::bitboard::Team;
+        use crate::cli::clock_from_args;
+        use crate::clock::{ChessClock, TimeControl};
+        use std::time::Duration;
+
+        let five_three = TimeControl::parse("5+3").expect("Couldn't read 5+3");
+        assert_eq!(
+            five_three,
+            TimeControl {
+                base: Duration::from_secs(300),
+                increment: Duration::from_secs(3),
+                delay: Duration::ZERO
+            }
+        );
+        assert_eq!(
+            TimeControl::parse("1").map(|control| control.increment),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(TimeControl::parse("x"), None);
+        assert_eq!(TimeControl::parse("inf+0"), None);
+        assert_eq!(TimeControl::parse("NaN"), None);
+        assert_eq!(TimeControl::parse("1e30+2"), None);
+
+        let one_minute = TimeControl::parse("1").unwrap();
+        let mut clock = ChessClock::new(five_three, one_minute);
+        assert!(
+            clock.move_budget(Team::Black) <= Duration::from_secs(30),
+            "Budget should never be over half the time left"
+        );
+        assert!(
+            clock.move_budget(Team::Black) < clock.move_budget(Team::White),
+            "The side with odds should get to think longer"
+        );
+
+        clock.start(Team::White);
+        clock.press(Team::White);
+        assert!(
+            clock.remaining(Team::White) > Duration::from_secs(300),
+            "The increment wasn't added after the move"
+        );
+        assert!(clock.remaining(Team::Black) <= Duration::from_secs(60));
+        assert_eq!(clock.flagged(), None);
+
+        let args: Vec<String> = ["--time", "5", "--bot-time", "1"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let clock = clock_from_args(&args, Team::Black)
+            .unwrap()
+            .expect("No clock from the time flags");
+        assert_eq!(
+            clock.control(Team::White),
+            one_minute,
+            "The bot plays white here so it should get its own time"
+        );
+        assert!(clock_from_args(&[], Team::White).unwrap().is_none());
+    }
+
+    #[test]
+    fn correspondence_save() {
+        use crate::bitboard::{BoardSize, Team};
+        use crate::board::{BoardState, Variant};
+        use crate::cli::correspondence_from_args;
+        use crate::correspondence::CorrespondenceGame;
+        use crate::r#move::Move;
+        use chrono::{Duration, Utc};
+
+        let path =
+            std::env::temp_dir().join(format!("chess-r-correspondence-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let args: Vec<String> = ["--correspondence", path.to_str().unwrap(), "--days", "2"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        let mut game = correspondence_from_args(&args, Team::Black, Variant::Standard)
+            .unwrap()
+            .expect("No game from the flags");
+        assert!(
+            path.exists(),
+            "A new correspondence game should be saved straight away"
+        );
+        let board = game.board().unwrap();
+        game.record_move(Move::from_uci(&board, "e2e4").unwrap(), board.size)
+            .unwrap();
+
+        let resumed = correspondence_from_args(&args, Team::White, Variant::Atomic)
+            .unwrap()
+            .expect("Couldn't pick the game back up");
+        assert_eq!(
+            resumed.player_team,
+            Team::Black,
+            "The saved game should keep its own player"
+        );
+        assert_eq!(resumed.days_per_move, 2);
+        assert_eq!(
+            resumed.variant,
+            Variant::Standard,
+            "The saved game should keep its own rules"
+        );
+        assert_eq!(resumed.moves, vec!["e2e4"]);
+        let mut played = board;
+        played
+            .make_move(Move::from_uci(&board, "e2e4").unwrap())
+            .unwrap();
+        assert_eq!(
+            resumed.board().unwrap().as_fen(),
+            played.as_fen(),
+            "The resumed game should be where it was left"
+        );
+        assert!(!resumed.is_overdue(Utc::now()));
+        assert!(resumed.is_overdue(Utc::now() + Duration::days(3)));
+        let _ = std::fs::remove_file(&path);
+
+        // A variant's game starts from its own position and is played by its rules once picked back up
+        let mut game = correspondence_from_args(&args, Team::White, Variant::Capablanca)
+            .unwrap()
+            .expect("No game from the flags");
+        let board = game.board().unwrap();
+        assert_eq!(board.size, BoardSize::TenByEight);
+        game.record_move(Move::from_uci(&board, "b1c3").unwrap(), board.size)
+            .unwrap();
+        let resumed = correspondence_from_args(&args, Team::White, Variant::Standard)
+            .unwrap()
+            .expect("Couldn't pick the game back up");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(resumed.variant, Variant::Capablanca);
+        assert_eq!(resumed.start_board().unwrap().variant, Variant::Capablanca);
+        assert_eq!(resumed.board().unwrap().variant, Variant::Capablanca);
+        assert_eq!(resumed.moves, vec!["b1c3"]);
+        let reparsed = CorrespondenceGame::parse(path.clone(), &resumed.to_text()).unwrap();
+        assert_eq!(reparsed.variant, Variant::Capablanca);
+
+        // Starting over on a new game takes its rules along
+        let atomic = BoardState::from_fen(String::from(crate::START_POS_CHESS))
+            .unwrap()
+            .with_variant(Variant::Atomic);
+        let mut restarted = resumed;
+        restarted.restart(&atomic).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(restarted.moves.is_empty());
+        assert_eq!(restarted.board().unwrap().variant, Variant::Atomic);
+
+        assert!(CorrespondenceGame::parse(path, "days 1\nplayer White\nstart 8/8/8/8/8/8/8/8 w - - 0 1\nmoves e2e4\nlast_move 2026-01-01T00:00:00+00:00\n").is_err(), "Accepted a game that can't be replayed");
+    }
+
+    #[test]
+    fn tournament() {
+        use crate::arena::{parse_entrant, play_tournament, round_robin, Adjudication};
+        use crate::board::BoardState;
+        use crate::pgn::parse_games;
+
+        for entrants in 2..=5 {
+            let pairings = round_robin(entrants, 1);
+            assert_eq!(
+                pairings.len(),
+                entrants * (entrants - 1),
+                "Every pair should meet once with each colour"
+            );
+            for white in 0..entrants {
+                for black in (0..entrants).filter(|black| *black != white) {
+                    assert_eq!(
+                        pairings
+                            .iter()
+                            .filter(|pairing| pairing.white == white && pairing.black == black)
+                            .count(),
+                        1
+                    );
+                }
+            }
+            for round in 1..=pairings.last().unwrap().round {
+                let mut seated: Vec<usize> = pairings
+                    .iter()
+                    .filter(|pairing| pairing.round == round)
+                    .flat_map(|pairing| [pairing.white, pairing.black])
+                    .collect();
+                let total = seated.len();
+                seated.sort();
+                seated.dedup();
+                assert_eq!(seated.len(), total, "Someone played twice in round {round}");
+            }
+        }
+
+        assert!(parse_entrant("bogus").is_err());
+        assert!(parse_entrant("matt:deep").is_err());
+        assert!(
+            parse_entrant("uci:/nonexistent/engine").is_err(),
+            "Launched an engine that isn't there"
+        );
+
+        let mut entrants = vec![
+            parse_entrant("randy").unwrap(),
+            parse_entrant("matt:1").unwrap(),
+            parse_entrant("randy").unwrap(),
+        ];
+        let pairings = round_robin(entrants.len(), 1);
+        let start = BoardState::from_fen(String::from("4k3/8/8/8/8/8/4P3/4K2R w K - 0 1"))
+            .expect("Invalid FEN used in testing");
+        let rules = Adjudication {
+            move_limit: 6,
+            ..Default::default()
+        };
+        let mut played = 0;
+        let (crosstable, pgn) =
+            play_tournament(&mut entrants, &pairings, &[start], rules, &mut |_, _| {
+                played += 1
+            });
+
+        assert_eq!(played, 6);
+        let total: f32 = (0..3).map(|entrant| crosstable.score(entrant)).sum();
+        assert_eq!(total, 6.0, "Every game should hand out exactly one point");
+        let games = parse_games(&pgn);
+        assert_eq!(games.len(), 6);
+        assert_eq!(games[0].tag("Event"), Some("chess-r tournament"));
+        assert_eq!(
+            games[0].tag("FEN"),
+            Some("4k3/8/8/8/8/8/4P3/4K2R w K - 0 1"),
+            "Games from a set-up position should say where they started"
+        );
+        assert!(crosstable.to_string().contains("Matt(1)"));
+    }
+
+    #[test]
+    fn engine_match() {
+        use crate::arena::{match_pairings, read_openings, MatchResult, MatchScore};
+        let pairings = match_pairings(4);
+        assert_eq!(
+            pairings
+                .iter()
+                .map(|pairing| (pairing.white, pairing.black))
+                .collect::<Vec<_>>(),
+            vec![(0, 1), (1, 0), (0, 1), (1, 0)]
+        );
+
+        let mut score = MatchScore::new([String::from("Ada"), String::from("Matt")]);
+        assert_eq!(score.elo_difference(), None);
+        for (pairing, result) in pairings.iter().cycle().zip([
+            MatchResult::WhiteWins,
+            MatchResult::BlackWins,
+            MatchResult::Draw,
+            MatchResult::WhiteWins,
+            MatchResult::WhiteWins,
+            MatchResult::BlackWins,
+            MatchResult::BlackWins,
+            MatchResult::Draw,
+            MatchResult::WhiteWins,
+            MatchResult::BlackWins,
+        ]) {
+            score.record(*pairing, result);
+        }
+        assert_eq!((score.wins, score.draws, score.losses), (6, 2, 2));
+        let (elo, margin) = score.elo_difference().unwrap();
+        assert_eq!(elo.round(), 147.0, "A 70% score is about 147 Elo");
+        assert!(margin > 0.0 && margin < 400.0);
+        assert!(score.to_string().contains("Matt"));
+
+        let sweep = MatchScore {
+            wins: 3,
+            ..MatchScore::new([String::from("Ada"), String::from("Randy")])
+        };
+        assert_eq!(
+            sweep.elo_difference(),
+            None,
+            "A clean sweep has no finite Elo difference"
+        );
+
+        let openings = read_openings("# Suite\nrnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\n\nrnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - bm Nf3; id \"open\";").unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(
+            openings[1].as_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert!(read_openings("not a position").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn uci_opponent() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::clock::{ChessClock, TimeControl};
+        use crate::opponents::uci::UciTime;
+        use crate::opponents::{ChessOpponent, MoveComputer};
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::Duration;
+
+        // A stand-in engine that answers every search with e7e5, and keeps a log of the searches it was asked for
+        let directory =
+            std::env::temp_dir().join(format!("chess-r-uci-opponent-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let script = directory.join("engine.sh");
+        let log = directory.join("go.log");
+        std::fs::write(&script, format!("#!/bin/sh\nwhile read line; do\n  case \"$line\" in\n    uci) echo 'id name Fakefish'; echo uciok ;;\n    isready) echo readyok ;;\n    go*) echo \"$line\" >> '{}'; echo 'info depth 7 score cp -25 pv e7e5'; echo 'bestmove e7e5' ;;\n    quit) exit 0 ;;\n  esac\ndone\n", log.display())).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let board = BoardState::from_fen(String::from(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+        ))
+        .unwrap();
+        let mut opponent = ChessOpponent::uci(script.clone());
+        assert_eq!(
+            opponent.to_string(),
+            "engine.sh",
+            "Named after the program until it says otherwise"
+        );
+        let outcome = opponent.think(board, &History::default(), false, &mut |_| {});
+        assert_eq!(
+            outcome.best_move.map(|best| best.to_uci()),
+            Some(String::from("e7e5"))
+        );
+        assert_eq!((outcome.info.depth, outcome.info.score), (7, -25));
+        assert_eq!(opponent.to_string(), "Fakefish");
+
+        // Copies play through the same process, and on the clock they hand the engine both sides' time
+        let control = TimeControl {
+            base: Duration::from_secs(60),
+            increment: Duration::from_secs(2),
+            delay: Duration::ZERO,
+        };
+        let mut on_clock = opponent.clone();
+        if let ChessOpponent::Uci(_, time, _) = &mut on_clock {
+            *time = UciTime::from_clock(&ChessClock::new(control, control));
+        }
+        assert!(on_clock.get_move(board, &History::default()).is_some());
+        drop((opponent, on_clock));
+        let searches = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_dir_all(&directory);
+        assert_eq!(
+            searches,
+            "go movetime 500\ngo wtime 60000 btime 60000 winc 2000 binc 2000\n"
+        );
+
+        let mut missing = ChessOpponent::uci(directory.join("not-an-engine"));
+        assert!(missing
+            .get_move(
+                BoardState::from_fen(String::from(crate::START_POS_CHESS)).unwrap(),
+                &History::default()
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn ics_client() {
+        use crate::bitboard::Team;
+        use crate::ics::{parse_game_over, parse_style12, IcsEvent, IcsSession};
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::time::Duration;
+
+        let update = parse_style12("<12> rnbqkbnr pppppppp -------- -------- ----P--- -------- PPPP-PPP RNBQKBNR B 4 1 1 1 1 0 7 GuestA GuestB -1 5 3 39 39 300 297 1 P/e2-e4 (0:03) e4 0 1 0").expect("Couldn't read the style 12 line");
+        assert_eq!(
+            update.fen,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(update.game_number, 7);
+        assert_eq!(
+            update.our_team(),
+            Some(Team::White),
+            "We just moved, so we're white"
+        );
+        assert_eq!(update.black_time, Duration::from_secs(297));
+        assert_eq!(update.last_move.as_deref(), Some("e4"));
+        assert_eq!(parse_style12("Welcome to FICS"), None);
+        assert_eq!(
+            parse_game_over("{Game 7 (GuestA vs. GuestB) GuestA resigns} 0-1"),
+            Some(IcsEvent::GameOver {
+                game_number: 7,
+                reason: String::from("GuestA resigns"),
+                result: String::from("0-1")
+            })
+        );
+
+        // A stand-in server that lets a guest in and then reports the first command it gets
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            stream.write_all(b"Welcome\n\rlogin: ").unwrap();
+            reader.read_line(&mut line).unwrap();
+            stream
+                .write_all(b"Press return to enter the server as \"GuestWXYZ\": ")
+                .unwrap();
+            reader.read_line(&mut line).unwrap();
+            stream
+                .write_all(b"**** Starting FICS session as GuestWXYZ(U) ****\n\rfics% ")
+                .unwrap();
+            let mut commands = Vec::new();
+            while commands.len() < 4 {
+                let mut command = String::new();
+                reader.read_line(&mut command).unwrap();
+                commands.push(command.trim().to_string());
+            }
+            stream
+                .write_all(b"fics% {Game 3 (GuestWXYZ vs. Bot) Bot resigns} 1-0\n\r")
+                .unwrap();
+            commands
+        });
+
+        let mut session = IcsSession::connect(&address, "guest", None).expect("Couldn't log in");
+        assert_eq!(session.handle, "GuestWXYZ");
+        session.seek(5, 3).unwrap();
+        let commands = server.join().unwrap();
+        assert_eq!(commands.last().map(String::as_str), Some("seek 5 3"));
+
+        let mut events = Vec::new();
+        for _ in 0..50 {
+            events.extend(session.poll());
+            if events.contains(&IcsEvent::Disconnected) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(
+            matches!(
+                events.first(),
+                Some(IcsEvent::GameOver { game_number: 3, .. })
+            ),
+            "Got {events:?}"
+        );
+    }
+
+    #[test]
+    fn html_report() {
+        use crate::cli::report_command;
+
+        let directory = std::env::temp_dir();
+        let input = directory.join(format!("chess-r-report-{}.pgn", std::process::id()));
+        let output = directory.join(format!("chess-r-report-{}.html", std::process::id()));
+        std::fs::write(
+            &input,
+            "[White \"Fool\"]\n[Black \"Scholar\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n",
+        )
+        .unwrap();
+
+        let args = vec![input.display().to_string(), output.display().to_string()];
+        report_command(&args).expect("Couldn't write the report");
+        let html = std::fs::read_to_string(&output).unwrap();
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+
+        assert!(html.contains("<title>Fool - Scholar 0-1</title>"));
+        assert_eq!(
+            html.matches("<section class=\"frame\"").count(),
+            5,
+            "Every position needs a frame, including the start"
+        );
+        assert_eq!(html.matches("<svg").count(), 5);
+        assert!(
+            html.contains("class=\"Blunder\">2. g4??"),
+            "g4 should be marked as the blunder it is"
+        );
+        assert!(!html.contains("<script"), "Reports shouldn't need scripts");
+        assert!(report_command(&args[..1]).is_err());
+    }
+
+    #[test]
+    fn pgn_replay() {
+        use crate::analysis::pgn_moves;
+        use crate::board::History;
+        use crate::cli::replay_from_args;
+
+        let path = std::env::temp_dir().join(format!("chess-r-replay-{}.pgn", std::process::id()));
+        std::fs::write(&path, "[White \"A\"]\n\n1. e4 e5 1-0\n\n[White \"B\"]\n\n1. d4 {a comment\nover two lines} d5 (1... Nf6 2. c4 (2. Nf3 g6)) 2. c4 *\n").unwrap();
+        let args = |flags: &[&str]| {
+            flags
+                .iter()
+                .map(|flag| flag.to_string())
+                .collect::<Vec<String>>()
+        };
+        let file = path.display().to_string();
+        let game = replay_from_args(&args(&["chess-r", "--replay", &file, "--game", "2"]));
+        let missing = replay_from_args(&args(&["chess-r", "--replay", &file, "--game", "3"]));
+        let _ = std::fs::remove_file(&path);
+
+        let game = game
+            .expect("Couldn't read the game")
+            .expect("--replay was ignored");
+        assert_eq!(game.tag("White"), Some("B"));
+        assert_eq!(
+            game.moves,
+            vec!["d4", "d5", "c4"],
+            "Comments or variations leaked into the mainline"
+        );
+        assert!(missing.is_err(), "Picked a game past the end of the file");
+        assert_eq!(replay_from_args(&args(&["chess-r"])), Ok(None));
+
+        let mut board = game.start_board().unwrap();
+        let mut history = History::default();
+        for played in pgn_moves(&game).expect("The mainline is legal") {
+            history.make_move(&mut board, played).unwrap();
+        }
+        assert!(
+            board
+                .as_fen()
+                .starts_with("rnbqkbnr/ppp1pppp/8/3p4/2PP4/8/PP2PPPP/RNBQKBNR b "),
+            "Replayed to {}",
+            board.as_fen()
+        );
+        assert_eq!(
+            history.take_back(&mut board).map(|played| played.to_uci()),
+            Some(String::from("c2c4"))
+        );
+        assert_eq!(history.moves_played().count(), 2);
+    }
+
+    #[test]
+    fn pgn_export() {
+        use crate::board::BoardState;
+        use crate::opponents::ChessOpponent;
+        use crate::r#move::Move;
+        use crate::ui::Game;
+
+        let start = BoardState::from_fen(String::from(crate::START_POS_CHESS)).unwrap();
+        let mut game = Game::new(start, crate::bitboard::Team::White, ChessOpponent::Matt(1));
+        for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            let played = Move::from_uci(&game.board, uci).unwrap();
+            game.history.make_move(&mut game.board, played).unwrap();
+        }
+        let result = game.board.status().result().expect("Fool's mate is over");
+        let pgn = game.to_pgn(result, "normal");
+        assert!(
+            pgn.contains("[Result \"0-1\"]\n[Termination \"normal\"]\n"),
+            "Missing tags:\n{pgn}"
+        );
+        assert!(
+            !pgn.contains("[FEN"),
+            "The usual start position doesn't need a FEN tag:\n{pgn}"
+        );
+        assert!(
+            pgn.ends_with("\n1. f3 e5 2. g4 Qh4# 0-1\n"),
+            "Movetext is off:\n{pgn}"
+        );
+
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 30";
+        let mut game = Game::new(
+            BoardState::from_fen(String::from(fen)).unwrap(),
+            crate::bitboard::Team::White,
+            ChessOpponent::Matt(1),
+        );
+        let played = Move::from_uci(&game.board, "g8f8").unwrap();
+        game.history.make_move(&mut game.board, played).unwrap();
+        let directory = std::env::temp_dir().join(format!("chess-r-games-{}", std::process::id()));
+        let saved = game
+            .save_pgn(&directory, "*", "time forfeit")
+            .expect("Couldn't save the game");
+        let pgn = std::fs::read_to_string(&saved).unwrap();
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert!(
+            pgn.contains(&format!("[SetUp \"1\"]\n[FEN \"{fen}\"]\n")),
+            "A set up position needs its FEN:\n{pgn}"
+        );
+        assert!(pgn.contains("[Termination \"time forfeit\"]"));
+        assert!(pgn.ends_with("\n30... Kf8 *\n"), "Movetext is off:\n{pgn}");
+    }
+
+    #[test]
+    fn illegal_move_reasons() {
+        use crate::board::BoardState;
+        use crate::r#move::{explain_illegal_move, IllegalMoveReason};
+        let pinned =
+            BoardState::from_fen(String::from("4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1")).unwrap();
+        assert_eq!(
+            explain_illegal_move(&pinned, 12, 29),
+            Some(IllegalMoveReason::PiecePinned { pinned_by: 60 })
+        );
+        assert_eq!(
+            explain_illegal_move(&pinned, 12, 12 + 3),
+            Some(IllegalMoveReason::CannotMoveThere)
+        );
+        assert_eq!(
+            explain_illegal_move(&pinned, 60, 52),
+            Some(IllegalMoveReason::NotYourTurn)
+        );
+        assert_eq!(
+            explain_illegal_move(&pinned, 20, 28),
+            Some(IllegalMoveReason::NoPiece)
+        );
+        assert_eq!(explain_illegal_move(&pinned, 4, 3), None);
+
+        let checked =
+            BoardState::from_fen(String::from("4r1k1/8/8/8/8/8/3N4/4K3 w - - 0 1")).unwrap();
+        assert_eq!(
+            explain_illegal_move(&checked, 11, 26),
+            Some(IllegalMoveReason::KingInCheck)
+        );
+        assert_eq!(
+            explain_illegal_move(&checked, 4, 12),
+            Some(IllegalMoveReason::KingInCheck)
+        );
+
+        let castling =
+            BoardState::from_fen(String::from("5rk1/8/8/8/8/8/8/R3K2R w KQ - 0 1")).unwrap();
+        assert_eq!(
+            explain_illegal_move(&castling, 4, 6),
+            Some(IllegalMoveReason::CastlingPathAttacked { square: 5 })
+        );
+        assert_eq!(explain_illegal_move(&castling, 4, 2), None);
+        let no_rights =
+            BoardState::from_fen(String::from("6k1/8/8/8/8/8/8/R3K2R w K - 0 1")).unwrap();
+        assert_eq!(
+            explain_illegal_move(&no_rights, 4, 2),
+            Some(IllegalMoveReason::CastlingRightsLost)
+        );
+    }
+
+    #[test]
+    fn dgt_board_moves() {
+        use crate::board::BoardState;
+        use crate::dgt::{move_from_placement, placement_of, read_messages, Placement};
+        let board = BoardState::from_fen(String::from(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ))
+        .unwrap();
+
+        // A board dump lists the fields from a8 to h1, split by a bit of line noise
+        let back_rank = [2, 3, 4, 6, 5, 4, 3, 2];
+        let mut dump = vec![0x86, 0, 67];
+        dump.extend(back_rank.iter().map(|code| code + 6));
+        dump.extend([7; 8]);
+        dump.extend([0; 32]);
+        dump.extend([1; 8]);
+        dump.extend(back_rank);
+        let mut received = vec![0x12];
+        received.extend(&dump[..20]);
+        let mut placement: Placement = [None; 64];
+        assert!(!read_messages(&mut received, &mut placement));
+        received.extend(&dump[20..]);
+        assert!(read_messages(&mut received, &mut placement));
+        assert_eq!(placement, placement_of(&board));
+        assert_eq!(move_from_placement(&board, &placement), None);
+
+        // Lifting the pawn off e2 isn't a move yet, setting it down on e4 is
+        let mut received = vec![0x8E, 0, 5, 52, 0];
+        assert!(read_messages(&mut received, &mut placement));
+        assert_eq!(move_from_placement(&board, &placement), None);
+        let mut received = vec![0x8E, 0, 5, 36, 1];
+        assert!(read_messages(&mut received, &mut placement));
+        assert_eq!(
+            move_from_placement(&board, &placement).map(|played| played.to_uci()),
+            Some(String::from("e2e4"))
+        );
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn voice_moves() {
+        use crate::board::BoardState;
+        use crate::voice::{spoken_confirmation, spoken_moves, spoken_to_san, Confirmation};
+        assert_eq!(spoken_to_san("Knight f3"), Some(String::from("Nf3")));
+        assert_eq!(
+            spoken_to_san("bishop takes echo five"),
+            Some(String::from("Bxe5"))
+        );
+        assert_eq!(spoken_to_san("castle kingside"), Some(String::from("O-O")));
+        assert_eq!(spoken_to_san("castles long"), Some(String::from("O-O-O")));
+        assert_eq!(
+            spoken_to_san("pawn d eight promotes to queen"),
+            Some(String::from("d8=Q"))
+        );
+        assert_eq!(spoken_to_san("hello there"), None);
+
+        let board = BoardState::from_fen(String::from(
+            "rnbqkbnr/pppppppp/8/8/8/5N2/PPP1PPPP/RNBQKB1R w KQkq - 0 1",
+        ))
+        .unwrap();
+        let san = |phrase: &str| {
+            spoken_moves(&board, phrase)
+                .iter()
+                .map(|spoken| spoken.to_san(&board))
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(san("pawn to e4"), ["e4"]);
+        assert_eq!(san("knight b d2"), ["Nbd2"]);
+        let mut ambiguous = san("knight d2");
+        ambiguous.sort();
+        assert_eq!(ambiguous, ["Nbd2", "Nfd2"]);
+        assert_eq!(san("queen h5"), Vec::<String>::new());
+
+        assert_eq!(
+            spoken_confirmation("the second one"),
+            Some(Confirmation::Choice(2))
+        );
+        assert_eq!(
+            spoken_confirmation("cancel that"),
+            Some(Confirmation::Cancel)
+        );
+        assert_eq!(spoken_confirmation("knight"), None);
+        assert_eq!(spoken_confirmation("0"), None);
+        assert_eq!(
+            spoken_confirmation("zero or first"),
+            Some(Confirmation::Choice(1))
+        );
+
+        // Words spelt from file letters aren't squares
+        assert_eq!(spoken_to_san("be a good knight"), None);
+        assert_eq!(
+            spoken_to_san("bishop add bad c4"),
+            Some(String::from("Bc4"))
+        );
+        assert_eq!(spoken_to_san("knight g1 f3"), Some(String::from("Ng1f3")));
+    }
+
+    #[test]
+    fn log_level_flag() {
+        use crate::cli::log_directives_from_args;
+        let args = |flags: &[&str]| {
+            flags
+                .iter()
+                .map(|flag| flag.to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            log_directives_from_args(&args(&["chess-r"])),
+            Ok(vec![String::from("chess_r=warn")])
+        );
+        assert_eq!(
+            log_directives_from_args(&args(&[
+                "chess-r",
+                "--log-level",
+                "info,movegen=trace,wgpu=error"
+            ])),
+            Ok([
+                "chess_r=warn",
+                "chess_r=info",
+                "chess_r::board=trace",
+                "chess_r::move=trace",
+                "wgpu=error"
+            ]
+            .map(String::from)
+            .to_vec())
+        );
+        assert!(
+            log_directives_from_args(&args(&["chess-r", "--log-level", "search=loud"])).is_err()
+        );
+        assert!(log_directives_from_args(&args(&["chess-r", "--log-level"])).is_err());
+    }
+
+    #[test]
+    fn variants() {
+        use crate::bitboard::Team;
+        use crate::board::History;
+        use crate::board::{BoardState, GameStatus, Variant};
+        use crate::opponents::{static_breakdown, ChessOpponent, EngineOptions, MoveComputer};
+        use crate::r#move::Move;
+        use std::time::Duration;
+
+        assert_eq!(Variant::from_name("koth"), Some(Variant::KingOfTheHill));
+        assert_eq!(Variant::from_name("Three-check"), Some(Variant::ThreeCheck));
+        assert_eq!(Variant::from_name("crazyhouse"), None);
+
+        // Bare kings are a draw, unless one of them can still walk to the hill
+        let bare_kings =
+            BoardState::from_fen(String::from("7k/8/8/8/8/4K3/8/8 w - - 0 1")).unwrap();
+        assert_eq!(bare_kings.status(), GameStatus::DrawByMaterial);
+        assert_eq!(
+            bare_kings.with_variant(Variant::ThreeCheck).status(),
+            GameStatus::DrawByMaterial
+        );
+        let mut hill = bare_kings.with_variant(Variant::KingOfTheHill);
+        assert_eq!(hill.status(), GameStatus::Ongoing);
+
+        // The evaluation knows which king is nearer the centre, and Ada heads straight for it
+        assert!(static_breakdown(&hill).variant > 0);
+        let best = ChessOpponent::ada(EngineOptions::with_move_time(Duration::from_millis(200)))
+            .get_move(hill, &History::default())
+            .unwrap();
+        assert!(
+            ["e3d4", "e3e4"].contains(&best.to_uci().as_str()),
+            "Ada played {}",
+            best.to_uci()
+        );
+        hill.make_move(Move::from_uci(&hill, "e3e4").unwrap())
+            .unwrap();
+        assert_eq!(hill.status(), GameStatus::KingOfTheHill(Team::White));
+        assert_eq!(hill.status().winner(), Some(Team::White));
+
+        // Only checks count in Three-check, and taking the third back takes the win back too
+        let mut checks = BoardState::from_fen(String::from("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"))
+            .unwrap()
+            .with_variant(Variant::ThreeCheck);
+        checks.checks_given = [2, 0];
+        let (before, zobrist) = (checks, checks.zobrist);
+        let undo = checks
+            .make_move(Move::from_uci(&checks, "a1a8").unwrap())
+            .unwrap();
+        assert_eq!(checks.checks_given, [3, 0]);
+        assert_eq!(checks.status(), GameStatus::ThirdCheck(Team::White));
+        checks.unmake_move(undo).unwrap();
+        assert_eq!(checks.checks_given, before.checks_given);
+        assert_eq!(checks.zobrist, zobrist);
+        let undo = checks
+            .make_move(Move::from_uci(&checks, "a1a7").unwrap())
+            .unwrap();
+        assert_eq!(checks.checks_given, [2, 0]);
+        checks.unmake_move(undo).unwrap();
+
+        // Standard chess doesn't count them at all
+        let mut standard =
+            BoardState::from_fen(String::from("4k3/8/8/8/8/8/8/R3K3 w - - 0 1")).unwrap();
+        standard
+            .make_move(Move::from_uci(&standard, "a1a8").unwrap())
+            .unwrap();
+        assert_eq!(standard.checks_given, [0, 0]);
+    }
+
+    #[test]
+    fn atomic() {
+        use crate::bitboard::Team;
+        use crate::board::{BoardState, GameStatus, Variant};
+        use crate::r#move::Move;
+        let atomic = |fen: &str| {
+            BoardState::from_fen(String::from(fen))
+                .unwrap()
+                .with_variant(Variant::Atomic)
+        };
+        let is_legal = |board: &BoardState, uci: &str| {
+            board
+                .legal_moves(board.active_team)
+                .iter()
+                .any(|packed| packed.unpack(board).to_uci() == uci)
+        };
+
+        // The queen takes the knight and goes up with it, along with the bishop and her own rook beside it. Pawns survive
+        let mut board = atomic("4k3/8/2p1b3/3n4/2P1R3/8/8/3QK3 w - - 0 1");
+        let before = board.as_fen();
+        let zobrist = board.zobrist;
+        let undo = board
+            .make_move(Move::from_uci(&board, "d1d5").unwrap())
+            .unwrap();
+        assert!(
+            board.as_fen().starts_with("4k3/8/2p5/8/2P5/8/8/4K3 b"),
+            "{}",
+            board.as_fen()
+        );
+        assert_eq!(board.zobrist, board.compute_zobrist());
+        board.unmake_move(undo).unwrap();
+        assert_eq!(board.as_fen(), before);
+        assert_eq!(board.zobrist, zobrist);
+
+        // Kings can't capture, and no move may blow up its own king
+        assert!(!is_legal(
+            &atomic("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1"),
+            "e1d2"
+        ));
+        assert!(!is_legal(
+            &atomic("4k3/8/8/8/8/8/3n4/3RK3 w - - 0 1"),
+            "d1d2"
+        ));
+        assert!(is_legal(
+            &atomic("4k3/8/8/8/8/8/3n4/3RK3 w - - 0 1").with_variant(Variant::Standard),
+            "d1d2"
+        ));
+
+        // Blowing up the enemy king wins, even from check
+        let mut board = atomic("4k3/3n4/8/8/8/8/8/3QK2r w - - 0 1");
+        assert!(board.is_team_checked(Team::White));
+        assert!(is_legal(&board, "d1d7"));
+        board
+            .make_move(Move::from_uci(&board, "d1d7").unwrap())
+            .unwrap();
+        assert_eq!(board.status(), GameStatus::KingExploded(Team::White));
+
+        // Kings side by side can't be checked, since taking one would blow up the other
+        assert!(!atomic("8/8/8/8/8/3k4/3K3r/8 w - - 0 1").is_team_checked(Team::White));
+        assert!(atomic("8/8/8/8/3k4/8/3K3r/8 w - - 0 1").is_team_checked(Team::White));
+        assert_eq!(Variant::from_name("Atomic"), Some(Variant::Atomic));
+    }
+
+    #[test]
+    fn horde_and_racing_kings() {
+        use crate::bitboard::Team;
+        use crate::board::History;
+        use crate::board::{BoardState, GameStatus, Variant};
+        use crate::opponents::{ChessOpponent, EngineOptions, MoveComputer};
+        use crate::r#move::Move;
+        use std::time::Duration;
+        let variant = |fen: &str, variant: Variant| {
+            BoardState::from_fen(String::from(fen))
+                .unwrap()
+                .with_variant(variant)
+        };
+        let legal_ucis = |board: &BoardState| {
+            board
+                .legal_moves(board.active_team)
+                .iter()
+                .map(|packed| packed.unpack(board).to_uci())
+                .collect::<Vec<String>>()
+        };
+        let play = |board: &mut BoardState, uci: &str| {
+            board
+                .make_move(Move::from_uci(board, uci).unwrap())
+                .unwrap();
+        };
+
+        // The horde has no king to lose, so it's only beaten once every pawn is gone. Back-rank pawns can step twice
+        let horde = variant(Variant::Horde.start_position(), Variant::Horde);
+        assert_eq!(horde.status(), GameStatus::Ongoing);
+        assert!(!legal_ucis(&horde).is_empty());
+        assert!(
+            ChessOpponent::ada(EngineOptions::with_move_time(Duration::from_millis(50)))
+                .get_move(horde, &History::default())
+                .is_some()
+        );
+        assert_eq!(
+            legal_ucis(&variant("4k3/8/8/8/8/8/8/P7 w - - 0 1", Variant::Horde)),
+            ["a1a2", "a1a3"]
+        );
+        let mut last_pawn = variant("r3k3/8/8/8/8/8/8/P7 b - - 0 1", Variant::Horde);
+        play(&mut last_pawn, "a8a1");
+        assert_eq!(last_pawn.status(), GameStatus::HordeCaptured(Team::Black));
+
+        // Nobody may give check in Racing Kings
+        let race = variant(Variant::RacingKings.start_position(), Variant::RacingKings);
+        assert_eq!(race.perft(1), 21);
+        assert!(race
+            .legal_moves(Team::White)
+            .iter()
+            .all(|packed| !race.gives_check(packed.unpack(&race))));
+        let checking = legal_ucis(&variant(
+            "8/8/8/8/8/k7/8/1R5K w - - 0 1",
+            Variant::RacingKings,
+        ));
+        assert!(
+            !checking.contains(&String::from("b1a1")) && checking.contains(&String::from("b1b2"))
+        );
+
+        // White reaching the eighth rank wins, unless black's king can get there on the very next move as well
+        let mut runaway = variant("8/6K1/8/8/8/8/k7/8 w - - 0 1", Variant::RacingKings);
+        play(&mut runaway, "g7g8");
+        assert_eq!(runaway.status(), GameStatus::RaceWon(Team::White));
+        let mut close = variant("8/1k4K1/8/8/8/8/8/8 w - - 0 1", Variant::RacingKings);
+        play(&mut close, "g7g8");
+        assert_eq!(close.status(), GameStatus::Ongoing);
+        let mut missed = close;
+        play(&mut missed, "b7a7");
+        assert_eq!(missed.status(), GameStatus::RaceWon(Team::White));
+        play(&mut close, "b7b8");
+        assert_eq!(close.status(), GameStatus::DrawByRace);
+    }
+
+    #[test]
+    fn fairy_pieces() {
+        use crate::bitboard::PieceType;
+        use crate::board::BoardState;
+        use crate::pieces::{definition, piece_from_letter};
+        use crate::r#move::Move;
+        let board = |fen: &str| BoardState::from_fen(String::from(fen)).unwrap();
+        let moves_from = |board: &BoardState, square: &str| {
+            let mut ucis: Vec<String> = board
+                .legal_moves(board.active_team)
+                .iter()
+                .map(|packed| packed.unpack(board).to_uci())
+                .filter(|uci| uci.starts_with(square))
+                .collect();
+            ucis.sort();
+            ucis
+        };
+
+        assert_eq!(piece_from_letter('i'), Some(PieceType::Nightrider));
+        assert_eq!(definition(PieceType::Chancellor).letter, 'C');
+
+        // The nightrider keeps leaping like a knight until the board ends or something is in the way
+        let open = board("4k3/8/8/8/8/8/8/I3K3 w - - 0 1");
+        assert_eq!(open.as_fen(), "4k3/8/8/8/8/8/8/I3K3 w - - 0 1");
+        assert_eq!(
+            moves_from(&open, "a1"),
+            ["a1b3", "a1c2", "a1c5", "a1d7", "a1e3", "a1g4"]
+        );
+        assert_eq!(
+            moves_from(&board("4k3/8/8/2P5/8/8/8/I3K3 w - - 0 1"), "a1"),
+            ["a1b3", "a1c2", "a1e3", "a1g4"]
+        );
+        assert_eq!(Move::from_uci(&open, "a1c5").unwrap().to_san(&open), "Ic5");
+
+        // Its checks can be blocked along the line it rides, and it pins along that line too
+        let checked = board("4k3/1i6/8/8/8/8/8/2R1K3 w - - 0 1");
+        assert!(checked.is_team_checked(crate::bitboard::Team::White));
+        assert_eq!(moves_from(&checked, "c1"), ["c1c5"]);
+        let pinned = board("4k3/1i6/8/8/8/3R4/8/4K3 w - - 0 1");
+        assert!(moves_from(&pinned, "d3").is_empty());
+        assert!(pinned
+            .prune_moves_for_team(pinned.get_legal_moves(), pinned.active_team)
+            .iter()
+            .all(|legal| legal.start != 19));
+
+        // Every piece set has art for every piece, so none of them is left drawn as its letter
+        for set in crate::settings::PIECE_SETS {
+            for sprite in crate::pieces::PIECE_DEFINITIONS
+                .iter()
+                .map(|definition| definition.sprite)
+                .filter(|sprite| !sprite.is_empty())
+            {
+                for team in ["w", "b"] {
+                    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join(format!("resources/{set}/{team}{sprite}.png"));
+                    assert!(path.exists(), "{} is missing", path.display());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fen_errors() {
+        use crate::{
+            bitboard::Team,
+            board::{BoardState, FENErr, Variant},
+        };
+        let parse = |fen: &str| BoardState::from_fen(fen.to_string());
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1"),
+            Err(FENErr::BadPiece('X'))
+        ));
+        assert!(matches!(
+            parse("rnbqkbnrr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Err(FENErr::BadRank(8))
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/7/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Err(FENErr::BadRank(3))
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Err(FENErr::BadPiece('9'))
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Err(FENErr::IncompleteBoard)
+        ));
+        assert!(matches!(parse(""), Err(FENErr::IncompleteBoard)));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"),
+            Err(FENErr::BadTeam)
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkz - 0 1"),
+            Err(FENErr::BadCastling(_))
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w -K - 0 1"),
+            Err(FENErr::BadCastling(_))
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1"),
+            Err(FENErr::BadEnPassant(_))
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1"),
+            Err(FENErr::MalformedNumber)
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 9223372036854775807"),
+            Err(FENErr::MalformedNumber)
+        ));
+        assert!(matches!(
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra"),
+            Err(FENErr::TooManyParts)
+        ));
+        // Positions that parse but can't be played from
+        let playable = |fen: &str, variant: Variant| {
+            parse(fen).expect("Parses").with_variant(variant).playable()
+        };
+        assert!(playable(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            Variant::Standard
+        )
+        .is_ok());
+        assert!(matches!(
+            playable("8/8/8/8/8/8/8/4K3 w - - 0 1", Variant::Standard),
+            Err(FENErr::KingCount(Team::Black, 0))
+        ));
+        assert!(matches!(
+            playable("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", Variant::Standard),
+            Err(FENErr::KingCount(Team::White, 2))
+        ));
+        assert!(matches!(
+            playable("4k2P/8/8/8/8/8/8/4K3 w - - 0 1", Variant::Standard),
+            Err(FENErr::PawnOnBackRank(square)) if square == "h8"
+        ));
+        assert!(matches!(
+            playable("R3k3/8/8/8/8/8/8/4K3 w - - 0 1", Variant::Standard),
+            Err(FENErr::OpponentInCheck(Team::Black))
+        ));
+        assert!(playable(Variant::Horde.start_position(), Variant::Horde).is_ok());
+        let err =
+            parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1").expect_err("Fails");
+        assert_eq!(
+            err.to_string(),
+            "En passant square 'e4' is not '-' or a square on the 3rd or 6th rank\n"
+        );
+    }
+
+    #[test]
+    fn ten_by_eight() {
+        use crate::bitboard::{BoardSize, PieceType, Team};
+        use crate::board::BoardState;
+        use crate::r#move::{promotes_on, Move};
+
+        let fen = "r4k3r/pppppppppp/10/10/10/10/PPPPPPPPPP/R4K3R w KQkq - 0 1";
+        let board = BoardState::from_fen(String::from(fen)).expect("Invalid FEN used in testing");
+        let size = board.size;
+        assert_eq!(size, BoardSize::TenByEight);
+        assert_eq!(board.as_fen(), fen);
+        assert!(board.playable().is_ok());
+        assert_eq!(size.square_at("j8"), Some(79));
+        assert_eq!(size.notation(79).as_deref(), Some("j8"));
+        assert_eq!(BoardSize::Standard.square_at("i1"), None);
+        assert!(promotes_on(
+            size,
+            Team::White,
+            size.square_at("j8").unwrap()
+        ));
+        assert!(!promotes_on(
+            size,
+            Team::White,
+            size.square_at("a7").unwrap()
+        ));
+
+        // Twenty pawn moves, two king steps, both castles, four rook moves from a1 and three from j1
+        let moves = board.prune_moves_for_team(board.get_legal_moves(), Team::White);
+        assert_eq!(moves.len(), 31);
+        // The search's generator agrees, down to which pawn moves are double steps
+        let packed = board.legal_moves(Team::White);
+        assert_eq!(packed.iter().count(), 31);
+        assert_eq!(
+            packed
+                .iter()
+                .filter(|packed| packed.is_pawn_double())
+                .count(),
+            10
+        );
+
+        // The king castles from f1 to i1 with the rook landing on h1, and to c1 with the rook on d1
+        let kingside = Move::from_uci(&board, "f1i1").expect("f1i1 should castle");
+        assert!(kingside.is_castle);
+        assert_eq!(kingside.to_uci_on(size), "f1i1");
+        let mut castled = board;
+        castled.make_move(kingside).unwrap();
+        assert_eq!(
+            castled.piece_list[size.square_at("h1").unwrap()],
+            PieceType::Rook
+        );
+        assert_eq!(
+            castled.piece_list[size.square_at("j1").unwrap()],
+            PieceType::None
+        );
+        let queenside = Move::from_uci(&board, "f1c1").expect("f1c1 should castle");
+        let mut castled = board;
+        let undo = castled.make_move(queenside).unwrap();
+        assert_eq!(
+            castled.piece_list[size.square_at("d1").unwrap()],
+            PieceType::Rook
+        );
+        assert_eq!(
+            castled.piece_list[size.square_at("a1").unwrap()],
+            PieceType::None
+        );
+        castled.unmake_move(undo).unwrap();
+        assert_eq!(castled, board);
+
+        // A pawn on the j file steps twice and leaves the square it passed over for en passant
+        let mut pushed = board;
+        pushed
+            .make_move(Move::from_uci(&board, "j2j4").expect("j2j4 should be legal"))
+            .unwrap();
+        assert_eq!(pushed.en_passant_square, size.square_at("j3"));
+        assert_eq!(
+            pushed.as_fen(),
+            "r4k3r/pppppppppp/10/10/9P/10/PPPPPPPPP1/R4K3R b KQkq j3 0 1"
+        );
+    }
+
+    #[test]
+    fn capablanca() {
+        use crate::bitboard::{BoardSize, PieceType, Team};
+        use crate::board::{BoardState, GameStatus, Variant};
+        use crate::r#move::Move;
+
+        assert_eq!(Variant::from_name("Capablanca"), Some(Variant::Capablanca));
+        assert!(Variant::ALL.contains(&Variant::Capablanca));
+        let start = BoardState::from_fen(String::from(Variant::Capablanca.start_position()))
+            .unwrap()
+            .with_variant(Variant::Capablanca);
+        assert_eq!(start.size, BoardSize::TenByEight);
+        assert_eq!(start.as_fen(), Variant::Capablanca.start_position());
+        assert!(start.playable().is_ok());
+        assert_eq!(start.status(), GameStatus::Ongoing);
+        assert_eq!(
+            start.piece_list[start.size.square_at("c1").unwrap()],
+            PieceType::Archbishop
+        );
+        assert_eq!(
+            start.piece_list[start.size.square_at("h8").unwrap()],
+            PieceType::Chancellor
+        );
+        // Published totals for the Capablanca start position
+        assert_eq!(
+            (1..=3).map(|depth| start.perft(depth)).collect::<Vec<_>>(),
+            vec![28, 784, 25228]
+        );
+
+        // A pawn on the wide board can also become an archbishop or a chancellor, written A and C
+        let promoting = |fen: &str| {
+            let board = BoardState::from_fen(String::from(fen)).unwrap();
+            let mut promotions: Vec<String> = board
+                .legal_moves(Team::White)
+                .iter()
+                .map(|packed| packed.unpack(&board).to_uci_on(board.size))
+                .filter(|uci| uci.starts_with("a7"))
+                .collect();
+            promotions.sort();
+            promotions
+        };
+        assert_eq!(
+            promoting("5k4/P9/10/10/10/10/10/5K4 w - - 0 1"),
+            ["a7a8a", "a7a8b", "a7a8c", "a7a8n", "a7a8q", "a7a8r"]
+        );
+        assert_eq!(
+            promoting("4k3/P7/8/8/8/8/8/4K3 w - - 0 1"),
+            ["a7a8b", "a7a8n", "a7a8q", "a7a8r"]
+        );
+        let wide =
+            BoardState::from_fen(String::from("5k4/P9/10/10/10/10/10/5K4 w - - 0 1")).unwrap();
+        let chancellor = Move::from_uci(&wide, "a7a8c").expect("a7a8c should promote");
+        assert_eq!(chancellor.promotion, Some(PieceType::Chancellor));
+        assert_eq!(chancellor.to_san(&wide), "a8=C+");
+    }
+
+    #[test]
+    fn three_player() {
+        use crate::bitboard::{PieceType, Team};
+        use crate::three_player::{
+            from_notation, notation, Elimination, ThreePlayerBoard, ThreePlayerStatus,
+        };
+
+        let play = |board: &mut ThreePlayerBoard, moves: &[&str]| {
+            for name in moves {
+                let played = board
+                    .legal_moves()
+                    .into_iter()
+                    .find(|legal| legal.to_string() == *name)
+                    .unwrap_or_else(|| panic!("{name} isn't legal for {:?}", board.to_move));
+                board.make_move(played).unwrap();
+            }
+        };
+        let place = |board: &mut ThreePlayerBoard, pieces: &[(Team, PieceType, &str)]| {
+            for &(team, piece_type, name) in pieces {
+                board.squares[from_notation(name).unwrap()] = Some((team, piece_type));
+            }
+        };
+
+        // The corners are cut away, and every army has its sixteen pieces along its own edge
+        assert_eq!(from_notation("a1"), None);
+        assert_eq!(from_notation("n14"), None);
+        assert_eq!(from_notation("d1").map(notation).as_deref(), Some("d1"));
+        let mut board = ThreePlayerBoard::new();
+        for team in [Team::White, Team::Red, Team::Black] {
+            let army = board
+                .squares
+                .iter()
+                .flatten()
+                .filter(|(owner, _)| *owner == team);
+            assert_eq!(army.count(), 16, "{team:?} is missing pieces");
+        }
+        assert_eq!(
+            board.piece_at(from_notation("g1").unwrap()),
+            Some((Team::White, PieceType::Queen))
+        );
+        assert_eq!(
+            board.piece_at(from_notation("g14").unwrap()),
+            Some((Team::Black, PieceType::Queen))
+        );
+        assert_eq!(
+            board.piece_at(from_notation("a8").unwrap()),
+            Some((Team::Red, PieceType::Queen))
+        );
+
+        // Each side has the usual twenty moves to start with, and they take turns white, red, black
+        for (team, first) in [
+            (Team::White, "h2h4"),
+            (Team::Red, "b7d7"),
+            (Team::Black, "h13h11"),
+        ] {
+            assert_eq!(board.to_move, team);
+            assert_eq!(
+                board.legal_moves().len(),
+                20,
+                "{team:?} has the wrong moves"
+            );
+            play(&mut board, &[first]);
+        }
+        assert_eq!(board.to_move, Team::White);
+
+        // Mating red puts it out, clears its pieces away and passes its turn on to black
+        let mut board = ThreePlayerBoard::empty();
+        place(
+            &mut board,
+            &[
+                (Team::White, PieceType::King, "h1"),
+                (Team::White, PieceType::Rook, "b4"),
+                (Team::White, PieceType::Rook, "e11"),
+                (Team::Red, PieceType::King, "a7"),
+                (Team::Red, PieceType::Pawn, "b10"),
+                (Team::Black, PieceType::King, "h14"),
+            ],
+        );
+        play(&mut board, &["e11a11"]);
+        assert_eq!(board.eliminated, [(Team::Red, Elimination::Checkmated)]);
+        assert_eq!(board.to_move, Team::Black);
+        assert!(board
+            .squares
+            .iter()
+            .flatten()
+            .all(|(team, _)| *team != Team::Red));
+        assert_eq!(board.status, ThreePlayerStatus::Ongoing);
+
+        // A king another team's move left open can be taken
+        let mut board = ThreePlayerBoard::empty();
+        place(
+            &mut board,
+            &[
+                (Team::White, PieceType::King, "h1"),
+                (Team::White, PieceType::Bishop, "f14"),
+                (Team::Red, PieceType::King, "a7"),
+                (Team::Red, PieceType::Rook, "d14"),
+                (Team::Black, PieceType::King, "h14"),
+            ],
+        );
+        play(&mut board, &["f14e13", "d14h14"]);
+        assert_eq!(board.eliminated, [(Team::Black, Elimination::KingTaken)]);
+        assert_eq!(board.to_move, Team::White, "Black's turn should be skipped");
+
+        // The last team left wins
+        let mut board = ThreePlayerBoard::empty();
+        board.eliminated.push((Team::Black, Elimination::KingTaken));
+        place(
+            &mut board,
+            &[
+                (Team::White, PieceType::King, "h1"),
+                (Team::White, PieceType::Rook, "b4"),
+                (Team::White, PieceType::Rook, "e11"),
+                (Team::Red, PieceType::King, "a7"),
+            ],
+        );
+        play(&mut board, &["e11a11"]);
+        assert_eq!(board.status, ThreePlayerStatus::Won(Team::White));
+        assert!(
+            board.legal_moves().is_empty(),
+            "A won game has no more moves"
+        );
+
+        // With two teams left stalemate is a draw, not a way out of the game
+        let mut board = ThreePlayerBoard::empty();
+        board.eliminated.push((Team::Black, Elimination::KingTaken));
+        place(
+            &mut board,
+            &[
+                (Team::White, PieceType::King, "h1"),
+                (Team::White, PieceType::Rook, "b11"),
+                (Team::White, PieceType::Rook, "e6"),
+                (Team::Red, PieceType::King, "a4"),
+            ],
+        );
+        play(&mut board, &["e6e5"]);
+        assert_eq!(board.status, ThreePlayerStatus::Drawn);
+        assert!(board.eliminated.iter().all(|(team, _)| *team != Team::Red));
+
+        // Pawns promote to queens wherever they run out of board ahead of them, even in a side arm
+        let mut board = ThreePlayerBoard::empty();
+        place(
+            &mut board,
+            &[
+                (Team::White, PieceType::King, "h1"),
+                (Team::White, PieceType::Pawn, "b10"),
+                (Team::White, PieceType::Pawn, "h13"),
+                (Team::Red, PieceType::King, "a4"),
+                (Team::Black, PieceType::King, "k14"),
+            ],
+        );
+        let promotions: Vec<String> = board
+            .legal_moves()
+            .iter()
+            .filter(|legal| legal.promotes)
+            .map(|legal| legal.to_string())
+            .collect();
+        assert_eq!(promotions, ["b10b11q", "h13h14q"]);
+        play(&mut board, &["b10b11q"]);
+        assert_eq!(
+            board.piece_at(from_notation("b11").unwrap()),
+            Some((Team::White, PieceType::Queen))
+        );
+    }
+
+    #[test]
+    fn setup_menu() {
+        use crate::{
+            bitboard::Team, board::Variant, clock::TimeControl, opponents::ChessOpponent,
+            ui::GameSetup,
+        };
+        let mut setup = GameSetup {
+            colour: None,
+            opponent: ChessOpponent::Randy,
+            time_control: None,
+            bot_time_control: None,
+            variant: Variant::Standard,
+            row: 0,
+        };
+        setup.change(1);
+        assert_eq!(setup.colour, Some(Team::White));
+        setup.change(-1);
+        setup.change(-1);
+        assert_eq!(setup.colour, Some(Team::Black));
+        setup.row = 1;
+        setup.change(1);
+        assert!(matches!(setup.opponent, ChessOpponent::Matt(_)));
+        // Time controls step through the presets from untimed and wrap back round to it
+        setup.row = 3;
+        setup.change(1);
+        assert_eq!(setup.time_control, TimeControl::parse("1+0"));
+        setup.change(-1);
+        setup.change(-1);
+        assert_eq!(setup.time_control, TimeControl::parse("30+0"));
+        setup.row = 4;
+        setup.change(-1);
+        assert_eq!(setup.variant, Variant::Capablanca);
+        assert!(setup.menu_text().contains("> Variant: < Capablanca >"));
+    }
+
+    #[test]
+    fn clock_delay_and_flag_fall() {
+        use crate::{
+            bitboard::Team,
+            board::{BoardState, GameStatus},
+            clock::{ChessClock, TimeControl},
+            opponents::ChessOpponent,
+            ui::Game,
+        };
+        use std::time::Duration;
+
+        let delayed = TimeControl::parse("1d30").expect("Couldn't read 1d30");
+        assert_eq!(
+            (delayed.increment, delayed.delay),
+            (Duration::ZERO, Duration::from_secs(30))
+        );
+        assert_eq!(delayed.to_string(), "1d30");
+        let mut clock = ChessClock::new(delayed, delayed);
+        clock.start(Team::White);
+        assert_eq!(
+            clock.remaining(Team::White),
+            Duration::from_secs(60),
+            "The clock ran down inside the delay"
+        );
+        clock.press(Team::White);
+        assert_eq!(
+            clock.remaining(Team::White),
+            Duration::from_secs(60),
+            "A delay isn't added like an increment"
+        );
+        clock.stop();
+        assert!(!clock.is_running());
+        assert_eq!(
+            clock.remaining(Team::Black),
+            Duration::from_secs(60),
+            "A stopped clock still ran down"
+        );
+
+        // A flag falling ends the game like the board would, unless the other side only has its king left
+        let flagged = TimeControl::parse("0").unwrap();
+        let with_clock = |fen: &str| {
+            let mut game = Game::new(
+                BoardState::from_fen(fen.to_string()).unwrap(),
+                Team::White,
+                ChessOpponent::Randy,
+            );
+            game.clock = Some(ChessClock::new(flagged, delayed));
+            game.clock.as_mut().unwrap().start(Team::White);
+            game
+        };
+        assert_eq!(
+            with_clock(crate::START_POS_CHESS).status(),
+            GameStatus::TimeForfeit(Team::Black)
+        );
+        assert_eq!(
+            with_clock(crate::START_POS_CHESS).status().result(),
+            Some("0-1")
+        );
+        assert_eq!(
+            with_clock("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").status(),
+            GameStatus::DrawByTimeout
+        );
+        assert_eq!(
+            Game::new(
+                BoardState::from_fen(crate::START_POS_CHESS.to_string()).unwrap(),
+                Team::White,
+                ChessOpponent::Randy
+            )
+            .status(),
+            GameStatus::Ongoing
+        );
+    }
+
+    #[test]
+    fn move_list() {
+        use crate::{
+            bitboard::Team, board::BoardState, opponents::ChessOpponent, r#move::Move, ui::Game,
+        };
+
+        let start = BoardState::from_fen(crate::START_POS_CHESS.to_string()).unwrap();
+        let mut game = Game::new(start, Team::White, ChessOpponent::Randy);
+        for san in ["e4", "e5", "Nf3"] {
+            let played = Move::from_san(&game.board, san).expect("Legal in the game");
+            game.history.make_move(&mut game.board, played).unwrap();
+        }
+        assert_eq!(game.san_moves(), ["e4", "e5", "Nf3"]);
+        assert_eq!(game.board_at(0).as_fen(), start.as_fen());
+        assert_eq!(game.board_at(3).as_fen(), game.board.as_fen());
+        let mut after_e4 = start;
+        after_e4
+            .make_move(Move::from_san(&start, "e4").unwrap())
+            .unwrap();
+        assert_eq!(game.board_at(1).as_fen(), after_e4.as_fen());
+    }
+
+    #[test]
+    fn eval_graph() {
+        use crate::{
+            analysis::{analyse_moves, eval_graph_height},
+            board::BoardState,
+            r#move::Move,
+            report::eval_graph_svg,
+        };
+
+        assert_eq!(eval_graph_height(0), 0.5);
+        assert_eq!(eval_graph_height(500), 0.75);
+        assert_eq!(
+            eval_graph_height(-50_000),
+            0.0,
+            "Mates should sit at the edge, not off the graph"
+        );
+
+        let mut board = BoardState::from_fen(crate::START_POS_CHESS.to_string()).unwrap();
+        let start = board;
+        let moves: Vec<Move> = ["f3", "e5", "g4", "Qh4#"]
+            .iter()
+            .map(|san| {
+                let played = Move::from_san(&board, san).expect("Legal in the game");
+                board.make_move(played).unwrap();
+                played
+            })
+            .collect();
+        let svg = eval_graph_svg(&analyse_moves(start, &moves, 1).unwrap());
+        let points = svg
+            .split("<polyline points=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("The graph needs a line");
+        assert_eq!(
+            points.split(' ').count(),
+            5,
+            "One point for the start and one for each move"
+        );
+        assert!(
+            points.ends_with(",200.0"),
+            "Black mating should put the line at the bottom: {points}"
+        );
+    }
+
+    #[test]
+    fn resign_and_draw_offers() {
+        use crate::{bitboard::Team, board::GameStatus, opponents::ChessOpponent};
+
+        assert_eq!(GameStatus::Resignation(Team::Black).result(), Some("0-1"));
+        assert_eq!(
+            GameStatus::Resignation(Team::Black).to_string(),
+            "White resigns - Black wins"
+        );
+        assert_eq!(GameStatus::DrawByAgreement.result(), Some("1/2-1/2"));
+
+        let matt = ChessOpponent::Matt(3);
+        assert!(
+            matt.accepts_draw(&[400, 20, -10, 30]),
+            "Level for the last three moves should be enough"
+        );
+        assert!(
+            !matt.accepts_draw(&[20, -10]),
+            "Two moves isn't long enough to call it level"
+        );
+        assert!(
+            !matt.accepts_draw(&[0, 0, 0, 45]),
+            "The game stopped being level"
+        );
+        assert!(!ChessOpponent::Randy.accepts_draw(&[0, 0, 0]));
+    }
+
+    #[test]
+    fn hot_seat_opponent() {
+        use crate::board::History;
+        use crate::{
+            board::BoardState,
+            opponents::{ChessOpponent, MoveComputer},
+        };
+
+        let mut human = ChessOpponent::Human;
+        let start = BoardState::from_fen(crate::START_POS_CHESS.to_string()).unwrap();
+        assert!(
+            human.get_move(start, &History::default()).is_none(),
+            "A human's moves only come from the board"
+        );
+        assert!(!human.uses_book());
+        assert!(human.accepts_draw(&[]));
+        assert!(matches!(
+            ChessOpponent::ada(Default::default()).next_kind(),
+            ChessOpponent::Human
+        ));
+        assert!(matches!(human.next_kind(), ChessOpponent::Randy));
+    }
+
+    #[test]
+    fn spectated_game() {
+        use crate::{
+            bitboard::Team, board::BoardState, opponents::ChessOpponent, r#move::Move, ui::Game,
+        };
+
+        let mut board = BoardState::from_fen(crate::START_POS_CHESS.to_string()).unwrap();
+        let mut game = Game::new(board, Team::White, ChessOpponent::Matt(2));
+        assert!(
+            matches!(game.engine_to_move(), ChessOpponent::Matt(2)),
+            "Without a spectated engine the opponent moves for both"
+        );
+        game.spectated = Some(ChessOpponent::Randy);
+        assert!(
+            matches!(game.engine_to_move(), ChessOpponent::Randy),
+            "The player's side is played by the spectated engine"
+        );
+        board
+            .make_move(Move::from_uci(&board, "e2e4").unwrap())
+            .unwrap();
+        game.board = board;
+        assert!(matches!(game.engine_to_move(), ChessOpponent::Matt(2)));
+
+        let tags = game.tags("*", "unterminated");
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| tag == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(tag("White"), Some(format!("Bot {}", ChessOpponent::Randy)));
+        assert_eq!(
+            tag("Black"),
+            Some(format!("Bot {}", ChessOpponent::Matt(2)))
+        );
+    }
+
+    #[test]
+    fn puzzles() {
+        use crate::{
+            bitboard::Team,
+            puzzle::{parse_puzzles, PuzzleAttempt, PuzzleRating, PuzzleStep},
+            r#move::Move,
+        };
+
+        let csv = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags\n00008,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,1913,75,94,6230,crushing hangingPiece long middlegame,https://lichess.org/787zsVup/black#48,\n\nmate1,6k1/5ppp/8/8/8/8/5PPP/RR4K1 b - - 0 1,g8h8 a1a8,900,80,90,100,mate mateIn1 oneMove,,\n";
+        let puzzles = parse_puzzles(csv).unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!(puzzles[0].rating, 1913);
+        assert_eq!(
+            puzzles[0].themes,
+            ["crushing", "hangingPiece", "long", "middlegame"]
+        );
+        assert_eq!(puzzles[0].solver(), Ok(Team::White));
+        assert!(
+            parse_puzzles("1,8/8/8/8/8/8/8/K1k5 w - - 0 1,a1a2,1500").is_err(),
+            "A puzzle needs a move for the player"
+        );
+        assert!(parse_puzzles("1,not a fen,a1a2 c1c2,1500").is_err());
+
+        // Playing the solution through, with a wrong move along the way that loses the puzzle but can be tried again
+        let mut attempt = PuzzleAttempt::new(puzzles[0].clone());
+        let mut board = puzzles[0].board().unwrap();
+        let play = |board: &mut crate::board::BoardState, uci: &str| {
+            let played = Move::from_uci(board, uci).unwrap();
+            board.make_move(played).unwrap();
+        };
+        let reply = attempt.reply(&board).unwrap();
+        assert_eq!(reply.to_uci(), "f2g3");
+        assert_eq!(attempt.solution(&board), None, "The opponent moves first");
+        attempt.reply_played();
+        play(&mut board, "f2g3");
+        assert_eq!(
+            attempt.try_move(&board, Move::from_uci(&board, "h6h7").unwrap()),
+            PuzzleStep::Wrong
+        );
+        assert!(attempt.failed);
+        assert_eq!(
+            attempt.try_move(&board, Move::from_uci(&board, "e6e7").unwrap()),
+            PuzzleStep::Correct
+        );
+        play(&mut board, "e6e7");
+        for (reply, answer) in [("b2b1", "b3c1"), ("b1c1", "h6c1")] {
+            assert_eq!(
+                attempt.reply(&board).map(|reply| reply.to_uci()).as_deref(),
+                Some(reply)
+            );
+            attempt.reply_played();
+            play(&mut board, reply);
+            let step = attempt.try_move(&board, Move::from_uci(&board, answer).unwrap());
+            play(&mut board, answer);
+            assert_eq!(
+                step,
+                if answer == "h6c1" {
+                    PuzzleStep::Solved
+                } else {
+                    PuzzleStep::Correct
+                }
+            );
+        }
+        assert!(attempt.is_solved());
+
+        // Any mate on the last move solves it, but a move that isn't one doesn't, and a hint gives the answer away
+        let mut board = puzzles[1].board().unwrap();
+        let mut attempt = PuzzleAttempt::new(puzzles[1].clone());
+        play(&mut board, "g8h8");
+        attempt.reply_played();
+        assert_eq!(
+            attempt.try_move(&board, Move::from_uci(&board, "a1a7").unwrap()),
+            PuzzleStep::Wrong
+        );
+        assert_eq!(
+            attempt.hint(&board).map(|hint| hint.to_uci()).as_deref(),
+            Some("a1a8")
+        );
+        assert_eq!(attempt.hints, 1);
+        assert_eq!(
+            attempt.try_move(&board, Move::from_uci(&board, "b1b8").unwrap()),
+            PuzzleStep::Solved
+        );
+
+        // An even puzzle moves the rating half the K-factor either way, and a much easier one hardly at all
+        let mut rating = PuzzleRating::default();
+        assert_eq!(rating.record(1500, true), 16);
+        assert_eq!(rating.record(1516, false), -16);
+        assert_eq!(rating.record(700, true), 0);
+        assert_eq!((rating.rating, rating.played, rating.solved), (1500, 3, 2));
+        let path =
+            std::env::temp_dir().join(format!("chess-r-puzzle-rating-{}.txt", std::process::id()));
+        rating.save(&path).unwrap();
+        assert_eq!(PuzzleRating::load(&path), Ok(rating));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            PuzzleRating::load(&path),
+            Ok(PuzzleRating::default()),
+            "A missing file starts a fresh rating"
+        );
+    }
+
+    #[test]
+    fn opening_names() {
+        use crate::{
+            bitboard::Team,
+            board::BoardState,
+            openings::{identify, OPENINGS},
+            opponents::ChessOpponent,
+            r#move::Move,
+            ui::Game,
+        };
+
+        for opening in OPENINGS {
+            assert!(
+                opening.board().is_some(),
+                "{} {} has an illegal move",
+                opening.eco,
+                opening.name
+            );
+        }
+        let start = BoardState::from_fen(crate::START_POS_CHESS.to_string()).unwrap();
+        let play = |sans: &str| {
+            let mut board = start;
+            let mut moves = Vec::new();
+            for san in sans.split_whitespace() {
+                let played = Move::from_san(&board, san).unwrap();
+                board.make_move(played).unwrap();
+                moves.push(played);
+            }
+            moves
+        };
+        let named =
+            |sans: &str| identify(&start, play(sans)).map(|opening| (opening.eco, opening.name));
+
+        assert_eq!(named(""), None);
+        assert_eq!(
+            named("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6"),
+            Some(("B90", "Sicilian Defense: Najdorf Variation"))
+        );
+        assert_eq!(
+            named("Nf3 d6 e4 c5 d4 cxd4 Nxd4 Nf6 Nc3 a6"),
+            Some(("B90", "Sicilian Defense: Najdorf Variation")),
+            "Transpositions are named after the line they reach"
+        );
+        assert_eq!(
+            named("d4 Nf6 g3 e6 c4"),
+            Some(("E00", "Catalan Opening")),
+            "The en passant square doesn't split a transposition"
+        );
+        assert_eq!(
+            named("e4 e5 Nf3 Nc6 Bb5 a6 Ba4 b5 Bb3"),
+            Some(("C70", "Ruy Lopez: Morphy Defense")),
+            "Leaving the book keeps the last opening reached"
+        );
+
+        let mut game = Game::new(start, Team::White, ChessOpponent::Randy);
+        for played in play("e4 e6 d4 d5 e5") {
+            game.history.make_move(&mut game.board, played).unwrap();
+        }
+        let tags = game.tags("*", "unterminated");
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| tag == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(tag("ECO").as_deref(), Some("C02"));
+        assert_eq!(
+            tag("Opening").as_deref(),
+            Some("French Defense: Advance Variation")
+        );
+    }
+
+    #[test]
+    fn settings_file() {
+        use crate::settings::{Settings, BOARD_THEMES, PIECE_SETS};
+
+        let path =
+            std::env::temp_dir().join(format!("chess-r-settings-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            Settings::load(&path),
+            Ok(Settings::default()),
+            "No file means the defaults"
+        );
+
+        let mut settings = Settings {
+            board_theme: BOARD_THEMES.len() - 1,
+            piece_set: PIECE_SETS.len() - 1,
+            muted: true,
+            ..Settings::default()
+        };
+        settings.adjust_volume(-3);
+        assert_eq!(settings.volume, 70);
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path), Ok(settings));
+        settings.adjust_volume(20);
+        assert_eq!(
+            settings.volume(),
+            1.0,
+            "The volume stops at the top of the slider"
+        );
+
+        std::fs::write(&path, "pieces horsey\n").unwrap();
+        assert_eq!(
+            Settings::load(&path).map(|loaded| loaded.board_theme().name),
+            Ok(BOARD_THEMES[0].name),
+            "Settings left out keep their defaults"
+        );
+        std::fs::write(&path, "board Plaid\n").unwrap();
+        assert!(Settings::load(&path).is_err());
+        std::fs::write(&path, "volume 150\n").unwrap();
+        assert!(Settings::load(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn window_scaling() {
+        use crate::ui::{layout_view, to_layout, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+        let view = layout_view(WINDOW_WIDTH, WINDOW_HEIGHT);
+        assert_eq!(
+            (view.x, view.y, view.w, view.h),
+            (0.0, 0.0, WINDOW_WIDTH, WINDOW_HEIGHT)
+        );
+        assert_eq!(
+            to_layout(
+                WINDOW_WIDTH * 2.0,
+                WINDOW_HEIGHT * 2.0,
+                WINDOW_WIDTH,
+                WINDOW_HEIGHT
+            ),
+            (WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0),
+            "A window twice the size shows everything twice as big"
+        );
+
+        // A window too wide for the layout has bars down the sides, which are outside it
+        let (width, height) = (WINDOW_WIDTH * 2.0 + 200.0, WINDOW_HEIGHT * 2.0);
+        let view = layout_view(width, height);
+        assert_eq!(
+            (view.x, view.w, view.h),
+            (-50.0, WINDOW_WIDTH + 100.0, WINDOW_HEIGHT)
+        );
+        assert_eq!(to_layout(width, height, 100.0, 0.0), (0.0, 0.0));
+        assert!(to_layout(width, height, 10.0, 0.0).0 < 0.0);
+        assert_eq!(
+            layout_view(0.0, 0.0).w,
+            WINDOW_WIDTH,
+            "A minimised window doesn't divide by zero"
+        );
+    }
+
+    #[test]
+    fn panel_buttons() {
+        use crate::ui::{panel_button_at, PanelButton, WINDOW_HEIGHT};
+
+        // The panel is right of the board, which is as wide as the window is high
+        let panel_x = WINDOW_HEIGHT + 100.0;
+        let along_bottom: Vec<_> = (0..180)
+            .step_by(10)
+            .filter_map(|x| panel_button_at(panel_x - 90.0 + x as f32, WINDOW_HEIGHT - 25.0))
+            .collect();
+        assert_eq!(along_bottom.first(), Some(&PanelButton::Undo));
+        assert!(along_bottom.contains(&PanelButton::OfferDraw));
+        assert_eq!(along_bottom.last(), Some(&PanelButton::Resign));
+        assert_eq!(
+            panel_button_at(panel_x, WINDOW_HEIGHT / 2.0),
+            None,
+            "The panel's text isn't a button"
+        );
+        assert_eq!(
+            panel_button_at(WINDOW_HEIGHT / 2.0, WINDOW_HEIGHT - 25.0),
+            None
+        );
+    }
+
+    #[test]
+    fn move_sounds() {
+        use crate::{audio::Sound, board::BoardState, r#move::Move};
+
+        let sound = |fen: &str, uci: &str| {
+            let mut board = BoardState::from_fen(fen.to_string()).unwrap();
+            let played = Move::from_uci(&board, uci).unwrap();
+            board.make_move(played).unwrap();
+            Sound::for_move(played, &board)
+        };
+        assert_eq!(sound(crate::START_POS_CHESS, "e2e4"), Sound::Move);
+        assert_eq!(
+            sound("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"),
+            Sound::Capture
+        );
+        assert_eq!(
+            sound("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"),
+            Sound::Castle
+        );
+        assert_eq!(
+            sound("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8n"),
+            Sound::Promotion
+        );
+        assert_eq!(
+            sound("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", "e1c1"),
+            Sound::Check,
+            "A check outweighs the castling that gave it"
+        );
+    }
+
+    #[test]
+    fn autosave_round_trip() {
+        use crate::autosave::{opponent_from_spec, opponent_spec, SavedGame};
+        use crate::bitboard::Team;
+        use crate::board::BoardState;
+        use crate::clock::{ChessClock, TimeControl};
+        use crate::opponents::{ChessOpponent, EngineOptions};
+        use crate::r#move::Move;
+        use crate::ui::Game;
+        use std::time::Duration;
+
+        let start = BoardState::from_fen(String::from(crate::START_POS_CHESS)).unwrap();
+        let options = EngineOptions {
+            move_time: Duration::from_millis(750),
+            skill_level: 12,
+            ..EngineOptions::default()
+        };
+        let mut game = Game::new(start, Team::Black, ChessOpponent::ada(options));
+        game.clock = Some(ChessClock::new(
+            TimeControl::parse("5+3").unwrap(),
+            TimeControl::parse("3d2").unwrap(),
+        ));
+        for uci in ["e2e4", "c7c5", "g1f3"] {
+            let played = Move::from_uci(&game.board, uci).unwrap();
+            game.history.make_move(&mut game.board, played).unwrap();
+        }
+
+        let saved = game.to_saved();
+        let loaded = SavedGame::parse(&saved.to_text()).unwrap();
+        assert_eq!(loaded.moves, vec!["e2e4", "c7c5", "g1f3"]);
+        assert_eq!(loaded.player_team, Team::Black);
+        assert_eq!(loaded.board().unwrap().as_fen(), game.board.as_fen());
+        let clock = loaded.clock.unwrap();
+        assert_eq!(
+            clock.controls,
+            [
+                TimeControl::parse("5+3").unwrap(),
+                TimeControl::parse("3d2").unwrap()
+            ]
+        );
+        assert_eq!(
+            clock.remaining,
+            [Duration::from_secs(300), Duration::from_secs(180)]
+        );
+        assert_eq!(loaded.saved_at.timestamp(), saved.saved_at.timestamp());
+        let ChessOpponent::Ada(restored, _) = loaded.opponent().unwrap() else {
+            panic!("Ada should come back as Ada")
+        };
+        assert_eq!(
+            (restored.move_time, restored.skill_level),
+            (Duration::from_millis(750), 12)
+        );
+
+        // Without a clock there's no clock line, and the other opponents come back as they were
+        game.clock = None;
+        assert_eq!(
+            SavedGame::parse(&game.to_saved().to_text()).unwrap().clock,
+            None
+        );
+        for spec in ["randy", "matt:3", "human", "uci:/usr/bin/stockfish"] {
+            assert_eq!(opponent_spec(&opponent_from_spec(spec).unwrap()), spec);
+        }
+        assert!(opponent_from_spec("magnus").is_none());
+
+        // A save that can't be replayed is turned away when it's read, and a missing one is nothing to resume
+        let broken = saved.to_text().replace("moves e2e4", "moves e2e5");
+        assert!(SavedGame::parse(&broken).is_err());
+        let path =
+            std::env::temp_dir().join(format!("chess-r-autosave-{}.txt", std::process::id()));
+        saved.save(&path).unwrap();
+        assert_eq!(
+            SavedGame::load(&path).unwrap().map(|game| game.moves.len()),
+            Some(3)
+        );
+        SavedGame::discard(&path).unwrap();
+        assert_eq!(SavedGame::load(&path).unwrap(), None);
+        SavedGame::discard(&path).unwrap();
+    }
+
+    #[test]
+    fn netplay_messages() {
+        use crate::bitboard::Team;
+        use crate::board::Variant;
+        use crate::clock::TimeControl;
+        use crate::netplay::NetMessage;
+        use std::time::Duration;
+        let messages = [
+            NetMessage::Hello { game: None },
+            NetMessage::Hello {
+                game: Some(0x0123_4567_89ab_cdef),
+            },
+            NetMessage::Start {
+                game: 1,
+                guest_team: Team::Black,
+                variant: Variant::KingOfTheHill,
+                clock: Some((
+                    TimeControl::parse("5+3").unwrap(),
+                    TimeControl::parse("3d2").unwrap(),
+                )),
+                fen: String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            },
+            NetMessage::Start {
+                game: u64::MAX,
+                guest_team: Team::White,
+                variant: Variant::Standard,
+                clock: None,
+                fen: String::from("8/8/8/8/8/8/8/K1k5 w - - 0 1"),
+            },
+            NetMessage::History(vec![
+                String::from("e2e4"),
+                String::from("e7e5"),
+                String::from("g1f3"),
+            ]),
+            NetMessage::History(Vec::new()),
+            NetMessage::Move {
+                ply: 3,
+                uci: String::from("e7e8q"),
+                time_left: Some(Duration::from_millis(61_250)),
+                hash: 0x0123_4567_89ab_cdef,
+            },
+            NetMessage::Move {
+                ply: 1,
+                uci: String::from("e2e4"),
+                time_left: None,
+                hash: 7,
+            },
+            NetMessage::DrawOffer,
+            NetMessage::DrawAccept,
+            NetMessage::Resign,
+            NetMessage::Final {
+                hash: u64::MAX,
+                fen: String::from("8/8/8/8/8/8/8/K1k5 b - - 10 60"),
+            },
+        ];
+        for message in messages {
+            assert_eq!(
+                NetMessage::parse(&message.to_string()),
+                Some(message.clone()),
+                "{message}"
+            );
+        }
+        // Anything else that comes down the line is left unread rather than guessed at
+        for line in [
+            "",
+            "HELLO",
+            "HELLO game",
+            "MOVE 3 e2e4",
+            "MOVE x e2e4 - 00",
+            "START 1 x standard - - 8/8/8/8/8/8/8/K1k5 w - - 0 1",
+            "START 1 w standard 5+3 - 8/8/8/8/8/8/8/K1k5 w - - 0 1",
+            "START w standard - - 8/8/8/8/8/8/8/K1k5 w - - 0 1",
+            "DRAW maybe",
+        ] {
+            assert_eq!(NetMessage::parse(line), None, "{line}");
+        }
+    }
+    #[test]
+    fn netplay_guests() {
+        use crate::netplay::{NetEvent, NetMessage, NetSession};
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::time::{Duration, Instant};
+
+        const PORT: u16 = 47_815;
+        let address = format!("127.0.0.1:{PORT}");
+        // Whether the host hears of a new connection before `wait` is up
+        let connects = |host: &mut NetSession, wait: Duration| {
+            let deadline = Instant::now() + wait;
+            while Instant::now() < deadline {
+                if host.poll().contains(&NetEvent::Connected) {
+                    return true;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            false
+        };
+        let say = |line: String| {
+            let mut stream = TcpStream::connect(&address).expect("Couldn't reach the host");
+            stream.write_all(line.as_bytes()).unwrap();
+            stream
+        };
+
+        let mut host = NetSession::host(PORT).expect("Couldn't host");
+        let game = host.game().expect("The host has no game to hand out");
+        let mut guest = say(format!("{}\n", NetMessage::Hello { game: None }));
+        assert!(
+            connects(&mut host, Duration::from_secs(5)),
+            "The first guest wasn't let in"
+        );
+
+        // Anyone else has to name the game to take the guest's place
+        let mut stranger = say(format!("{}\n", NetMessage::Hello { game: None }));
+        assert!(
+            !connects(&mut host, Duration::from_millis(500)),
+            "A stranger took over the game"
+        );
+        stranger
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(
+            stranger.read(&mut [0; 8]).unwrap(),
+            0,
+            "The stranger wasn't hung up on"
+        );
+        let _returning = say(format!("{}\n", NetMessage::Hello { game: Some(game) }));
+        assert!(
+            connects(&mut host, Duration::from_secs(5)),
+            "The guest couldn't come back"
+        );
+        assert_eq!(
+            guest.read(&mut [0; 8]).unwrap(),
+            0,
+            "The lost connection was kept"
+        );
+
+        // Once the session is gone the port is free to host on again
+        drop(host);
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(NetSession::host(PORT).is_ok(), "The port was still taken");
+    }
+    #[test]
+    fn board_state_size() {
+        use crate::board::BoardState;
+        // The search copies boards constantly, so they have to stay plain data. `chess-r bench` times the copy
+        fn copies<T: Copy>() {}
+        copies::<BoardState>();
+        /*
+            Lookup tables belong in statics and the game's moves in a History, not in the position. The 128-bit
+            bitboards and the 80-square piece list a 10x8 board needs take it up from about 310 bytes
+        */
+        assert!(
+            std::mem::size_of::<BoardState>() <= 512,
+            "BoardState has grown to {} bytes",
+            std::mem::size_of::<BoardState>()
+        );
+    }
+
+    #[test]
+    fn bench_command_usage() {
+        use crate::cli::bench_command;
+
+        // The timing itself is too slow for a debug build, so only the arguments are checked here
+        for line in [&["--depth"][..], &["--depth", "deep"], &["--nodes", "5"]] {
+            let args = line
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>();
+            assert!(bench_command(&args).is_err(), "Accepted {line:?}");
+        }
+    }
+
+    #[test]
+    fn zobrist_hashing() {
+        use crate::board::BoardState;
+        use crate::opponents::transposition::{Bound, TranspositionTable};
+        use crate::r#move::Move;
+        let play = |fen: &str, moves: &[&str]| {
+            let mut board = BoardState::from_fen(String::from(fen)).unwrap();
+            for uci in moves {
+                let played = Move::from_uci(&board, uci).unwrap();
+                board.make_move(played).unwrap();
+                assert_eq!(
+                    board.zobrist,
+                    board.compute_zobrist(),
+                    "Hash drifted after {uci}"
+                );
+            }
+            board
+        };
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        // The same position by different move orders hashes the same, and a different one doesn't
+        let knights_first = play(start, &["g1f3", "g8f6", "b1c3", "b8c6"]);
+        let queenside_first = play(start, &["b1c3", "b8c6", "g1f3", "g8f6"]);
+        assert_eq!(knights_first.zobrist, queenside_first.zobrist);
+        assert_ne!(
+            knights_first.zobrist,
+            play(start, &["g1f3", "g8f6", "b1c3"]).zobrist
+        );
+
+        // Captures and castling keep the hash in step, and unmaking a move puts it back
+        let mut board = play(
+            "r3k2r/pppq1ppp/8/3p4/4P3/8/PPPQ1PPP/R3K2R w KQkq - 0 1",
+            &["e4d5", "d7d5", "e1g1"],
+        );
+        let before = board;
+        let capture = Move::from_uci(&board, "d5a2").unwrap();
+        let undo = board.make_move(capture).unwrap();
+        assert_eq!(board.zobrist, board.compute_zobrist());
+        board.unmake_move(undo).unwrap();
+        assert_eq!(board.zobrist, before.zobrist);
+
+        let mut tt = TranspositionTable::with_capacity(64);
+        tt.store(board.zobrist, 3, 120, Bound::Lower);
+        assert_eq!(tt.probe(board.zobrist, 2, 0, 100), Some(120));
+        assert_eq!(tt.probe(board.zobrist, 2, 0, 200), None);
+        assert_eq!(tt.probe(board.zobrist, 4, 0, 100), None);
+        assert_eq!(tt.probe(knights_first.zobrist, 1, 0, 100), None);
+        assert_eq!(
+            TranspositionTable::with_capacity(0).probe(board.zobrist, 0, 0, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn move_ordering() {
+        use crate::board::BoardState;
+        use crate::opponents::SearchContext;
+        // The queen on d5 can be taken by the pawn or the rook, and nothing else is a capture
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/3q4/4P3/8/8/3RK3 w - - 0 1")).unwrap();
+        let legals = board.prune_moves_for_team(board.get_legal_moves(), board.active_team);
+        let ordered = |search: &SearchContext| {
+            let mut moves = board.legal_moves(board.active_team);
+            search.order_moves(&board, &mut moves, 2);
+            moves
+                .iter()
+                .map(|ordered| ordered.unpack(&board).to_uci())
+                .collect::<Vec<String>>()
+        };
+        let mut search = SearchContext::new(0);
+        let fresh = ordered(&search);
+        assert_eq!(fresh[..2], ["e4d5", "d1d5"]);
+
+        // Quiet moves that caused cutoffs come straight after the captures, this ply's killers first and then by history
+        let quiet = |uci: &str| {
+            legals
+                .iter()
+                .copied()
+                .find(|legal| legal.to_uci() == uci)
+                .unwrap()
+        };
+        search.record_cutoff(quiet("d1a1"), 5, 3);
+        search.record_cutoff(quiet("d1d2"), 2, 1);
+        search.record_cutoff(quiet("e1f2"), 2, 2);
+        search.record_cutoff(quiet("e4d5"), 2, 4);
+        assert_eq!(
+            ordered(&search)[..5],
+            ["e4d5", "d1d5", "e1f2", "d1d2", "d1a1"]
+        );
+    }
+
+    #[test]
+    fn principal_variation() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{line_to_san, rank_moves, SearchContext, SearchInfo};
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/3q4/4P3/8/8/3RK3 w - - 0 1")).unwrap();
+        let mut info = SearchInfo::default();
+        let ranked = rank_moves(&board, &History::default(), 2, &mut info);
+        assert_eq!(
+            info.pv.first(),
+            ranked.first().map(|(best, _)| best),
+            "The line doesn't start with the best move"
+        );
+        assert!(info.pv.len() > 1, "The line stops at the best move");
+        assert_eq!(
+            line_to_san(&board, &info.pv).len(),
+            info.pv.len(),
+            "The line can't be played out"
+        );
+        assert!(info.to_uci().ends_with(&format!(
+            "pv {}",
+            info.pv
+                .iter()
+                .map(|pv_move| pv_move.to_uci())
+                .collect::<Vec<String>>()
+                .join(" ")
+        )));
+
+        // The next iteration tries the line's reply before even the best capture
+        let mut after = board;
+        after.make_move(info.pv[0]).unwrap();
+        let mut replies = after.legal_moves(after.active_team);
+        let reply = replies.last().unwrap().unpack(&after);
+        let mut search = SearchContext::new(0);
+        search.follow_pv(&[info.pv[0], reply]);
+        search.order_moves(&after, &mut replies, 1);
+        assert_eq!(replies[0].unpack(&after), reply);
+    }
+
+    #[test]
+    fn late_move_reductions() {
+        use crate::board::BoardState;
+        use crate::opponents::SearchContext;
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/3q4/4P3/8/8/3RK3 w - - 0 1")).unwrap();
+        let legals = board.prune_moves_for_team(board.get_legal_moves(), board.active_team);
+        let find = |uci: &str| {
+            legals
+                .iter()
+                .copied()
+                .find(|legal| legal.to_uci() == uci)
+                .unwrap()
+        };
+        let mut search = SearchContext::new(0);
+        assert_eq!(
+            search.late_move_reduction(&board, find("d1c1"), 6, 1, 4),
+            1,
+            "A late quiet move wasn't reduced"
+        );
+        assert_eq!(
+            search.late_move_reduction(&board, find("d1c1"), 1, 1, 4),
+            0,
+            "One of the first moves was reduced"
+        );
+        assert_eq!(
+            search.late_move_reduction(&board, find("d1c1"), 6, 1, 2),
+            0,
+            "A move was reduced with too little depth left"
+        );
+        assert_eq!(
+            search.late_move_reduction(&board, find("e4d5"), 6, 1, 4),
+            0,
+            "A capture was reduced"
+        );
+        assert_eq!(
+            search.late_move_reduction(&board, find("d1d5"), 6, 1, 4),
+            0,
+            "A capture was reduced"
+        );
+
+        // Killers are kept at full depth too
+        search.record_cutoff(find("d1c1"), 1, 4);
+        assert_eq!(
+            search.late_move_reduction(&board, find("d1c1"), 6, 1, 4),
+            0,
+            "A killer was reduced"
+        );
+
+        // As are checks
+        let board = BoardState::from_fen(String::from("4k3/8/8/8/8/8/8/R3K3 w - - 0 1")).unwrap();
+        let legals = board.prune_moves_for_team(board.get_legal_moves(), board.active_team);
+        let check = legals
+            .iter()
+            .copied()
+            .find(|legal| legal.to_uci() == "a1a8")
+            .unwrap();
+        assert_eq!(
+            search.late_move_reduction(&board, check, 6, 1, 4),
+            0,
+            "A check was reduced"
+        );
+    }
+
+    #[test]
+    fn negamax_search() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{rank_moves, SearchContext, SearchInfo};
+        // Back rank mates, the same for either team
+        let white =
+            BoardState::from_fen(String::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1")).unwrap();
+        let black =
+            BoardState::from_fen(String::from("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1")).unwrap();
+        let white_ranked = rank_moves(&white, &History::default(), 1, &mut SearchInfo::default());
+        let black_ranked = rank_moves(&black, &History::default(), 1, &mut SearchInfo::default());
+        assert_eq!(white_ranked[0].0.to_uci(), "a1a8", "White missed the mate");
+        assert_eq!(black_ranked[0].0.to_uci(), "a8a1", "Black missed the mate");
+        assert_eq!(
+            white_ranked[0].1, black_ranked[0].1,
+            "Scores aren't from the mover's point of view"
+        );
+
+        // Whoever is to move, the position is worth the same to them however wide the window is
+        let search = |board: &BoardState, alpha, beta| {
+            SearchContext::new(0).search(&mut board.clone(), 2, alpha, beta, 0)
+        };
+        let score = search(&white, -i32::MAX, i32::MAX);
+        assert_eq!(score, search(&black, -i32::MAX, i32::MAX));
+        assert!(
+            score > 1000000,
+            "Being able to mate isn't scored as winning"
+        );
+        // Fail-soft scores still land on the right side of a window they fall outside of
+        assert!(search(&white, -i32::MAX, 0) >= 0);
+        assert!(search(&white, score + 1, i32::MAX) <= score + 1);
+    }
+
+    #[test]
+    fn search_stop_flag() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{ChessOpponent, EngineOptions, MoveComputer, SearchContext};
+        use std::sync::atomic::Ordering;
+        use std::time::{Duration, Instant};
+        let board = BoardState::from_fen(String::from(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ))
+        .unwrap();
+
+        // A stopped search gives up at the first position it looks at
+        let mut search = SearchContext::new(0);
+        search.stop_flag().store(true, Ordering::Relaxed);
+        search.search(&mut board.clone(), 6, -i32::MAX, i32::MAX, 0);
+        assert_eq!(search.info.nodes, 1);
+
+        // Running out of time stops it partway through a subtree, not just between root moves
+        let mut search = SearchContext::new(0);
+        search.stop_after(Duration::ZERO);
+        search.search(&mut board.clone(), 6, -i32::MAX, i32::MAX, 0);
+        assert!(search.stopped());
+        assert!(
+            search.info.nodes <= 1024,
+            "The search ran on for {} positions",
+            search.info.nodes
+        );
+
+        // Ada still has a move however little time it gets
+        let started = Instant::now();
+        assert!(
+            ChessOpponent::ada(EngineOptions::with_move_time(Duration::from_millis(1)))
+                .get_move(board, &History::default())
+                .is_some()
+        );
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn pondering() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{ChessOpponent, EngineOptions, MoveComputer};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::time::Duration;
+        let board =
+            BoardState::from_fen(String::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1")).unwrap();
+
+        // Ada thinks until she's told to stop, then has her answer ready
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopper = Arc::clone(&stop);
+        let stopping = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            stopper.store(true, Ordering::Relaxed);
+        });
+        let outcome = ChessOpponent::ada(EngineOptions::with_move_time(Duration::from_millis(100)))
+            .ponder(board, &History::default(), false, stop)
+            .expect("Ada didn't ponder");
+        stopping.join().unwrap();
+        assert_eq!(
+            outcome.best_move.map(|best| best.to_uci()),
+            Some(String::from("a1a8"))
+        );
+        assert!(outcome.info.depth > 0);
+
+        assert!(
+            ChessOpponent::Matt(2)
+                .ponder(board, &History::default(), false, Arc::default())
+                .is_none(),
+            "Matt can't be stopped partway, so he shouldn't ponder"
+        );
+    }
+
+    #[test]
+    fn tapered_evaluation() {
+        use crate::board::BoardState;
+        use crate::opponents::tapered::{game_phase, placement_score};
+        let fen = |fen: &str| BoardState::from_fen(String::from(fen)).unwrap();
+        let start = fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(game_phase(&start), 24);
+        assert_eq!(
+            placement_score(&start),
+            0,
+            "The starting position isn't even"
+        );
+        assert_eq!(game_phase(&fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")), 0);
+
+        // With the pieces on the king should stay home, with only pawns left it should come out
+        let (pieces_home, pieces_out) = (
+            fen("rnbqkbnr/8/8/8/8/8/8/RNBQKBNR w - - 0 1"),
+            fen("rnbqkbnr/8/8/8/4K3/8/8/RNBQ1BNR w - - 0 1"),
+        );
+        assert!(placement_score(&pieces_home) > placement_score(&pieces_out));
+        let (pawns_home, pawns_out) = (
+            fen("4k3/pp6/8/8/8/8/PP6/6K1 w - - 0 1"),
+            fen("4k3/pp6/8/8/4K3/8/PP6/8 w - - 0 1"),
+        );
+        assert!(placement_score(&pawns_out) > placement_score(&pawns_home));
+
+        // A passed pawn is worth more the further it gets, and more so in the endgame
+        let passed = |rank: usize| {
+            let mut ranks = vec![String::from("8"); 8];
+            ranks[0] = String::from("4k3");
+            ranks[7] = String::from("4K3");
+            ranks[8 - rank] = String::from("P7");
+            placement_score(&fen(&format!("{} w - - 0 1", ranks.join("/"))))
+        };
+        assert!(passed(6) > passed(4) && passed(4) > passed(2));
+        assert!(
+            passed(6) - passed(5) > 40,
+            "Pushing a passed pawn in the endgame isn't worth much"
+        );
+    }
+
+    #[test]
+    fn mate_distance() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{format_score, mate_in, rank_moves, SearchInfo};
+        let fen = |fen: &str| BoardState::from_fen(String::from(fen)).unwrap();
+
+        let mut info = SearchInfo::default();
+        let ranked = rank_moves(
+            &fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"),
+            &History::default(),
+            2,
+            &mut info,
+        );
+        assert_eq!(mate_in(ranked[0].1), Some(1));
+        assert_eq!(format_score(ranked[0].1), "mate in 1");
+
+        // Searched deep enough to see the mate in one along with slower ones, the quickest still comes first
+        let ranked = rank_moves(
+            &fen("7k/8/5K2/8/8/8/8/R7 w - - 0 1"),
+            &History::default(),
+            2,
+            &mut info,
+        );
+        assert_eq!(mate_in(ranked[0].1), Some(2));
+        assert!(ranked.iter().skip(1).all(|(_, eval)| *eval <= ranked[0].1));
+
+        // The side getting mated sees it coming too
+        let ranked = rank_moves(
+            &fen("7k/8/6K1/8/8/8/8/R7 b - - 0 1"),
+            &History::default(),
+            1,
+            &mut info,
+        );
+        assert_eq!(
+            ranked.iter().map(|(_, eval)| mate_in(*eval)).max(),
+            Some(Some(-1))
+        );
+        assert_eq!(format_score(ranked[0].1), "mated in 1");
+
+        assert_eq!(mate_in(450), None);
+        assert_eq!(format_score(-120), "-120e");
+    }
+
+    #[test]
+    fn search_statistics() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{rank_moves, SearchInfo};
+        use std::time::Duration;
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/3q4/4P3/8/8/3RK3 w - - 0 1")).unwrap();
+        let mut info = SearchInfo::default();
+        rank_moves(&board, &History::default(), 2, &mut info);
+        assert!(info.cutoffs > 0, "Nothing was pruned");
+        assert!(info.tt_hits <= info.tt_probes && info.tt_probes <= info.nodes);
+
+        info.elapsed = Duration::from_millis(500);
+        assert_eq!(info.nps(), info.nodes * 2);
+        assert!(info.to_uci().contains(&format!(" nps {} ", info.nodes * 2)));
+    }
+
+    #[test]
+    fn multi_pv_lines() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{multi_pv, rank_moves, MultiPv, SearchInfo};
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/3q4/4P3/8/8/3RK3 w - - 0 1")).unwrap();
+        let lines = multi_pv(
+            &board,
+            &History::default(),
+            1,
+            MultiPv(2),
+            &mut SearchInfo::default(),
+        );
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[0].eval >= lines[1].eval,
+            "Lines should come best first"
+        );
+        assert!(
+            lines.iter().all(|line| line.pv.first() == Some(&line.root)),
+            "Each line should start with its root move"
+        );
+        assert_eq!(
+            lines[0].root,
+            rank_moves(&board, &History::default(), 1, &mut SearchInfo::default())[0].0
+        );
+    }
+
+    #[test]
+    fn uci_engine_mode() {
+        use crate::engine::UciSession;
+        use crate::opponents::EngineOptions;
+        use std::sync::{Arc, Mutex};
+        use std::time::{Duration, Instant};
+
+        let lines = Arc::new(Mutex::new(Vec::<String>::new()));
+        let sent = lines.clone();
+        let mut session = UciSession::new(
+            EngineOptions::default(),
+            Arc::new(move |line| sent.lock().unwrap().push(line)),
+        );
+        // Searches answer from their own thread, so wait for the best move to turn up
+        let best_move = |session: &mut UciSession, command: &str| {
+            lines.lock().unwrap().clear();
+            assert!(session.handle(command));
+            let started = Instant::now();
+            while started.elapsed() < Duration::from_secs(20) {
+                if let Some(line) = lines
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|line| line.starts_with("bestmove"))
+                {
+                    return line.clone();
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            panic!("No best move after {command}");
+        };
+
+        assert!(session.handle("uci"));
+        assert!(session.handle("isready"));
+        {
+            let lines = lines.lock().unwrap();
+            assert!(lines.iter().any(|line| line.starts_with("id name ")));
+            assert!(lines
+                .iter()
+                .any(|line| line.starts_with("option name Hash type spin")));
+            assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+        }
+        assert!(session.handle("setoption name Skill Level value 20"));
+        assert!(session.handle("position fen 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"));
+        assert_eq!(best_move(&mut session, "go depth 2"), "bestmove a1a8");
+        let info = lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|line| line.starts_with("info depth"))
+            .cloned()
+            .collect::<Vec<String>>();
+        assert_eq!(info.len(), 2, "One info line per iteration: {info:?}");
+        assert!(
+            info[1].contains(" score mate 1 ") && info[1].ends_with(" pv a1a8"),
+            "{}",
+            info[1]
+        );
+
+        // Moves after the position are played on it, so black answers here
+        assert!(session.handle("position startpos moves e2e4 e7e5 g1f3"));
+        let reply = best_move(&mut session, "go wtime 1000 btime 1000");
+        let board = crate::board::BoardState::from_fen(String::from(
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+        ))
+        .unwrap();
+        assert!(
+            crate::r#move::Move::from_uci(&board, reply.trim_start_matches("bestmove ")).is_some(),
+            "{reply} isn't one of black's moves"
+        );
+
+        // An infinite search only ends when it's stopped
+        lines.lock().unwrap().clear();
+        assert!(session.handle("go infinite"));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(session.handle("stop"));
+        assert!(lines
+            .lock()
+            .unwrap()
+            .last()
+            .is_some_and(|line| line.starts_with("bestmove ")));
+
+        assert!(session.handle("position startpos moves e2e5"));
+        assert!(lines
+            .lock()
+            .unwrap()
+            .last()
+            .is_some_and(|line| line.starts_with("info string Illegal move e2e5")));
+        assert!(!session.handle("quit"));
+    }
+
+    #[test]
+    fn engine_options() {
+        use crate::cli::engine_options_from_args;
+        use crate::opponents::{ChessOpponent, EngineOptions};
+        use std::time::Duration;
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<String>>();
+
+        assert_eq!(engine_options_from_args(&[]), Ok(EngineOptions::default()));
+        let options = engine_options_from_args(&args("--hash 64 --threads 4 --move-overhead 50 --contempt 20 --skill 5 --movetime 900 --no-book")).unwrap();
+        assert_eq!(
+            options,
+            EngineOptions {
+                move_time: Duration::from_millis(900),
+                hash_mb: 64,
+                threads: 4,
+                move_overhead: Duration::from_millis(50),
+                contempt: 20,
+                skill_level: 5,
+                book: false,
+                max_depth: None
+            }
+        );
+        assert!(!ChessOpponent::ada(options).uses_book());
+        assert!(ChessOpponent::ada(EngineOptions::default()).uses_book());
+        assert!(engine_options_from_args(&args("--skill 21")).is_err());
+        assert!(engine_options_from_args(&args("--hash lots")).is_err());
+    }
+
+    #[test]
+    fn skill_levels() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{ChessOpponent, EngineOptions, MoveComputer, MAX_SKILL_LEVEL};
+        use std::time::{Duration, Instant};
+        assert_eq!(EngineOptions::skill_for_elo(400), 0);
+        assert_eq!(EngineOptions::skill_for_elo(1600), MAX_SKILL_LEVEL / 2);
+        assert_eq!(EngineOptions::skill_for_elo(3000), MAX_SKILL_LEVEL);
+
+        // The weakest Ada stops after a single iteration, however long she's given
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/3q4/4P3/8/8/3RK3 w - - 0 1")).unwrap();
+        let mut beginner = ChessOpponent::ada(EngineOptions {
+            move_time: Duration::from_secs(30),
+            skill_level: 0,
+            ..Default::default()
+        });
+        assert_eq!(beginner.to_string(), "Ada(30s, skill 0)");
+        let started = Instant::now();
+        let outcome = beginner.think(board, &History::default(), false, &mut |_| {});
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(outcome.info.depth, 1);
+
+        // but a whole queen is never close enough to the alternatives to be passed up
+        for _ in 0..10 {
+            let best_move = beginner.get_move(board, &History::default()).unwrap();
+            assert!(
+                best_move.captures.is_some(),
+                "{best_move} leaves the queen hanging"
+            );
+        }
+    }
+
+    #[test]
+    fn contempt() {
+        use crate::board::BoardState;
+        use crate::opponents::SearchContext;
+        let mut stalemate =
+            BoardState::from_fen(String::from("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1")).unwrap();
+        let mut search = SearchContext::new(0);
+        assert_eq!(search.search(&mut stalemate, 1, -i32::MAX, i32::MAX, 0), 0);
+
+        // With contempt, the side to move at the root counts a draw as a small loss and its opponent as a small win
+        search.contempt = 50;
+        assert_eq!(
+            search.search(&mut stalemate, 1, -i32::MAX, i32::MAX, 0),
+            -50
+        );
+        assert_eq!(search.search(&mut stalemate, 0, -i32::MAX, i32::MAX, 1), 50);
+    }
+
+    #[test]
+    fn gives_check() {
+        use crate::board::BoardState;
+        // Direct checks, discovered ones from the bishop leaving the rook's file, and the rook's check after castling
+        for fen in [
+            "4k3/8/8/8/8/8/4B3/4R1K1 w - - 0 1",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+        ] {
+            let board = BoardState::from_fen(String::from(fen)).unwrap();
+            let moves = board.prune_moves_for_team(board.get_legal_moves(), board.active_team);
+            let mut checks = 0;
+            for legal_move in moves {
+                let mut after = board;
+                after.make_move(legal_move).unwrap();
+                let checked = after.is_team_checked(after.active_team);
+                assert_eq!(
+                    board.gives_check(legal_move),
+                    checked,
+                    "{fen}: {legal_move}"
+                );
+                checks += usize::from(checked);
+            }
+            assert!(checks > 0, "{fen} should have a check to find");
+        }
+    }
+
+    #[test]
+    fn check_extensions() {
+        use crate::board::BoardState;
+        use crate::board::History;
+        use crate::opponents::{rank_moves, SearchInfo};
+        // Without looking past the check, Qxf7+ wins a pawn. A ply further on the king takes the queen back
+        let board =
+            BoardState::from_fen(String::from("6k1/5p2/6p1/8/8/8/8/5QK1 w - - 0 1")).unwrap();
+        let ranked = rank_moves(&board, &History::default(), 0, &mut SearchInfo::default());
+        let queen_takes = ranked
+            .iter()
+            .find(|(candidate, _)| candidate.captures.is_some())
+            .unwrap();
+        assert!(board.gives_check(queen_takes.0));
+        assert_ne!(
+            ranked[0].0, queen_takes.0,
+            "Qxf7+ loses the queen and shouldn't be the best move"
+        );
+    }
+
+    #[test]
+    fn persistent_table() {
+        use crate::opponents::transposition::{Bound, SharedTable, TranspositionTable};
+        // Within a search a deeper entry holds its slot, but the next search's entries push it out
+        let mut tt = TranspositionTable::with_capacity(1);
+        tt.store(1, 5, 10, Bound::Exact);
+        tt.store(2, 1, 20, Bound::Exact);
+        assert_eq!(
+            (tt.probe(1, 0, 0, 0), tt.probe(2, 0, 0, 0)),
+            (Some(10), None)
+        );
+        assert_eq!(tt.hashfull(), 1000);
+        tt.new_search();
+        assert_eq!(
+            tt.hashfull(),
+            0,
+            "Only entries from the current search count as full"
+        );
+        assert_eq!(
+            tt.probe(1, 0, 0, 0),
+            Some(10),
+            "Aged entries are still found"
+        );
+        tt.store(2, 1, 20, Bound::Exact);
+        assert_eq!(tt.probe(2, 0, 0, 0), Some(20));
+
+        // A copy of the opponent's table hands back whatever the other copy searched
+        let table = SharedTable::default();
+        let mut searched = table.take(64);
+        searched.store(7, 3, 30, Bound::Exact);
+        table.clone().put_back(searched);
+        assert_eq!(table.take(64).probe(7, 3, 0, 0), Some(30));
+        assert_eq!(
+            table.take(64).probe(7, 3, 0, 0),
+            None,
+            "Taking the table should leave none behind"
+        );
+
+        // A new hash size starts a new table
+        table.put_back(TranspositionTable::with_capacity(64));
+        assert_eq!(table.take(32).capacity(), 32);
+    }
+
+    #[test]
+    fn texel_tuning() {
+        use crate::opponents::{static_breakdown, tapered::placement_score};
+        use crate::tune::{parse_positions, tune, tuning_error, write_constants, Weights};
+        let positions = parse_positions("# A few finished games\n4k3/8/8/8/8/8/4PPPP/4K3 w - - 0 1 1-0\n4k3/pppp4/8/8/8/8/8/4K3 b - - c9 \"0-1\";\n\n4k3/8/8/8/8/8/8/4K3 w - - [0.5]").unwrap();
+        assert_eq!(
+            positions
+                .iter()
+                .map(|position| position.result)
+                .collect::<Vec<_>>(),
+            vec![1.0, 0.0, 0.5]
+        );
+        assert!(parse_positions("4k3/8/8/8/8/8/8/4K3 w - - 0 1 2-0").is_err());
+
+        // The tuner starts from the evaluation the engine plays with
+        let mut weights = Weights::default();
+        let board = &positions[0].board;
+        let breakdown = static_breakdown(board);
+        assert_eq!(
+            weights.evaluate(board),
+            breakdown.material + placement_score(board)
+        );
+
+        let before = tuning_error(&positions, &weights, 1.0);
+        let mut passes = 0;
+        let after = tune(&positions, &mut weights, 1.0, 1, &mut |pass, _| {
+            passes = pass
+        });
+        assert_eq!(passes, 1);
+        assert!(
+            after < before,
+            "A pass should bring the error down from {before}"
+        );
+        assert_ne!(weights, Weights::default());
+        assert!(write_constants(&Weights::default()).contains(
+            "const KING_MIDGAME: [i32; 64] = [\n    -30, -40, -40, -50, -50, -40, -40, -30,\n"
+        ));
+    }
+
+    #[test]
+    fn perft() {
+        use crate::{board::BoardState, START_POS_CHESS};
+        let board = BoardState::from_fen(START_POS_CHESS.to_string()).unwrap();
+        assert_eq!(board.perft(0), 1);
+        assert_eq!(
+            (1..=2).map(|depth| board.perft(depth)).collect::<Vec<_>>(),
+            vec![20, 400]
+        );
+
+        let divide = board.perft_divide(2);
+        assert_eq!(divide.len(), 20);
+        assert!(
+            divide.iter().all(|(_, nodes)| *nodes == 20),
+            "Every first move leaves black twenty replies"
+        );
+        assert_eq!(
+            divide.iter().map(|(_, nodes)| nodes).sum::<u64>(),
+            board.perft(2)
+        );
+        assert!(board.perft_divide(0).is_empty());
+    }
+
+    #[test]
+    fn line_tables() {
+        use crate::bitboard::{Bitboard, BoardSize, BOARD_SIZES};
+
+        let square = |name: &str| Bitboard::al_notation_to_bit_idx(name).unwrap();
+        let line = &BoardSize::Standard.tables().line;
+        assert_eq!(
+            line[square("c3")][square("e5")].state,
+            0x8040201008040201,
+            "c3 and e5 share the long diagonal"
+        );
+        assert_eq!(
+            line[square("e5")][square("c3")],
+            line[square("c3")][square("e5")]
+        );
+        assert_eq!(
+            line[square("b1")][square("g1")].state,
+            0xFF,
+            "b1 and g1 share the first rank"
+        );
+        assert_eq!(line[square("d2")][square("d7")].state, 0x0808080808080808);
+        assert_eq!(
+            line[square("a1")][square("b3")].state,
+            0,
+            "a1 and b3 don't share a line"
+        );
+        assert_eq!(line[square("e4")][square("e4")].state, 0);
+
+        // A wider board's lines run on past the h file without wrapping onto the next rank
+        let wide = BoardSize::TenByEight;
+        let wide_square = |name: &str| wide.square_at(name).unwrap();
+        assert_eq!(
+            wide.tables().line[wide_square("b1")][wide_square("i1")],
+            wide.rank_mask(0),
+            "b1 and i1 share the first rank"
+        );
+        assert_eq!(
+            wide.tables().line[wide_square("h1")][wide_square("j3")]
+                .state
+                .count_ones(),
+            3,
+            "The diagonal from h1 stops at the j file"
+        );
+
+        for size in BOARD_SIZES {
+            let tables = size.tables();
+            for from in 0..size.squares() {
+                for to in 0..size.squares() {
+                    let between = tables.between[from][to].state;
+                    assert_eq!(
+                        between & !tables.line[from][to].state,
+                        0,
+                        "Squares between {from} and {to} are off their line on {size:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn king_safety() {
+        use crate::bitboard::Team;
+        use crate::board::BoardState;
+
+        let square = |name: &str| Bitboard::al_notation_to_bit_idx(name).unwrap();
+        let bit = |name: &str| 1u128 << square(name);
+
+        // Nothing attacks the king, so any square answers
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/8/4r3/8/3N4/2K1R2b w - - 0 1")).unwrap();
+        let safety = board.king_safety(Team::White).unwrap();
+        assert_eq!(safety.king, square("c1"));
+        assert_eq!(safety.checkers.state, 0);
+        assert_eq!(safety.check_mask, board.size.all());
+
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/8/8/b7/3N4/2K1R3 w - - 0 1")).unwrap();
+        let safety = board.king_safety(Team::White).unwrap();
+        assert_eq!(
+            safety.pinned.state, 0,
+            "The knight isn't between the bishop and the king"
+        );
+
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/8/8/8/1b1N4/2K1R3 w - - 0 1")).unwrap();
+        let safety = board.king_safety(Team::White).unwrap();
+        assert_eq!(safety.checkers.state, bit("b2"));
+        assert_eq!(
+            safety.check_mask.state,
+            bit("b2"),
+            "Next to the king there is nothing to block"
+        );
+
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/b7/8/8/3N4/4K2r w - - 0 1")).unwrap();
+        let safety = board.king_safety(Team::White).unwrap();
+        assert_eq!(safety.checkers.state, bit("h1"));
+        assert_eq!(safety.check_mask.state, bit("f1") | bit("g1") | bit("h1"));
+        assert_eq!(safety.pinned.state, bit("d2"));
+        assert!(
+            safety.danger.get_bit(square("d1")),
+            "The king can't step back along the rook's line"
+        );
+        let moves = board.prune_moves_for_team(board.get_legal_moves(), Team::White);
+        assert!(
+            moves.iter().all(|legal| legal.start == square("e1")),
+            "A pinned knight can't block the check"
+        );
+
+        // Double check leaves only king moves
+        let board =
+            BoardState::from_fen(String::from("4k3/8/8/8/4r3/3n4/5B2/4K3 w - - 0 1")).unwrap();
+        let safety = board.king_safety(Team::White).unwrap();
+        assert_eq!(safety.checkers.state.count_ones(), 2);
+        assert_eq!(safety.check_mask.state, 0);
+        assert!(board
+            .prune_moves_for_team(board.get_legal_moves(), Team::White)
+            .iter()
+            .all(|legal| legal.start == square("e1")));
+
+        // A pinned rook can still slide along the pin
+        let board =
+            BoardState::from_fen(String::from("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1")).unwrap();
+        let rook_moves: Vec<usize> = board
+            .prune_moves_for_team(board.get_legal_moves(), Team::White)
+            .iter()
+            .filter(|legal| legal.start == square("e2"))
+            .map(|legal| legal.target)
+            .collect();
+        assert_eq!(
+            rook_moves.len(),
+            5,
+            "The rook should only move up and down the e-file"
+        );
+        assert!(rook_moves.iter().all(|target| target % 8 == 4));
+    }
+
+    #[test]
+    fn packed_moves() {
+        use crate::bitboard::Team;
+        use crate::board::BoardState;
+        use crate::r#move::{Move, MoveList, PackedMove};
+
+        let board = BoardState::from_fen(String::from(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ))
+        .unwrap();
+        let castle = Move {
+            start: 4,
+            target: 6,
+            captures: None,
+            is_pawn_double: false,
+            is_castle: true,
+            promotion: None,
+        };
+        let packed = PackedMove::from(castle);
+        assert_eq!(
+            (
+                packed.start(),
+                packed.target(),
+                packed.is_castle(),
+                packed.is_pawn_double()
+            ),
+            (4, 6, true, false)
+        );
+        assert_eq!(packed.unpack(&board), castle);
+        assert!(!packed.is_capture(&board));
+        let double = PackedMove::new(8, 24, true, false, None);
+        assert!(double.is_pawn_double() && !double.is_castle());
+        let capture = PackedMove::new(36, 45, false, false, None).unpack(&board);
+        assert_eq!(
+            capture.captures.map(|taken| taken.piece_type),
+            Some(PieceType::Knight),
+            "e5xf7 should know it takes the knight"
+        );
+
+        let mut list = MoveList::default();
+        assert!(list.is_empty());
+        list.push(packed);
+        list.push(double);
+        assert_eq!(list.len(), 2);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            vec![packed, double]
+        );
+
+        // The flat list holds exactly the moves of the per-square one
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/b7/8/8/3N4/4K2r w - - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 b - - 0 10",
+        ] {
+            let board = BoardState::from_fen(String::from(fen)).unwrap();
+            let team = board.active_team;
+            let mut flat: Vec<Move> = board
+                .legal_moves(team)
+                .iter()
+                .map(|packed| packed.unpack(&board))
+                .collect();
+            let mut per_square = board.prune_moves_for_team(board.get_legal_moves(), team);
+            flat.sort_by_key(|legal| (legal.start, legal.target));
+            per_square.sort_by_key(|legal| (legal.start, legal.target));
+            assert_eq!(flat, per_square, "Move lists disagree in {fen}");
+            assert_eq!(
+                board.legal_moves(Team::White).is_empty(),
+                !board.has_legal_move(Team::White)
+            );
+        }
+
+        // A made-up position full of queens and compound pieces has more moves than the array holds
+        let crowded = BoardState::from_fen(String::from(
+            "Q1Q1CAQ2k/6C1Q1/9C/1Q2Q5/3C4Q1/1Q4Q1Q1/1AC1QC4/K6QQA w - - 0 1",
+        ))
+        .unwrap();
+        let moves = crowded.legal_moves(Team::White);
+        assert_eq!(moves.len(), 278);
+        let mut flat: Vec<Move> = moves.iter().map(|packed| packed.unpack(&crowded)).collect();
+        let mut per_square = crowded.prune_moves_for_team(crowded.get_legal_moves(), Team::White);
+        flat.sort_by_key(|legal| (legal.start, legal.target));
+        per_square.sort_by_key(|legal| (legal.start, legal.target));
+        assert_eq!(flat, per_square);
+        assert_eq!(crowded.perft(1), 278);
+        let mut long = MoveList::default();
+        for target in 0..300 {
+            long.push(PackedMove::new(0, target % 80, false, false, None));
+        }
+        assert_eq!(long.len(), 300);
+        assert_eq!(long[299].target(), 299 % 80);
+        assert_eq!(long[0].target(), 0);
+    }
+
+    #[test]
+    fn undo_round_trip() {
+        use crate::board::BoardState;
+
+        // Every legal move two plies deep, made and unmade, has to leave the exact position it started from
+        fn walk(board: &mut BoardState, depth: u32) {
+            let before = *board;
+            for packed in board.legal_moves(board.active_team).iter() {
+                let played = packed.unpack(board);
+                let undo = board.make_move(played).unwrap();
+                if depth > 1 {
+                    walk(board, depth - 1);
+                }
+                board.unmake_move(undo).unwrap();
+                assert_eq!(
+                    board.as_fen(),
+                    before.as_fen(),
+                    "FEN changed after unmaking {played}"
+                );
+                assert_eq!(
+                    board.zobrist, before.zobrist,
+                    "Hash changed after unmaking {played}"
+                );
+                assert_eq!(*board, before, "Position changed after unmaking {played}");
+            }
+        }
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/pppq1ppp/8/3p4/4P3/8/PPPQ1PPP/R3K2R b KQkq - 7 12",
+            "8/7p/8/5r2/P3K2k/1P4p1/2P5/8 w - - 0 40",
+        ] {
+            let mut board = BoardState::from_fen(String::from(fen)).unwrap();
+            walk(&mut board, 2);
+            assert_eq!(board.as_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn position_history() {
+        use crate::board::{BoardState, History};
+        use crate::r#move::Move;
+
+        let start = "r3k2r/pppq1ppp/8/3p4/4P3/8/PPPQ1PPP/R3K2R w KQkq - 0 1";
+        let mut board = BoardState::from_fen(String::from(start)).unwrap();
+        let mut history = History::default();
+        let mut hashes = vec![board.zobrist];
+        for uci in ["e4d5", "d7d5", "e1g1", "e8c8"] {
+            let played = Move::from_uci(&board, uci).unwrap();
+            history.make_move(&mut board, played).unwrap();
+            hashes.push(board.zobrist);
+        }
+
+        // Each entry has the move and the hash of the position it was played from
+        assert_eq!(
+            history
+                .moves_played()
+                .map(|played| played.to_uci())
+                .collect::<Vec<_>>(),
+            vec!["e4d5", "d7d5", "e1g1", "e8c8"]
+        );
+        assert_eq!(
+            history
+                .undos
+                .iter()
+                .map(|undo| undo.zobrist)
+                .collect::<Vec<_>>(),
+            hashes[..4]
+        );
+        assert_eq!(
+            history.last_move().map(|played| played.to_uci()),
+            Some(String::from("e8c8"))
+        );
+
+        // Taking every move back walks the hashes in reverse and ends on the set-up position
+        while let Some(taken) = history.take_back(&mut board) {
+            hashes.pop();
+            assert_eq!(
+                Some(&board.zobrist),
+                hashes.last(),
+                "Wrong position after taking back {taken}"
+            );
+        }
+        assert_eq!(board.as_fen(), start);
+        assert!(history.undos.is_empty());
+        assert_eq!(history.last_move(), None);
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        use crate::board::{BoardState, GameStatus, History};
+        use crate::opponents::SearchContext;
+        use crate::r#move::Move;
+
+        let play = |board: &mut BoardState, history: &mut History, moves: &[&str]| {
+            for uci in moves {
+                let played =
+                    Move::from_uci(board, uci).unwrap_or_else(|| panic!("{uci} isn't legal"));
+                history.make_move(board, played).unwrap();
+            }
+        };
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        // The start position comes up a second time after one shuffle and a third after two
+        let mut board = BoardState::from_fen(String::from(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ))
+        .unwrap();
+        let mut history = History::default();
+        play(&mut board, &mut history, &shuffle);
+        assert_eq!(history.repetition_count(), 2);
+        assert_eq!(history.status(&board), GameStatus::Ongoing);
+        play(&mut board, &mut history, &shuffle[..3]);
+        assert_eq!(history.status(&board), GameStatus::Ongoing);
+        // Making the move that brings the position up a third time is what draws the game
+        let third = Move::from_uci(&board, "f6g8").unwrap();
+        history.make_move(&mut board, third).unwrap();
+        assert_eq!(history.repetitions, [1, 1, 1, 2, 2, 2, 2, 3]);
+        assert_eq!(history.repetition_status(), GameStatus::DrawByRepetition);
+        assert_eq!(history.status(&board), GameStatus::DrawByRepetition);
+        // The board alone can't tell it's been here before
+        assert_eq!(board.status(), GameStatus::Ongoing);
+
+        // Leaving the repeated position, or taking the move back, is no longer a draw
+        history.take_back(&mut board);
+        assert_eq!(history.status(&board), GameStatus::Ongoing);
+        play(&mut board, &mut history, &["f6g8", "e2e4"]);
+        assert_eq!(history.status(&board), GameStatus::Ongoing);
+
+        // The search scores walking into the third repetition as a draw, even a queen up
+        let mut board =
+            BoardState::from_fen(String::from("q3k3/8/8/8/8/8/8/4K3 w - - 0 1")).unwrap();
+        let mut history = History::default();
+        play(
+            &mut board,
+            &mut history,
+            &["e1d1", "e8d8", "d1e1", "d8e8", "e1d1", "e8d8", "d1e1"],
+        );
+        let repeating = Move::from_uci(&board, "d8e8").unwrap();
+        let mut search = SearchContext::new(0);
+        search.played = history;
+        assert_eq!(
+            search.search_root_move(&mut board, repeating, 3, -i32::MAX, i32::MAX),
+            0
+        );
+        let other = Move::from_uci(&board, "d8c8").unwrap();
+        assert!(search.search_root_move(&mut board, other, 3, -i32::MAX, i32::MAX) > 500);
+    }
+
+    #[test]
+    fn game_status() {
+        use crate::bitboard::Team;
+        use crate::board::{BoardState, GameStatus};
+
+        let status = |fen: &str| BoardState::from_fen(String::from(fen)).unwrap().status();
+        assert_eq!(
+            status("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            GameStatus::Ongoing
+        );
+        assert_eq!(
+            status("6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 1"),
+            GameStatus::Ongoing
+        );
+        assert_eq!(
+            status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"),
+            GameStatus::Checkmate(Team::White)
+        );
+        assert_eq!(
+            status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+            GameStatus::Stalemate
+        );
+        assert_eq!(
+            status("4k3/8/8/8/8/8/4P3/R3K3 w - - 100 80"),
+            GameStatus::DrawByFiftyMove
+        );
+
+        // A mate on the hundredth ply still counts
+        assert_eq!(
+            status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80"),
+            GameStatus::Checkmate(Team::White)
+        );
+
+        // Bare kings, a lone minor piece, and bishops all on one colour can't mate. A knight and bishop, or bishops on both colours, can
+        assert_eq!(
+            status("4k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+            GameStatus::DrawByMaterial
+        );
+        assert_eq!(
+            status("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1"),
+            GameStatus::DrawByMaterial
+        );
+        assert_eq!(
+            status("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"),
+            GameStatus::DrawByMaterial
+        );
+        assert_eq!(
+            status("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1"),
+            GameStatus::Ongoing
+        );
+        assert_eq!(
+            status("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"),
+            GameStatus::Ongoing
+        );
+        assert_eq!(
+            status("4k3/8/8/8/8/8/8/3QK3 w - - 0 1"),
+            GameStatus::Ongoing
+        );
+
+        assert_eq!(GameStatus::Checkmate(Team::Black).result(), Some("0-1"));
+        assert_eq!(GameStatus::DrawByMaterial.result(), Some("1/2-1/2"));
+        assert_eq!(GameStatus::Ongoing.result(), None);
+    }
+
+    #[test]
+    fn pawn_promotion() {
+        use crate::board::BoardState;
+        use crate::r#move::{Move, PackedMove};
+
+        let fen = "1n2k3/P7/8/8/8/8/6p1/4K2R w - - 0 1";
+        let mut board = BoardState::from_fen(String::from(fen)).unwrap();
+
+        // A push and a capture onto the last rank, each once for every piece it can become
+        let promotions: Vec<String> = board
+            .legal_moves(board.active_team)
+            .iter()
+            .filter(|packed| packed.promotion().is_some())
+            .map(|packed| packed.unpack(&board).to_uci())
+            .collect();
+        assert_eq!(
+            promotions,
+            vec!["a7a8q", "a7a8r", "a7a8b", "a7a8n", "a7b8q", "a7b8r", "a7b8b", "a7b8n"]
+        );
+        assert_eq!(
+            board
+                .prune_moves_for_team(board.get_legal_moves(), board.active_team)
+                .iter()
+                .filter(|legal| legal.promotion.is_some())
+                .count(),
+            8
+        );
+
+        // The chosen piece is what lands, and unmaking brings the pawn back
+        let underpromotion = Move::from_uci(&board, "a7b8n").unwrap();
+        assert_eq!(underpromotion.promotion, Some(PieceType::Knight));
+        assert_eq!(underpromotion.to_san(&board), "axb8=N");
+        assert_eq!(
+            PackedMove::from(underpromotion).unpack(&board),
+            underpromotion
+        );
+        let undo = board.make_move(underpromotion).unwrap();
+        assert_eq!(board.as_fen(), "1N2k3/8/8/8/8/8/6p1/4K2R b - - 0 1");
+        assert_eq!(board.zobrist, board.compute_zobrist());
+        board.unmake_move(undo).unwrap();
+        assert_eq!(board.as_fen(), fen);
+
+        // Without a letter it's a queen, and SAN says which piece it was
+        assert_eq!(
+            Move::from_uci(&board, "a7a8").unwrap().promotion,
+            Some(PieceType::Queen)
+        );
+        board
+            .make_move(Move::from_san(&board, "a8=Q+").unwrap())
+            .unwrap();
+        assert_eq!(board.piece_list[56], PieceType::Queen);
+        let black_promotion = Move::from_san(&board, "gxh1=R").unwrap();
+        assert_eq!(
+            Move::from_san(&board, "gxh1R"),
+            Some(black_promotion),
+            "Typed promotions can leave out the ="
+        );
+        board.make_move(black_promotion).unwrap();
+        assert_eq!(board.as_fen(), "Qn2k3/8/8/8/8/8/8/4K2r w - - 0 2");
+    }
+
+    #[test]
+    fn has_legal_move() {
+        use crate::bitboard::Team;
+        use crate::board::BoardState;
+
+        let stalemate = BoardState::from_fen(String::from("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"))
+            .expect("Invalid FEN used in testing");
+        assert!(
+            !stalemate.has_legal_move(Team::Black),
+            "Stalemated king found a move"
+        );
+        assert!(
+            stalemate.has_legal_move(Team::White),
+            "White should have moves in a stalemate it caused"
+        );
+
+        let start = BoardState::from_fen(String::from(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ))
+        .expect("Invalid FEN used in testing");
+        assert!(
+            start.has_legal_move(Team::White),
+            "No legal move found in the starting position"
+        );
+    }
+
+    #[test]
+    fn jump_tables() {
+        use crate::bitboard::{BoardSize, Team, BOARD_SIZES};
+
+        // Slow but obviously right: step from the square by file and rank and keep what stays on the board
+        let reference = |size: BoardSize, square: usize, steps: &[(i32, i32)]| {
+            let (files, ranks) = (size.files() as i32, size.ranks() as i32);
+            let (file, rank) = (square as i32 % files, square as i32 / files);
+            steps.iter().fold(0u128, |bits, (file_step, rank_step)| {
+                let (target_file, target_rank) = (file + file_step, rank + rank_step);
+                if (0..files).contains(&target_file) && (0..ranks).contains(&target_rank) {
+                    bits | (1 << (target_rank * files + target_file))
+                } else {
+                    bits
+                }
+            })
+        };
+        let knight_steps = [
+            (1, 2),
+            (2, 1),
+            (2, -1),
+            (1, -2),
+            (-1, -2),
+            (-2, -1),
+            (-2, 1),
+            (-1, 2),
+        ];
+        let king_steps = [
+            (1, 1),
+            (1, 0),
+            (1, -1),
+            (0, -1),
+            (-1, -1),
+            (-1, 0),
+            (-1, 1),
+            (0, 1),
+        ];
+
+        for size in BOARD_SIZES {
+            let tables = size.tables();
+            for square in 0..size.squares() {
+                let rank = size.rank(square);
+
+                assert_eq!(
+                    tables.knight_attacks[square].state,
+                    reference(size, square, &knight_steps),
+                    "Knight table wrong on {square} of {size:?}"
+                );
+                for team in [Team::White, Team::Black] {
+                    assert_eq!(
+                        tables.king_attacks[team as usize][square].state,
+                        reference(size, square, &king_steps),
+                        "King table wrong on {square} of {size:?}"
+                    );
+                }
+                assert_eq!(
+                    tables.pawn_attacks[Team::White as usize][square].state,
+                    reference(size, square, &[(-1, 1), (1, 1)]),
+                    "White pawn attacks wrong on {square}"
+                );
+                assert_eq!(
+                    tables.pawn_attacks[Team::Black as usize][square].state,
+                    reference(size, square, &[(-1, -1), (1, -1)]),
+                    "Black pawn attacks wrong on {square}"
+                );
+
+                let white_pushes: &[(i32, i32)] = match rank {
+                    0 | 7 => &[],
+                    1 => &[(0, 1), (0, 2)],
+                    _ => &[(0, 1)],
+                };
+                let black_pushes: &[(i32, i32)] = match rank {
+                    0 | 7 => &[],
+                    6 => &[(0, -1), (0, -2)],
+                    _ => &[(0, -1)],
+                };
+                assert_eq!(
+                    tables.pawn_pushes[Team::White as usize][square].state,
+                    reference(size, square, white_pushes),
+                    "White pawn pushes wrong on {square}"
+                );
+                assert_eq!(
+                    tables.pawn_pushes[Team::Black as usize][square].state,
+                    reference(size, square, black_pushes),
+                    "Black pawn pushes wrong on {square}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pseudo_legal_iter() {
+        use crate::bitboard::Team;
+        use crate::board::BoardState;
+
+        let test_board = BoardState::from_fen(String::from(
+            "r3k2r/pppq1ppp/2n2n2/3pp3/3PP3/2N2N2/PPPQ1PPP/R3K2R w KQkq - 0 1",
+        ))
+        .expect("Invalid FEN used in testing");
+
+        for team in [Team::White, Team::Black] {
+            let mut lazy_moves: Vec<_> = test_board.pseudo_legal_iter(team).collect();
+            let mut eager_moves =
+                test_board.prune_moves_for_team(test_board.get_psuedolegal_moves(), team);
+            lazy_moves.sort_by_key(|found| (found.start, found.target));
+            eager_moves.sort_by_key(|found| (found.start, found.target));
+
+            assert_eq!(
+                lazy_moves, eager_moves,
+                "Lazy move generation disagrees for {team:?}"
+            );
+        }
+        assert!(
+            test_board
+                .pseudo_legal_iter(Team::White)
+                .any(|found| found.is_castle),
+            "Lazy move generation did not castle"
+        );
+    }
+
+    #[test]
+    fn attack_maps() {
+        use crate::bitboard::{Bitboard, Team};
+        use crate::board::BoardState;
+
+        let test_board = BoardState::from_fen(String::from("4k3/8/8/3p4/4p3/8/8/4K2R w - - 0 1"))
+            .expect("Invalid FEN used in testing");
+        let e4 = Bitboard::al_notation_to_bit_idx("e4").unwrap();
+
+        assert!(
+            test_board.is_attacked_by(e4, Team::Black),
+            "Defended pawn is not attacked by its own team"
+        );
+        assert!(
+            !test_board.is_attacked_by(e4, Team::White),
+            "White attacks a square it cannot reach"
+        );
+        assert!(
+            test_board.is_attacked_by(Bitboard::al_notation_to_bit_idx("h8").unwrap(), Team::White),
+            "Rook does not attack up its open file"
+        );
+        assert!(
+            !test_board.is_team_checked(Team::White),
+            "King defended by its own rook counts as checked"
+        );
+    }
+
+    #[test]
+    fn fifty_move_clock() {
+        use crate::board::BoardState;
+        use crate::r#move::Move;
+
+        let mut test_board =
+            BoardState::from_fen(String::from("4k3/8/8/8/8/8/4P3/4K2R w - - 7 30"))
+                .expect("Invalid FEN used in testing");
+
+        test_board
+            .make_move(Move::from_uci(&test_board, "h1h5").unwrap())
+            .unwrap();
+        assert_eq!(
+            test_board.fifty_move_clock, 8,
+            "Quiet rook move did not advance the fifty-move clock"
+        );
+        test_board
+            .make_move(Move::from_uci(&test_board, "e8d8").unwrap())
+            .unwrap();
+        test_board
+            .make_move(Move::from_uci(&test_board, "e2e4").unwrap())
+            .unwrap();
+        assert_eq!(
+            test_board.fifty_move_clock, 0,
+            "Pawn move did not reset the fifty-move clock"
+        );
+        assert!(
+            test_board.as_fen().ends_with(" 0 31"),
+            "FEN did not carry the clocks: {}",
+            test_board.as_fen()
+        );
+    }
+
+    #[test]
+    fn move_parsing() {
+        use crate::board::BoardState;
+        use crate::r#move::Move;
+
+        let test_board = BoardState::from_fen(String::from(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ))
+        .expect("Invalid FEN used in testing");
+
+        let knight_move = Move::from_san(&test_board, "Nf3").expect("Nf3 was not parsed");
+        assert_eq!(
+            knight_move.to_uci(),
+            "g1f3",
+            "Nf3 was parsed as the wrong move"
+        );
+        assert_eq!(
+            Move::from_uci(&test_board, "g1f3"),
+            Some(knight_move),
+            "SAN and UCI disagree on the same move"
+        );
+        assert!(
+            Move::from_san(&test_board, "e4").is_some(),
+            "Pawn push was not parsed"
+        );
+        assert!(
+            Move::from_san(&test_board, "Ke2").is_none(),
+            "Illegal king move was parsed"
+        );
+        assert!(
+            Move::from_uci(&test_board, "e7e5").is_none(),
+            "Black move was parsed on white's turn"
+        );
+        assert!(
+            Move::from_uci(&test_board, "e2e9").is_none(),
+            "Off-board square was parsed"
+        );
+    }
+
+    #[test]
+    fn compound_pieces() {
+        use crate::bitboard::Bitboard;
+        use crate::board::BoardState;
+
+        let fen = String::from("4k2c/8/8/8/3A4/8/8/4K3 w - - 0 1");
+        let test_board = BoardState::from_fen(fen.clone()).expect("Invalid FEN used in testing");
+        let moves = test_board.get_legal_moves();
+
+        assert_eq!(
+            fen,
+            test_board.as_fen(),
+            "Compound pieces did not survive FEN conversion"
+        );
+        assert_eq!(
+            moves[Bitboard::al_notation_to_bit_idx("d4").unwrap()]
+                .1
+                .len(),
+            21,
+            "Archbishop should have 13 bishop moves and 8 knight moves from d4"
+        );
+        assert_eq!(
+            moves[Bitboard::al_notation_to_bit_idx("h8").unwrap()]
+                .1
+                .len(),
+            11,
+            "Chancellor should have 9 rook moves and 2 knight moves from h8"
+        );
+    }
+
+    #[test]
+    fn pawn_jump() {
+	use crate::bitboard::Bitboard;
+        use crate::bitboard::Team;
+        use crate::board::BoardState;
+
+	let mut test_board = BoardState::from_fen(String::from("rnbqkb1r/pppppppp/6N1/8/6n1/8/PPPPPPPP/RNBQKB1R b KQkq - 0 1")).expect("Invalid FEN used in testing");
+
+        let moves = test_board.get_legal_moves();
+	
+	let can_jump_knight = moves[Bitboard::al_notation_to_bit_idx("g7").unwrap()].0.get_bit(Bitboard::al_notation_to_bit_idx("g5").unwrap());
+	assert!(!can_jump_knight, "Pawn is moving twice with a knight in the way")
+	
+    }
+
+    /*
+        Perft counts for the standard positions that trip move generators up, checked against the published totals.
+        The shallow depths run with the other tests; the deep ones take minutes, so they're ignored unless asked for
+        with `cargo test --release perft_suite -- --ignored`
+    */
+    #[cfg(test)]
+    mod perft_suite {
+        use crate::board::BoardState;
+
+        // FEN and the node counts from depth 1 up
+        const START: (&str, &[u64]) = (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281, 4865609],
+        );
+        // Castling both ways, pins, en passant and promotions all at once
+        const KIWIPETE: (&str, &[u64]) = (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862, 4085603],
+        );
+        // En passant captures that would expose the king along the rank
+        const EN_PASSANT_PINS: (&str, &[u64]) = (
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238, 674624],
+        );
+        // Promotions with captures, checks and a side that can no longer castle
+        const PROMOTIONS: (&str, &[u64]) = (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422333],
+        );
+        const PROMOTION_CHECKS: (&str, &[u64]) = (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379, 2103487],
+        );
+        const MIDDLEGAME: (&str, &[u64]) = (
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890, 3894594],
+        );
+        // Both sides racing to promote, with underpromotions to knights deciding mates
+        const PROMOTION_RACE: (&str, &[u64]) = (
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            &[24, 496, 9483, 182838, 3605103],
+        );
+
+        // Every depth in the table, up to 4 or 5 plies
+        fn assert_perft((fen, counts): (&str, &[u64])) {
+            let board = BoardState::from_fen(fen.to_string()).expect("Invalid FEN used in testing");
+            for (depth, expected) in counts.iter().enumerate() {
+                assert_eq!(
+                    board.perft(depth as u32 + 1),
+                    *expected,
+                    "Perft {} of {fen}",
+                    depth + 1
+                );
+            }
+        }
+
+        #[test]
+        fn start_position() {
+            assert_perft(START);
+        }
+
+        #[test]
+        fn kiwipete() {
+            assert_perft(KIWIPETE);
+        }
+
+        #[test]
+        fn en_passant_pins() {
+            assert_perft(EN_PASSANT_PINS);
+        }
+
+        #[test]
+        fn promotions() {
+            assert_perft(PROMOTIONS);
+            assert_perft(PROMOTION_CHECKS);
+            assert_perft(PROMOTION_RACE);
+        }
+
+        #[test]
+        fn middlegame() {
+            assert_perft(MIDDLEGAME);
+        }
     }
 }
@@ -0,0 +1,133 @@
+use std::{fs, path::PathBuf};
+
+use crate::ui::ColorRGBA;
+
+pub const SETTINGS_FILE: &str = "settings.txt"; // Where the settings menu's choices are kept between runs
+
+// The colours the squares are drawn in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoardTheme {
+    pub name: &'static str,
+    pub light: ColorRGBA,
+    pub dark: ColorRGBA,
+}
+
+pub const BOARD_THEMES: [BoardTheme; 5] = [
+    BoardTheme {
+        name: "Coral",
+        light: [0.941, 0.467, 0.467, 1.0],
+        dark: [0.651, 0.141, 0.141, 1.0],
+    },
+    BoardTheme {
+        name: "Wood",
+        light: [0.941, 0.851, 0.710, 1.0],
+        dark: [0.710, 0.533, 0.388, 1.0],
+    },
+    BoardTheme {
+        name: "Green",
+        light: [0.933, 0.933, 0.824, 1.0],
+        dark: [0.463, 0.588, 0.337, 1.0],
+    },
+    BoardTheme {
+        name: "Blue",
+        light: [0.871, 0.890, 0.902, 1.0],
+        dark: [0.549, 0.635, 0.678, 1.0],
+    },
+    BoardTheme {
+        name: "Grey",
+        light: [0.850, 0.850, 0.850, 1.0],
+        dark: [0.550, 0.550, 0.550, 1.0],
+    },
+];
+// Folders of piece art under resources, with a png for each piece named like wk.png
+pub const PIECE_SETS: [&str; 2] = ["alila", "horsey"];
+pub const VOLUME_STEP: u8 = 10; // How far the volume slider moves at a time, in percent
+
+/*
+    What's been chosen on the settings menu. The file is one `name value` line per setting, with themes and piece
+    sets saved by name so the lists can change order. A setting the file leaves out keeps its default
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub board_theme: usize, // Into BOARD_THEMES
+    pub piece_set: usize,   // Into PIECE_SETS
+    pub volume: u8,         // In percent
+    pub muted: bool,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            board_theme: 0,
+            piece_set: 0,
+            volume: 100,
+            muted: false,
+        }
+    }
+}
+impl Settings {
+    pub fn board_theme(&self) -> BoardTheme {
+        BOARD_THEMES[self.board_theme]
+    }
+    pub fn piece_set(&self) -> &'static str {
+        PIECE_SETS[self.piece_set]
+    }
+    // The volume from 0 to 1, as sounds are played at
+    pub fn volume(&self) -> f32 {
+        f32::from(self.volume) / 100.0
+    }
+    // Moves the volume `step` notches of VOLUME_STEP along the slider, stopping at either end
+    pub fn adjust_volume(&mut self, step: i32) {
+        self.volume = (i32::from(self.volume) + step * i32::from(VOLUME_STEP)).clamp(0, 100) as u8;
+    }
+    // The settings saved at `path`, or the defaults if there aren't any yet
+    pub fn load(path: &PathBuf) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        if !path.exists() {
+            return Ok(settings);
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {err}", path.display()))?;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let Some((name, value)) = line.trim().split_once(' ') else {
+                return Err(format!("{} has a bad line: {line}", path.display()));
+            };
+            match name {
+                "board" => {
+                    settings.board_theme = BOARD_THEMES
+                        .iter()
+                        .position(|theme| theme.name == value)
+                        .ok_or_else(|| format!("There's no {value} board theme"))?
+                }
+                "pieces" => {
+                    settings.piece_set = PIECE_SETS
+                        .iter()
+                        .position(|set| *set == value)
+                        .ok_or_else(|| format!("There's no {value} piece set"))?
+                }
+                "volume" => {
+                    settings.volume = value
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|volume| *volume <= 100)
+                        .ok_or_else(|| format!("Bad volume {value}"))?
+                }
+                "sound" => settings.muted = value == "off",
+                _ => return Err(format!("{} has an unknown setting: {name}", path.display())),
+            }
+        }
+        Ok(settings)
+    }
+    pub fn save(&self, path: &PathBuf) -> Result<(), String> {
+        fs::write(
+            path,
+            format!(
+                "board {}\npieces {}\nvolume {}\nsound {}\n",
+                self.board_theme().name,
+                self.piece_set(),
+                self.volume,
+                if self.muted { "off" } else { "on" }
+            ),
+        )
+        .map_err(|err| format!("Couldn't save {}: {err}", path.display()))
+    }
+}
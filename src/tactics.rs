@@ -0,0 +1,144 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    bitboard::{BoardSize, PieceType, Team},
+    board::BoardState,
+    opponents::piece_value,
+    r#move::{compute_attacks, ray_directions, Piece},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Motif {
+    Fork,
+    Pin,
+    Skewer,
+    DiscoveredAttack,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tactic {
+    pub motif: Motif,
+    pub attacker: usize,        // The piece making the threat
+    pub targets: Vec<usize>,    // The enemy pieces it hits, nearest first
+    pub through: Option<usize>, // For discovered attacks, the piece that has to move out of the way
+}
+impl Tactic {
+    // The tactic written out, naming its squares by where they are on a `size` board
+    pub fn describe(&self, size: BoardSize) -> String {
+        let square_name = |square: usize| size.notation(square).unwrap_or_default();
+
+        let mut description = format!("{:?} from {}", self.motif, square_name(self.attacker));
+        if let Some(through) = self.through {
+            description.push_str(&format!(" past {}", square_name(through)));
+        }
+        let targets: Vec<String> = self
+            .targets
+            .iter()
+            .map(|target| square_name(*target))
+            .collect();
+        description.push_str(&format!(" on {}", targets.join(", ")));
+        description
+    }
+}
+impl Display for Tactic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.describe(BoardSize::Standard))
+    }
+}
+
+/*
+    Finds the forks, pins, skewers and discovered attacks `team` has on the board. A fork needs two targets that
+    are each worth more than the forking piece or left undefended; the line tactics need the piece further back to
+    be worth winning
+*/
+pub fn find_tactics(board: &BoardState, team: Team) -> Vec<Tactic> {
+    let enemy = team.opponent();
+    let mut tactics = Vec::new();
+
+    for square in 0..board.size.squares() {
+        if board.piece_list[square] == PieceType::None || board.get_square_team(square) != team {
+            continue;
+        }
+        let piece = Piece {
+            piece_type: board.piece_list[square],
+            team,
+            position: square,
+        };
+
+        if let Some(fork) = find_fork(board, piece, enemy) {
+            tactics.push(fork);
+        }
+        tactics.extend(find_line_tactics(board, piece, enemy));
+    }
+    tactics
+}
+
+fn is_worth_hitting(board: &BoardState, attacker: PieceType, target: usize, enemy: Team) -> bool {
+    let target_type = board.piece_list[target];
+
+    target_type == PieceType::King
+        || piece_value(target_type) > piece_value(attacker)
+        || !board.is_attacked_by(target, enemy)
+}
+
+fn find_fork(board: &BoardState, piece: Piece, enemy: Team) -> Option<Tactic> {
+    let hits = compute_attacks(board, piece) & board.get_team_coverage(enemy);
+    let targets: Vec<usize> = hits
+        .iter_ones()
+        .filter(|target| is_worth_hitting(board, piece.piece_type, *target, enemy))
+        .collect();
+
+    (targets.len() > 1).then_some(Tactic {
+        motif: Motif::Fork,
+        attacker: piece.position,
+        targets,
+        through: None,
+    })
+}
+
+// Pins, skewers and discovered attacks all come from a slider lined up with two pieces, with nothing else between
+fn find_line_tactics(board: &BoardState, piece: Piece, enemy: Team) -> Vec<Tactic> {
+    let occupied = board.get_team_coverage(Team::White) | board.get_team_coverage(Team::Black);
+    let mut tactics = Vec::new();
+
+    for direction in ray_directions(piece.piece_type) {
+        let ray = board.tables().rays[piece.position][direction] & board.get_team_coverage(enemy);
+
+        for back in ray.iter_ones() {
+            let blockers = board.tables().between[piece.position][back] & occupied;
+            if blockers.state.count_ones() != 1 {
+                continue;
+            }
+            let front = blockers.state.trailing_zeros() as usize;
+            let front_type = board.piece_list[front];
+            let back_type = board.piece_list[back];
+
+            let tactic = if board.get_square_team(front) == piece.team {
+                is_worth_hitting(board, piece.piece_type, back, enemy).then_some(Tactic {
+                    motif: Motif::DiscoveredAttack,
+                    attacker: piece.position,
+                    targets: vec![back],
+                    through: Some(front),
+                })
+            } else {
+                let motif = if front_type == PieceType::King
+                    || piece_value(front_type) > piece_value(back_type)
+                {
+                    Some(Motif::Skewer)
+                } else if piece_value(back_type) > piece_value(front_type) {
+                    Some(Motif::Pin)
+                } else {
+                    None
+                };
+                motif.map(|motif| Tactic {
+                    motif,
+                    attacker: piece.position,
+                    targets: vec![front, back],
+                    through: None,
+                })
+            };
+            tactics.extend(tactic);
+        }
+    }
+    tactics
+}
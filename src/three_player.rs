@@ -0,0 +1,355 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    bitboard::{PieceType, Team},
+    pieces::definition,
+};
+
+/*
+    Three-player chess, on the cross-shaped board four-player chess is played on: fourteen squares a side with the
+    three by three corners cut away. White starts along the bottom, Red down the left and Black along the top, and
+    they move in that order. BoardState and the search are built around two sides, so this board keeps its own rules.
+    Pieces move as usual, pawns forward from their own side and promoting to a queen at the far edge, with no castling
+    or en passant. A team that's mated, or whose king gets taken because another team's move left it open, is out and
+    its pieces come off the board. The last team left wins
+*/
+pub const THREE_PLAYER_SIDE: usize = 14;
+pub const THREE_PLAYER_SQUARES: usize = THREE_PLAYER_SIDE * THREE_PLAYER_SIDE;
+const CUT_CORNER: usize = 3; // How far each missing corner reaches along both edges
+pub const TURN_ORDER: [Team; 3] = [Team::White, Team::Red, Team::Black];
+const BACK_RANK: [PieceType; 8] = [
+    PieceType::Rook,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Queen,
+    PieceType::King,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Rook,
+];
+const FILE_LETTERS: [char; THREE_PLAYER_SIDE] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n',
+];
+
+// Whether (file, rank) is on the board, which is every square but the cut away corners
+pub fn on_board(file: i32, rank: i32) -> bool {
+    let side = THREE_PLAYER_SIDE as i32;
+    let corner = |at: i32| at < CUT_CORNER as i32 || at >= side - CUT_CORNER as i32;
+    (0..side).contains(&file) && (0..side).contains(&rank) && !(corner(file) && corner(rank))
+}
+pub const fn square(file: usize, rank: usize) -> usize {
+    rank * THREE_PLAYER_SIDE + file
+}
+pub const fn file_rank(square: usize) -> (usize, usize) {
+    (square % THREE_PLAYER_SIDE, square / THREE_PLAYER_SIDE)
+}
+// `square` moved by a step of (files, ranks), unless that leaves the board
+fn offset(square: usize, (files, ranks): (i32, i32)) -> Option<usize> {
+    let (file, rank) = file_rank(square);
+    let (file, rank) = (file as i32 + files, rank as i32 + ranks);
+    on_board(file, rank).then(|| self::square(file as usize, rank as usize))
+}
+// Squares are named like the usual board's, with files from a to n and ranks from 1 to 14
+pub fn notation(square: usize) -> String {
+    let (file, rank) = file_rank(square);
+    format!("{}{}", FILE_LETTERS[file], rank + 1)
+}
+pub fn from_notation(name: &str) -> Option<usize> {
+    let mut chars = name.chars();
+    let letter = chars.next()?;
+    let file = FILE_LETTERS.iter().position(|file| *file == letter)?;
+    let rank = chars.as_str().parse::<usize>().ok()?.checked_sub(1)?;
+    on_board(file as i32, rank as i32).then(|| square(file, rank))
+}
+// The way a team's pawns move, which is away from the edge it starts on
+pub fn forward(team: Team) -> (i32, i32) {
+    match team {
+        Team::Red => (1, 0),
+        Team::Black => (0, -1),
+        _ => (0, 1),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ThreePlayerMove {
+    pub start: usize,
+    pub target: usize,
+    pub promotes: bool, // Always to a queen
+}
+impl Display for ThreePlayerMove {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}{}", notation(self.start), notation(self.target))?;
+        if self.promotes {
+            write!(f, "q")?;
+        }
+        Ok(())
+    }
+}
+
+// How a team went out of the game
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Elimination {
+    Checkmated,
+    Stalemated,
+    KingTaken,
+}
+impl Display for Elimination {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Checkmated => write!(f, "checkmated"),
+            Self::Stalemated => write!(f, "stalemated"),
+            Self::KingTaken => write!(f, "lost its king"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ThreePlayerStatus {
+    Ongoing,
+    Won(Team),
+    Drawn, // Stalemate with only two teams left, which is a draw like it is in the usual game
+}
+impl Display for ThreePlayerStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Ongoing => write!(f, "Ongoing"),
+            Self::Won(team) => write!(f, "{team:?} wins"),
+            Self::Drawn => write!(f, "Draw by stalemate"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreePlayerBoard {
+    pub squares: [Option<(Team, PieceType)>; THREE_PLAYER_SQUARES],
+    pub to_move: Team,
+    pub eliminated: Vec<(Team, Elimination)>, // In the order they went out
+    pub status: ThreePlayerStatus,
+}
+impl Default for ThreePlayerBoard {
+    fn default() -> Self {
+        ThreePlayerBoard::new()
+    }
+}
+impl ThreePlayerBoard {
+    // No pieces at all, with white to move, for setting positions up a piece at a time
+    pub fn empty() -> Self {
+        ThreePlayerBoard {
+            squares: [None; THREE_PLAYER_SQUARES],
+            to_move: Team::White,
+            eliminated: Vec::new(),
+            status: ThreePlayerStatus::Ongoing,
+        }
+    }
+    /*
+        Each army lined up along its own edge between the cut corners. White and black face each other queen to
+        queen like in the usual game, and red has its queen on its king's left as it sees it, like white does
+    */
+    pub fn new() -> Self {
+        let mut board = ThreePlayerBoard::empty();
+        let last = THREE_PLAYER_SIDE - 1;
+        for (index, &piece_type) in BACK_RANK.iter().enumerate() {
+            let along = CUT_CORNER + index;
+            board.squares[square(along, 0)] = Some((Team::White, piece_type));
+            board.squares[square(along, 1)] = Some((Team::White, PieceType::Pawn));
+            board.squares[square(along, last)] = Some((Team::Black, piece_type));
+            board.squares[square(along, last - 1)] = Some((Team::Black, PieceType::Pawn));
+            // Facing along the ranks, red's left is up the board
+            board.squares[square(0, last - along)] = Some((Team::Red, piece_type));
+            board.squares[square(1, along)] = Some((Team::Red, PieceType::Pawn));
+        }
+        board
+    }
+    pub fn piece_at(&self, square: usize) -> Option<(Team, PieceType)> {
+        self.squares.get(square).copied().flatten()
+    }
+    pub fn is_playing(&self, team: Team) -> bool {
+        TURN_ORDER.contains(&team) && self.eliminated.iter().all(|(out, _)| *out != team)
+    }
+    pub fn teams_left(&self) -> impl Iterator<Item = Team> + '_ {
+        TURN_ORDER.into_iter().filter(|team| self.is_playing(*team))
+    }
+    // Who moves after `team`, skipping anyone who's out
+    pub fn next_to_move(&self, team: Team) -> Team {
+        let at = TURN_ORDER
+            .iter()
+            .position(|turn| *turn == team)
+            .unwrap_or(0);
+        (1..=TURN_ORDER.len())
+            .map(|ahead| TURN_ORDER[(at + ahead) % TURN_ORDER.len()])
+            .find(|next| self.is_playing(*next))
+            .unwrap_or(team)
+    }
+    pub fn king_square(&self, team: Team) -> Option<usize> {
+        (0..THREE_PLAYER_SQUARES).find(|&at| self.piece_at(at) == Some((team, PieceType::King)))
+    }
+    // The squares the piece on `start` attacks, which for a pawn are the two diagonals ahead of it
+    fn attacks_from(&self, start: usize) -> Vec<usize> {
+        let Some((team, piece_type)) = self.piece_at(start) else {
+            return Vec::new();
+        };
+        if piece_type == PieceType::Pawn {
+            let (files, ranks) = forward(team);
+            // Sideways from forward, either way
+            return [
+                (files + ranks, ranks + files),
+                (files - ranks, ranks - files),
+            ]
+            .into_iter()
+            .filter_map(|step| offset(start, step))
+            .collect();
+        }
+        let piece = definition(piece_type);
+        let mut attacks: Vec<usize> = piece
+            .leaps
+            .iter()
+            .filter_map(|&step| offset(start, step))
+            .collect();
+        for &step in piece.rides {
+            let mut from = start;
+            while let Some(target) = offset(from, step) {
+                attacks.push(target);
+                if self.piece_at(target).is_some() {
+                    break;
+                }
+                from = target;
+            }
+        }
+        attacks
+    }
+    // Whether any team still playing other than `team` attacks `square`
+    pub fn attacked_by_others(&self, square: usize, team: Team) -> bool {
+        (0..THREE_PLAYER_SQUARES).any(|from| {
+            self.piece_at(from)
+                .is_some_and(|(owner, _)| owner != team && self.is_playing(owner))
+                && self.attacks_from(from).contains(&square)
+        })
+    }
+    pub fn in_check(&self, team: Team) -> bool {
+        self.king_square(team)
+            .is_some_and(|king| self.attacked_by_others(king, team))
+    }
+    // The moves of the piece on `start` without regard for its own king
+    fn psuedolegal_moves(&self, start: usize) -> Vec<ThreePlayerMove> {
+        let Some((team, piece_type)) = self.piece_at(start) else {
+            return Vec::new();
+        };
+        let promotes = |target: usize| {
+            piece_type == PieceType::Pawn && offset(target, forward(team)).is_none()
+        };
+        let to = |target: usize| ThreePlayerMove {
+            start,
+            target,
+            promotes: promotes(target),
+        };
+        let mut targets: Vec<usize> = self
+            .attacks_from(start)
+            .into_iter()
+            .filter(|&target| match self.piece_at(target) {
+                Some((owner, _)) => owner != team,
+                // Pawns only go diagonally to take something
+                None => piece_type != PieceType::Pawn,
+            })
+            .collect();
+        if piece_type == PieceType::Pawn {
+            // A pawn still on the line it started on can go two squares
+            let (file, rank) = file_rank(start);
+            let last = THREE_PLAYER_SIDE - 2;
+            let unmoved = match team {
+                Team::Red => file == 1,
+                Team::Black => rank == last,
+                _ => rank == 1,
+            };
+            let step = forward(team);
+            if let Some(ahead) = offset(start, step).filter(|at| self.piece_at(*at).is_none()) {
+                targets.push(ahead);
+                if let Some(further) =
+                    offset(ahead, step).filter(|at| unmoved && self.piece_at(*at).is_none())
+                {
+                    targets.push(further);
+                }
+            }
+        }
+        targets.into_iter().map(to).collect()
+    }
+    // The moves the team to move can make without leaving its king attacked
+    pub fn legal_moves(&self) -> Vec<ThreePlayerMove> {
+        if self.status != ThreePlayerStatus::Ongoing {
+            return Vec::new();
+        }
+        (0..THREE_PLAYER_SQUARES)
+            .filter(|&start| {
+                self.piece_at(start)
+                    .is_some_and(|(team, _)| team == self.to_move)
+            })
+            .flat_map(|start| self.psuedolegal_moves(start))
+            .filter(|legal| {
+                let mut after = self.clone();
+                after.move_piece(*legal);
+                !after.in_check(self.to_move)
+            })
+            .collect()
+    }
+    // Moves the piece without any of the rules that come after, and gives back what it took
+    fn move_piece(&mut self, played: ThreePlayerMove) -> Option<(Team, PieceType)> {
+        let moving = self.squares[played.start].take();
+        let taken = self.squares[played.target];
+        self.squares[played.target] = moving.map(|(team, piece_type)| {
+            (
+                team,
+                if played.promotes {
+                    PieceType::Queen
+                } else {
+                    piece_type
+                },
+            )
+        });
+        taken
+    }
+    // Takes a team out of the game, and its pieces off the board with it
+    fn eliminate(&mut self, team: Team, how: Elimination) {
+        for piece in &mut self.squares {
+            if piece.is_some_and(|(owner, _)| owner == team) {
+                *piece = None;
+            }
+        }
+        self.eliminated.push((team, how));
+    }
+    /*
+        Plays a legal move and hands the turn on. A king left open by someone else's move can be taken, which
+        puts its team out. Any team that then has no moves on its turn is out too, until a single team is left,
+        except that stalemating one of the last two teams draws
+    */
+    pub fn make_move(&mut self, played: ThreePlayerMove) -> Result<(), String> {
+        if !self.legal_moves().contains(&played) {
+            return Err(format!(
+                "{played} isn't a legal move for {:?}",
+                self.to_move
+            ));
+        }
+        if let Some((owner, PieceType::King)) = self.move_piece(played) {
+            self.eliminate(owner, Elimination::KingTaken);
+        }
+        loop {
+            let left: Vec<Team> = self.teams_left().collect();
+            if let [winner] = left[..] {
+                self.status = ThreePlayerStatus::Won(winner);
+                return Ok(());
+            }
+            self.to_move = self.next_to_move(self.to_move);
+            if !self.legal_moves().is_empty() {
+                return Ok(());
+            }
+            if !self.in_check(self.to_move) && left.len() == 2 {
+                self.status = ThreePlayerStatus::Drawn;
+                return Ok(());
+            }
+            let how = if self.in_check(self.to_move) {
+                Elimination::Checkmated
+            } else {
+                Elimination::Stalemated
+            };
+            self.eliminate(self.to_move, how);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    bitboard::Team,
+    board::BoardState,
+    r#move::Move,
+    repertoire::{Repertoire, RepertoireLine},
+};
+
+pub const REVIEW_FILE: &str = "opening_review.txt"; // Where what's due for review is kept between runs
+
+// A line played without a slip is next due this many days later, doubling each time after that
+const FIRST_REVIEW_DAYS: i64 = 1;
+const MAX_REVIEW_DAYS: i64 = 180;
+
+// When a line is next due, and how long the wait was last time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReviewCard {
+    pub interval_days: i64, // 0 for a line that was got wrong, which is due again straight away
+    pub due: DateTime<Utc>,
+}
+
+/*
+    The lines being learned and when each is due, in the spirit of flash cards. A line played without a slip waits
+    twice as long as last time before it comes round again, and one with a slip starts over. The file is one line
+    per card: `due interval key`, where the key is the line's moves
+*/
+#[derive(Debug, Clone)]
+pub struct ReviewSchedule {
+    pub path: PathBuf,
+    cards: HashMap<String, ReviewCard>,
+}
+impl ReviewSchedule {
+    // The schedule kept at `path`, or an empty one if there isn't one yet
+    pub fn load(path: PathBuf) -> Result<ReviewSchedule, String> {
+        let mut schedule = ReviewSchedule {
+            path,
+            cards: HashMap::new(),
+        };
+        if !schedule.path.exists() {
+            return Ok(schedule);
+        }
+        let text = fs::read_to_string(&schedule.path)
+            .map_err(|err| format!("Couldn't read {}: {err}", schedule.path.display()))?;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let mut words = line.splitn(3, ' ');
+            let (Some(due), Some(interval), Some(key)) = (words.next(), words.next(), words.next())
+            else {
+                return Err(format!(
+                    "{} has a bad line: {line}",
+                    schedule.path.display()
+                ));
+            };
+            let card = ReviewCard {
+                due: due
+                    .parse()
+                    .map_err(|err| format!("Bad review time {due}: {err}"))?,
+                interval_days: interval
+                    .parse()
+                    .map_err(|_| format!("Bad review interval {interval}"))?,
+            };
+            schedule.cards.insert(key.to_string(), card);
+        }
+        Ok(schedule)
+    }
+    pub fn save(&self) -> Result<(), String> {
+        let mut keys: Vec<&String> = self.cards.keys().collect();
+        keys.sort();
+        let text: String = keys
+            .into_iter()
+            .map(|key| {
+                let card = self.cards[key];
+                format!("{} {} {key}\n", card.due.to_rfc3339(), card.interval_days)
+            })
+            .collect();
+        fs::write(&self.path, text)
+            .map_err(|err| format!("Couldn't save {}: {err}", self.path.display()))
+    }
+    // When the line is due, which for one never played is any time now
+    pub fn due(&self, key: &str) -> Option<DateTime<Utc>> {
+        self.cards.get(key).map(|card| card.due)
+    }
+    // Puts the line back in the schedule after it's been played, passed or not
+    pub fn record(&mut self, key: &str, passed: bool, now: DateTime<Utc>) -> ReviewCard {
+        let interval_days = match self.cards.get(key) {
+            _ if !passed => 0,
+            Some(card) if card.interval_days > 0 => (card.interval_days * 2).min(MAX_REVIEW_DAYS),
+            _ => FIRST_REVIEW_DAYS,
+        };
+        let card = ReviewCard {
+            interval_days,
+            due: now + Duration::days(interval_days),
+        };
+        self.cards.insert(key.to_string(), card);
+        card
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrillStep {
+    Correct,
+    LineDone,
+    OtherLine, // Prepared, but the line being drilled goes another way here
+    Wrong,
+}
+
+// The line being drilled and how far into it the player has got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Drill {
+    pub line: usize, // Into the trainer's lines
+    pub ply: usize,
+    pub slips: usize, // Wrong moves and hints
+}
+
+/*
+    Drills a repertoire from one side. The lines come round as the schedule says they're due, the opponent's moves
+    are played from the line, and the player has to find theirs
+*/
+pub struct OpeningTrainer {
+    pub team: Team,
+    pub lines: Vec<RepertoireLine>, // Just those with a move for the player to find
+    pub schedule: ReviewSchedule,
+    pub drill: Option<Drill>,
+}
+impl OpeningTrainer {
+    pub fn new(
+        repertoire: &Repertoire,
+        team: Team,
+        schedule: ReviewSchedule,
+    ) -> Result<OpeningTrainer, String> {
+        let mut lines = Vec::new();
+        for line in &repertoire.lines {
+            let start = BoardState::from_fen(line.start_fen.clone())
+                .map_err(|err| format!("Invalid repertoire start ({err}): {}", line.start_fen))?;
+            // The player's moves are every other one, starting with the first if they're the side to move
+            let first_ply = usize::from(start.active_team != team);
+            if line.moves.len() > first_ply {
+                lines.push(line.clone());
+            }
+        }
+        if lines.is_empty() {
+            return Err(format!("The repertoire has no moves for {team:?}"));
+        }
+        Ok(OpeningTrainer {
+            team,
+            lines,
+            schedule,
+            drill: None,
+        })
+    }
+    // How many lines are due by `now`, counting those never played
+    pub fn due_count(&self, now: DateTime<Utc>) -> usize {
+        self.lines
+            .iter()
+            .filter(|line| self.schedule.due(&line.key()).is_none_or(|due| due <= now))
+            .count()
+    }
+    /*
+        Starts on the line that's been due longest, then lines never played in the order the file gives them. With
+        neither left, the one due soonest comes round early
+    */
+    pub fn next_line(&mut self, now: DateTime<Utc>) -> &RepertoireLine {
+        let line = (0..self.lines.len())
+            .min_by_key(|index| match self.schedule.due(&self.lines[*index].key()) {
+                Some(due) if due <= now => (0, due, *index),
+                None => (1, now, *index),
+                Some(due) => (2, due, *index),
+            })
+            .unwrap_or_default();
+        self.drill = Some(Drill {
+            line,
+            ply: 0,
+            slips: 0,
+        });
+        &self.lines[line]
+    }
+    // The move the line has next, whoever's it is
+    pub fn expected(&self) -> Option<Move> {
+        let drill = self.drill?;
+        self.lines[drill.line].moves.get(drill.ply).copied()
+    }
+    // The opponent's move from the line has been played
+    pub fn reply_played(&mut self) {
+        if let Some(drill) = self.drill.as_mut() {
+            drill.ply += 1;
+        }
+    }
+    /*
+        Checks the player's move on `board` against the line. Another prepared move from here isn't held against
+        them, but it's still not the line being drilled
+    */
+    pub fn try_move(
+        &mut self,
+        repertoire: &Repertoire,
+        board: &BoardState,
+        played: Move,
+    ) -> DrillStep {
+        let expected = self.expected();
+        let Some(drill) = self.drill.as_mut() else {
+            return DrillStep::Wrong;
+        };
+        if expected == Some(played) {
+            drill.ply += 1;
+            if drill.ply >= self.lines[drill.line].moves.len() {
+                DrillStep::LineDone
+            } else {
+                DrillStep::Correct
+            }
+        } else if repertoire.moves_for(board).contains(&played) {
+            DrillStep::OtherLine
+        } else {
+            drill.slips += 1;
+            DrillStep::Wrong
+        }
+    }
+    // The line's next move, which counts as a slip
+    pub fn hint(&mut self) -> Option<Move> {
+        let expected = self.expected()?;
+        self.drill.as_mut()?.slips += 1;
+        Some(expected)
+    }
+    pub fn is_line_done(&self) -> bool {
+        self.drill
+            .is_some_and(|drill| drill.ply >= self.lines[drill.line].moves.len())
+    }
+    // Schedules the finished line's next review and saves the schedule
+    pub fn finish_line(&mut self, now: DateTime<Utc>) -> Option<ReviewCard> {
+        let drill = self.drill?;
+        let card = self
+            .schedule
+            .record(&self.lines[drill.line].key(), drill.slips == 0, now);
+        if let Err(err) = self.schedule.save() {
+            eprintln!("{err}");
+        }
+        Some(card)
+    }
+}
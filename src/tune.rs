@@ -0,0 +1,191 @@
+use crate::{
+    bitboard::{PieceType, Team, PIECE_TYPES},
+    board::BoardState,
+    opponents::{
+        tapered::{placement_score_with, PlacementWeights, PLACEMENT_WEIGHTS},
+        team_material, SCORES,
+    },
+};
+
+// How far a weight is nudged each time the tuner tries it, in centipawns
+const TUNE_STEP: i32 = 5;
+// Range and resolution searched for the scaling that best turns the current evaluation into results
+const SCALING_RANGE: (f64, f64) = (0.1, 3.0);
+const SCALING_STEP: f64 = 0.05;
+
+// A position and how the game it came from ended, from 1 for a white win down to 0 for a black one
+#[derive(Debug, Clone)]
+pub struct TuningPosition {
+    pub board: BoardState,
+    pub result: f64,
+}
+
+/*
+    Reads one position a line: a FEN followed by the result, as `1-0`, `0-1`, `1/2-1/2` or a number from 0 to 1.
+    The result may be bracketed or quoted, and an EPD `c9` opcode in front of it is skipped. Blank lines and ones
+    starting with `#` are left out
+*/
+pub fn parse_positions(text: &str) -> Result<Vec<TuningPosition>, String> {
+    let mut positions = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (fen, result) = line
+            .rsplit_once(char::is_whitespace)
+            .ok_or_else(|| format!("Line {} has no result: {line}", number + 1))?;
+        let result = match result.trim_matches(|c| "[]\";".contains(c)) {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            "1/2-1/2" => 0.5,
+            score => score
+                .parse::<f64>()
+                .ok()
+                .filter(|score| (0.0..=1.0).contains(score))
+                .ok_or_else(|| format!("Line {} has an unknown result {result}", number + 1))?,
+        };
+
+        let mut fen = fen.trim().trim_end_matches(" c9").trim().to_string();
+        // EPD leaves out the move counters
+        if fen.split_whitespace().count() == 4 {
+            fen.push_str(" 0 1");
+        }
+        let board = BoardState::from_fen(fen.clone())
+            .map_err(|err| format!("Line {} has an invalid FEN ({err}): {fen}", number + 1))?;
+        positions.push(TuningPosition { board, result });
+    }
+    Ok(positions)
+}
+
+// Everything the tuner is allowed to change: what the pieces are worth and the placement tables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Weights {
+    pub piece_values: [(PieceType, i32); PIECE_TYPES],
+    pub placement: PlacementWeights,
+}
+impl Default for Weights {
+    // The weights the engine plays with now
+    fn default() -> Self {
+        Weights {
+            piece_values: SCORES,
+            placement: PLACEMENT_WEIGHTS,
+        }
+    }
+}
+impl Weights {
+    // Every weight in a fixed order. Empty squares and the king are worth nothing to tune
+    fn parameters(&mut self) -> Vec<&mut i32> {
+        self.piece_values
+            .iter_mut()
+            .filter(|(piece_type, _)| ![PieceType::None, PieceType::King].contains(piece_type))
+            .map(|(_, value)| value)
+            .chain(self.placement.king.iter_mut().flatten())
+            .chain(self.placement.pawn.iter_mut().flatten())
+            .chain(self.placement.passed_pawn.iter_mut().flatten())
+            .collect()
+    }
+    // The static evaluation with these weights, from white's point of view
+    pub fn evaluate(&self, board: &BoardState) -> i32 {
+        team_material(board, Team::White, &self.piece_values)
+            - team_material(board, Team::Black, &self.piece_values)
+            + placement_score_with(board, &self.placement)
+    }
+}
+
+// The expected result for white of a position evaluated at `eval`, where `scaling` sets how sure a lead makes it
+fn expected_result(eval: i32, scaling: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling * eval as f64 / 400.0))
+}
+// Mean squared difference between how the games ended and what the weights expected of them
+pub fn tuning_error(positions: &[TuningPosition], weights: &Weights, scaling: f64) -> f64 {
+    let total: f64 = positions
+        .iter()
+        .map(|position| {
+            (position.result - expected_result(weights.evaluate(&position.board), scaling)).powi(2)
+        })
+        .sum();
+    total / positions.len().max(1) as f64
+}
+// The scaling that fits the weights as they are best, so tuning starts out measuring the weights and not the scale
+pub fn fit_scaling(positions: &[TuningPosition], weights: &Weights) -> f64 {
+    let steps = ((SCALING_RANGE.1 - SCALING_RANGE.0) / SCALING_STEP).round() as usize;
+    (0..=steps)
+        .map(|step| SCALING_RANGE.0 + step as f64 * SCALING_STEP)
+        .min_by(|a, b| {
+            tuning_error(positions, weights, *a).total_cmp(&tuning_error(positions, weights, *b))
+        })
+        .unwrap_or(1.0)
+}
+
+/*
+    Texel's local search: each pass nudges every weight up and then down by TUNE_STEP, keeping whichever brings
+    the error down. Stops after `passes` passes or the first one that changes nothing, and returns the error left.
+    `progress` hears the error after every pass
+*/
+pub fn tune(
+    positions: &[TuningPosition],
+    weights: &mut Weights,
+    scaling: f64,
+    passes: usize,
+    progress: &mut dyn FnMut(usize, f64),
+) -> f64 {
+    let mut best_error = tuning_error(positions, weights, scaling);
+    for pass in 1..=passes {
+        let mut improved = false;
+        for index in 0..weights.parameters().len() {
+            for step in [TUNE_STEP, -TUNE_STEP] {
+                *weights.parameters()[index] += step;
+                let error = tuning_error(positions, weights, scaling);
+                if error < best_error {
+                    best_error = error;
+                    improved = true;
+                    break;
+                }
+                *weights.parameters()[index] -= step;
+            }
+        }
+        progress(pass, best_error);
+        if !improved {
+            break;
+        }
+    }
+    best_error
+}
+
+// A square table laid out like the ones in opponents/tapered.rs, eight squares a row
+fn write_table(name: &str, values: &[i32; 64]) -> String {
+    let rows: Vec<String> = values
+        .chunks(8)
+        .map(|row| {
+            let row: Vec<String> = row.iter().map(|value| format!("{value:>3}")).collect();
+            format!("    {},", row.join(", "))
+        })
+        .collect();
+    format!(
+        "#[rustfmt::skip]\nconst {name}: [i32; 64] = [\n{}\n];\n",
+        rows.join("\n")
+    )
+}
+// The weights as Rust constants to paste over the ones in opponents/mod.rs and opponents/tapered.rs
+pub fn write_constants(weights: &Weights) -> String {
+    let piece_values: Vec<String> = weights
+        .piece_values
+        .iter()
+        .map(|(piece_type, value)| format!("    (PieceType::{piece_type:?}, {value}),"))
+        .collect();
+    let mut constants = format!(
+        "pub const SCORES: [(PieceType, i32); PIECE_TYPES] = [\n{}\n];\n\n",
+        piece_values.join("\n")
+    );
+    let placement = &weights.placement;
+    constants.push_str(&write_table("KING_MIDGAME", &placement.king[0]));
+    constants.push_str(&write_table("KING_ENDGAME", &placement.king[1]));
+    constants.push_str(&write_table("PAWN_MIDGAME", &placement.pawn[0]));
+    constants.push_str(&write_table("PAWN_ENDGAME", &placement.pawn[1]));
+    constants.push_str(&format!(
+        "const PASSED_PAWN_MIDGAME: [i32; 8] = {:?};\nconst PASSED_PAWN_ENDGAME: [i32; 8] = {:?};\n",
+        placement.passed_pawn[0], placement.passed_pawn[1]
+    ));
+    constants
+}
@@ -1,12 +1,12 @@
 use std::collections::HashMap;
-use std::process;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Receiver;
+use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
-use bitvec::order::Lsb0;
-use bitvec::view::BitView;
-use ggez::audio::SoundSource;
-use ggez::audio::Source;
+use ggez::conf::FullscreenType;
 use ggez::event;
 use ggez::glam::*;
 use ggez::graphics::Canvas;
@@ -16,18 +16,50 @@ use ggez::graphics::Rect;
 use ggez::graphics::Text;
 use ggez::graphics::Transform;
 use ggez::graphics::{self, Color};
+use ggez::input::keyboard::KeyCode;
+use ggez::input::keyboard::KeyInput;
+use ggez::input::keyboard::KeyMods;
 use ggez::mint::Point2;
 use ggez::mint::Vector2;
 use ggez::GameError;
 use ggez::{Context, GameResult};
 
+use crate::analysis::{self, AnnotatedMove, MoveQuality};
+use crate::audio::{AudioManager, Sound, DEFAULT_SOUND_PACK};
+use crate::autosave::{opponent_spec, SavedClock, SavedGame, AUTOSAVE_FILE};
 use crate::bitboard::Bitboard;
+use crate::bitboard::BoardSize;
 use crate::bitboard::PieceType;
 use crate::bitboard::Team;
-use crate::bitboard::PIECE_TYPE_ARRAY;
 use crate::board::BoardState;
+use crate::board::FENErr;
+use crate::board::GameStatus;
+use crate::board::History;
+use crate::board::Variant;
+use crate::clipboard;
+use crate::clock::{format_clock, ChessClock, TimeControl};
+use crate::correspondence::{CorrespondenceGame, CORRESPONDENCE_SEARCH_DEPTH};
+use crate::dgt::{move_from_placement, placement_of, DgtBoard, Placement};
+use crate::ics::{IcsEvent, IcsSession, Style12};
+use crate::netplay::{NetEvent, NetMessage, NetSession};
+use crate::openings::{self, Opening};
+use crate::opponents::uci::UciTime;
 use crate::opponents::*;
-use crate::r#move::Move;
+use crate::pgn::{write_movetext, PgnGame};
+use crate::pieces::{definition, PIECE_DEFINITIONS};
+use crate::puzzle::{PuzzleSession, PuzzleStep};
+use crate::r#move::{explain_illegal_move, promotion_choices, IllegalMoveReason, Move};
+use crate::repertoire::Repertoire;
+use crate::report::{eval_graph_svg, html_report};
+use crate::settings::{Settings, BOARD_THEMES, PIECE_SETS, SETTINGS_FILE, VOLUME_STEP};
+use crate::tactics::find_tactics;
+use crate::three_player::{
+    file_rank, on_board, square, ThreePlayerBoard, ThreePlayerStatus, THREE_PLAYER_SIDE,
+    THREE_PLAYER_SQUARES,
+};
+use crate::trainer::{DrillStep, OpeningTrainer, ReviewCard};
+use crate::voice::{spoken_confirmation, spoken_moves, Confirmation, VoiceInput};
+use crate::START_POS_CHESS;
 use chrono::prelude::*;
 
 pub type ColorRGBA = [f32; 4];
@@ -36,12 +68,148 @@ const BLACK: ColorRGBA = [0.2, 0.2, 0.2, 1.0];
 const SELECTED_SQUARE_COLOR: ColorRGBA = [1.0, 1.0, 1.0, 1.0];
 const OLD_MOVE_COLOR: ColorRGBA = [1.0, 0.8, 0.25, 1.0];
 const LEGAL_MOVE_COLOR_LERP: f32 = 0.3;
-const LIGHT_SQUARE_COLOR: ColorRGBA = [0.941, 0.467, 0.467, 1.0];
-const DARK_SQUARE_COLOR: ColorRGBA = [0.651, 0.141, 0.141, 1.0];
 const WIDTH: f32 = 600.0;
 const SQUARE_SIZE: f32 = WIDTH / 8.0;
 const FLAG_DEBUG_UI_COORDS: bool = false;
+const PANEL_MARGIN: f32 = 10.0;
+const PANEL_WIDTH: f32 = 200.0 - (2.0 * PANEL_MARGIN);
+const PANEL_CHARS_PER_LINE: usize = 20;
+const PANEL_BUTTON_HEIGHT: f32 = 30.0;
+const PANEL_BUTTON_TOP: f32 = WIDTH - PANEL_MARGIN - PANEL_BUTTON_HEIGHT; // The buttons sit along the panel's bottom
+const PANEL_BUTTON_GAP: f32 = 5.0;
+const PANEL_BUTTON_COLOR: ColorRGBA = [0.35, 0.35, 0.35, 1.0];
+const PANEL_BUTTONS: [PanelButton; 3] = [
+    PanelButton::Undo,
+    PanelButton::OfferDraw,
+    PanelButton::Resign,
+];
+const MOVE_LIST_X: f32 = WIDTH + PANEL_WIDTH + (2.0 * PANEL_MARGIN); // The move list's column, right of the panel
+const MOVE_LIST_WIDTH: f32 = 200.0;
+const MOVE_LIST_LINE_HEIGHT: f32 = 20.0;
+const MOVE_LIST_WHITE_X: f32 = 45.0;
+const MOVE_LIST_BLACK_X: f32 = 110.0; // Where black's move sits along each line, after the number and white's
+const MOVE_LIST_HEADER_LINES: usize = 3; // Kept above the moves for the opening's name, which can wrap
+const MOVE_LIST_TOP: f32 = PANEL_MARGIN + (MOVE_LIST_HEADER_LINES as f32 * MOVE_LIST_LINE_HEIGHT);
+const MOVE_LIST_ROWS: usize =
+    ((WIDTH - (2.0 * PANEL_MARGIN)) / MOVE_LIST_LINE_HEIGHT) as usize - MOVE_LIST_HEADER_LINES;
+const VIEWED_MOVE_COLOR: ColorRGBA = [1.0, 0.8, 0.25, 1.0];
+const OPENING_NAME_COLOR: ColorRGBA = [0.7, 0.85, 1.0, 1.0];
+// How the move list picks out moves the post-game review marked down
+const INACCURACY_COLOR: ColorRGBA = [0.9, 0.9, 0.4, 1.0];
+const MISTAKE_COLOR: ColorRGBA = [1.0, 0.6, 0.2, 1.0];
+const BLUNDER_COLOR: ColorRGBA = [1.0, 0.3, 0.3, 1.0];
+const PREMOVE_COLOR: ColorRGBA = [0.3, 0.5, 1.0, 1.0];
+const CHECK_COLOR: ColorRGBA = [1.0, 0.0, 0.0, 1.0]; // Under the king of the side in check
+const RED_TEAM_TINT: ColorRGBA = [1.0, 0.35, 0.35, 1.0]; // Red has no piece art, so it's white's tinted this
+const BANNER_SHADE: ColorRGBA = [0.0, 0.0, 0.0, 0.7]; // Behind the result once the game is over
+const EVAL_GRAPH_LINE_COLOR: ColorRGBA = [1.0, 0.8, 0.25, 1.0];
+const EVAL_GRAPH_MIDDLE_COLOR: ColorRGBA = [0.5, 0.5, 0.5, 1.0]; // Where neither side is better
+pub const WINDOW_WIDTH: f32 = MOVE_LIST_X + MOVE_LIST_WIDTH;
+pub const WINDOW_HEIGHT: f32 = WIDTH;
+const THINKING_FRAME_INTERVAL: Duration = Duration::from_millis(150);
+const THINKING_SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+const LAST_MOVE_ARROW_COLOR: ColorRGBA = [0.1, 0.4, 0.9, 0.6]; // Points out the opponent's last move
+const GHOST_PIECE_ALPHA: f32 = 0.45; // How solid the expected reply's piece looks
+const PROMOTION_SHADE: ColorRGBA = [0.0, 0.0, 0.0, 0.5]; // Laid over the board while a promotion piece is picked
+const GAMES_DIRECTORY: &str = "games"; // Where each finished game is saved as PGN
+const LOW_TIME_WARNING: Duration = Duration::from_secs(10); // The player's clock sounds once on running down past this
+const REJECTION_SHOWN_FOR: Duration = Duration::from_secs(3); // How long the reason a move was refused stays up
+const REVIEW_SEARCH_BUDGET: i32 = 1; // How far past each move the post-game review looks
+const ANALYSIS_SEARCH_BUDGET: i32 = 2; // How far past each move the analysis board's engine lines look at first
+const ANALYSIS_MAX_BUDGET: i32 = 4; // How far they keep deepening to while the position stays put
+const ANALYSIS_LINES: MultiPv = MultiPv(3);
+const DEFAULT_MOVE_DELAY: Duration = Duration::from_millis(500); // Between moves of a game between two engines
+const MOVE_DELAY_STEP: Duration = Duration::from_millis(250);
+const MAX_MOVE_DELAY: Duration = Duration::from_secs(5);
+const TIME_CONTROL_PRESETS: [&str; 6] = ["1+0", "3+2", "5+0", "10+5", "15+10", "30+0"]; // Offered on the setup menu
+const SETUP_ROWS: usize = 5;
+const SETTINGS_ROWS: usize = 4;
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5); // How often a game in progress is written out
 
+/*
+    Everything is laid out at WINDOW_WIDTH by WINDOW_HEIGHT and scaled to fit the window, keeping its shape by
+    leaving bars down the sides or along the top and bottom. This is the part of the layout a window of `width` by
+    `height` shows, which takes in the bars
+*/
+pub fn layout_view(width: f32, height: f32) -> Rect {
+    // A minimised window has no size to fit to
+    if width < 1.0 || height < 1.0 {
+        return Rect::new(0.0, 0.0, WINDOW_WIDTH, WINDOW_HEIGHT);
+    }
+    let scale = (width / WINDOW_WIDTH).min(height / WINDOW_HEIGHT);
+    let (view_width, view_height) = (width / scale, height / scale);
+    Rect::new(
+        (WINDOW_WIDTH - view_width) / 2.0,
+        (WINDOW_HEIGHT - view_height) / 2.0,
+        view_width,
+        view_height,
+    )
+}
+// The side panel's buttons, which do the same as their keys for a player who'd rather click
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PanelButton {
+    Undo,
+    OfferDraw,
+    Resign,
+}
+impl PanelButton {
+    fn label(self) -> &'static str {
+        match self {
+            PanelButton::Undo => "Undo",
+            PanelButton::OfferDraw => "Draw",
+            PanelButton::Resign => "Resign",
+        }
+    }
+}
+// Where each of the panel's buttons is drawn, side by side and sharing the panel's width
+fn panel_button_rects() -> impl Iterator<Item = (PanelButton, Rect)> {
+    let count = PANEL_BUTTONS.len() as f32;
+    let width = (PANEL_WIDTH - (count - 1.0) * PANEL_BUTTON_GAP) / count;
+    PANEL_BUTTONS
+        .into_iter()
+        .enumerate()
+        .map(move |(index, button)| {
+            let x = WIDTH + PANEL_MARGIN + index as f32 * (width + PANEL_BUTTON_GAP);
+            (
+                button,
+                Rect::new(x, PANEL_BUTTON_TOP, width, PANEL_BUTTON_HEIGHT),
+            )
+        })
+}
+// The panel button at `x`, `y` in the layout, if there's one there
+pub fn panel_button_at(x: f32, y: f32) -> Option<PanelButton> {
+    panel_button_rects()
+        .find(|(_, rect)| rect.contains(Point2 { x, y }))
+        .map(|(button, _)| button)
+}
+// Where the point `x`, `y` on a window of `width` by `height` is in the layout
+pub fn to_layout(width: f32, height: f32, x: f32, y: f32) -> (f32, f32) {
+    let view = layout_view(width, height);
+    (
+        view.x + (x * view.w / width.max(1.0)),
+        view.y + (y * view.h / height.max(1.0)),
+    )
+}
+
+// A search score, which is from the side to move's point of view, turned round to white's
+fn white_score(score: i32, mover: Team) -> i32 {
+    if mover == Team::White {
+        score
+    } else {
+        -score
+    }
+}
+// What the player is told once a drilled line is through, going by when it's due again
+fn review_note(card: Option<ReviewCard>) -> String {
+    match card {
+        Some(card) if card.interval_days > 0 => format!(
+            "Line done, next review in {} day{}",
+            card.interval_days,
+            if card.interval_days == 1 { "" } else { "s" }
+        ),
+        _ => String::from("Line done with slips, so it's due again now"),
+    }
+}
 pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
     a + (b - a) * t
 }
@@ -54,218 +222,2130 @@ pub fn color_lerp(left: Color, right: Color, t: f32) -> Color {
     ])
 }
 
-#[derive(Clone, Copy)]
+// What the opponent's search task sends back to the UI
+pub enum OpponentMessage {
+    Progress(SearchInfo),
+    Finished(SearchOutcome),
+}
+
+// The opponent thinking on the player's time about the position after the reply it expects
+pub struct Ponder {
+    pub expected: Move,
+    pub stop: Arc<AtomicBool>,
+    pub outcome: Receiver<Option<SearchOutcome>>,
+}
+impl Drop for Ponder {
+    // Nobody is waiting on a ponder that's been let go, so its search can stop
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// A single line of typed text
+#[derive(Default)]
+pub struct TextInput {
+    pub text: String,
+}
+impl TextInput {
+    pub fn push_char(&mut self, character: char) {
+        // Keys like backspace and enter also arrive as characters, those are handled as key presses instead
+        if !character.is_control() {
+            self.text.push(character);
+        }
+    }
+    pub fn push_str(&mut self, text: &str) {
+        text.chars().for_each(|character| self.push_char(character));
+    }
+    pub fn backspace(&mut self) {
+        self.text.pop();
+    }
+    pub fn clear(&mut self) {
+        self.text.clear();
+    }
+    // Long words don't wrap on their own, so break the text up to fit in the side panel
+    pub fn wrapped(&self, width: usize) -> String {
+        self.text
+            .chars()
+            .collect::<Vec<char>>()
+            .chunks(width)
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// The set-up-position dialog, which previews the pasted FEN on the board as it is typed
+pub struct FenDialog {
+    pub input: TextInput,
+    pub preview: Result<BoardState, FENErr>,
+    pub variant: Variant, // The rules the position is checked against, which are the current game's
+}
+impl FenDialog {
+    pub fn new(variant: Variant) -> FenDialog {
+        let mut dialog = FenDialog {
+            input: TextInput::default(),
+            preview: Err(FENErr::IncompleteBoard),
+            variant,
+        };
+        dialog.update_preview();
+        dialog
+    }
+    pub fn update_preview(&mut self) {
+        self.preview = BoardState::from_fen(self.input.text.trim().to_string())
+            .map(|board| board.with_variant(self.variant))
+            .and_then(|board| board.playable().map(|_| board));
+    }
+    pub fn paste(&mut self) {
+        if let Some(pasted) = clipboard::paste() {
+            self.input.push_str(pasted.trim());
+            self.update_preview();
+        }
+    }
+}
+
+/*
+    What's on screen: the setup menu before a game, the board once one is being played, or the settings over it.
+    On launch, a game left unfinished last time is offered back before the setup menu
+*/
+pub enum Scene {
+    Setup(GameSetup),
+    Resume(SavedGame),
+    Settings(SettingsMenu),
+    ThreePlayer(Box<ThreePlayerGame>), // Boxed, the board being much bigger than the other scenes
+    Playing,
+}
+
+// A three-player game on its own board, played by clicking a piece and then where it goes
+pub struct ThreePlayerGame {
+    pub board: ThreePlayerBoard,
+    pub selected: Option<usize>,
+}
+impl ThreePlayerGame {
+    pub fn new() -> Self {
+        ThreePlayerGame {
+            board: ThreePlayerBoard::new(),
+            selected: None,
+        }
+    }
+    // Plays the picked up piece to `clicked` if it can go there, otherwise picks up whatever of the mover's is there
+    pub fn click(&mut self, clicked: usize) {
+        if self.board.status != ThreePlayerStatus::Ongoing {
+            return;
+        }
+        let chosen = self.selected.and_then(|start| {
+            self.board
+                .legal_moves()
+                .into_iter()
+                .find(|legal| legal.start == start && legal.target == clicked)
+        });
+        self.selected = None;
+        match chosen {
+            Some(chosen) => self
+                .board
+                .make_move(chosen)
+                .expect("Legal moves can always be made"),
+            None => {
+                if self
+                    .board
+                    .piece_at(clicked)
+                    .is_some_and(|(team, _)| team == self.board.to_move)
+                {
+                    self.selected = Some(clicked);
+                }
+            }
+        }
+    }
+    pub fn panel_text(&self) -> String {
+        let mut lines = vec![String::from("Three-player chess"), String::new()];
+        let mover = self.board.to_move;
+        match self.board.status {
+            ThreePlayerStatus::Ongoing if self.board.in_check(mover) => {
+                lines.push(format!("{mover:?} to move, in check"))
+            }
+            ThreePlayerStatus::Ongoing => lines.push(format!("{mover:?} to move")),
+            over => lines.push(over.to_string()),
+        }
+        for (team, how) in &self.board.eliminated {
+            lines.push(format!("{team:?} {how}"));
+        }
+        lines.push(String::from("\nN: new game\nEsc: setup menu"));
+        lines.join("\n")
+    }
+}
+impl Default for ThreePlayerGame {
+    fn default() -> Self {
+        ThreePlayerGame::new()
+    }
+}
+
+/*
+    The choices on the setup menu. Up and down pick a row, left and right change what's on it,
+    and Enter starts the game with them
+*/
+pub struct GameSetup {
+    pub colour: Option<Team>, // None picks a side at random when the game starts
+    pub opponent: ChessOpponent,
+    pub time_control: Option<TimeControl>, // None plays without a clock
+    pub bot_time_control: Option<TimeControl>, // Odds given with `--bot-time`, which the menu has no row for
+    pub variant: Variant,
+    pub row: usize,
+}
+impl GameSetup {
+    // The step after (or before, for a negative `step`) `current` in `choices`, wrapping round at either end
+    fn cycle<T: PartialEq + Copy>(choices: &[T], current: T, step: i32) -> T {
+        let index = choices
+            .iter()
+            .position(|choice| *choice == current)
+            .unwrap_or(0) as i32;
+        choices[(index + step).rem_euclid(choices.len() as i32) as usize]
+    }
+    pub fn change(&mut self, step: i32) {
+        match self.row {
+            0 => {
+                self.colour = GameSetup::cycle(
+                    &[None, Some(Team::White), Some(Team::Black)],
+                    self.colour,
+                    step,
+                )
+            }
+            1 => self.opponent = self.opponent.next_kind(),
+            2 => self.opponent.adjust_strength(step),
+            3 => {
+                let choices: Vec<Option<TimeControl>> = std::iter::once(None)
+                    .chain(
+                        TIME_CONTROL_PRESETS
+                            .iter()
+                            .map(|text| TimeControl::parse(text)),
+                    )
+                    .collect();
+                self.time_control = GameSetup::cycle(&choices, self.time_control, step);
+            }
+            _ => self.variant = GameSetup::cycle(&Variant::ALL, self.variant, step),
+        }
+    }
+    pub fn menu_text(&self) -> String {
+        let kind = match &self.opponent {
+            ChessOpponent::Randy => String::from("Randy"),
+            ChessOpponent::Matt(_) => String::from("Matt"),
+            ChessOpponent::Ada(..) => String::from("Ada"),
+            ChessOpponent::Human => String::from("Human (hot seat)"),
+            uci => uci.to_string(),
+        };
+        let time = match (self.time_control, self.bot_time_control) {
+            (Some(player), Some(bot)) if player != bot => format!("{player} (bot {bot})"),
+            (Some(player), _) => player.to_string(),
+            (None, Some(bot)) => format!("bot {bot}"),
+            (None, None) => String::from("Untimed"),
+        };
+        let rows = [
+            (
+                "Play as",
+                self.colour
+                    .map_or(String::from("Random"), |team| format!("{team:?}")),
+            ),
+            ("Opponent", kind),
+            ("Strength", self.opponent.to_string()),
+            ("Time control", time),
+            ("Variant", self.variant.to_string()),
+        ];
+        let lines: Vec<String> = rows
+            .iter()
+            .enumerate()
+            .map(|(row, (name, value))| {
+                let cursor = if row == self.row { ">" } else { " " };
+                format!("{cursor} {name}: < {value} >")
+            })
+            .collect();
+        format!(
+            "New game\n\n{}\n\nUp/Down: choose\nLeft/Right: change\nEnter: start\n3: three-player chess\nEsc: keep playing",
+            lines.join("\n")
+        )
+    }
+}
+
+/*
+    The settings menu. Changes show on the board behind it straight away, Enter keeps them and Esc puts back
+    what there was before the menu opened
+*/
+pub struct SettingsMenu {
+    pub saved: Settings,
+    pub row: usize,
+}
+impl SettingsMenu {
+    pub fn menu_text(&self, settings: &Settings) -> String {
+        // The volume as a slider of one mark per step
+        let marks = usize::from(settings.volume / VOLUME_STEP);
+        let slider = format!(
+            "{}{} {}%",
+            "#".repeat(marks),
+            "-".repeat(usize::from(100 / VOLUME_STEP) - marks),
+            settings.volume
+        );
+        let rows = [
+            ("Board", String::from(settings.board_theme().name)),
+            ("Pieces", String::from(settings.piece_set())),
+            ("Volume", slider),
+            (
+                "Sound",
+                String::from(if settings.muted { "off" } else { "on" }),
+            ),
+        ];
+        let lines: Vec<String> = rows
+            .iter()
+            .enumerate()
+            .map(|(row, (name, value))| {
+                let cursor = if row == self.row { ">" } else { " " };
+                format!("{cursor} {name}: < {value} >")
+            })
+            .collect();
+        format!(
+            "Settings\n\n{}\n\nUp/Down: choose\nLeft/Right: change\nEnter: keep\nEsc: cancel",
+            lines.join("\n")
+        )
+    }
+}
+
+// The typed move box, so a game can be played without the mouse
+#[derive(Default)]
+pub struct MoveEntry {
+    pub input: TextInput,
+    pub rejection: Option<String>, // Why the last submitted move wasn't played
+}
+
+// A game read from PGN, stepped through on the analysis board with the arrow keys
+pub struct Replay {
+    pub game: PgnGame,
+    pub moves: Vec<Move>, // Its mainline, checked against the positions the moves are played in
+}
+
+// A move of the game, with what the post-game review made of it
+#[derive(Clone)]
 pub struct MoveHistoryEntry {
-    piece_type: PieceType,
     team: Team,
-    captures: bool,
-    checks: bool,
-    mate: bool,
-    target: usize,
-    start: usize,
-    castle: bool,
-}
-impl MoveHistoryEntry {
-    pub fn to_string(self) -> String {
-        // TODO: Piece disambiguation
-
-        let piece_id = match self.piece_type {
-            PieceType::None => "",
-            PieceType::Pawn => "",
-            PieceType::Knight => "n",
-            PieceType::Queen => "q",
-            PieceType::King => "k",
-            PieceType::Rook => "r",
-            PieceType::Bishop => "b",
-        }
-        .to_uppercase();
-
-        let capture_string = if self.captures { "x" } else { "" };
-        let file_array = ["a", "b", "c", "d", "e", "f", "g", "h"];
-        let target_file = file_array[self.target % 8];
-        let target_rank = (((self.target / 8) as i32) + 1).to_string();
-        let append_string = if self.mate {
-            "#"
-        } else if self.checks {
-            "+"
+    played: Move,
+    review: Option<AnnotatedMove>, // Filled in by the post-game review
+}
+
+/*
+    Everything that makes up the game being played, as opposed to how it is being shown.
+    The opponent lives here so it can be swapped between moves without losing the game so far
+*/
+pub struct Game {
+    pub board: BoardState,
+    pub start_board: BoardState,
+    pub history: History, // The moves that led from start_board to board
+    pub player_team: Team,
+    pub opponent: ChessOpponent,
+    pub spectated: Option<ChessOpponent>, // Plays the player's side instead, so the player only watches
+    pub move_history: Vec<MoveHistoryEntry>, // for PGN
+    pub game_log: Vec<String>,
+    pub engine_stats: SearchStats,
+    pub opponent_scores: Vec<i32>, // What each of the opponent's searches made of the position, from its side
+    pub clock: Option<ChessClock>,
+}
+impl Game {
+    pub fn new(board: BoardState, player_team: Team, opponent: ChessOpponent) -> Game {
+        Game {
+            start_board: board,
+            board,
+            history: History::default(),
+            player_team,
+            opponent,
+            spectated: None,
+            move_history: Vec::new(),
+            game_log: Vec::new(),
+            engine_stats: SearchStats::default(),
+            opponent_scores: Vec::new(),
+            clock: None,
+        }
+    }
+    // How the game stands, with a flag falling on the clock ending it like anything on the board does
+    pub fn status(&self) -> GameStatus {
+        match self.clock.as_ref().and_then(ChessClock::flagged) {
+            Some(flagged) => self.board.timeout_status(flagged),
+            None => self.history.status(&self.board),
+        }
+    }
+    // The opponent whose move it is, which when watching two engines can be the one on the player's side
+    pub fn engine_to_move(&self) -> &ChessOpponent {
+        match &self.spectated {
+            Some(engine) if self.board.active_team == self.player_team => engine,
+            _ => &self.opponent,
+        }
+    }
+    // The game so far as it's autosaved, to be picked up again where it was left
+    pub fn to_saved(&self) -> SavedGame {
+        SavedGame {
+            player_team: self.player_team,
+            opponent: opponent_spec(&self.opponent),
+            variant: self.board.variant,
+            start_fen: self.start_board.as_fen(),
+            moves: self
+                .history
+                .moves_played()
+                .map(|played| played.to_uci_on(self.board.size))
+                .collect(),
+            clock: self.clock.as_ref().map(SavedClock::of),
+            saved_at: Utc::now(),
+        }
+    }
+    /*
+        The PGN tags for this game once it has ended with `result`. `termination` is how it ended, in PGN's words,
+        like "normal" or "time forfeit"
+    */
+    pub fn tags(&self, result: &str, termination: &str) -> Vec<(String, String)> {
+        let current_date = Utc::now().format("%Y.%m.%d").to_string();
+        let bot_name = format!("Bot {}", self.opponent);
+        let player_name = self
+            .spectated
+            .as_ref()
+            .map_or(String::from("Player"), |engine| format!("Bot {engine}"));
+
+        let white_name = if self.player_team == Team::White {
+            &player_name
+        } else {
+            &bot_name
+        };
+        let black_name = if self.player_team != Team::White {
+            &player_name
+        } else {
+            &bot_name
+        };
+
+        let mut tags: Vec<(String, String)> = [
+            ("Event", "chess-r match"),
+            ("Site", "chess-r"),
+            ("Date", &current_date),
+            ("Round", "1"),
+            ("White", white_name),
+            ("Black", black_name),
+            ("Result", result),
+            ("Termination", termination),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        // Games that didn't start from the usual position say where they did
+        let fen = self.start_board.as_fen();
+        if fen != START_POS_CHESS {
+            tags.push((String::from("SetUp"), String::from("1")));
+            tags.push((String::from("FEN"), fen));
+        }
+        if self.start_board.variant != Variant::Standard {
+            tags.push((
+                String::from("Variant"),
+                self.start_board.variant.to_string(),
+            ));
+        }
+        if let Some(opening) = self.opening() {
+            tags.push((String::from("ECO"), opening.eco.to_string()));
+            tags.push((String::from("Opening"), opening.name.to_string()));
+        }
+        tags
+    }
+    // The named opening the game went into, if it's one that's known
+    pub fn opening(&self) -> Option<&'static Opening> {
+        openings::identify(&self.start_board, self.history.moves_played())
+    }
+    /*
+        The game so far as PGN. Each move the review went over gets its grade as a NAG and a comment with the eval
+        from white's point of view, along with the line the engine preferred for anything worse than good
+    */
+    pub fn to_pgn(&self, result: &str, termination: &str) -> String {
+        let header: String = self
+            .tags(result, termination)
+            .iter()
+            .map(|(name, value)| format!("[{name} \"{value}\"]\n"))
+            .collect();
+
+        let annotated: Vec<String> = self
+            .san_moves()
+            .into_iter()
+            .enumerate()
+            .map(|(ply, san)| {
+                let Some(review) = self
+                    .move_history
+                    .get(ply)
+                    .and_then(|entry| entry.review.as_ref())
+                else {
+                    return san;
+                };
+                let mut entry = san;
+                if let Some(nag) = review.quality.nag() {
+                    entry.push_str(&format!(" ${nag}"));
+                }
+                entry.push_str(&format!(" {{{:+.2}", review.eval as f32 / 100.0));
+                if let Some(line) = &review.better_line {
+                    entry.push_str(&format!(
+                        " {}, better was {}",
+                        review.quality,
+                        line.join(" ")
+                    ));
+                }
+                entry.push('}');
+                entry
+            })
+            .collect();
+
+        format!(
+            "{header}\n{}",
+            write_movetext(analysis::first_ply(&self.start_board), &annotated, result)
+        )
+    }
+    // The moves played so far in SAN
+    pub fn san_moves(&self) -> Vec<String> {
+        let mut board = self.start_board;
+        let mut sans = Vec::new();
+        for played in self.history.moves_played() {
+            sans.push(played.to_san(&board));
+            if board.make_move(played).is_err() {
+                break;
+            }
+        }
+        sans
+    }
+    // The position after the game's first `ply` moves
+    pub fn board_at(&self, ply: usize) -> BoardState {
+        let mut board = self.start_board;
+        for played in self.history.moves_played().take(ply) {
+            if board.make_move(played).is_err() {
+                break;
+            }
+        }
+        board
+    }
+    // Writes the game's PGN to a new file in `directory`, named for when it was saved
+    pub fn save_pgn(
+        &self,
+        directory: &Path,
+        result: &str,
+        termination: &str,
+    ) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(directory)
+            .map_err(|err| format!("Couldn't create {}: {err}", directory.display()))?;
+        let path = directory.join(format!(
+            "chess-r-{}.pgn",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        std::fs::write(&path, self.to_pgn(result, termination))
+            .map_err(|err| format!("Couldn't write {}: {err}", path.display()))?;
+        Ok(path)
+    }
+    /*
+        Grades every move of the game, both sides', against a shallow search, so the move list and PGN can be
+        annotated. Returns the graded moves in order
+    */
+    pub fn review(&mut self) -> Result<Vec<AnnotatedMove>, String> {
+        let moves: Vec<Move> = self.move_history.iter().map(|entry| entry.played).collect();
+        let analysed = analysis::analyse_moves(self.start_board, &moves, REVIEW_SEARCH_BUDGET)?;
+        for (entry, analysed_move) in self.move_history.iter_mut().zip(&analysed) {
+            entry.review = Some(analysed_move.clone());
+        }
+        Ok(analysed)
+    }
+    // How many of `team`'s reviewed moves got each grade, in the order of `MoveQuality`
+    pub fn review_tally(&self, team: Team) -> Vec<(MoveQuality, usize)> {
+        let mut tally: Vec<(MoveQuality, usize)> = Vec::new();
+        let graded = self
+            .move_history
+            .iter()
+            .filter(|entry| entry.team == team)
+            .filter_map(|entry| entry.review.as_ref());
+        for review in graded {
+            match tally
+                .iter_mut()
+                .find(|(quality, _)| *quality == review.quality)
+            {
+                Some((_, count)) => *count += 1,
+                None => tally.push((review.quality, 1)),
+            }
+        }
+        tally.sort_by_key(|(quality, _)| *quality as usize);
+        tally
+    }
+    pub fn print_game_log(&self) {
+        if !self.game_log.is_empty() {
+            println!("{}", self.game_log.join("\n"));
+        }
+    }
+}
+
+pub struct MainState {
+    pub game: Game,
+    pub piece_imgs: HashMap<String, Image>,
+    pub audio: AudioManager,
+    pub low_time_warned: [bool; 2], // For white and black, so the warning sounds only once a game
+    pub settings: Settings,
+    pub settings_path: PathBuf,
+    pub autosave_path: PathBuf,
+    pub last_autosave: Option<Instant>, // None until the game in progress has been written out
+    pub selected_square: Option<usize>,
+    pub queued_move: Option<Move>, // Moves are queued to the draw queue so nothing changes during drawing
+    pub promotion_choice: Option<(usize, usize)>, // A pawn move to the last rank, waiting for the player to pick a piece
+    pub auto_queen: bool,                         // Promote straight to a queen without asking
+    pub drag_x: Option<f32>,
+    pub drag_y: Option<f32>,
+    pub board_legal_moves: Option<Vec<(Bitboard, Vec<Move>)>>,
+    pub last_move_origin: Option<usize>,
+    pub last_move_end: Option<usize>,
+    pub opp_thread: Option<Receiver<OpponentMessage>>,
+    pub search_started: Option<Instant>, // When the opponent began thinking about its current move
+    pub explain_search: bool,            // Ask the opponent to explain each of its moves
+    pub show_tactics: bool,              // List the tactics the side to move has, as a training aid
+    pub show_expected_reply: bool, // Ghost the move the opponent is leaning towards while it thinks
+    pub expected_reply: Option<Move>, // The opponent's best move so far in the search it's running
+    pub thinking_depth: Option<i32>, // How deep that search has got, once it has reported in
+    pub last_explanation: Option<SearchExplanation>,
+    pub last_search_info: Option<SearchInfo>,
+    pub fen_dialog: Option<FenDialog>,
+    pub move_entry: Option<MoveEntry>,
+    pub analysis_mode: bool, // Nobody plays against us, we move for whoever is to play
+    pub replay: Option<Replay>, // A finished game being looked through, rather than played
+    pub repertoire: Option<Repertoire>, // While in it, the opponent replies from it and the player is checked against it
+    pub book_note: Option<String>,      // Set when the player leaves their repertoire
+    pub correspondence: Option<CorrespondenceGame>, // Saved to disk after every move when playing by post
+    pub ics: Option<IcsSession>, // When set, the opponent is whoever the chess server pairs us with
+    pub ics_board: Option<Style12>, // The server's last word on the game we're playing or watching
+    pub netplay: Option<NetSession>, // When set, the opponent is another player over the network
+    pub puzzles: Option<PuzzleSession>, // When set, the board plays through puzzles instead of games
+    pub trainer: Option<OpeningTrainer>, // When set, the board drills the repertoire's lines instead
+    pub report_path: Option<PathBuf>, // Where to write an HTML report of each game once it's over
+    pub eval_graph_path: Option<PathBuf>, // Where to write an SVG of each game's eval graph once it's over
+    pub move_rejection: Option<(IllegalMoveReason, Instant)>, // Why the last move tried was refused, and when
+    pub dgt: Option<DgtBoard>, // An electronic board the player makes their moves on
+    pub dgt_placement: Option<Placement>, // What was last seen standing on the electronic board
+    pub dgt_unmirrored: Vec<String>, // Moves played on screen that still have to be made on the electronic board
+    pub voice: Option<VoiceInput>,   // A speech recogniser the player can say their moves to
+    pub voice_choices: Vec<Move>, // The moves a spoken command could have meant, waiting for the player to pick one
+    pub voice_note: Option<String>, // What was last heard, and what came of it
+    pub action_note: Option<String>, // What came of the last copy, takeback, draw offer or switch to analysis
+    pub pondering: bool,             // Let the opponent think while it's the player's move
+    pub ponder: Option<Ponder>,
+    pub analysis_search: Option<(u64, i32, Receiver<Vec<AnalysisLine>>)>, // The analysed position's hash, the search budget, and where its lines arrive
+    pub analysis_lines: Vec<AnalysisLine>, // The engine's best lines for the position on the analysis board
+    pub analysis_depth: i32,               // How many plies deep those lines were searched
+    pub analysis_line_spans: Vec<(f32, f32)>, // Where each engine line sits down the panel, top to bottom, for clicking
+    pub scene: Scene,
+    pub finished: Option<GameStatus>, // How the game ended, shown over the board until another one starts
+    pub premove: Option<(usize, usize)>, // A move made while the opponent thinks, as the squares it goes from and to
+    pub blindfold: bool, // Hide the pieces, for playing by typed moves and the move list alone
+    pub auto_flip: bool, // Turn the board round after every move of a hot-seat game
+    pub flipped: bool,   // Black at the bottom of the board instead of white
+    pub fullscreen: bool,
+    pub viewed_ply: Option<usize>, // An earlier point in the game shown on the board, or None for the game as it stands
+    pub move_list: (Vec<Move>, Vec<String>), // The moves played and their SAN, worked out again only when they change
+    pub opening: Option<&'static Opening>, // Named above the move list, and worked out again along with it
+    pub move_delay: Duration, // How long a game between two engines waits after each move before the next
+    pub paused: bool,         // Holds a game between two engines after the move being thought about
+    pub step_move: bool,      // Lets one more move through while paused
+    pub last_move_at: Option<Instant>,
+    pub live_eval: Option<i32>, // The thinking engine's latest score, from white's point of view
+}
+
+impl MainState {
+    pub fn new(
+        board_state: BoardState,
+        ctx: &mut Context,
+        plr_team: Team,
+        opponent: ChessOpponent,
+    ) -> GameResult<MainState> {
+        let mut s = MainState {
+            game: Game::new(board_state, plr_team, opponent),
+            piece_imgs: HashMap::new(),
+            audio: AudioManager::default(),
+            low_time_warned: [false; 2],
+            settings: Settings::default(),
+            settings_path: PathBuf::from(SETTINGS_FILE),
+            autosave_path: PathBuf::from(AUTOSAVE_FILE),
+            last_autosave: None,
+            selected_square: None,
+            queued_move: None,
+            promotion_choice: None,
+            auto_queen: false,
+            drag_x: None,
+            drag_y: None,
+            board_legal_moves: None,
+            last_move_origin: None,
+            last_move_end: None,
+            opp_thread: None,
+            search_started: None,
+            explain_search: false,
+            show_tactics: false,
+            show_expected_reply: false,
+            expected_reply: None,
+            thinking_depth: None,
+            last_explanation: None,
+            last_search_info: None,
+            fen_dialog: None,
+            move_entry: None,
+            analysis_mode: false,
+            replay: None,
+            repertoire: None,
+            book_note: None,
+            correspondence: None,
+            ics: None,
+            ics_board: None,
+            netplay: None,
+            puzzles: None,
+            trainer: None,
+            report_path: None,
+            eval_graph_path: None,
+            move_rejection: None,
+            dgt: None,
+            dgt_placement: None,
+            dgt_unmirrored: Vec::new(),
+            voice: None,
+            voice_choices: Vec::new(),
+            voice_note: None,
+            action_note: None,
+            pondering: true,
+            ponder: None,
+            analysis_search: None,
+            analysis_lines: Vec::new(),
+            analysis_depth: 0,
+            analysis_line_spans: Vec::new(),
+            scene: Scene::Playing,
+            finished: None,
+            premove: None,
+            blindfold: false,
+            auto_flip: true,
+            flipped: plr_team == Team::Black,
+            fullscreen: false,
+            viewed_ply: None,
+            move_list: (Vec::new(), Vec::new()),
+            opening: None,
+            move_delay: DEFAULT_MOVE_DELAY,
+            paused: false,
+            step_move: false,
+            last_move_at: None,
+            live_eval: None,
+        };
+        s.board_legal_moves = Some(s.game.board.get_legal_moves());
+        s.load_piece_set(ctx, s.settings.piece_set());
+        s.audio.load_pack(ctx, DEFAULT_SOUND_PACK);
+        Ok(s)
+    }
+    // Swaps in the piece art from a folder under resources, see PIECE_SETS
+    pub fn load_piece_set(&mut self, ctx: &mut Context, set: &str) {
+        // Preload piece data for speed - pulling it every frame is slow as I learned the hard way
+        self.piece_imgs.clear();
+        let mut piece_ids: Vec<String> = Vec::new();
+
+        // Every piece's sprite, once for each team
+        for sprite in PIECE_DEFINITIONS
+            .iter()
+            .map(|definition| definition.sprite)
+            .filter(|sprite| !sprite.is_empty())
+        {
+            for team in ["w", "b"] {
+                let piece_id = format!("{team}{sprite}");
+                if !piece_ids.contains(&piece_id) {
+                    piece_ids.push(piece_id);
+                }
+            }
+        }
+
+        piece_ids.iter().for_each(|id| {
+            let file_path = format!("/{set}/{id}.png");
+            let image_res = graphics::Image::from_path(ctx, file_path);
+
+            if let Ok(image) = image_res {
+                self.piece_imgs.insert(id.to_owned(), image);
+            }
+        });
+    }
+    // Reads the settings saved last time and puts them to use
+    pub fn load_settings(&mut self, ctx: &mut Context) -> Result<(), String> {
+        let settings = Settings::load(&self.settings_path)?;
+        self.apply_settings(ctx, settings);
+        Ok(())
+    }
+    fn apply_settings(&mut self, ctx: &mut Context, settings: Settings) {
+        if settings.piece_set != self.settings.piece_set {
+            self.load_piece_set(ctx, settings.piece_set());
+        }
+        self.settings = settings;
+    }
+    pub fn show_settings(&mut self) {
+        self.scene = Scene::Settings(SettingsMenu {
+            saved: self.settings,
+            row: 0,
+        });
+    }
+    // Changes the setting on `row` of the settings menu a step either way. Lists of choices wrap round at the ends
+    fn change_setting(&mut self, ctx: &mut Context, row: usize, step: i32) {
+        let mut settings = self.settings;
+        let cycle = |choice: usize, choices: usize| {
+            (choice as i32 + step).rem_euclid(choices as i32) as usize
+        };
+        match row {
+            0 => settings.board_theme = cycle(settings.board_theme, BOARD_THEMES.len()),
+            1 => settings.piece_set = cycle(settings.piece_set, PIECE_SETS.len()),
+            2 => settings.adjust_volume(step),
+            _ => settings.muted = !settings.muted,
+        }
+        self.apply_settings(ctx, settings);
+        // A sound to hear the new volume by
+        if row >= 2 {
+            self.audio.queue(Sound::Move);
+        }
+    }
+    // Whether the player may move pieces of this team right now
+    fn controls_team(&self, team: Team) -> bool {
+        if self.ics_board.as_ref().is_some_and(Style12::is_observed) || self.spectating() {
+            false
+        } else if self.analysis_mode || self.hot_seat() {
+            team == self.game.board.active_team
+        } else {
+            team == self.game.player_team
+        }
+    }
+    // Why the player can't move the piece on `start` to `target` right now, or None if they can
+    fn rejection_reason(&self, start: usize, target: usize) -> Option<IllegalMoveReason> {
+        let team = self.game.board.get_square_team(start);
+        if team == Team::None {
+            Some(IllegalMoveReason::NoPiece)
+        } else if !self.analysis_mode && team != self.game.player_team {
+            Some(IllegalMoveReason::NotYourPiece)
+        } else if self.opponent_to_move() || !self.controls_team(team) {
+            Some(IllegalMoveReason::NotYourTurn)
+        } else {
+            explain_illegal_move(&self.game.board, start, target)
+        }
+    }
+    fn opponent_to_move(&self) -> bool {
+        !self.analysis_mode
+            && !self.hot_seat()
+            && (self.spectating() || self.game.player_team != self.game.board.active_team)
+    }
+    // Two engines playing each other, with the player only watching
+    fn spectating(&self) -> bool {
+        self.game.spectated.is_some() && !self.analysis_mode
+    }
+    /*
+        Whether the engine to move may start thinking. Between two engines each move is given a moment on the board
+        first, and nothing more is played while paused except a move the player steps through
+    */
+    fn engine_may_start(&self) -> bool {
+        if !self.spectating() || self.step_move {
+            return true;
+        }
+        !self.paused
+            && self
+                .last_move_at
+                .is_none_or(|moved| moved.elapsed() >= self.move_delay)
+    }
+    /*
+        Hands the player's side to a copy of the opponent so the two play each other, or takes it back. Not for
+        games whose other side is a person
+    */
+    fn toggle_spectating(&mut self) -> Result<(), String> {
+        if self.game.spectated.take().is_some() {
+            self.paused = false;
+            self.opp_thread = None;
+            return Ok(());
+        }
+        if self.analysis_mode
+            || self.hot_seat()
+            || self.correspondence.is_some()
+            || self.ics.is_some()
+            || self.netplay.is_some()
+            || self.drilling()
+        {
+            return Err(String::from("Nothing to watch in this game"));
+        }
+        self.game.spectated = Some(self.game.opponent.clone());
+        self.ponder = None;
+        self.premove = None;
+        Ok(())
+    }
+    // Pauses a game between two engines, or carries it on, with the clocks stopping and starting along with it
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if let Some(clock) = self.game.clock.as_mut().filter(|_| self.paused) {
+            clock.stop();
+        }
+    }
+    // Two people taking turns at the same machine, each moving for their own side
+    fn hot_seat(&self) -> bool {
+        matches!(self.game.opponent, ChessOpponent::Human)
+    }
+    // In a hot-seat game the board can turn round after every move, so whoever is to move has their pieces at the bottom
+    fn turn_board_to_mover(&mut self) {
+        if self.hot_seat() && self.auto_flip {
+            self.flipped = self.game.board.active_team == Team::Black;
+        }
+    }
+    // Puts the setup menu up, starting from how the current game is being played
+    pub fn show_setup(&mut self) {
+        self.scene = Scene::Setup(GameSetup {
+            colour: None,
+            ..self.current_setup()
+        });
+    }
+    // The settings the current game is being played with, as the setup menu would have them
+    pub fn current_setup(&self) -> GameSetup {
+        let player_team = self.game.player_team;
+        GameSetup {
+            colour: Some(player_team),
+            opponent: self.game.opponent.clone(),
+            time_control: self
+                .game
+                .clock
+                .as_ref()
+                .map(|clock| clock.control(player_team)),
+            bot_time_control: self
+                .game
+                .clock
+                .as_ref()
+                .map(|clock| clock.control(player_team.opponent())),
+            variant: self.game.board.variant,
+            row: 0,
+        }
+    }
+    // Starts a game from the start of the chosen variant, with the side, opponent and clock picked on the setup menu
+    pub fn start_game(&mut self, setup: GameSetup) {
+        let board = BoardState::from_fen(setup.variant.start_position().to_string())
+            .expect("Every variant starts from a valid FEN");
+        self.start_game_from(setup, board);
+    }
+    // Leaves the two-player game where it is for a new three-player one, all three sides played at this machine
+    pub fn start_three_player(&mut self) {
+        self.scene = Scene::ThreePlayer(Box::default());
+        self.audio.queue(Sound::Intro);
+    }
+    /*
+        Starts the game over with the same opponent, clock and starting position, the player taking the other side.
+        Only once the game is over, and not for games kept on disk or on a server
+    */
+    pub fn rematch(&mut self) -> Result<(), String> {
+        if self.finished.is_none() {
+            return Err(String::from("Finish this game first"));
+        }
+        if self.correspondence.is_some() || self.ics.is_some() || self.netplay.is_some() {
+            return Err(String::from("No rematches in this game"));
+        }
+        let setup = GameSetup {
+            colour: Some(self.game.player_team.opponent()),
+            ..self.current_setup()
+        };
+        self.start_game_from(setup, self.game.start_board);
+        Ok(())
+    }
+    // Resets everything for a new game from `board`, played with the settings in `setup`
+    fn start_game_from(&mut self, setup: GameSetup, board: BoardState) {
+        let player_team = setup.colour.unwrap_or(if rand::random_bool(0.5) {
+            Team::White
+        } else {
+            Team::Black
+        });
+        // Whichever side has no time control of its own plays on the other's, like on the command line
+        let controls = match (setup.time_control, setup.bot_time_control) {
+            (None, None) => None,
+            (player, bot) => Some((player.or(bot).unwrap(), bot.or(player).unwrap())),
+        };
+        self.game.player_team = player_team;
+        self.game.opponent = setup.opponent;
+        self.game.clock = controls.map(|(player, bot)| {
+            if player_team == Team::White {
+                ChessClock::new(player, bot)
+            } else {
+                ChessClock::new(bot, player)
+            }
+        });
+        self.game.board.variant = setup.variant;
+        self.load_position(board, false);
+        self.scene = Scene::Playing;
+        self.audio.queue(Sound::Intro);
+        // Whatever was saved is of the game before, which isn't coming back now
+        self.discard_autosave();
+    }
+    /*
+        Throws away the current game and starts over from a new position, either as a game against the opponent
+        or as an analysis board where the player moves for both teams
+    */
+    pub fn load_position(&mut self, board: BoardState, analysis: bool) {
+        // A fresh game keeps the time controls, but the analysis board is untimed
+        let clock =
+            self.game.clock.as_ref().filter(|_| !analysis).map(|clock| {
+                ChessClock::new(clock.control(Team::White), clock.control(Team::Black))
+            });
+        // A new position is played under the same rules as the last one
+        let board = board.with_variant(self.game.board.variant);
+        let spectated = self.game.spectated.take();
+        self.game = Game::new(board, self.game.player_team, self.game.opponent.clone());
+        self.game.spectated = spectated;
+        self.game.clock = clock;
+        self.board_legal_moves = Some(self.game.board.get_legal_moves());
+        self.analysis_mode = analysis;
+        self.replay = None;
+        self.selected_square = None;
+        self.queued_move = None;
+        self.promotion_choice = None;
+        self.move_entry = None;
+        self.drag_x = None;
+        self.drag_y = None;
+        self.viewed_ply = None;
+        self.premove = None;
+        self.finished = None;
+        self.move_rejection = None;
+        self.action_note = None;
+        self.low_time_warned = [false; 2];
+        // The player's pieces start at the bottom, whichever side they're playing
+        self.flipped = self.game.player_team == Team::Black;
+        self.turn_board_to_mover();
+        self.last_move_origin = None;
+        self.last_move_end = None;
+        // Dropping the receiver lets any search that is still running finish into the void
+        self.opp_thread = None;
+        self.ponder = None;
+        self.last_explanation = None;
+        self.last_search_info = None;
+        self.book_note = None;
+        self.search_started = None;
+        self.expected_reply = None;
+        self.thinking_depth = None;
+        self.live_eval = None;
+        self.last_move_at = None;
+        self.voice_choices.clear();
+        if let Some(correspondence) = self.correspondence.as_mut().filter(|_| !analysis) {
+            if let Err(err) = correspondence.restart(&board) {
+                eprintln!("{err}");
+            }
+        }
+    }
+    /*
+        Hands the rest of the game to a different opponent. A search the old opponent still has running is
+        abandoned, and the new one starts thinking if it's their move
+    */
+    pub fn swap_opponent(&mut self, opponent: ChessOpponent) {
+        self.game.opponent = opponent;
+        self.ponder = None;
+        // Nobody replies for a human, so whatever the engine was thinking goes too
+        if self.opponent_to_move() || self.hot_seat() {
+            self.opp_thread = None;
+            self.last_explanation = None;
+        }
+    }
+    /*
+        On the player's turn, starts the opponent searching the position after the reply its last search expected,
+        so if that's what gets played it can answer straight away
+    */
+    fn start_pondering(&mut self) {
+        if !self.pondering
+            || self.ponder.is_some()
+            || self.analysis_mode
+            || self.ics.is_some()
+            || self.netplay.is_some()
+            || self.opponent_to_move()
+        {
+            return;
+        }
+        // The expected line has to follow on from the opponent's move that's actually on the board
+        let last_played = self.game.history.last_move();
+        let Some(&expected) = self
+            .last_search_info
+            .as_ref()
+            .filter(|info| info.pv.first() == last_played.as_ref())
+            .and_then(|info| info.pv.get(1))
+        else {
+            return;
+        };
+        let mut pondered = self.game.board;
+        let mut pondered_history = self.game.history.clone();
+        if pondered_history.make_move(&mut pondered, expected).is_err() {
+            return;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (outcome_tx, outcome) = std::sync::mpsc::channel();
+        let mut opponent_clone = self.game.opponent.clone();
+        let explain = self.explain_search;
+        let ponder_stop = Arc::clone(&stop);
+        tokio::spawn(async move {
+            let _ = outcome_tx.send(opponent_clone.ponder(
+                pondered,
+                &pondered_history,
+                explain,
+                ponder_stop,
+            ));
+        });
+        self.ponder = Some(Ponder {
+            expected,
+            stop,
+            outcome,
+        });
+    }
+    // The opponent's answer to the player's move, if it had already been thinking about that move
+    fn ponder_hit(&mut self) -> Option<SearchOutcome> {
+        let ponder = self.ponder.take()?;
+        ponder.stop.store(true, Ordering::Relaxed);
+        let last_played = self.game.history.last_move();
+        if last_played != Some(ponder.expected) {
+            tracing::debug!(expected = %ponder.expected, "Ponder miss");
+            return None;
+        }
+        // A stopped search gives up within a few positions, so this hardly holds the frame up
+        let outcome = ponder.outcome.recv().ok().flatten()?;
+        tracing::debug!(expected = %ponder.expected, depth = outcome.info.depth, "Ponder hit");
+        outcome.best_move.is_some().then_some(outcome)
+    }
+    /*
+        Plays a move on the game board and records it everywhere the game keeps track of moves.
+        False if the move couldn't be made
+    */
+    fn apply_move(&mut self, c_move: Move) -> bool {
+        let mover = self.game.board.active_team;
+        if self
+            .game
+            .history
+            .make_move(&mut self.game.board, c_move)
+            .is_err()
+        {
+            return false;
+        }
+        self.move_rejection = None;
+        if let Some(clock) = self.game.clock.as_mut().filter(|_| !self.analysis_mode) {
+            clock.press(mover);
+        }
+        self.last_move_origin = Some(c_move.start);
+        self.last_move_end = Some(c_move.target);
+        self.last_move_at = Some(Instant::now());
+        // Regenerate moves
+        self.board_legal_moves = Some(self.game.board.get_legal_moves());
+
+        self.game.move_history.push(MoveHistoryEntry {
+            team: mover,
+            played: c_move,
+            review: None,
+        });
+        self.turn_board_to_mover();
+        true
+    }
+    // Shows the position after `ply` moves, or the game as it stands from its last move on
+    fn view_ply(&mut self, ply: usize) {
+        self.viewed_ply = (ply < self.game.move_history.len()).then_some(ply);
+    }
+    // Undoes the last move on the board and everywhere the game keeps track of moves. False if there was none
+    fn take_back(&mut self) -> bool {
+        if self.game.history.take_back(&mut self.game.board).is_none() {
+            return false;
+        }
+        self.game.move_history.pop();
+        self.viewed_ply = None;
+        let last_played = self.game.history.last_move();
+        self.last_move_origin = last_played.map(|played| played.start);
+        self.last_move_end = last_played.map(|played| played.target);
+        self.board_legal_moves = Some(self.game.board.get_legal_moves());
+        self.move_rejection = None;
+        self.turn_board_to_mover();
+        true
+    }
+    /*
+        Takes back the player's last move, with the opponent's reply to it if there was one, so it's the player's move
+        again. On the analysis board there's nobody to reply, so only the last move goes. Games on the clock, by post
+        or on a server can't be taken back
+    */
+    fn undo(&mut self) -> Result<(), String> {
+        if self.game.clock.is_some()
+            || self.correspondence.is_some()
+            || self.ics.is_some()
+            || self.netplay.is_some()
+            || self.drilling()
+        {
+            return Err(String::from("No takebacks in this game"));
+        }
+        if self.finished.is_some() {
+            return Err(String::from("The game is over"));
+        }
+        let plies = if self.analysis_mode || self.hot_seat() || self.opponent_to_move() {
+            1
+        } else {
+            2
+        };
+        if self.game.move_history.len() < plies {
+            return Err(String::from("Nothing to take back"));
+        }
+        // The opponent may be thinking about the move being taken back
+        self.opp_thread = None;
+        self.ponder = None;
+        self.expected_reply = None;
+        self.queued_move = None;
+        self.promotion_choice = None;
+        self.selected_square = None;
+        self.premove = None;
+        for _ in 0..plies {
+            self.take_back();
+        }
+        Ok(())
+    }
+    // Does what one of the panel's buttons is for, noting why when it can't be done
+    fn press_panel_button(&mut self, pressed: PanelButton) {
+        match pressed {
+            PanelButton::Undo => {
+                self.action_note = Some(match self.undo() {
+                    Ok(()) => String::from("Took back your last move"),
+                    Err(err) => err,
+                });
+            }
+            PanelButton::OfferDraw => {
+                if let Err(err) = self.offer_draw() {
+                    self.action_note = Some(err);
+                }
+            }
+            PanelButton::Resign => {
+                if let Err(err) = self.resign() {
+                    self.action_note = Some(err);
+                }
+            }
+        }
+    }
+    /*
+        Shows a game from PGN on the analysis board, from where it started. The arrow keys step through its moves,
+        and the player is free to try their own from any position along the way
+    */
+    pub fn start_replay(&mut self, game: PgnGame) -> Result<(), String> {
+        let moves = analysis::pgn_moves(&game)?;
+        self.load_position(game.start_board()?, true);
+        self.replay = Some(Replay { game, moves });
+        Ok(())
+    }
+    // How many of the replayed game's moves are on the board, or None once the player's own moves have left it
+    fn replay_ply(&self) -> Option<usize> {
+        let replay = self.replay.as_ref()?;
+        let played: Vec<Move> = self.game.history.moves_played().collect();
+        replay.moves.starts_with(&played).then_some(played.len())
+    }
+    // Plays the replayed game's next move. False at its end, or while the board is off its moves
+    fn replay_forward(&mut self) -> bool {
+        let next = self
+            .replay_ply()
+            .zip(self.replay.as_ref())
+            .and_then(|(ply, replay)| replay.moves.get(ply).copied());
+        next.is_some_and(|next| self.apply_move(next))
+    }
+    /*
+        Sets the board up as a correspondence game left it and keeps saving to its file from then on.
+        A player who let the deadline pass has lost on time
+    */
+    pub fn resume_correspondence(
+        &mut self,
+        correspondence: CorrespondenceGame,
+    ) -> Result<(), String> {
+        let start = correspondence.start_board()?;
+        self.game.player_team = correspondence.player_team;
+        self.game.opponent = ChessOpponent::Matt(CORRESPONDENCE_SEARCH_DEPTH);
+        self.load_position(start, false);
+        self.game.clock = None;
+
+        for uci in &correspondence.moves {
+            let played = Move::from_uci(&self.game.board, uci)
+                .ok_or_else(|| format!("Illegal move {uci} in {}", self.game.board.as_fen()))?;
+            self.apply_move(played);
+        }
+        let overdue = correspondence.is_overdue(Utc::now());
+        self.correspondence = Some(correspondence);
+        // The opponent answers as soon as the game is open, so only the player can run out of days
+        if overdue && !self.opponent_to_move() {
+            self.end_game(self.game.board.timeout_status(self.game.player_team));
+        }
+        Ok(())
+    }
+    // Offers back the game left unfinished last time, or goes to the setup menu when there isn't one
+    pub fn offer_resume(&mut self) {
+        match SavedGame::load(&self.autosave_path) {
+            Ok(Some(saved)) => self.scene = Scene::Resume(saved),
+            Ok(None) => self.show_setup(),
+            Err(err) => {
+                eprintln!("Couldn't pick up the last game: {err}");
+                self.show_setup();
+            }
+        }
+    }
+    // Sets the board up as `saved` left it, clocks and all, and carries on playing from there
+    pub fn resume_saved_game(&mut self, saved: SavedGame) -> Result<(), String> {
+        let player_team = saved.player_team;
+        let setup = GameSetup {
+            colour: Some(player_team),
+            opponent: saved.opponent()?,
+            time_control: saved
+                .clock
+                .map(|clock| clock.controls[player_team as usize]),
+            bot_time_control: saved
+                .clock
+                .map(|clock| clock.controls[player_team.opponent() as usize]),
+            variant: saved.variant,
+            row: 0,
+        };
+        self.start_game_from(setup, saved.start_board()?);
+
+        for uci in &saved.moves {
+            let played = Move::from_uci(&self.game.board, uci)
+                .ok_or_else(|| format!("Illegal move {uci} in {}", self.game.board.as_fen()))?;
+            self.apply_move(played);
+        }
+        // Replaying the moves pressed the clocks, so they're set back to what was left on them
+        if let (Some(clock), Some(saved_clock)) = (self.game.clock.as_mut(), saved.clock) {
+            for team in [Team::White, Team::Black] {
+                clock.set_remaining(team, saved_clock.remaining[team as usize]);
+            }
+        }
+        self.action_note = Some(format!(
+            "Picked up the game from {}",
+            saved.saved_at.with_timezone(&Local).format("%H:%M")
+        ));
+        Ok(())
+    }
+    /*
+        Whether the game on the board is one worth autosaving. Games on a server or over the network are kept by
+        the other side, correspondence games save themselves, and drills, replays and the analysis board have
+        nothing to lose
+    */
+    fn autosaving(&self) -> bool {
+        !self.analysis_mode
+            && self.finished.is_none()
+            && self.replay.is_none()
+            && self.correspondence.is_none()
+            && self.ics.is_none()
+            && self.netplay.is_none()
+            && self.game.spectated.is_none()
+            && !self.drilling()
+            && !self.game.move_history.is_empty()
+    }
+    // Writes the game out if it hasn't been for AUTOSAVE_INTERVAL, or straight away when `now` is set
+    fn autosave(&mut self, now: bool) {
+        let due = now
+            || self
+                .last_autosave
+                .is_none_or(|saved| saved.elapsed() >= AUTOSAVE_INTERVAL);
+        if !due || !self.autosaving() {
+            return;
+        }
+        self.last_autosave = Some(Instant::now());
+        if let Err(err) = self.game.to_saved().save(&self.autosave_path) {
+            eprintln!("{err}");
+        }
+    }
+    fn discard_autosave(&mut self) {
+        self.last_autosave = None;
+        if let Err(err) = SavedGame::discard(&self.autosave_path) {
+            eprintln!("{err}");
+        }
+    }
+    /*
+        Brings the board in line with what the server sent. Moves are played through the usual queue so they show
+        up in the history, while a new game or a position we can't reach by one move is loaded outright
+    */
+    fn handle_ics_events(&mut self) {
+        let Some(ics) = &self.ics else {
+            return;
+        };
+        for event in ics.poll() {
+            match event {
+                IcsEvent::Board(update) => {
+                    let same_game = self
+                        .ics_board
+                        .as_ref()
+                        .is_some_and(|last| last.game_number == update.game_number);
+                    let relayed_move = update
+                        .last_move
+                        .as_ref()
+                        .and_then(|san| Move::from_san(&self.game.board, san));
+                    if !same_game {
+                        println!(
+                            "Game {}: {} vs {}",
+                            update.game_number, update.white, update.black
+                        );
+                    }
+                    if self.game.board.as_fen().split(' ').next() != update.fen.split(' ').next() {
+                        match relayed_move.filter(|_| same_game) {
+                            Some(relayed) => self.queued_move = Some(relayed),
+                            None => match BoardState::from_fen(update.fen.clone()) {
+                                Ok(board) => {
+                                    self.game.player_team =
+                                        update.our_team().unwrap_or(Team::White);
+                                    self.load_position(board, false);
+                                }
+                                Err(err) => eprintln!("Unreadable board from the server: {err}"),
+                            },
+                        }
+                    }
+                    self.ics_board = Some(update);
+                }
+                IcsEvent::GameOver {
+                    game_number,
+                    reason,
+                    result,
+                } => {
+                    println!("Game {game_number}: {reason} {result}");
+                    let ours = self.ics_board.as_ref().is_some_and(|board| {
+                        board.game_number == game_number && !board.is_observed()
+                    });
+                    if ours {
+                        let termination = if reason.to_lowercase().contains("time") {
+                            "time forfeit"
+                        } else {
+                            "normal"
+                        };
+                        self.finish_game(&result, termination);
+                    }
+                    self.ics_board = None;
+                }
+                IcsEvent::Text(text) => println!("{text}"),
+                IcsEvent::Disconnected => {
+                    eprintln!("The chess server hung up");
+                    self.ics = None;
+                    self.ics_board = None;
+                    return;
+                }
+            }
+        }
+    }
+    /*
+        Catches up with the electronic board. Once it matches the game again, a legal move made on it is played
+        for the player, while anything that doesn't make one is a piece still in the hand and is waited out
+    */
+    fn handle_dgt_board(&mut self) {
+        let Some(placement) = self.dgt.as_ref().and_then(DgtBoard::poll) else {
+            return;
+        };
+        // The electronic board has the standard 64 squares, so a wider game can't be played on it
+        if self.game.board.size != BoardSize::Standard {
+            return;
+        }
+        self.dgt_placement = Some(placement);
+        if placement == placement_of(&self.game.board) {
+            self.dgt_unmirrored.clear();
+        } else if self.dgt_unmirrored.is_empty()
+            && self.queued_move.is_none()
+            && !self.opponent_to_move()
+            && self.controls_team(self.game.board.active_team)
+        {
+            self.queued_move = move_from_placement(&self.game.board, &placement);
+        }
+    }
+    // The position the board is showing, which is further back in the game while the move list is being looked through
+    fn shown_board(&self) -> BoardState {
+        self.viewed_ply
+            .map_or_else(|| self.game.board, |ply| self.game.board_at(ply))
+    }
+    // The moves that led to the position the board is showing
+    fn shown_history(&self) -> History {
+        let shown = self.viewed_ply.unwrap_or(self.game.history.undos.len());
+        self.game.history.truncated(shown)
+    }
+    /*
+        On the analysis board the engine keeps its best few lines up to date with whatever position is shown.
+        Each search that finishes is followed by one a ply deeper, until the position changes or the lines are
+        as deep as they go
+    */
+    fn handle_analysis(&mut self) {
+        if !self.analysis_mode {
+            self.analysis_search = None;
+            self.analysis_lines.clear();
+            return;
+        }
+        let board = self.shown_board();
+        if self
+            .analysis_search
+            .as_ref()
+            .is_none_or(|(analysed, _, _)| *analysed != board.zobrist)
+        {
+            // Lines for a position that's been left behind are dropped along with their receiver
+            self.analysis_lines.clear();
+            self.analysis_search = Some(MainState::spawn_analysis(
+                board,
+                self.shown_history(),
+                ANALYSIS_SEARCH_BUDGET,
+            ));
+            return;
+        }
+        let Some((position, budget, lines_rx)) = &self.analysis_search else {
+            return;
+        };
+        if let Ok(lines) = lines_rx.try_recv() {
+            let (position, budget) = (*position, *budget);
+            self.analysis_lines = lines;
+            self.analysis_depth = budget + 1;
+            if budget < ANALYSIS_MAX_BUDGET {
+                self.analysis_search = Some(MainState::spawn_analysis(
+                    board,
+                    self.shown_history(),
+                    budget + 1,
+                ));
+            } else {
+                // Nothing more is coming for this position, but it's still the one the lines are for
+                self.analysis_search = Some((position, budget, std::sync::mpsc::channel().1));
+            }
+        }
+    }
+    fn spawn_analysis(
+        board: BoardState,
+        history: History,
+        search_budget: i32,
+    ) -> (u64, i32, Receiver<Vec<AnalysisLine>>) {
+        let (lines_tx, lines_rx) = std::sync::mpsc::channel();
+        let position = board.zobrist;
+        tokio::spawn(async move {
+            let lines = multi_pv(
+                &board,
+                &history,
+                search_budget,
+                ANALYSIS_LINES,
+                &mut SearchInfo::default(),
+            );
+            let _ = lines_tx.send(lines);
+        });
+        (position, search_budget, lines_rx)
+    }
+    /*
+        Plays the first move of one of the engine's lines. A line for a position further back in the game branches
+        off from there, so the moves after it are taken back first
+    */
+    fn play_analysis_line(&mut self, index: usize) {
+        let Some(first) = self.analysis_lines.get(index).map(|line| line.root) else {
+            return;
+        };
+        if let Some(ply) = self.viewed_ply {
+            while self.game.move_history.len() > ply && self.take_back() {}
+        }
+        self.queued_move = Some(first);
+    }
+    // Which engine line is at height `y` down the panel, if any
+    fn analysis_line_at(&self, y: f32) -> Option<usize> {
+        self.analysis_line_spans
+            .iter()
+            .position(|(top, bottom)| (*top..*bottom).contains(&(y - PANEL_MARGIN)))
+    }
+    // Gives the game to the opponent. In a hot-seat game it's whoever is to move that resigns
+    fn resign(&mut self) -> Result<(), String> {
+        self.check_can_conclude()?;
+        let resigning = if self.hot_seat() {
+            self.game.board.active_team
+        } else {
+            self.game.player_team
+        };
+        if let Some(netplay) = self.netplay.as_mut() {
+            netplay.send(&NetMessage::Resign)?;
+        }
+        self.end_game(GameStatus::Resignation(resigning.opponent()));
+        Ok(())
+    }
+    /*
+        Asks the opponent for a draw, which it takes or turns down on the spot. Another player over the network
+        answers in their own time, and offering a draw back to them takes theirs
+    */
+    fn offer_draw(&mut self) -> Result<(), String> {
+        self.check_can_conclude()?;
+        if let Some(netplay) = self.netplay.as_mut() {
+            if netplay.peer_offers_draw {
+                netplay.send(&NetMessage::DrawAccept)?;
+                self.end_game(GameStatus::DrawByAgreement);
+            } else {
+                netplay.send(&NetMessage::DrawOffer)?;
+                netplay.draw_offered = true;
+                self.action_note = Some(String::from("Offered the other player a draw"));
+            }
+            return Ok(());
+        }
+        if !self.game.opponent.accepts_draw(&self.game.opponent_scores) {
+            return Err(format!("{} declines the draw", self.game.opponent));
+        }
+        self.end_game(GameStatus::DrawByAgreement);
+        Ok(())
+    }
+    /*
+        Brings the game in line with the other player's over the network. Whenever the two connect the host says how
+        the game is played and both send their moves so far, so whichever side missed moves while they were apart
+        catches up. Their moves are played through the usual queue, checked against the position they reached
+    */
+    fn handle_net_events(&mut self) {
+        let Some(netplay) = self.netplay.as_mut() else {
+            return;
+        };
+        for event in netplay.poll() {
+            let handled = match event {
+                NetEvent::Connected => {
+                    println!("Connected to the other player");
+                    self.greet_peer()
+                }
+                NetEvent::Message(message) => self.handle_net_message(message),
+                NetEvent::Unreadable(line) => Err(format!("Unreadable message: {line}")),
+                NetEvent::Disconnected => Err(String::from(
+                    "Lost the other player, waiting for them to come back",
+                )),
+            };
+            if let Err(err) = handled {
+                eprintln!("{err}");
+            }
+        }
+    }
+    // What a newly connected player is told about the game
+    fn greet_peer(&mut self) -> Result<(), String> {
+        // Only the host says how the game is played, and it always has a game to name
+        let game = self
+            .netplay
+            .as_ref()
+            .and_then(NetSession::game)
+            .unwrap_or_default();
+        let start = NetMessage::Start {
+            game,
+            guest_team: self.game.player_team.opponent(),
+            variant: self.game.board.variant,
+            clock: self
+                .game
+                .clock
+                .as_ref()
+                .map(|clock| (clock.control(Team::White), clock.control(Team::Black))),
+            fen: self.game.start_board.as_fen(),
+        };
+        let history = NetMessage::History(self.played_uci());
+        let Some(netplay) = self.netplay.as_mut() else {
+            return Ok(());
+        };
+        if netplay.hosting {
+            netplay.send(&start)?;
+        }
+        netplay.send(&history)?;
+        if self.finished.is_some() {
+            let fen = self.game.board.as_fen();
+            let hash = self.game.board.zobrist;
+            netplay.send(&NetMessage::Final { hash, fen })?;
+        }
+        Ok(())
+    }
+    fn handle_net_message(&mut self, message: NetMessage) -> Result<(), String> {
+        let hosting = self.netplay.as_ref().is_some_and(|netplay| netplay.hosting);
+        match message {
+            // Only ever the first thing a guest says, which the connection has already dealt with
+            NetMessage::Hello { .. } => {}
+            NetMessage::Start {
+                guest_team,
+                variant,
+                clock,
+                fen,
+                ..
+            } => {
+                if hosting {
+                    return Err(String::from(
+                        "The guest tried to say how the game is played",
+                    ));
+                }
+                // Coming back to a game already going keeps it, moves and clocks and all
+                if self.game.player_team == guest_team
+                    && self.game.board.variant == variant
+                    && self.game.start_board.as_fen() == fen
+                {
+                    return Ok(());
+                }
+                let board = BoardState::from_fen(fen.clone())
+                    .map_err(|err| format!("Unreadable start position {fen}: {err}"))?;
+                self.game.player_team = guest_team;
+                self.game.board.variant = variant;
+                self.game.clock = clock.map(|(white, black)| ChessClock::new(white, black));
+                self.load_position(board, false);
+                println!("Playing {guest_team:?} in a game of {variant}");
+            }
+            NetMessage::History(moves) => {
+                let ours = self.played_uci();
+                let shared = ours
+                    .iter()
+                    .zip(&moves)
+                    .take_while(|(our, their)| our == their)
+                    .count();
+                // The host's game wins out when the two have gone different ways
+                if shared < ours.len() {
+                    if hosting || shared == moves.len() {
+                        return Ok(());
+                    }
+                    self.load_position(self.game.start_board, false);
+                    self.apply_uci_moves(&moves)?;
+                } else {
+                    self.apply_uci_moves(&moves[shared..])?;
+                }
+            }
+            NetMessage::Move {
+                ply,
+                uci,
+                time_left,
+                hash,
+            } => {
+                if !self.opponent_to_move() || ply != self.game.move_history.len() + 1 {
+                    return Err(format!(
+                        "Got {uci} as move {ply}, but the game is {} moves in",
+                        self.game.move_history.len()
+                    ));
+                }
+                let played = Move::from_uci(&self.game.board, &uci)
+                    .ok_or_else(|| format!("Illegal move {uci} in {}", self.game.board.as_fen()))?;
+                let mut after = self.game.board;
+                if after.make_move(played).is_err() || after.zobrist != hash {
+                    return Err(format!(
+                        "The boards went different ways at {uci} from {}",
+                        self.game.board.as_fen()
+                    ));
+                }
+                // Their clock is put back to what it read as they moved, so time on the wire isn't taken off it
+                if let (Some(clock), Some(time_left)) = (self.game.clock.as_mut(), time_left) {
+                    clock.set_remaining(self.game.board.active_team, time_left);
+                }
+                self.queued_move = Some(played);
+            }
+            NetMessage::DrawOffer => {
+                if let Some(netplay) = self.netplay.as_mut() {
+                    netplay.peer_offers_draw = true;
+                }
+                self.action_note = Some(String::from("The other player offers a draw"));
+            }
+            NetMessage::DrawAccept => {
+                if self
+                    .netplay
+                    .as_ref()
+                    .is_some_and(|netplay| netplay.draw_offered)
+                    && self.finished.is_none()
+                {
+                    self.end_game(GameStatus::DrawByAgreement);
+                }
+            }
+            NetMessage::Resign => {
+                if self.finished.is_none() {
+                    self.end_game(GameStatus::Resignation(self.game.player_team));
+                }
+            }
+            NetMessage::Final { hash, fen } => {
+                if let Some(netplay) = self.netplay.as_mut() {
+                    netplay.peer_final = Some((hash, fen));
+                }
+                self.verify_final_position();
+            }
+        }
+        Ok(())
+    }
+    // The moves of the game so far, as they go over the network
+    fn played_uci(&self) -> Vec<String> {
+        self.game
+            .move_history
+            .iter()
+            .map(|entry| entry.played.to_uci_on(self.game.board.size))
+            .collect()
+    }
+    // Plays moves the other player made while we weren't there to hear them
+    fn apply_uci_moves(&mut self, moves: &[String]) -> Result<(), String> {
+        for uci in moves {
+            let played = Move::from_uci(&self.game.board, uci)
+                .ok_or_else(|| format!("Illegal move {uci} in {}", self.game.board.as_fen()))?;
+            self.apply_move(played);
+        }
+        Ok(())
+    }
+    // Once both games are over, checks they ended in the same position
+    fn verify_final_position(&self) {
+        let Some((hash, fen)) = self
+            .netplay
+            .as_ref()
+            .and_then(|netplay| netplay.peer_final.as_ref())
+        else {
+            return;
+        };
+        if self.finished.is_none() {
+            return;
+        }
+        if *hash == self.game.board.zobrist {
+            println!("Both players' games ended in the same position");
         } else {
-            ""
+            eprintln!(
+                "The other player's game ended in {fen}, but ours ended in {}",
+                self.game.board.as_fen()
+            );
+        }
+    }
+    /*
+        Sets the next puzzle up on the board, with the opponent's move that starts it still to come. A puzzle left
+        before it was solved counts as lost, so there's no skipping the hard ones for the rating
+    */
+    pub fn next_puzzle(&mut self) -> Result<(), String> {
+        let Some(session) = self.puzzles.as_mut() else {
+            return Ok(());
         };
-
-        if self.castle {
-            let diff = self.target as i32 - self.start as i32;
-            if diff < 0 {
-                return String::from("O-O-O");
-            } else {
-                return String::from("O-O");
-            }
+        let left_unsolved = session.record_result(false);
+        let puzzle = session
+            .next_puzzle()
+            .ok_or("That was the last puzzle in the set")?;
+        let board = puzzle.board()?;
+        self.game.player_team = puzzle.solver()?;
+        self.game.clock = None;
+        self.game.board.variant = Variant::Standard;
+        self.load_position(board, false);
+        if let Some(change) = left_unsolved {
+            self.action_note = Some(format!("Left the last puzzle unsolved ({change:+})"));
         }
-        format!("{piece_id}{capture_string}{target_file}{target_rank}{append_string}")
+        Ok(())
     }
-}
-
-pub struct MainState {
-    pub board: BoardState,
-    pub piece_imgs: HashMap<String, Image>,
-    pub sound_sources: HashMap<String, Source>,
-    pub selected_square: Option<usize>,
-    pub queued_move: Option<Move>, // Moves are queued to the draw queue so nothing changes during drawing
-    pub drag_x: Option<f32>,
-    pub drag_y: Option<f32>,
-    pub board_legal_moves: Option<Vec<(Bitboard, Vec<Move>)>>,
-    pub last_move_origin: Option<usize>,
-    pub last_move_end: Option<usize>,
-    pub player_team: Team,
-    pub opp_thread: Option<Receiver<Option<Move>>>,
-    pub opponent: ChessOpponent,
-    pub move_history: Vec<MoveHistoryEntry>, // for PGN
-    pub start_board: BoardState,
-}
-
-impl MainState {
-    pub fn new(
-        board_state: BoardState,
-        ctx: &mut Context,
-        plr_team: Team,
-        opponent: ChessOpponent,
-    ) -> GameResult<MainState> {
-        let mut s = MainState {
-            board: board_state,
-            piece_imgs: HashMap::new(),
-            sound_sources: HashMap::new(),
-            selected_square: None,
-            queued_move: None,
-            drag_x: None,
-            drag_y: None,
-            board_legal_moves: None,
-            last_move_origin: None,
-            last_move_end: None,
-            player_team: plr_team,
-            opponent,
-            opp_thread: None,
-            move_history: Vec::new(),
-            start_board: board_state,
+    /*
+        Plays the puzzle out. The player's moves are checked against the solution before they reach the board, so a
+        wrong one is never made and can be tried again, while the opponent's moves come from the solution
+    */
+    fn handle_puzzle(&mut self) {
+        let opponent_to_move = self.opponent_to_move();
+        let Some(session) = self.puzzles.as_mut() else {
+            return;
         };
-        s.board_legal_moves = Some(s.board.get_legal_moves());
-        // Preload piece data for speed - pulling it every frame is slow as I learned the hard way
-
-        let mut piece_ids: Vec<String> = Vec::new();
-
-        PIECE_TYPE_ARRAY.iter().for_each(|p| {
-            // So we know there is a piece, we can just match its type now
-            let piece_id = match p {
-                PieceType::Pawn => "p",
-                PieceType::Knight => "n",
-                PieceType::Rook => "r",
-                PieceType::Queen => "q",
-                PieceType::King => "k",
-                PieceType::Bishop => "b",
-                PieceType::None => "-",
-            };
-
-            if piece_id != "-" {
-                piece_ids.push(String::from("w") + piece_id);
-                piece_ids.push(String::from("b") + piece_id);
+        let Some(attempt) = session.attempt.as_mut() else {
+            return;
+        };
+        if attempt.is_solved() {
+            self.queued_move = None;
+            return;
+        }
+        if opponent_to_move {
+            if self.queued_move.is_none() {
+                self.queued_move = attempt.reply(&self.game.board);
+                attempt.reply_played();
             }
-        });
-
-        piece_ids.iter().for_each(|id| {
-            let file_path = format!("/alila/{}.png", id);
-            let image_res = graphics::Image::from_path(ctx, file_path);
-
-            if let Ok(image) = image_res {
-                s.piece_imgs.insert(id.to_owned(), image);
+            return;
+        }
+        let Some(played) = self.queued_move else {
+            return;
+        };
+        let step = attempt.try_move(&self.game.board, played);
+        let failed = attempt.failed;
+        let change = match step {
+            PuzzleStep::Correct => return,
+            PuzzleStep::Solved => session.record_result(!failed),
+            PuzzleStep::Wrong => {
+                self.queued_move = None;
+                session.record_result(false)
             }
+        };
+        let rating = change.map_or(String::new(), |change| {
+            format!(", rating {} ({change:+})", session.rating.rating)
         });
-
-        // Preload sounds
-        let sound_paths = [
-            "bass_intro".to_string(),
-            "piece_move".to_string(),
-            "capture".to_string(),
-        ];
-
-        sound_paths.iter().for_each(|id| {
-            let sound_source_r = Source::new(ctx, format!("/sounds/{}.ogg", id));
-
-            if let Ok(sound_source) = sound_source_r {
-                s.sound_sources.insert(id.to_owned(), sound_source);
-            } else {
-                sound_source_r.unwrap();
-            }
+        self.action_note = Some(match step {
+            PuzzleStep::Wrong => format!(
+                "{} isn't it, try again{rating}",
+                played.to_san(&self.game.board)
+            ),
+            _ => format!("Solved{rating}"),
         });
-        Ok(s)
     }
-    pub fn to_pgn(&self, result: &str) {
-        let current_date = Utc::now().format("%Y-%m-%d");
-        let bot_name = format!("Bot {}", self.opponent);
-
-        let white_name = if self.player_team == Team::White {
-            "Player"
-        } else {
-            &bot_name
+    // Working through puzzles or repertoire lines, where the moves to find are already known
+    fn drilling(&self) -> bool {
+        self.puzzles.is_some() || self.trainer.is_some()
+    }
+    // Sets the line that's due next up on the board, from the start of its game
+    pub fn next_training_line(&mut self) -> Result<(), String> {
+        let Some(trainer) = self.trainer.as_mut() else {
+            return Ok(());
         };
-        let black_name = if self.player_team != Team::White {
-            "Player"
+        let line = trainer.next_line(Utc::now());
+        let board = BoardState::from_fen(line.start_fen.clone())
+            .map_err(|err| format!("Invalid repertoire start ({err}): {}", line.start_fen))?;
+        self.game.player_team = trainer.team;
+        self.game.clock = None;
+        self.game.board.variant = Variant::Standard;
+        self.load_position(board, false);
+        Ok(())
+    }
+    /*
+        Plays the drilled line out like a puzzle. The opponent's moves come from the line, and the player's are
+        checked against it before they reach the board. Once the line is through it's scheduled for review
+    */
+    fn handle_training(&mut self) {
+        let opponent_to_move = self.opponent_to_move();
+        let (Some(trainer), Some(repertoire)) = (self.trainer.as_mut(), self.repertoire.as_ref())
+        else {
+            return;
+        };
+        if trainer.is_line_done() {
+            self.queued_move = None;
+            return;
+        }
+        if opponent_to_move {
+            if self.queued_move.is_none() {
+                self.queued_move = trainer.expected();
+                trainer.reply_played();
+                if trainer.is_line_done() {
+                    self.action_note = Some(review_note(trainer.finish_line(Utc::now())));
+                }
+            }
+            return;
+        }
+        let Some(played) = self.queued_move else {
+            return;
+        };
+        let step = trainer.try_move(repertoire, &self.game.board, played);
+        let san = played.to_san(&self.game.board);
+        self.action_note = match step {
+            DrillStep::Correct => None,
+            DrillStep::LineDone => Some(review_note(trainer.finish_line(Utc::now()))),
+            DrillStep::OtherLine => {
+                self.queued_move = None;
+                Some(format!("{san} is prepared too, but not in this line"))
+            }
+            DrillStep::Wrong => {
+                self.queued_move = None;
+                Some(format!("{san} isn't in your repertoire, try again"))
+            }
+        };
+    }
+    // Names the line's next move, which counts as a slip
+    fn training_hint(&mut self) -> Result<(), String> {
+        let opponent_to_move = self.opponent_to_move();
+        let trainer = self.trainer.as_mut().ok_or("Hints are for drills")?;
+        if opponent_to_move {
+            return Err(String::from("Wait for the line's next move"));
+        }
+        let expected = trainer.hint().ok_or("That's the end of the line")?;
+        self.action_note = Some(format!(
+            "The line plays {}",
+            expected.to_san(&self.game.board)
+        ));
+        Ok(())
+    }
+    // Gives away the piece the solution moves next, and after that where it goes. The puzzle is lost either way
+    fn puzzle_hint(&mut self) -> Result<(), String> {
+        let attempt = self
+            .puzzles
+            .as_mut()
+            .and_then(|session| session.attempt.as_mut())
+            .ok_or("Hints are for puzzles")?;
+        let solution = attempt
+            .hint(&self.game.board)
+            .ok_or("Wait for the puzzle's next move")?;
+        let first = attempt.hints == 1;
+        if let Some(session) = self.puzzles.as_mut() {
+            session.record_result(false);
+        }
+        if first {
+            self.selected_square = Some(solution.start);
+            self.action_note = Some(String::from("Move this piece"));
         } else {
-            &bot_name
+            self.action_note = Some(format!("Play {}", solution.to_san(&self.game.board)));
+        }
+        Ok(())
+    }
+    // Resigning and draw offers need a game going against the opponent here. A server game is settled on the server
+    fn check_can_conclude(&self) -> Result<(), String> {
+        if self.finished.is_some() {
+            Err(String::from("The game is over"))
+        } else if self.analysis_mode {
+            Err(String::from("There's no opponent on the analysis board"))
+        } else if self.ics.is_some() {
+            Err(String::from("Not in a server game"))
+        } else if self.spectating() {
+            Err(String::from("You're only watching"))
+        } else if self.drilling() {
+            Err(String::from("Not in a puzzle or a drill"))
+        } else {
+            Ok(())
+        }
+    }
+    /*
+        Turns the current game into an analysis board, with no opponent and the engine's lines alongside. A game on
+        the clock has to be over first, and games kept on disk or on a server are left to their own rules
+    */
+    fn start_analysis(&mut self) -> Result<(), String> {
+        if self.analysis_mode {
+            return Ok(());
+        }
+        if self.correspondence.is_some()
+            || self.ics.is_some()
+            || self.netplay.is_some()
+            || self.drilling()
+        {
+            return Err(String::from("This game can't be analysed here"));
+        }
+        if self.game.clock.is_some() && self.finished.is_none() {
+            return Err(String::from("Finish the game on the clock first"));
+        }
+        self.analysis_mode = true;
+        self.finished = None;
+        self.opp_thread = None;
+        self.ponder = None;
+        self.expected_reply = None;
+        self.premove = None;
+        Ok(())
+    }
+    /*
+        Plays what the player said, the same as a typed move. A command that fits more than one move is read back
+        as numbered choices and only played once the player picks one
+    */
+    fn handle_voice(&mut self) {
+        let Some(voice) = &self.voice else {
+            return;
         };
-
-        let mut pgn_header = format!(
-            "[Event \"chess-r match\"]\n[Site \"chess-r\"]\n[Date \"{current_date}\"]\n[Round \"1\"]\n[White \"{white_name}\"]\n[Black \"{black_name}\"]\n[Result \"{result}\"]\n\n"
-        );
-
-        for (ply, move_data) in self.move_history.iter().enumerate() {
-            let turn_string = if ply % 2 == 0 {
-                format!("{}.", (ply / 2) + 1)
+        for phrase in voice.poll() {
+            let can_move = !self.opponent_to_move()
+                && self.queued_move.is_none()
+                && self.controls_team(self.game.board.active_team);
+            let heard = if !self.voice_choices.is_empty() {
+                match spoken_confirmation(&phrase) {
+                    Some(Confirmation::Choice(choice)) if choice <= self.voice_choices.len() => {
+                        let chosen = self.voice_choices[choice - 1];
+                        self.voice_choices.clear();
+                        if can_move {
+                            self.queued_move = Some(chosen);
+                            format!("Playing {}", chosen.to_san(&self.game.board))
+                        } else {
+                            String::from("Wait for your turn")
+                        }
+                    }
+                    Some(Confirmation::Cancel) => {
+                        self.voice_choices.clear();
+                        String::from("Cancelled")
+                    }
+                    _ => String::from("Say the number of the move you meant, or cancel"),
+                }
             } else {
-                String::from("")
+                match spoken_moves(&self.game.board, &phrase)[..] {
+                    [] => String::from("That isn't a legal move"),
+                    [spoken] if can_move => {
+                        self.queued_move = Some(spoken);
+                        format!("Playing {}", spoken.to_san(&self.game.board))
+                    }
+                    [_] => String::from("Wait for your turn"),
+                    ref choices => {
+                        self.voice_choices = choices.to_vec();
+                        String::from("Which did you mean?")
+                    }
+                }
             };
-
-            pgn_header.push_str(&format!("{turn_string}{} ", move_data.to_string()));
+            self.voice_note = Some(format!("Heard \"{phrase}\": {heard}"));
+        }
+    }
+    // Sounds once when the player's time, or either side's in a hot-seat game, runs down past LOW_TIME_WARNING
+    fn warn_low_time(&mut self) {
+        let Some(clock) = self.game.clock.as_ref().filter(|_| !self.spectating()) else {
+            return;
+        };
+        for team in [Team::White, Team::Black] {
+            let watched = self.hot_seat() || team == self.game.player_team;
+            if watched
+                && !self.low_time_warned[team as usize]
+                && clock.remaining(team) < LOW_TIME_WARNING
+            {
+                self.low_time_warned[team as usize] = true;
+                self.audio.queue(Sound::LowTime);
+            }
         }
-
-        println!("{pgn_header}");
     }
-    fn end_game(&self) {
-        let opponent = self.board.active_team.opponent();
+    // Stops the game where it stands, with `status` shown over the board, and saves it
+    fn end_game(&mut self, status: GameStatus) {
+        self.finished = Some(status);
+        self.audio.queue(Sound::GameEnd);
+        self.discard_autosave();
+        self.opp_thread = None;
+        self.ponder = None;
+        if let Some(clock) = self.game.clock.as_mut() {
+            clock.stop();
+        }
+        println!("{status}");
+        if let Some(netplay) = self.netplay.as_mut() {
+            let fen = self.game.board.as_fen();
+            let hash = self.game.board.zobrist;
+            if let Err(err) = netplay.send(&NetMessage::Final { hash, fen }) {
+                eprintln!("{err}");
+            }
+            self.verify_final_position();
+        }
+        let termination = match status {
+            GameStatus::TimeForfeit(_) | GameStatus::DrawByTimeout => "time forfeit",
+            _ => "normal",
+        };
+        self.finish_game(status.result().unwrap_or("*"), termination);
+    }
+    // Everything printed once a game is over, whichever way it ended
+    fn finish_game(&mut self, result: &str, termination: &str) {
+        let reviewed = self
+            .game
+            .review()
+            .inspect_err(|err| eprintln!("Couldn't review the game: {err}"));
+        let tally = self.game.review_tally(self.game.player_team);
+        if !tally.is_empty() && self.game.spectated.is_none() {
+            let summary: Vec<String> = tally
+                .iter()
+                .map(|(quality, count)| format!("{quality}: {count}"))
+                .collect();
+            println!("Your moves - {}", summary.join(", "));
+        }
 
-        if self.board.is_team_checked(self.board.active_team) {
-            println!("Checkmate - {opponent:?} wins");
-            let mut result_string = "1-0";
-            if self.board.active_team == Team::White {
-                result_string = "0-1"
+        match self
+            .game
+            .save_pgn(Path::new(GAMES_DIRECTORY), result, termination)
+        {
+            Ok(path) => println!("Saved the game to {}", path.display()),
+            Err(err) => {
+                // The game still shouldn't be lost, so it goes to the terminal instead
+                eprintln!("{err}");
+                println!("{}", self.game.to_pgn(result, termination));
             }
-            self.to_pgn(result_string);
-        } else {
-            println!("Stalemate");
-            self.to_pgn("0-0")
+        }
+        if let (Some(path), Ok(analysed)) = (&self.eval_graph_path, &reviewed) {
+            match std::fs::write(path, eval_graph_svg(analysed)) {
+                Ok(()) => println!("Saved the eval graph to {}", path.display()),
+                Err(err) => eprintln!("Couldn't write {}: {err}", path.display()),
+            }
+        }
+        if let (Some(path), Ok(analysed)) = (&self.report_path, reviewed) {
+            let html = html_report(
+                &self.game.tags(result, termination),
+                self.game.start_board,
+                &analysed,
+                result,
+            );
+            let written = std::fs::write(path, html)
+                .map_err(|err| format!("Couldn't write {}: {err}", path.display()));
+            match written {
+                Ok(()) => println!("Saved the game report to {}", path.display()),
+                Err(err) => eprintln!("{err}"),
+            }
+        }
+        self.game.print_game_log();
+        if self.game.engine_stats.moves > 0 {
+            println!("{} stats:\n{}", self.game.opponent, self.game.engine_stats);
         }
     }
-    fn draw_board(&mut self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
-        for rank in 0..8 {
-            for file in 0..8 {
-                let square_number = 63 - (((7 - rank) * 8) + 7 - file) as usize;
+    fn draw_board(
+        &mut self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        highlights: bool,
+    ) -> GameResult<()> {
+        let board = &self.game.board;
+        let checked_king = board.is_team_checked(board.active_team).then(|| {
+            board.board_pieces[board.active_team as usize][PieceType::King as usize]
+                .state
+                .trailing_zeros() as usize
+        });
+        // The edges the coordinates are written along, which are black's when the board is flipped
+        let size = board.size;
+        let square_size = self.square_size();
+        let (bottom_rank, left_file) = if self.flipped {
+            (size.last_rank(), size.files() - 1)
+        } else {
+            (0, 0)
+        };
+        for rank in 0..size.ranks() {
+            for file in 0..size.files() {
+                let square_number = size.square(file, rank);
+                let (square_x, square_y) = self.square_origin(square_number);
                 // What an unholy if statement. TODO: Make it neater maybe
+                let theme = self.settings.board_theme();
                 let default_color = if (rank + file) % 2 != 0 {
-                    Color::from(LIGHT_SQUARE_COLOR)
+                    Color::from(theme.light)
                 } else {
-                    Color::from(DARK_SQUARE_COLOR)
+                    Color::from(theme.dark)
                 };
-                let color = if Some(square_number) == self.selected_square {
+                let color = if !highlights {
+                    default_color
+                } else if Some(square_number) == self.selected_square {
                     Color::from(SELECTED_SQUARE_COLOR)
                 } else if let Some(selected_square) = self.selected_square {
                     if let Some(pl_moves) = &self.board_legal_moves {
-                        let status_on_bitboard = pl_moves[selected_square]
-                            .0
-                            .state
-                            .view_bits::<Lsb0>()
-                            .get(square_number.min(63));
-
-                        let board_team = self.board.get_square_team(selected_square);
-                        if status_on_bitboard.unwrap().then_some(true).is_some()
-                            && self.player_team == board_team
-                        {
+                        let status_on_bitboard = pl_moves[selected_square].0.get_bit(square_number);
+
+                        let board_team = self.game.board.get_square_team(selected_square);
+                        if status_on_bitboard && self.controls_team(board_team) {
                             color_lerp(
                                 Color::from(SELECTED_SQUARE_COLOR),
                                 default_color,
@@ -277,6 +2357,12 @@ impl MainState {
                     } else {
                         default_color
                     }
+                } else if Some(square_number) == checked_king {
+                    color_lerp(Color::from(CHECK_COLOR), default_color, 0.6)
+                } else if self.premove.is_some_and(|(start, target)| {
+                    square_number == start || square_number == target
+                }) {
+                    color_lerp(Color::from(PREMOVE_COLOR), default_color, 0.5)
                 } else if Some(square_number) == self.last_move_origin {
                     color_lerp(Color::from(OLD_MOVE_COLOR), default_color, 0.7)
                 } else if Some(square_number) == self.last_move_end {
@@ -289,10 +2375,10 @@ impl MainState {
                     ctx,
                     graphics::DrawMode::fill(),
                     Rect {
-                        x: file as f32 * SQUARE_SIZE,
-                        y: (7 - rank) as f32 * SQUARE_SIZE,
-                        h: SQUARE_SIZE,
-                        w: SQUARE_SIZE,
+                        x: square_x,
+                        y: square_y,
+                        h: square_size,
+                        w: square_size,
                     },
                     color,
                 )?;
@@ -305,16 +2391,16 @@ impl MainState {
                 if FLAG_DEBUG_UI_COORDS {
                     let mut text_mesh = Text::new(sqr_txt);
                     text_mesh.set_bounds(Vector2 {
-                        x: SQUARE_SIZE,
-                        y: SQUARE_SIZE,
+                        x: square_size,
+                        y: square_size,
                     });
                     canvas.draw(
                         &text_mesh,
                         DrawParam::default().transform({
                             Transform::Values {
                                 dest: Point2 {
-                                    x: file as f32 * SQUARE_SIZE,
-                                    y: (7 - rank) as f32 * SQUARE_SIZE,
+                                    x: square_x,
+                                    y: square_y,
                                 },
                                 rotation: 0.0,
                                 scale: Vector2 { x: 1.0, y: 1.0 },
@@ -323,30 +2409,27 @@ impl MainState {
                             .to_bare_matrix()
                         }),
                     )
-                } else if square_number <= 7 || square_number % 8 == 0 {
-                    let file_array = ["a", "b", "c", "d", "e", "f", "g", "h"];
-                    let text_frag = if square_number <= 7 {
-                        file_array[square_number]
-                    } else {
-                        &((square_number / 8) + 1).to_string()
-                    };
-                    let mut text_frag_str = String::from(text_frag);
-                    if square_number == 0 {
-                        text_frag_str.push('1');
+                } else if rank == bottom_rank || file == left_file {
+                    let mut text_frag_str = String::new();
+                    if rank == bottom_rank {
+                        text_frag_str.push(size.file_letter(file));
+                    }
+                    if file == left_file {
+                        text_frag_str.push_str(&(rank + 1).to_string());
                     }
 
                     let mut text_mesh = Text::new(text_frag_str);
                     text_mesh.set_bounds(Vector2 {
-                        x: SQUARE_SIZE,
-                        y: SQUARE_SIZE,
+                        x: square_size,
+                        y: square_size,
                     });
                     canvas.draw(
                         &text_mesh,
                         DrawParam::default().transform({
                             Transform::Values {
                                 dest: Point2 {
-                                    x: file as f32 * SQUARE_SIZE,
-                                    y: (7 - rank) as f32 * SQUARE_SIZE,
+                                    x: square_x,
+                                    y: square_y,
                                 },
                                 rotation: 0.0,
                                 scale: Vector2 { x: 1.0, y: 1.0 },
@@ -360,48 +2443,24 @@ impl MainState {
         }
         Ok(())
     }
-    fn draw_pieces(&mut self, _ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+    fn draw_pieces(
+        &self,
+        _ctx: &mut Context,
+        canvas: &mut Canvas,
+        board: &BoardState,
+    ) -> GameResult<()> {
         // Map each piece and team in the game state to the image.
         // To do this, use the team bitboard to check the square's team
         // then the piece list to check the square's type
 
-        for rank in (0..8).rev() {
-            for file in 0..8 {
-                let square_bit_idx = 63 - ((rank * 8) + (7 - file)) as usize;
+        for rank in (0..board.size.ranks()).rev() {
+            for file in 0..board.size.files() {
+                let square_bit_idx = board.size.square(file, rank);
 
-                let square_team = self.board.get_square_team(square_bit_idx);
+                let square_team = board.get_square_team(square_bit_idx);
 
                 if square_team != Team::None {
-                    // We use the team id to compose the team part of the file name
-                    let file_team =
-                        String::from(if square_team == Team::White { "w" } else { "b" });
-
-                    // So we know there is a piece, we can just match its type now
-                    let team_bitboard = self.board.get_team_coverage(square_team);
-                    let square_piece = match self.board.piece_list[square_bit_idx] {
-                        PieceType::Pawn => "p",
-                        PieceType::Knight => "n",
-                        PieceType::Rook => "r",
-                        PieceType::Queen => "q",
-                        PieceType::King => "k",
-                        PieceType::Bishop => "b",
-                        PieceType::None => {
-                            // Should be unreachable
-                            return Err(GameError::RenderError(format!(
-                                "Attempted to draw a piece that does not exist for team {square_team:?}. Bitboard: {team_bitboard}",
-                            )));
-                        }
-                    };
-
-                    let square_piece_id = file_team + square_piece;
-                    //file_team + square_piece
-                    let image = self
-                        .piece_imgs
-                        .get(&square_piece_id)
-                        .unwrap_or_else(|| panic!("Couldn't find piece png for {square_piece_id}"));
-
-                    let piece_x = file as f32 * SQUARE_SIZE;
-                    let piece_y = rank as f32 * SQUARE_SIZE;
+                    let (piece_x, piece_y) = self.square_origin(square_bit_idx);
                     let piece_x = if Some(square_bit_idx) == self.selected_square {
                         self.drag_x.unwrap_or(piece_x)
                     } else {
@@ -412,83 +2471,1039 @@ impl MainState {
                     } else {
                         piece_y
                     };
-                    canvas.draw(
-                        image,
-                        DrawParam::default().transform(
-                            Transform::Values {
-                                dest: Point2 {
-                                    x: piece_x,
-                                    y: piece_y,
-                                },
-                                rotation: 0.0,
-                                scale: Vector2 {
-                                    x: SQUARE_SIZE / image.width() as f32,
-                                    y: SQUARE_SIZE / image.height() as f32,
-                                },
-                                offset: Point2 { x: 0.5, y: 0.5 },
-                            }
-                            .to_bare_matrix(),
-                        ),
-                    );
+                    self.draw_piece(
+                        canvas,
+                        board.piece_list[square_bit_idx],
+                        square_team,
+                        piece_x,
+                        piece_y,
+                        Color::WHITE,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+    // Draws one piece's sprite on the square drawn at x, y, tinted by `color`
+    fn draw_piece(
+        &self,
+        canvas: &mut Canvas,
+        piece_type: PieceType,
+        team: Team,
+        x: f32,
+        y: f32,
+        color: Color,
+    ) -> GameResult<()> {
+        // We use the team id to compose the team part of the file name
+        let file_team = String::from(if team == Team::Black { "b" } else { "w" });
+        let color = if team == Team::Red {
+            let tint = Color::from(RED_TEAM_TINT);
+            Color::new(
+                color.r * tint.r,
+                color.g * tint.g,
+                color.b * tint.b,
+                color.a,
+            )
+        } else {
+            color
+        };
+
+        let sprite = definition(piece_type).sprite;
+        if sprite.is_empty() {
+            // Should be unreachable
+            return Err(GameError::RenderError(format!(
+                "Attempted to draw a piece that does not exist for team {team:?}",
+            )));
+        }
+        // A piece whose art isn't in the piece set is drawn as its letter instead
+        let square_piece_id = file_team + sprite;
+        let Some(image) = self.piece_imgs.get(&square_piece_id) else {
+            let letter = definition(piece_type).letter;
+            let mut text_mesh = Text::new(String::from(if team != Team::Black {
+                letter
+            } else {
+                letter.to_ascii_lowercase()
+            }));
+            text_mesh.set_scale(self.square_size() * 0.6);
+            canvas.draw(
+                &text_mesh,
+                DrawParam::default()
+                    .dest(Point2 { x, y })
+                    .offset(Point2 { x: 0.5, y: 0.5 })
+                    .color(color),
+            );
+            return Ok(());
+        };
+        let square_size = self.square_size();
+
+        canvas.draw(
+            image,
+            DrawParam::default().color(color).transform(
+                Transform::Values {
+                    dest: Point2 { x, y },
+                    rotation: 0.0,
+                    scale: Vector2 {
+                        x: square_size / image.width() as f32,
+                        y: square_size / image.height() as f32,
+                    },
+                    offset: Point2 { x: 0.5, y: 0.5 },
+                }
+                .to_bare_matrix(),
+            ),
+        );
+        Ok(())
+    }
+    // The three-player board with white at the bottom and red on the left, and who's to move beside it
+    fn draw_three_player(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let Scene::ThreePlayer(game) = &self.scene else {
+            return Ok(());
+        };
+        let board = &game.board;
+        let square_size = self.square_size();
+        let theme = self.settings.board_theme();
+        let targets: Vec<usize> = game.selected.map_or(Vec::new(), |start| {
+            board
+                .legal_moves()
+                .into_iter()
+                .filter(|legal| legal.start == start)
+                .map(|legal| legal.target)
+                .collect()
+        });
+        let checked_kings = board
+            .teams_left()
+            .filter(|team| board.in_check(*team))
+            .filter_map(|team| board.king_square(team))
+            .collect::<Vec<usize>>();
+        for square_number in 0..THREE_PLAYER_SQUARES {
+            let (file, rank) = file_rank(square_number);
+            // The corners are cut out of the board, so nothing is drawn there
+            if !on_board(file as i32, rank as i32) {
+                continue;
+            }
+            let (square_x, square_y) = (
+                file as f32 * square_size,
+                (THREE_PLAYER_SIDE - 1 - rank) as f32 * square_size,
+            );
+            let default_color = if (rank + file) % 2 != 0 {
+                Color::from(theme.light)
+            } else {
+                Color::from(theme.dark)
+            };
+            let color = if Some(square_number) == game.selected {
+                Color::from(SELECTED_SQUARE_COLOR)
+            } else if targets.contains(&square_number) {
+                color_lerp(
+                    Color::from(SELECTED_SQUARE_COLOR),
+                    default_color,
+                    LEGAL_MOVE_COLOR_LERP,
+                )
+            } else if checked_kings.contains(&square_number) {
+                color_lerp(Color::from(CHECK_COLOR), default_color, 0.6)
+            } else {
+                default_color
+            };
+            let square_mesh = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                Rect {
+                    x: square_x,
+                    y: square_y,
+                    h: square_size,
+                    w: square_size,
+                },
+                color,
+            )?;
+            canvas.draw(&square_mesh, DrawParam::default());
+            if let Some((team, piece_type)) = board.piece_at(square_number) {
+                self.draw_piece(canvas, piece_type, team, square_x, square_y, Color::WHITE)?;
+            }
+        }
+        let mut text_mesh = Text::new(game.panel_text());
+        text_mesh.set_bounds(Vector2 {
+            x: PANEL_WIDTH,
+            y: WIDTH - (2.0 * PANEL_MARGIN),
+        });
+        canvas.draw(
+            &text_mesh,
+            DrawParam::default().dest(Point2 {
+                x: WIDTH + PANEL_MARGIN,
+                y: PANEL_MARGIN,
+            }),
+        );
+        Ok(())
+    }
+    // An arrow from where the opponent's last move started to where it went
+    fn draw_last_move_arrow(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let (Some(origin), Some(end)) = (self.last_move_origin, self.last_move_end) else {
+            return Ok(());
+        };
+        // The player's own moves don't need pointing out, and in analysis every move is the player's.
+        // In a hot-seat game the last move is always the other player's
+        if self.analysis_mode
+            || (!self.hot_seat() && self.game.board.active_team != self.game.player_team)
+        {
+            return Ok(());
+        }
+        let square_size = self.square_size();
+        let centre = |square| {
+            let (x, y) = self.square_origin(square);
+            Vec2::new(x + square_size / 2.0, y + square_size / 2.0)
+        };
+        let (from, to) = (centre(origin), centre(end));
+        let direction = (to - from).normalize_or_zero();
+        let head_length = square_size * 0.35;
+        // The shaft stops where the head starts, so the two don't overlap where they're see-through
+        let head_base = to - direction * head_length;
+        let across = direction.perp() * head_length * 0.6;
+        let color = Color::from(LAST_MOVE_ARROW_COLOR);
+
+        let shaft = graphics::Mesh::new_line(ctx, &[from, head_base], square_size * 0.12, color)?;
+        let head = graphics::Mesh::new_polygon(
+            ctx,
+            graphics::DrawMode::fill(),
+            &[to, head_base + across, head_base - across],
+            color,
+        )?;
+        canvas.draw(&shaft, DrawParam::default());
+        canvas.draw(&head, DrawParam::default());
+        Ok(())
+    }
+    /*
+        A see-through copy of the piece the opponent currently means to move, standing on the square it would go to.
+        Shown while the opponent is still thinking, so it can change before the move is made
+    */
+    fn draw_expected_reply(&self, canvas: &mut Canvas) -> GameResult<()> {
+        let Some(reply) = self.expected_reply.filter(|_| self.show_expected_reply) else {
+            return Ok(());
+        };
+        let board = &self.game.board;
+        if board.get_square_team(reply.start) != board.active_team {
+            return Ok(());
+        }
+        let (x, y) = self.square_origin(reply.target);
+
+        self.draw_piece(
+            canvas,
+            board.piece_list[reply.start],
+            board.active_team,
+            x,
+            y,
+            Color::new(1.0, 1.0, 1.0, GHOST_PIECE_ALPHA),
+        )
+    }
+    fn draw_panel(&mut self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        // The side panel to the right of the board
+        let mut line_ends = Vec::new(); // Where each engine line starts and ends in the text
+        let panel_text = if let Some(dialog) = &self.fen_dialog {
+            let status = match &dialog.preview {
+                Ok(_) => String::from("Valid position"),
+                Err(err) => format!("Invalid: {err}"),
+            };
+            format!(
+                "Paste or type a FEN:\n\n{}_\n\n{status}\nCtrl+V: paste\nDel: clear\nEnter: play from here\nTab: analyse from here\nEsc: cancel",
+                dialog.input.wrapped(PANEL_CHARS_PER_LINE)
+            )
+        } else {
+            let mover = if self.analysis_mode || !self.opponent_to_move() {
+                String::from("you")
+            } else if self.netplay.is_some() {
+                String::from("the other player")
+            } else {
+                self.game.engine_to_move().to_string()
+            };
+            let mut panel_text = format!("{:?} to move ({mover})\n", self.game.board.active_team);
+            if let Some(trainer) = &self.trainer {
+                panel_text.push_str(&format!(
+                    "Drilling {:?}'s lines\n{} of {} due\n{}I: hint\nN: next line\n",
+                    trainer.team,
+                    trainer.due_count(Utc::now()),
+                    trainer.lines.len(),
+                    if trainer.is_line_done() {
+                        "Line done\n"
+                    } else {
+                        ""
+                    }
+                ));
+            } else if let Some(session) = &self.puzzles {
+                if let Some(attempt) = &session.attempt {
+                    panel_text.push_str(&format!(
+                        "Puzzle {}, rated {}\n{}\n",
+                        attempt.puzzle.id,
+                        attempt.puzzle.rating,
+                        if attempt.is_solved() {
+                            String::from("Solved")
+                        } else {
+                            format!("Find the best move for {:?}", self.game.player_team)
+                        }
+                    ));
+                }
+                panel_text.push_str(&format!(
+                    "Your rating {} ({} solved of {})\nI: hint\nN: next puzzle\n",
+                    session.rating.rating, session.rating.solved, session.rating.played
+                ));
+            } else if let Some(netplay) = &self.netplay {
+                panel_text.push_str(&format!(
+                    "Network game, {} ({})\n",
+                    if netplay.hosting { "hosting" } else { "joined" },
+                    if netplay.connected {
+                        "connected"
+                    } else {
+                        "waiting for the other player"
+                    }
+                ));
+                if netplay.peer_offers_draw {
+                    panel_text.push_str("They offer a draw, D: accept\n");
+                } else if netplay.draw_offered {
+                    panel_text.push_str("Draw offered\n");
+                }
+            } else if let Some(engine) = self.game.spectated.as_ref().filter(|_| self.spectating())
+            {
+                panel_text.push_str(&format!(
+                    "Watching {engine} vs {}\nEval {}\nMove delay {:.2}s\nSpace: {}\n.: step one move\n[ ]: change the delay\nW: stop watching\n",
+                    self.game.opponent,
+                    self.live_eval
+                        .map_or(String::from("-"), |eval| format!("{} for white", format_score(eval))),
+                    self.move_delay.as_secs_f32(),
+                    if self.paused { "carry on" } else { "pause" }
+                ));
+            } else if self.hot_seat() {
+                panel_text.push_str(&format!(
+                    "Hot seat\nU: turn the board each move ({})\n",
+                    if self.auto_flip { "on" } else { "off" }
+                ));
+            }
+            if let Some((start, target)) = self.premove {
+                panel_text.push_str(&format!(
+                    "Premove {}{} (click to cancel)\n",
+                    self.game.board.size.notation(start).unwrap_or_default(),
+                    self.game.board.size.notation(target).unwrap_or_default()
+                ));
+            }
+            if let Some(ply) = self.viewed_ply {
+                panel_text.push_str(&format!(
+                    "Looking at move {} of {}\nEnd: back to the game\n",
+                    ply,
+                    self.game.move_history.len()
+                ));
+            }
+            if let Some((reason, _)) = self
+                .move_rejection
+                .filter(|(_, refused_at)| refused_at.elapsed() < REJECTION_SHOWN_FOR)
+            {
+                panel_text.push_str(&format!("{}\n", reason.describe(self.game.board.size)));
+            }
+            if let Some(replay) = &self.replay {
+                let game = &replay.game;
+                panel_text.push_str(&format!(
+                    "{} - {} {}\n",
+                    game.tag("White").unwrap_or("?"),
+                    game.tag("Black").unwrap_or("?"),
+                    game.result
+                ));
+                panel_text.push_str(&match self.replay_ply() {
+                    Some(ply) => format!("Move {ply}/{}\n", replay.moves.len()),
+                    None => String::from("Off the game's moves\n"),
+                });
+                panel_text.push_str("Left/Right: step through\nHome/End: go to start/end\n");
+            }
+            if self.promotion_choice.is_some() {
+                panel_text.push_str(
+                    "Promote to:\nQ: queen\nR: rook\nB: bishop\nN: knight\nEsc: cancel\n",
+                );
+            }
+            if let (Some(_), Some(started)) = (&self.opp_thread, self.search_started) {
+                let elapsed = started.elapsed();
+                let frame = (elapsed.as_millis() / THINKING_FRAME_INTERVAL.as_millis()) as usize;
+                panel_text.push_str(&format!(
+                    "Thinking {} {:.1}s",
+                    THINKING_SPINNER[frame % THINKING_SPINNER.len()],
+                    elapsed.as_secs_f32()
+                ));
+                if let Some(depth) = self.thinking_depth {
+                    panel_text.push_str(&format!(", depth {depth}"));
+                }
+                panel_text.push('\n');
+            }
+            if let Some(clock) = self.game.clock.as_ref().filter(|_| !self.analysis_mode) {
+                for team in [Team::White, Team::Black] {
+                    panel_text.push_str(&format!(
+                        "{team:?} {} ({})\n",
+                        format_clock(clock.remaining(team)),
+                        clock.control(team)
+                    ));
+                }
+            }
+            if let Some(board) = &self.ics_board {
+                panel_text.push_str(&format!(
+                    "Game {}{}\n{} {}\n{} {}\n",
+                    board.game_number,
+                    if board.is_observed() {
+                        " (observing)"
+                    } else {
+                        ""
+                    },
+                    board.white,
+                    format_clock(board.white_time),
+                    board.black,
+                    format_clock(board.black_time)
+                ));
+            }
+            if let Some(correspondence) =
+                self.correspondence.as_ref().filter(|_| !self.analysis_mode)
+            {
+                panel_text.push_str(&format!(
+                    "{} days a move, reply by {}\n",
+                    correspondence.days_per_move,
+                    correspondence
+                        .deadline()
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M")
+                ));
+            }
+            panel_text.push('\n');
+
+            // Draws can be claimed at 100 half-moves without a pawn move or capture, or on a third repetition
+            panel_text.push_str(&format!(
+                "Fifty-move count: {}/100\nRepetitions: {}/3\n\n",
+                self.game.board.fifty_move_clock,
+                self.game.history.repetition_count()
+            ));
+            match self.game.board.variant {
+                Variant::Standard => {}
+                Variant::ThreeCheck => panel_text.push_str(&format!(
+                    "Three-check: White {}/3, Black {}/3\n\n",
+                    self.game.board.checks_given[0], self.game.board.checks_given[1]
+                )),
+                variant => panel_text.push_str(&format!("{variant}\n\n")),
+            }
+            if let Some(entry) = &self.move_entry {
+                panel_text.push_str(&format!(
+                    "Move: {}_\n",
+                    entry.input.wrapped(PANEL_CHARS_PER_LINE)
+                ));
+                if let Some(rejection) = &entry.rejection {
+                    panel_text.push_str(&format!("{rejection}\n"));
+                }
+                panel_text.push('\n');
+            }
+            if let Some(repertoire) = &self.repertoire {
+                let prepared: Vec<String> = repertoire
+                    .moves_for(&self.game.board)
+                    .iter()
+                    .map(|book_move| book_move.to_san(&self.game.board))
+                    .collect();
+                if !prepared.is_empty() {
+                    panel_text.push_str(&format!("Repertoire: {}\n", prepared.join(", ")));
+                }
+                if let Some(note) = &self.book_note {
+                    panel_text.push_str(&format!("{note}\n"));
+                }
+                panel_text.push('\n');
+            }
+            if self.show_tactics {
+                let tactics = find_tactics(&self.game.board, self.game.board.active_team);
+                panel_text.push_str("Tactics:\n");
+                if tactics.is_empty() {
+                    panel_text.push_str("none\n");
+                }
+                for tactic in tactics {
+                    panel_text.push_str(&format!("{}\n", tactic.describe(self.game.board.size)));
+                }
+                panel_text.push('\n');
+            }
+            if let Some(dgt) = &self.dgt {
+                panel_text.push_str(&format!("Electronic board on {}\n", dgt.path));
+                if !self.dgt_unmirrored.is_empty() {
+                    panel_text.push_str(&format!(
+                        "Play {} on the board\n",
+                        self.dgt_unmirrored.join(" ")
+                    ));
+                }
+                panel_text.push('\n');
+            }
+            if let Some(note) = &self.action_note {
+                panel_text.push_str(&format!("{note}\n\n"));
+            }
+            if let Some(note) = &self.voice_note {
+                panel_text.push_str(&format!("{note}\n"));
+                for (number, choice) in self.voice_choices.iter().enumerate() {
+                    panel_text.push_str(&format!(
+                        "  {}. {}\n",
+                        number + 1,
+                        choice.to_san(&self.game.board)
+                    ));
+                }
+                panel_text.push('\n');
+            }
+            if let Some(reply) = self.expected_reply.filter(|_| self.show_expected_reply) {
+                panel_text.push_str(&format!(
+                    "Expected reply: {}\n\n",
+                    reply.to_san(&self.game.board)
+                ));
+            }
+            if let Some(ponder) = &self.ponder {
+                panel_text.push_str(&format!(
+                    "Pondering {}\n\n",
+                    ponder.expected.to_san(&self.game.board)
+                ));
+            }
+            // Until update catches up with a move, the lines are still for the position before it
+            let shown = self.shown_board();
+            let analysed = self
+                .analysis_search
+                .as_ref()
+                .map(|(position, _, _)| *position);
+            if !self.analysis_lines.is_empty() && analysed == Some(shown.zobrist) {
+                panel_text.push_str(&format!(
+                    "Engine lines, depth {} (click to play):",
+                    self.analysis_depth
+                ));
+                for line in &self.analysis_lines {
+                    let start = panel_text.len();
+                    panel_text.push_str(&format!(
+                        "\n{} {}",
+                        format_score(line.eval),
+                        line_to_san(&shown, &line.pv).join(" ")
+                    ));
+                    line_ends.push((start, panel_text.len()));
+                }
+                panel_text.push_str("\n\n");
+            }
+            if let Some(info) = &self.last_search_info {
+                panel_text.push_str(&format!(
+                    "{} depth {}/{} hashfull {}\u{2030}\n{} nodes, {} nps, {} cutoffs, {} TT hits\n\n",
+                    format_score(info.score),
+                    info.depth,
+                    info.seldepth,
+                    info.hashfull,
+                    info.nodes,
+                    info.nps(),
+                    info.cutoffs,
+                    info.tt_hits
+                ));
+                // The line is only worth showing while it still starts from the position on the board
+                if self.opponent_to_move() && !info.pv.is_empty() {
+                    panel_text.push_str(&format!(
+                        "PV: {}\n\n",
+                        line_to_san(&self.game.board, &info.pv).join(" ")
+                    ));
                 }
             }
+            panel_text.push_str(&if !self.explain_search {
+                format!(
+                    "Opponent: {}\nO: change opponent\n+/-: adjust strength\nE: explain engine moves\nT: show tactics\nG: ghost expected reply\nP: ponder on your time ({})\nA: always promote to a queen ({})\nS: sound pack ({})\nF: set up a position\nN: new game\nR: flip the board\nH: blindfold ({})\nL: analysis board\nW: watch two engines\nK: settings\nF11: full screen\nD: offer a draw\nCtrl+R: resign\nCtrl+Z: take back\nCtrl+C: copy FEN (Shift: PGN)\nEnter: type a move",
+                    self.game.opponent,
+                    if self.pondering { "on" } else { "off" },
+                    if self.auto_queen { "on" } else { "off" },
+                    self.audio.pack,
+                    if self.blindfold { "on" } else { "off" }
+                )
+            } else if let Some(explanation) = &self.last_explanation {
+                format!("{} explains:\n{explanation}", self.game.opponent)
+            } else {
+                format!("{} will explain its next move", self.game.opponent)
+            });
+            panel_text
+        };
+
+        // The text stops short of the buttons along the bottom
+        let bounds = Vector2 {
+            x: PANEL_WIDTH,
+            y: PANEL_BUTTON_TOP - (2.0 * PANEL_MARGIN),
+        };
+        // Each engine line's height is measured from the text up to the line, as the panel wraps it
+        let height_to = |end: usize| -> GameResult<f32> {
+            let mut text_mesh = Text::new(&panel_text[..end]);
+            text_mesh.set_bounds(bounds);
+            Ok(text_mesh.measure(ctx)?.y)
+        };
+        self.analysis_line_spans = line_ends
+            .iter()
+            .map(|&(start, end)| Ok((height_to(start)?, height_to(end)?)))
+            .collect::<GameResult<_>>()?;
+
+        let mut text_mesh = Text::new(panel_text);
+        text_mesh.set_bounds(bounds);
+        canvas.draw(
+            &text_mesh,
+            DrawParam::default().dest(Point2 {
+                x: WIDTH + PANEL_MARGIN,
+                y: PANEL_MARGIN,
+            }),
+        );
+        if self.fen_dialog.is_none() {
+            self.draw_panel_buttons(ctx, canvas)?;
         }
         Ok(())
     }
-    fn get_square_idx_from_pixel(x: f32, y: f32) -> f32 {
-        let file = (x / SQUARE_SIZE).floor();
-        let rank = (y / SQUARE_SIZE).floor();
+    fn draw_panel_buttons(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        for (button, rect) in panel_button_rects() {
+            let shape = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                rect,
+                Color::from(PANEL_BUTTON_COLOR),
+            )?;
+            canvas.draw(&shape, DrawParam::default());
+            let label = Text::new(button.label());
+            let size = label.measure(ctx)?;
+            canvas.draw(
+                &label,
+                DrawParam::default().dest(Point2 {
+                    x: rect.x + (rect.w - size.x) / 2.0,
+                    y: rect.y + (rect.h - size.y) / 2.0,
+                }),
+            );
+        }
+        Ok(())
+    }
+    /*
+        The squares the promotion picker puts each piece on: down the promotion square's file,
+        from the promotion square towards the middle of the board
+    */
+    fn promotion_squares(
+        size: BoardSize,
+        target: usize,
+    ) -> impl Iterator<Item = (PieceType, usize)> {
+        promotion_choices(size)
+            .iter()
+            .enumerate()
+            .map(move |(steps, &piece)| {
+                let square = if size.rank(target) == size.last_rank() {
+                    target - (steps * size.files())
+                } else {
+                    target + (steps * size.files())
+                };
+                (piece, square)
+            })
+    }
+    // Shades a band across the middle of the board with how the game ended written in it
+    fn draw_result_banner(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let Some(status) = self.finished else {
+            return Ok(());
+        };
+        let band_y = SQUARE_SIZE * 3.0;
+        let band_height = SQUARE_SIZE * 2.0;
+        let shade = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            Rect::new(0.0, band_y, WIDTH, band_height),
+            Color::from(BANNER_SHADE),
+        )?;
+        canvas.draw(&shade, DrawParam::default());
+
+        // Games kept on disk or on a server can't be followed by a new one from here
+        let text = if self.correspondence.is_none() && self.ics.is_none() && self.netplay.is_none()
+        {
+            format!("{status}\nN: new game  M: rematch")
+        } else {
+            status.to_string()
+        };
+        let mut text_mesh = Text::new(text);
+        text_mesh.set_scale(SQUARE_SIZE * 0.35);
+        let size = text_mesh.measure(ctx)?;
+        canvas.draw(
+            &text_mesh,
+            DrawParam::default()
+                .dest(Point2 {
+                    x: (WIDTH - size.x) / 2.0,
+                    y: band_y + (band_height - size.y) / 2.0,
+                })
+                .color(Color::WHITE),
+        );
+        Ok(())
+    }
+    // How the eval went over the reviewed game, in a band below the result banner, white's advantage up
+    fn draw_eval_graph(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let evals: Vec<i32> = self
+            .game
+            .move_history
+            .iter()
+            .map_while(|entry| entry.review.as_ref().map(|review| review.eval))
+            .collect();
+        if self.finished.is_none() || evals.is_empty() {
+            return Ok(());
+        }
+        let graph = Rect::new(
+            SQUARE_SIZE * 0.25,
+            SQUARE_SIZE * 5.5,
+            WIDTH - SQUARE_SIZE * 0.5,
+            SQUARE_SIZE * 2.0,
+        );
+        let shade = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graph,
+            Color::from(BANNER_SHADE),
+        )?;
+        canvas.draw(&shade, DrawParam::default());
+        let middle = graphics::Mesh::new_line(
+            ctx,
+            &[
+                Vec2::new(graph.left(), graph.center().y),
+                Vec2::new(graph.right(), graph.center().y),
+            ],
+            1.0,
+            Color::from(EVAL_GRAPH_MIDDLE_COLOR),
+        )?;
+        canvas.draw(&middle, DrawParam::default());
 
-        63.0 - ((rank * 8.0) + (7.0 - file))
+        // The game starts level, before the first move's eval
+        let step = graph.w / evals.len() as f32;
+        let points: Vec<Vec2> = std::iter::once(0)
+            .chain(evals)
+            .enumerate()
+            .map(|(index, eval)| {
+                Vec2::new(
+                    graph.x + index as f32 * step,
+                    graph.bottom() - graph.h * analysis::eval_graph_height(eval),
+                )
+            })
+            .collect();
+        let line = graphics::Mesh::new_line(ctx, &points, 2.0, Color::from(EVAL_GRAPH_LINE_COLOR))?;
+        canvas.draw(&line, DrawParam::default());
+        Ok(())
     }
-    fn play_sound(&mut self, ctx: &mut Context, id: &str, volume: f32) -> GameResult<()> {
-        let sound = self.sound_sources.get_mut(id).unwrap();
-        sound.set_volume(volume);
-        sound.play(ctx)?;
+    fn draw_promotion_picker(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let Some((start, target)) = self.promotion_choice else {
+            return Ok(());
+        };
+        let shade = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            Rect::new(0.0, 0.0, WIDTH, WIDTH),
+            Color::from(PROMOTION_SHADE),
+        )?;
+        canvas.draw(&shade, DrawParam::default());
 
+        let team = self.game.board.get_square_team(start);
+        let square_size = self.square_size();
+        for (piece, square) in MainState::promotion_squares(self.game.board.size, target) {
+            let (x, y) = self.square_origin(square);
+            let backing = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                Rect::new(x, y, square_size, square_size),
+                Color::from(SELECTED_SQUARE_COLOR),
+            )?;
+            canvas.draw(&backing, DrawParam::default());
+            self.draw_piece(canvas, piece, team, x, y, Color::WHITE)?;
+        }
         Ok(())
     }
+    // Queues the waiting promotion as `piece`, or just closes the picker if that isn't one of the moves
+    fn choose_promotion(&mut self, piece: Option<PieceType>) {
+        let Some((start, target)) = self.promotion_choice.take() else {
+            return;
+        };
+        self.queued_move = self.board_legal_moves.as_ref().and_then(|pl_moves| {
+            pl_moves[start]
+                .1
+                .iter()
+                .find(|fmove| fmove.target == target && fmove.promotion == piece)
+                .copied()
+        });
+    }
+    // How wide a square is drawn, so the board's longer side fills the space a standard board takes
+    fn square_size(&self) -> f32 {
+        if matches!(self.scene, Scene::ThreePlayer(_)) {
+            return WIDTH / THREE_PLAYER_SIDE as f32;
+        }
+        let size = self.game.board.size;
+        WIDTH / size.files().max(size.ranks()) as f32
+    }
+    // The top left corner of where `square` is drawn, with white at the bottom unless the board is flipped
+    fn square_origin(&self, square: usize) -> (f32, f32) {
+        let size = self.game.board.size;
+        let (file, rank) = (size.file(square) as f32, size.rank(square) as f32);
+        let (last_file, last_rank) = ((size.files() - 1) as f32, size.last_rank() as f32);
+        let square_size = self.square_size();
+        if self.flipped {
+            ((last_file - file) * square_size, rank * square_size)
+        } else {
+            (file * square_size, (last_rank - rank) * square_size)
+        }
+    }
+    // Where a point on the window is in the layout everything is drawn at, which the window is scaled from
+    fn layout_point(ctx: &Context, x: f32, y: f32) -> (f32, f32) {
+        let (width, height) = ctx.gfx.drawable_size();
+        to_layout(width, height, x, y)
+    }
+    fn get_square_idx_from_pixel(&self, x: f32, y: f32) -> f32 {
+        let size = self.game.board.size;
+        let square_size = self.square_size();
+        let (files, ranks) = (size.files() as f32, size.ranks() as f32);
+        // Anywhere off the board, like the bars either side of a stretched window, is past the last square
+        if !(0.0..files * square_size).contains(&x) || !(0.0..ranks * square_size).contains(&y) {
+            return size.squares() as f32;
+        }
+        let file = (x / square_size).floor();
+        let rank = (y / square_size).floor();
+
+        if self.flipped {
+            (rank * files) + (files - 1.0 - file)
+        } else {
+            ((ranks - 1.0 - rank) * files) + file
+        }
+    }
+    // The move list's first line on screen. Long games scroll to keep the move the board is showing in view
+    fn move_list_first_row(&self) -> usize {
+        let first_ply = analysis::first_ply(&self.game.start_board);
+        let shown_ply = self.viewed_ply.unwrap_or(self.game.move_history.len());
+        let shown_row = (first_ply + shown_ply.saturating_sub(1)) / 2 - first_ply / 2;
+        (shown_row + 1).saturating_sub(MOVE_LIST_ROWS)
+    }
+    // How many moves in the position clicked on in the move list is, or None if the click missed every move
+    fn move_list_ply_at(&self, x: f32, y: f32) -> Option<usize> {
+        let line = ((y - MOVE_LIST_TOP) / MOVE_LIST_LINE_HEIGHT).floor();
+        if x < MOVE_LIST_X || line < 0.0 {
+            return None;
+        }
+        let first_ply = analysis::first_ply(&self.game.start_board);
+        let row = line as usize + self.move_list_first_row() + first_ply / 2;
+        let ply = row * 2 + (x >= MOVE_LIST_X + MOVE_LIST_BLACK_X) as usize;
+        let index = ply.checked_sub(first_ply)?;
+        (index < self.game.move_history.len()).then_some(index + 1)
+    }
+    // The moves so far as numbered pairs in SAN, right of the panel, with the one the board is showing picked out
+    fn draw_move_list(&mut self, canvas: &mut Canvas) {
+        let played: Vec<Move> = self.game.history.moves_played().collect();
+        if self.move_list.0 != played {
+            self.move_list = (played, self.game.san_moves());
+            self.opening = self.game.opening();
+        }
+        if let Some(opening) = self.opening {
+            let mut text_mesh = Text::new(format!("{} {}", opening.eco, opening.name));
+            text_mesh.set_bounds(Vector2 {
+                x: MOVE_LIST_WIDTH - PANEL_MARGIN,
+                y: MOVE_LIST_TOP - PANEL_MARGIN,
+            });
+            canvas.draw(
+                &text_mesh,
+                DrawParam::default()
+                    .dest(Point2 {
+                        x: MOVE_LIST_X,
+                        y: PANEL_MARGIN,
+                    })
+                    .color(Color::from(OPENING_NAME_COLOR)),
+            );
+        }
+        let first_ply = analysis::first_ply(&self.game.start_board);
+        let first_row = self.move_list_first_row();
+
+        for (index, san) in self.move_list.1.iter().enumerate() {
+            let ply = first_ply + index;
+            let row = ply / 2 - first_ply / 2;
+            if !(first_row..first_row + MOVE_LIST_ROWS).contains(&row) {
+                continue;
+            }
+            let y = MOVE_LIST_TOP + (row - first_row) as f32 * MOVE_LIST_LINE_HEIGHT;
+            // A game that starts with black to move leaves white's first move out
+            if ply.is_multiple_of(2) || index == 0 {
+                let number = format!(
+                    "{}.{}",
+                    ply / 2 + 1,
+                    if ply.is_multiple_of(2) { "" } else { ".." }
+                );
+                canvas.draw(
+                    &Text::new(number),
+                    DrawParam::default().dest(Point2 { x: MOVE_LIST_X, y }),
+                );
+            }
+            let x = MOVE_LIST_X
+                + if ply.is_multiple_of(2) {
+                    MOVE_LIST_WHITE_X
+                } else {
+                    MOVE_LIST_BLACK_X
+                };
+            let quality = self
+                .game
+                .move_history
+                .get(index)
+                .and_then(|entry| entry.review.as_ref())
+                .map(|review| review.quality);
+            let color = match quality {
+                _ if self.viewed_ply == Some(index + 1) => Color::from(VIEWED_MOVE_COLOR),
+                Some(MoveQuality::Inaccuracy) => Color::from(INACCURACY_COLOR),
+                Some(MoveQuality::Mistake) => Color::from(MISTAKE_COLOR),
+                Some(MoveQuality::Blunder) => Color::from(BLUNDER_COLOR),
+                _ => Color::WHITE,
+            };
+            let suffix = quality.map_or("", |quality| quality.pgn_suffix());
+            canvas.draw(
+                &Text::new(format!("{san}{suffix}")),
+                DrawParam::default().dest(Point2 { x, y }).color(color),
+            );
+        }
+    }
 }
 
 impl event::EventHandler<ggez::GameError> for MainState {
     fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        // Nothing is played until the setup menu, or the offer of last time's game, has been left
+        if matches!(
+            self.scene,
+            Scene::Setup(_) | Scene::Resume(_) | Scene::ThreePlayer(_)
+        ) {
+            return Ok(());
+        }
+        if self.ics.is_some() {
+            // The server runs the clocks and decides when games end, so none of the local game logic applies
+            self.handle_ics_events();
+            return Ok(());
+        }
+        if self.drilling() {
+            // Puzzles and drills play out from the moves they expect, without clocks, engines or an ending to save
+            self.handle_puzzle();
+            self.handle_training();
+            return Ok(());
+        }
+        // The other player is still heard from once the game is over, to check their game ended the same way
+        self.handle_net_events();
+        // A finished game stays as it ended until a new one is set up
+        if self.finished.is_some() {
+            return Ok(());
+        }
+        self.handle_dgt_board();
+        self.handle_voice();
+        self.handle_analysis();
+        if let Some(clock) = self
+            .game
+            .clock
+            .as_mut()
+            .filter(|_| !self.analysis_mode && !self.paused)
+        {
+            if !clock.is_running() {
+                clock.start(self.game.board.active_team);
+            }
+        }
+        self.warn_low_time();
+        self.autosave(false);
+        let status = self.game.status();
+        let game_over = status.is_over();
+        if game_over && !self.analysis_mode {
+            self.end_game(status);
+            return Ok(());
+        }
+        self.start_pondering();
+        // Across the network the other player's moves come in as messages instead
         if self.opp_thread.is_none()
-            && self.player_team != self.board.active_team
-            && !self.board.active_team_checkmate
+            && self.opponent_to_move()
+            && !game_over
+            && self.netplay.is_none()
+            && self.engine_may_start()
         {
+            self.step_move = false;
+            let pondered = self.ponder_hit();
+            // The opponent sticks to the repertoire while it can, so the player gets to practise their lines
+            let book_move = self
+                .repertoire
+                .as_ref()
+                .filter(|_| self.game.engine_to_move().uses_book())
+                .and_then(|repertoire| repertoire.pick(&self.game.board));
+            if book_move.is_some() {
+                self.queued_move = book_move;
+                return Ok(());
+            }
             let (mv_tx, mv_rx) = std::sync::mpsc::channel();
-            let mut opponent_clone = self.opponent;
-            let board_clone = self.board.clone();
+            if let Some(outcome) = pondered {
+                self.game.engine_stats.ponder_hits += 1;
+                let _ = mv_tx.send(OpponentMessage::Finished(outcome));
+                self.opp_thread = Some(mv_rx);
+                self.search_started = Some(Instant::now());
+                return Ok(());
+            }
+            let mut opponent_clone = self.game.engine_to_move().clone();
+            // On the clock, a timed opponent thinks for as long as its share of the time left allows
+            // while an engine over UCI is handed both clocks to manage its time itself
+            match (&mut opponent_clone, &self.game.clock) {
+                (ChessOpponent::Ada(options, _), Some(clock)) => {
+                    options.move_time = clock.move_budget(self.game.board.active_team)
+                }
+                (ChessOpponent::Uci(_, time, _), Some(clock)) => *time = UciTime::from_clock(clock),
+                _ => {}
+            }
+            let board_clone = self.game.board;
+            let history = self.game.history.clone();
+            let explain = self.explain_search;
 
             tokio::spawn(async move {
-                let legal = opponent_clone.get_move(board_clone);
-                mv_tx.send(legal).unwrap();
+                let progress_tx = mv_tx.clone();
+                let outcome = opponent_clone.think(board_clone, &history, explain, &mut |info| {
+                    let _ = progress_tx.send(OpponentMessage::Progress(info.clone()));
+                });
+                let _ = mv_tx.send(OpponentMessage::Finished(outcome));
             });
             self.opp_thread = Some(mv_rx);
+            self.search_started = Some(Instant::now());
+            self.expected_reply = None;
+            self.thinking_depth = None;
         }
-        self.queued_move = if self.player_team != self.board.active_team {
+        self.queued_move = if self.opponent_to_move() {
+            let mut finished: Option<SearchOutcome> = None;
+            let mover = self.game.board.active_team;
             if let Some(ot) = &self.opp_thread {
-                let legal = ot.try_recv();
-
-                if let Ok(legal_move) = legal {
-                    if legal_move.is_none() {
-                        self.end_game();
+                while let Ok(message) = ot.try_recv() {
+                    match message {
+                        OpponentMessage::Progress(info) => {
+                            self.expected_reply = info.best_move;
+                            self.thinking_depth = Some(info.depth);
+                            self.live_eval = Some(white_score(info.score, mover));
+                            self.last_search_info = Some(info);
+                        }
+                        OpponentMessage::Finished(outcome) => {
+                            let think_time = self
+                                .search_started
+                                .take()
+                                .map(|started| started.elapsed())
+                                .unwrap_or_default();
+                            self.game.engine_stats.record(&outcome.info, think_time);
+                            self.live_eval = Some(white_score(outcome.info.score, mover));
+                            if mover != self.game.player_team {
+                                self.game.opponent_scores.push(outcome.info.score);
+                            }
+                            finished = Some(outcome)
+                        }
                     }
-                    legal_move
-                } else {
-                    self.queued_move
                 }
+            }
+
+            if let Some(outcome) = finished {
+                self.last_search_info = Some(outcome.info);
+
+                if let Some(explanation) = outcome.explanation {
+                    let explained_ply = self.game.move_history.len();
+                    self.game.game_log.push(format!(
+                        "{}{} {explanation}",
+                        (explained_ply / 2) + 1,
+                        if explained_ply.is_multiple_of(2) {
+                            "."
+                        } else {
+                            "..."
+                        }
+                    ));
+                    self.last_explanation = Some(explanation);
+                }
+                if outcome.best_move.is_none() {
+                    self.end_game(self.game.status());
+                    return Ok(());
+                }
+                outcome.best_move
             } else {
                 self.queued_move
             }
         } else {
-            let legal_moves = self.board.prune_moves_for_team(
+            let legal_moves = self.game.board.prune_moves_for_team(
                 self.board_legal_moves.clone().unwrap_or(vec![]),
-                self.board.active_team,
+                self.game.board.active_team,
             );
             if legal_moves.len() == 0 {
-                self.end_game();
-                process::exit(0);
+                if self.analysis_mode {
+                    // There's no game to end, the position just sits there until another one is set up
+                    return Ok(());
+                }
+                self.end_game(self.game.status());
+                return Ok(());
             } else {
                 self.queued_move
             }
@@ -496,19 +3511,438 @@ impl event::EventHandler<ggez::GameError> for MainState {
 
         Ok(())
     }
-    fn mouse_button_down_event(
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> Result<(), ggez::GameError> {
+        if input.keycode == Some(KeyCode::F11) {
+            self.fullscreen = !self.fullscreen;
+            ctx.gfx.set_fullscreen(if self.fullscreen {
+                FullscreenType::Desktop
+            } else {
+                FullscreenType::Windowed
+            })?;
+            return Ok(());
+        }
+        if let Scene::Setup(setup) = &mut self.scene {
+            match input.keycode {
+                Some(KeyCode::Up) => setup.row = (setup.row + SETUP_ROWS - 1) % SETUP_ROWS,
+                Some(KeyCode::Down) => setup.row = (setup.row + 1) % SETUP_ROWS,
+                Some(KeyCode::Left) => setup.change(-1),
+                Some(KeyCode::Right) => setup.change(1),
+                // Back to the game the menu was opened from
+                Some(KeyCode::Escape) => self.scene = Scene::Playing,
+                Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) => {
+                    if let Scene::Setup(setup) = std::mem::replace(&mut self.scene, Scene::Playing)
+                    {
+                        self.start_game(setup);
+                    }
+                }
+                Some(KeyCode::Key3) => self.start_three_player(),
+                _ => {}
+            }
+            return Ok(());
+        }
+        if matches!(self.scene, Scene::ThreePlayer(_)) {
+            match input.keycode {
+                Some(KeyCode::N) => self.start_three_player(),
+                Some(KeyCode::Escape) => self.show_setup(),
+                _ => {}
+            }
+            return Ok(());
+        }
+        if matches!(self.scene, Scene::Resume(_)) {
+            match input.keycode {
+                Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) => {
+                    if let Scene::Resume(saved) = std::mem::replace(&mut self.scene, Scene::Playing)
+                    {
+                        if let Err(err) = self.resume_saved_game(saved) {
+                            eprintln!("Couldn't pick up the last game: {err}");
+                            self.discard_autosave();
+                            self.show_setup();
+                        }
+                    }
+                }
+                Some(KeyCode::Escape) => {
+                    self.discard_autosave();
+                    self.show_setup();
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        if let Scene::Settings(menu) = &mut self.scene {
+            match input.keycode {
+                Some(KeyCode::Up) => menu.row = (menu.row + SETTINGS_ROWS - 1) % SETTINGS_ROWS,
+                Some(KeyCode::Down) => menu.row = (menu.row + 1) % SETTINGS_ROWS,
+                Some(KeyCode::Left) | Some(KeyCode::Right) => {
+                    let step = if input.keycode == Some(KeyCode::Left) {
+                        -1
+                    } else {
+                        1
+                    };
+                    let row = menu.row;
+                    self.change_setting(ctx, row, step);
+                }
+                Some(KeyCode::Escape) => {
+                    let saved = menu.saved;
+                    self.apply_settings(ctx, saved);
+                    self.scene = Scene::Playing;
+                }
+                Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) => {
+                    if let Err(err) = self.settings.save(&self.settings_path) {
+                        self.action_note = Some(err);
+                    }
+                    self.scene = Scene::Playing;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        if let Some(dialog) = &mut self.fen_dialog {
+            match input.keycode {
+                Some(KeyCode::Back) => {
+                    dialog.input.backspace();
+                    dialog.update_preview();
+                }
+                Some(KeyCode::Delete) => {
+                    dialog.input.clear();
+                    dialog.update_preview();
+                }
+                Some(KeyCode::V) if input.mods.contains(KeyMods::CTRL) => dialog.paste(),
+                Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) | Some(KeyCode::Tab) => {
+                    if let Some(board) = dialog.preview.as_ref().ok().cloned() {
+                        let analysis = input.keycode == Some(KeyCode::Tab);
+                        self.fen_dialog = None;
+                        self.load_position(board, analysis);
+                    }
+                }
+                Some(KeyCode::Escape) => self.fen_dialog = None,
+                _ => {}
+            }
+            return Ok(());
+        }
+        if self.promotion_choice.is_some() {
+            match input.keycode {
+                Some(KeyCode::Q) => self.choose_promotion(Some(PieceType::Queen)),
+                Some(KeyCode::R) => self.choose_promotion(Some(PieceType::Rook)),
+                Some(KeyCode::B) => self.choose_promotion(Some(PieceType::Bishop)),
+                Some(KeyCode::N) => self.choose_promotion(Some(PieceType::Knight)),
+                Some(KeyCode::C) => self.choose_promotion(Some(PieceType::Chancellor)),
+                Some(KeyCode::A) => self.choose_promotion(Some(PieceType::Archbishop)),
+                Some(KeyCode::Escape) => self.promotion_choice = None,
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        if self.replay.is_some() {
+            match input.keycode {
+                Some(KeyCode::Right) => {
+                    self.replay_forward();
+                }
+                Some(KeyCode::Left) => {
+                    self.take_back();
+                }
+                Some(KeyCode::Home) => while self.take_back() {},
+                Some(KeyCode::End) => while self.replay_forward() {},
+                _ => {}
+            }
+        } else if self.move_entry.is_none() {
+            // Outside a replay the arrow keys look back through the game without changing it
+            let played = self.game.move_history.len();
+            match input.keycode {
+                Some(KeyCode::Left) => {
+                    self.view_ply(self.viewed_ply.unwrap_or(played).saturating_sub(1))
+                }
+                Some(KeyCode::Right) => {
+                    self.view_ply(self.viewed_ply.map_or(played, |ply| ply + 1))
+                }
+                Some(KeyCode::Home) => self.view_ply(0),
+                Some(KeyCode::End) => self.viewed_ply = None,
+                _ => {}
+            }
+        }
+
+        let can_move = !self.opponent_to_move() && self.queued_move.is_none();
+        if let Some(entry) = &mut self.move_entry {
+            match input.keycode {
+                Some(KeyCode::Back) => entry.input.backspace(),
+                Some(KeyCode::V) if input.mods.contains(KeyMods::CTRL) => {
+                    if let Some(pasted) = clipboard::paste() {
+                        entry.input.push_str(pasted.trim());
+                    }
+                }
+                Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) => {
+                    let typed = entry.input.text.trim().to_string();
+                    let parsed = Move::from_uci(&self.game.board, &typed)
+                        .or_else(|| Move::from_san(&self.game.board, &typed));
+
+                    match parsed {
+                        Some(typed_move) if can_move => {
+                            self.queued_move = Some(typed_move);
+                            self.move_entry = None;
+                        }
+                        Some(_) => entry.rejection = Some(String::from("Wait for your turn")),
+                        None => {
+                            // Coordinates say which piece was meant, so the refusal can be explained
+                            let squares =
+                                typed
+                                    .get(0..2)
+                                    .zip(typed.get(2..4))
+                                    .and_then(|(start, target)| {
+                                        let size = self.game.board.size;
+                                        size.square_at(start).zip(size.square_at(target))
+                                    });
+                            let reason = squares.and_then(|(start, target)| {
+                                explain_illegal_move(&self.game.board, start, target)
+                            });
+                            entry.rejection = Some(match reason {
+                                Some(reason) => {
+                                    format!("{typed}: {}", reason.describe(self.game.board.size))
+                                }
+                                None => format!("{typed} is not a legal move"),
+                            });
+                        }
+                    }
+                }
+                Some(KeyCode::Escape) => self.move_entry = None,
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        if matches!(
+            input.keycode,
+            Some(KeyCode::Return) | Some(KeyCode::NumpadEnter)
+        ) {
+            self.move_entry = Some(MoveEntry::default());
+        }
+        if input.keycode == Some(KeyCode::Z) && input.mods.contains(KeyMods::CTRL) {
+            self.press_panel_button(PanelButton::Undo);
+        }
+        if input.keycode == Some(KeyCode::C) && input.mods.contains(KeyMods::CTRL) {
+            // A game still being played is copied with PGN's unfinished result
+            let (what, text) = if input.mods.contains(KeyMods::SHIFT) {
+                let status = self.game.status();
+                let termination = if status.is_over() {
+                    "normal"
+                } else {
+                    "unterminated"
+                };
+                (
+                    "PGN",
+                    self.game
+                        .to_pgn(status.result().unwrap_or("*"), termination),
+                )
+            } else {
+                ("FEN", self.game.board.as_fen())
+            };
+            self.action_note = Some(if clipboard::copy(&text) {
+                format!("Copied the {what}")
+            } else {
+                format!("Couldn't copy the {what}: no clipboard tool found")
+            });
+        }
+        // Games kept on disk or on a server carry on where they are instead
+        if input.keycode == Some(KeyCode::N) && self.drilling() {
+            if let Err(err) = self.next_puzzle().and_then(|()| self.next_training_line()) {
+                self.action_note = Some(err);
+            }
+        } else if input.keycode == Some(KeyCode::N)
+            && self.correspondence.is_none()
+            && self.ics.is_none()
+            && self.netplay.is_none()
+        {
+            self.show_setup();
+        }
+        if input.keycode == Some(KeyCode::I) {
+            let hinted = if self.trainer.is_some() {
+                self.training_hint()
+            } else {
+                self.puzzle_hint()
+            };
+            if let Err(err) = hinted {
+                self.action_note = Some(err);
+            }
+        }
+        if input.keycode == Some(KeyCode::R) && input.mods.contains(KeyMods::CTRL) {
+            self.press_panel_button(PanelButton::Resign);
+        } else if input.keycode == Some(KeyCode::R) {
+            self.flipped = !self.flipped;
+        }
+        if input.keycode == Some(KeyCode::M) {
+            if let Err(err) = self.rematch() {
+                self.action_note = Some(err);
+            }
+        }
+        if input.keycode == Some(KeyCode::D) {
+            self.press_panel_button(PanelButton::OfferDraw);
+        }
+        if input.keycode == Some(KeyCode::L) {
+            if let Err(err) = self.start_analysis() {
+                self.action_note = Some(err);
+            }
+        }
+        if input.keycode == Some(KeyCode::K) {
+            self.show_settings();
+        }
+        if input.keycode == Some(KeyCode::W) {
+            if let Err(err) = self.toggle_spectating() {
+                self.action_note = Some(err);
+            }
+        }
+        if self.spectating() {
+            match input.keycode {
+                Some(KeyCode::Space) => self.toggle_pause(),
+                Some(KeyCode::Period) => self.step_move = self.paused,
+                Some(KeyCode::LBracket) => {
+                    self.move_delay = self.move_delay.saturating_sub(MOVE_DELAY_STEP)
+                }
+                Some(KeyCode::RBracket) => {
+                    self.move_delay = (self.move_delay + MOVE_DELAY_STEP).min(MAX_MOVE_DELAY)
+                }
+                _ => {}
+            }
+        }
+        if input.keycode == Some(KeyCode::U) && self.hot_seat() {
+            self.auto_flip = !self.auto_flip;
+            self.turn_board_to_mover();
+        }
+        if input.keycode == Some(KeyCode::H) {
+            self.blindfold = !self.blindfold;
+        }
+        if input.keycode == Some(KeyCode::E) {
+            self.explain_search = !self.explain_search;
+            self.last_explanation = None;
+        }
+        if input.keycode == Some(KeyCode::T) {
+            self.show_tactics = !self.show_tactics;
+        }
+        if input.keycode == Some(KeyCode::G) {
+            self.show_expected_reply = !self.show_expected_reply;
+        }
+        if input.keycode == Some(KeyCode::P) {
+            self.pondering = !self.pondering;
+            self.ponder = None;
+        }
+        if input.keycode == Some(KeyCode::A) {
+            self.auto_queen = !self.auto_queen;
+        }
+        if input.keycode == Some(KeyCode::S) {
+            self.audio.next_pack(ctx);
+        }
+        // The search task takes its own copy of the opponent, so changes apply from its next move.
+        // There's no swapping out another player over the network
+        match input.keycode.filter(|_| self.netplay.is_none()) {
+            Some(KeyCode::O) => self.swap_opponent(self.game.opponent.next_kind()),
+            Some(KeyCode::Equals) | Some(KeyCode::Plus) | Some(KeyCode::NumpadAdd) => {
+                self.game.opponent.adjust_strength(1)
+            }
+            Some(KeyCode::Minus) | Some(KeyCode::NumpadSubtract) => {
+                self.game.opponent.adjust_strength(-1)
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+    fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> Result<(), ggez::GameError> {
+        if !matches!(self.scene, Scene::Playing) {
+            return Ok(());
+        }
+        // Opened on release so the typed 'f' doesn't end up in the dialog's text box
+        if self.fen_dialog.is_none()
+            && self.move_entry.is_none()
+            && self.netplay.is_none()
+            && !self.drilling()
+            && input.keycode == Some(KeyCode::F)
+        {
+            self.fen_dialog = Some(FenDialog::new(self.game.board.variant));
+            self.selected_square = None;
+            self.drag_x = None;
+            self.drag_y = None;
+        }
+
+        Ok(())
+    }
+    fn text_input_event(
         &mut self,
         _ctx: &mut Context,
+        character: char,
+    ) -> Result<(), ggez::GameError> {
+        if let Some(dialog) = &mut self.fen_dialog {
+            dialog.input.push_char(character);
+            dialog.update_preview();
+        } else if let Some(entry) = &mut self.move_entry {
+            entry.input.push_char(character);
+            entry.rejection = None;
+        }
+
+        Ok(())
+    }
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
         button: event::MouseButton,
         x: f32,
         y: f32,
     ) -> Result<(), ggez::GameError> {
-        if button == event::MouseButton::Left {
-            let square_idx = MainState::get_square_idx_from_pixel(x, y) as usize;
+        let (x, y) = MainState::layout_point(ctx, x, y);
+        if let Scene::ThreePlayer(game) = &mut self.scene {
+            let square_size = WIDTH / THREE_PLAYER_SIDE as f32;
+            let (file, rank) = (
+                (x / square_size).floor() as i32,
+                THREE_PLAYER_SIDE as i32 - 1 - (y / square_size).floor() as i32,
+            );
+            if button == event::MouseButton::Left && on_board(file, rank) {
+                game.click(square(file as usize, rank as usize));
+            }
+            return Ok(());
+        }
+        if !matches!(self.scene, Scene::Playing) {
+            return Ok(());
+        }
+        if button == event::MouseButton::Left && self.promotion_choice.is_some() {
+            // Clicking anywhere but one of the offered pieces takes the pawn move back
+            let square_idx = self.get_square_idx_from_pixel(x, y) as usize;
+            let picked = self.promotion_choice.and_then(|(_, target)| {
+                MainState::promotion_squares(self.game.board.size, target)
+                    .into_iter()
+                    .find(|(_, square)| *square == square_idx)
+            });
+            match picked {
+                Some((piece, _)) => self.choose_promotion(Some(piece)),
+                None => self.promotion_choice = None,
+            }
+        } else if let Some(pressed) = panel_button_at(x, y)
+            .filter(|_| button == event::MouseButton::Left && self.fen_dialog.is_none())
+        {
+            self.press_panel_button(pressed);
+        } else if button == event::MouseButton::Left && (WIDTH..MOVE_LIST_X).contains(&x) {
+            if let Some(index) = self.analysis_line_at(y) {
+                self.play_analysis_line(index);
+            }
+        } else if button == event::MouseButton::Left && x >= WIDTH {
+            if let Some(ply) = self.move_list_ply_at(x, y) {
+                self.view_ply(ply);
+            }
+        } else if button == event::MouseButton::Left && self.viewed_ply.is_some() {
+            // Moves are only played from where the game stands, so clicking the board goes back there
+            self.viewed_ply = None;
+        } else if button == event::MouseButton::Left
+            && self.fen_dialog.is_none()
+            && self.finished.is_none()
+        {
+            // Any click on the board calls off a waiting premove, and dragging a piece can make a new one
+            self.premove = None;
+            let square_idx = self.get_square_idx_from_pixel(x, y) as usize;
             tracing::debug!("Mouse down on square {}", square_idx);
 
             // If there's a piece here, "select" the piece at this index to drag
-            self.selected_square = if square_idx < 64 {
+            self.selected_square = if square_idx < self.game.board.size.squares() {
                 Some(square_idx)
             } else {
                 None
@@ -519,46 +3953,72 @@ impl event::EventHandler<ggez::GameError> for MainState {
     }
     fn mouse_motion_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         x: f32,
         y: f32,
         _dx: f32,
         _dy: f32,
     ) -> Result<(), ggez::GameError> {
+        let (x, y) = MainState::layout_point(ctx, x, y);
         // Do drag effect on the piece at the currently selected square
 
-        self.drag_x = Some(x - (0.5 * SQUARE_SIZE));
-        self.drag_y = Some(y - (0.5 * SQUARE_SIZE));
+        self.drag_x = Some(x - (0.5 * self.square_size()));
+        self.drag_y = Some(y - (0.5 * self.square_size()));
 
         Ok(())
     }
     fn mouse_button_up_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         button: event::MouseButton,
         x: f32,
         y: f32,
     ) -> Result<(), ggez::GameError> {
+        let (x, y) = MainState::layout_point(ctx, x, y);
         if button == event::MouseButton::Left && self.queued_move.is_none() {
-            let target_square_idx = MainState::get_square_idx_from_pixel(x, y) as usize;
+            let target_square_idx = self.get_square_idx_from_pixel(x, y) as usize;
+            let on_board = target_square_idx < self.game.board.size.squares();
             tracing::debug!("Mouse up at square {}", target_square_idx);
             // Attempt a move here if it's on the bitboard
 
             if let Some(selected_square) = self.selected_square {
-                let ss_team = self.board.get_square_team(selected_square);
+                let ss_team = self.game.board.get_square_team(selected_square);
 
+                let premoved = self.opponent_to_move()
+                    && self.controls_team(ss_team)
+                    && on_board
+                    && target_square_idx != selected_square;
+                if premoved {
+                    // Whether it's legal can't be known until the opponent has moved, so it waits until then
+                    self.premove = Some((selected_square, target_square_idx));
+                }
                 if let Some(pl_moves) = &self.board_legal_moves {
-                    self.queued_move = if self.player_team == self.board.active_team
-                        && ss_team == self.player_team
-                    {
-                        pl_moves[selected_square]
+                    if !self.opponent_to_move() && self.controls_team(ss_team) {
+                        let dropped = pl_moves[selected_square]
                             .1
                             .iter()
                             .find(|fmove| fmove.target == target_square_idx)
-                            .copied()
-                    } else {
-                        self.queued_move
-                    };
+                            .copied();
+                        // A promotion is four moves to the same square, so the player is asked which one they meant
+                        match dropped {
+                            Some(fmove) if fmove.promotion.is_some() && !self.auto_queen => {
+                                self.promotion_choice = Some((selected_square, target_square_idx))
+                            }
+                            _ => self.queued_move = dropped,
+                        }
+                    }
+                }
+                // Dropping a piece back where it was, or picking up an empty square, isn't an attempt at a move
+                let attempted =
+                    on_board && target_square_idx != selected_square && ss_team != Team::None;
+                if attempted
+                    && !premoved
+                    && self.queued_move.is_none()
+                    && self.promotion_choice.is_none()
+                {
+                    self.move_rejection = self
+                        .rejection_reason(selected_square, target_square_idx)
+                        .map(|reason| (reason, Instant::now()));
                 }
             }
             // Drop the square if there is one
@@ -569,55 +4029,186 @@ impl event::EventHandler<ggez::GameError> for MainState {
 
         Ok(())
     }
+    // Closing the window mid-game saves it as it stands, to be offered back next time
+    fn quit_event(&mut self, _ctx: &mut Context) -> Result<bool, ggez::GameError> {
+        self.autosave(true);
+        Ok(false)
+    }
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.audio
+            .play_queued(ctx, self.settings.volume(), self.settings.muted)?;
         let mut canvas = graphics::Canvas::from_frame(ctx, Some(graphics::Color::from(BLACK)));
+        let (width, height) = ctx.gfx.drawable_size();
+        canvas.set_screen_coordinates(layout_view(width, height));
+
+        let menu_text = match &self.scene {
+            Scene::Setup(setup) => Some(setup.menu_text()),
+            Scene::Resume(saved) => Some(saved.prompt_text()),
+            _ => None,
+        };
+        if let Some(menu_text) = menu_text {
+            let mut menu = Text::new(menu_text);
+            menu.set_scale(SQUARE_SIZE * 0.4);
+            canvas.draw(
+                &menu,
+                DrawParam::default().dest(Point2 {
+                    x: SQUARE_SIZE,
+                    y: SQUARE_SIZE,
+                }),
+            );
+            canvas.finish(ctx)?;
+            return Ok(());
+        }
+        if matches!(self.scene, Scene::ThreePlayer(_)) {
+            self.draw_three_player(ctx, &mut canvas)?;
+            canvas.finish(ctx)?;
+            return Ok(());
+        }
+        // The board stays up behind the settings menu, to show off what's being picked
+        if let Scene::Settings(menu) = &self.scene {
+            let mut text_mesh = Text::new(menu.menu_text(&self.settings));
+            text_mesh.set_bounds(Vector2 {
+                x: PANEL_WIDTH,
+                y: WIDTH - (2.0 * PANEL_MARGIN),
+            });
+            self.draw_board(ctx, &mut canvas, false)?;
+            self.draw_pieces(ctx, &mut canvas, &self.game.board)?;
+            canvas.draw(
+                &text_mesh,
+                DrawParam::default().dest(Point2 {
+                    x: WIDTH + PANEL_MARGIN,
+                    y: PANEL_MARGIN,
+                }),
+            );
+            canvas.finish(ctx)?;
+            return Ok(());
+        }
 
-        if let Some(c_move) = self.queued_move {
+        if let Some(c_move) = self.queued_move.filter(|_| self.finished.is_none()) {
             if c_move.is_castle {
                 println!("Castling!");
             }
-            if let Ok(()) = self.board.make_move(c_move) {
-                let moving_piece_type = self.board.piece_list[c_move.target];
-                let moving_piece_team = self.board.get_square_team(c_move.target);
-                self.play_sound(ctx, "piece_move", 0.1)?;
-                self.last_move_origin = Some(c_move.start);
-                self.last_move_end = Some(c_move.target);
-                // Regenerate moves
-                self.board_legal_moves = Some(self.board.get_legal_moves());
-                let team_legal_moves_active = self.board.prune_moves_for_team(
-                    self.board_legal_moves.clone().unwrap(),
-                    self.board.active_team,
-                );
-                let is_checked_active = self.board.is_team_checked(self.board.active_team);
-
-                self.move_history.push(MoveHistoryEntry {
-                    piece_type: moving_piece_type,
-                    team: moving_piece_team,
-                    checks: self.board.is_team_checked(self.board.active_team),
-                    mate: team_legal_moves_active.is_empty() && is_checked_active,
-                    captures: c_move.captures.is_some(),
-                    target: c_move.target,
-                    start: c_move.start,
-                    castle: c_move.is_castle,
-                })
+            let book_moves = self.repertoire.as_ref().map_or(Vec::new(), |repertoire| {
+                repertoire.moves_for(&self.game.board).to_vec()
+            });
+            let board_before = self.game.board;
+            let time_left = self
+                .game
+                .clock
+                .as_ref()
+                .map(|clock| clock.remaining(self.game.board.active_team));
+            if self.apply_move(c_move) {
+                // Right after the player moves it's the opponent's turn, unless the player moves for both sides
+                let player_moved =
+                    (self.analysis_mode || self.opponent_to_move()) && !self.spectating();
+                if player_moved {
+                    self.viewed_ply = None;
+                }
+                if player_moved && !book_moves.is_empty() && !book_moves.contains(&c_move) {
+                    let prepared: Vec<String> = book_moves
+                        .iter()
+                        .map(|book_move| book_move.to_san(&board_before))
+                        .collect();
+                    self.book_note = Some(format!(
+                        "Left the repertoire, which plays {}",
+                        prepared.join(" or ")
+                    ));
+                }
+                let observing = self.ics_board.as_ref().is_none_or(Style12::is_observed);
+                if let Some(ics) = self.ics.as_mut().filter(|_| player_moved && !observing) {
+                    if let Err(err) = ics.send_move(c_move) {
+                        eprintln!("{err}");
+                    }
+                }
+                if let Some(netplay) = self.netplay.as_mut() {
+                    // Making a move turns down a draw offer, and one of ours lapses once they've answered with theirs
+                    if player_moved {
+                        netplay.peer_offers_draw = false;
+                        let message = NetMessage::Move {
+                            ply: self.game.move_history.len(),
+                            uci: c_move.to_uci_on(self.game.board.size),
+                            time_left,
+                            hash: self.game.board.zobrist,
+                        };
+                        if let Err(err) = netplay.send(&message) {
+                            eprintln!("{err}");
+                        }
+                    } else {
+                        netplay.draw_offered = false;
+                    }
+                }
+                if let Some(correspondence) =
+                    self.correspondence.as_mut().filter(|_| !self.analysis_mode)
+                {
+                    if let Err(err) = correspondence.record_move(c_move, self.game.board.size) {
+                        eprintln!("{err}");
+                    }
+                }
+                // Moves that didn't come off the electronic board, like the opponent's, have to be made on it by hand
+                if self
+                    .dgt_placement
+                    .is_some_and(|placement| placement != placement_of(&self.game.board))
+                {
+                    self.dgt_unmirrored.push(c_move.to_san(&board_before));
+                }
+                self.audio.queue(Sound::for_move(c_move, &self.game.board));
             }
 
             tracing::debug!(
                 "White bitboard after move: {}",
-                self.board.get_team_coverage(Team::White)
+                self.game.board.get_team_coverage(Team::White)
             );
             tracing::debug!(
                 "Black bitboard after move: {}",
-                self.board.get_team_coverage(Team::Black)
+                self.game.board.get_team_coverage(Team::Black)
             );
             // Pull the move from queue
-            if self.player_team == self.board.active_team {
+            self.expected_reply = None;
+            if !self.opponent_to_move() {
                 self.opp_thread = None;
             }
             self.queued_move = None;
+            // Once the opponent has moved, a premove is played if it's legal now and called off if it isn't
+            if !self.opponent_to_move() {
+                if let Some((start, target)) = self.premove.take() {
+                    self.queued_move = self.board_legal_moves.as_ref().and_then(|pl_moves| {
+                        pl_moves[start]
+                            .1
+                            .iter()
+                            .find(|fmove| {
+                                fmove.target == target
+                                    && matches!(fmove.promotion, None | Some(PieceType::Queen))
+                            })
+                            .copied()
+                    });
+                }
+            }
+        }
+        // While a position is being set up, or the game looked back through, the board shows that instead
+        let preview = match &self.fen_dialog {
+            Some(FenDialog {
+                preview: Ok(board), ..
+            }) => Some(*board),
+            _ => self.viewed_ply.map(|ply| self.game.board_at(ply)),
+        };
+        self.draw_board(ctx, &mut canvas, preview.is_none())?;
+        // A position being set up is still shown blindfolded, since it has to be checked before it's played
+        if !self.blindfold || self.fen_dialog.is_some() {
+            self.draw_pieces(
+                ctx,
+                &mut canvas,
+                preview.as_ref().unwrap_or(&self.game.board),
+            )?;
+        }
+        if preview.is_none() {
+            self.draw_last_move_arrow(ctx, &mut canvas)?;
+            self.draw_expected_reply(&mut canvas)?;
+            self.draw_promotion_picker(ctx, &mut canvas)?;
+            self.draw_result_banner(ctx, &mut canvas)?;
+            self.draw_eval_graph(ctx, &mut canvas)?;
         }
-        self.draw_board(ctx, &mut canvas)?;
-        self.draw_pieces(ctx, &mut canvas)?;
+        self.draw_panel(ctx, &mut canvas)?;
+        self.draw_move_list(&mut canvas);
 
         //};
         canvas.finish(ctx)?;
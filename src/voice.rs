@@ -0,0 +1,224 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Receiver},
+};
+
+use crate::{board::BoardState, r#move::Move};
+
+// Words a recogniser might hear for each piece, and the letter SAN gives it
+const PIECE_WORDS: [(&str, &str); 7] = [
+    ("king", "K"),
+    ("queen", "Q"),
+    ("rook", "R"),
+    ("bishop", "B"),
+    ("knight", "N"),
+    ("night", "N"),
+    ("horse", "N"),
+];
+const FILE_WORDS: [&str; 8] = [
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel",
+];
+const NUMBER_WORDS: [&str; 8] = [
+    "one", "two", "three", "four", "five", "six", "seven", "eight",
+];
+const ORDINAL_WORDS: [&str; 8] = [
+    "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth",
+];
+const CANCEL_WORDS: [&str; 4] = ["cancel", "no", "neither", "none"];
+
+fn number_word(word: &str) -> Option<usize> {
+    word.parse::<usize>()
+        .ok()
+        .or_else(|| {
+            NUMBER_WORDS
+                .iter()
+                .position(|number| *number == word)
+                .map(|index| index + 1)
+        })
+        .or_else(|| {
+            ORDINAL_WORDS
+                .iter()
+                .position(|ordinal| *ordinal == word)
+                .map(|index| index + 1)
+        })
+        // Choices are counted from 1, so there's no zeroth of anything
+        .filter(|number| *number >= 1)
+}
+
+// A file, a rank or a whole square, so words like "be" or "add" aren't taken for squares
+fn is_square_text(word: &str) -> bool {
+    let is_file = |c: char| ('a'..='h').contains(&c);
+    let is_rank = |c: char| ('1'..='8').contains(&c);
+    let mut chars = word.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(c), None, None) => is_file(c) || is_rank(c),
+        (Some(file), Some(rank), None) => is_file(file) && is_rank(rank),
+        _ => false,
+    }
+}
+
+/*
+    Turns what was said into SAN, so "knight f3" becomes Nf3 and "bishop takes echo five" Bxe5. Files can be
+    said as letters or the phonetic alphabet and ranks as words. "castle kingside" and "castle queenside" castle
+*/
+pub fn spoken_to_san(phrase: &str) -> Option<String> {
+    let phrase = phrase.to_lowercase();
+    let words: Vec<&str> = phrase
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let castles = words.iter().any(|word| word.starts_with("castle"));
+    if castles || words.contains(&"short") || words.contains(&"long") {
+        if words
+            .iter()
+            .any(|word| ["queenside", "queen", "long"].contains(word))
+        {
+            return Some(String::from("O-O-O"));
+        }
+        if words
+            .iter()
+            .any(|word| ["kingside", "king", "short"].contains(word))
+        {
+            return Some(String::from("O-O"));
+        }
+    }
+
+    let mut san = String::new();
+    for (index, word) in words.iter().enumerate() {
+        let promoting =
+            index > 0 && ["promote", "promotes", "equals", "to"].contains(&words[index - 1]);
+        if let Some((_, letter)) = PIECE_WORDS.iter().find(|(name, _)| name == word) {
+            if promoting && !san.is_empty() {
+                san.push('=');
+            }
+            san.push_str(letter);
+        } else if ["takes", "captures", "x"].contains(word) {
+            san.push('x');
+        } else if let Some(file) = FILE_WORDS.iter().position(|name| name == word) {
+            san.push((b'a' + file as u8) as char);
+        } else if let Some(rank) = number_word(word).filter(|rank| (1..=8).contains(rank)) {
+            san.push_str(&rank.to_string());
+        } else if is_square_text(word) {
+            // Squares and file letters said outright, like "f3" or "b"
+            san.push_str(word);
+        }
+    }
+    // Words like "pawn", "to" and "check" add nothing to the move
+    Some(san).filter(|san| san.chars().any(|c| c.is_ascii_digit()))
+}
+
+/*
+    The legal moves that fit what was said. An exact SAN match is the move, otherwise every move with the right
+    piece and target square whose start square fits any file or rank that was said is a candidate, so "knight d7"
+    with two knights able to get there gives both of them to choose between
+*/
+pub fn spoken_moves(board: &BoardState, phrase: &str) -> Vec<Move> {
+    let Some(san) = spoken_to_san(phrase) else {
+        return Vec::new();
+    };
+    if let Some(exact) = Move::from_san(board, &san) {
+        return vec![exact];
+    }
+    if san.starts_with('O') {
+        return Vec::new();
+    }
+
+    let (san, promotion) = san.split_once('=').unwrap_or((&san, ""));
+    let (piece, rest) = match san.chars().next() {
+        Some(letter) if letter.is_ascii_uppercase() => (letter.to_string(), &san[1..]),
+        _ => (String::new(), san),
+    };
+    let rest = rest.replace('x', "");
+    if rest.len() < 2 {
+        return Vec::new();
+    }
+    let (hints, target) = rest.split_at(rest.len() - 2);
+
+    board
+        .prune_moves_for_team(board.get_legal_moves(), board.active_team)
+        .into_iter()
+        .filter(|legal_move| {
+            let legal_san = legal_move.to_san(board);
+            let start = board.size.notation(legal_move.start).unwrap_or_default();
+            let legal_piece = legal_san
+                .chars()
+                .next()
+                .filter(|letter| letter.is_ascii_uppercase() && *letter != 'O')
+                .map(String::from)
+                .unwrap_or_default();
+            legal_piece == piece
+                && !legal_san.starts_with('O')
+                && board.size.notation(legal_move.target).as_deref() == Some(target)
+                && hints.chars().all(|hint| start.contains(hint))
+                && (promotion.is_empty() || legal_san.contains(&format!("={promotion}")))
+        })
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Confirmation {
+    Choice(usize), // Counting from 1, the way the choices are read out
+    Cancel,
+}
+
+// The answer to "did you mean", which is either a choice by its number or calling the move off
+pub fn spoken_confirmation(phrase: &str) -> Option<Confirmation> {
+    let phrase = phrase.to_lowercase();
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    if words.iter().any(|word| CANCEL_WORDS.contains(word)) {
+        return Some(Confirmation::Cancel);
+    }
+    words
+        .iter()
+        .find_map(|word| number_word(word))
+        .map(Confirmation::Choice)
+}
+
+/*
+    A speech recogniser running as its own process, which is expected to write each phrase it hears on a line of
+    its own. Whatever it hears is read on a thread and picked up with `poll`
+*/
+pub struct VoiceInput {
+    process: Child,
+    phrases: Receiver<String>,
+}
+impl VoiceInput {
+    pub fn launch(command: &str) -> Result<VoiceInput, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("--voice needs a recogniser to run")?;
+        let mut process = Command::new(program)
+            .args(words)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Couldn't start {program}: {err}"))?;
+        let output = process
+            .stdout
+            .take()
+            .ok_or("The recogniser has no stdout")?;
+
+        let (phrase_tx, phrases) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(output).lines().map_while(Result::ok) {
+                let phrase = line.trim().to_string();
+                if !phrase.is_empty() && phrase_tx.send(phrase).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(VoiceInput { process, phrases })
+    }
+    // Everything heard since the last call
+    pub fn poll(&self) -> Vec<String> {
+        self.phrases.try_iter().collect()
+    }
+}
+impl Drop for VoiceInput {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}